@@ -0,0 +1,150 @@
+use bitcoin::blockdata::block::Block;
+use bitcoin::consensus::{encode, Decodable};
+use std::io::{Read, Write};
+
+// Network magic used to frame blocks in mainnet blk*.dat files
+pub const MAINNET_MAGIC: [u8; 4] = [0xF9, 0xBE, 0xB4, 0xD9];
+pub const REGTEST_MAGIC: [u8; 4] = [0xFA, 0xBF, 0xB5, 0xDA];
+
+// A single block record read from a blk*.dat file
+#[derive(Debug, Clone)]
+pub struct BlkRecord {
+    pub index: usize,
+    pub offset: u64, // offset of the magic bytes inside the file
+    pub block: Block,
+}
+
+// Iterator over the magic + length framed blocks of a blk*.dat file
+pub struct BlkFileReader<R: Read> {
+    reader: R,
+    magic: [u8; 4],
+    offset: u64,
+    index: usize,
+}
+
+impl<R: Read> BlkFileReader<R> {
+    pub fn new(reader: R, magic: [u8; 4]) -> Self {
+        Self { reader, magic, offset: 0, index: 0 }
+    }
+
+    // Read the next framed block, returning None at end of file or zero padding
+    fn read_record(&mut self) -> Result<Option<BlkRecord>, Box<dyn std::error::Error>> {
+        let mut magic = [0u8; 4];
+        match self.reader.read_exact(&mut magic) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        // Bitcoin Core preallocates blk files, so the tail is zero filled
+        if magic == [0u8; 4] {
+            return Ok(None);
+        }
+        if magic != self.magic {
+            return Err(format!("Invalid magic {} at offset {}", hex::encode(magic), self.offset).into());
+        }
+
+        let mut len_bytes = [0u8; 4];
+        self.reader.read_exact(&mut len_bytes)?;
+        let len = u32::from_le_bytes(len_bytes) as usize;
+
+        let mut data = vec![0u8; len];
+        self.reader.read_exact(&mut data)?;
+        let block = Block::consensus_decode(&mut &data[..])?;
+
+        let record = BlkRecord { index: self.index, offset: self.offset, block };
+        self.offset += 8 + len as u64;
+        self.index += 1;
+        Ok(Some(record))
+    }
+}
+
+impl<R: Read> Iterator for BlkFileReader<R> {
+    type Item = Result<BlkRecord, Box<dyn std::error::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+// Reading and writing of Bitcoin Core blk*.dat files
+pub struct BlkFile;
+
+impl BlkFile {
+    // Open a blk*.dat file for iteration
+    pub fn open(path: &str, magic: [u8; 4]) -> std::io::Result<BlkFileReader<std::io::BufReader<std::fs::File>>> {
+        let file = std::fs::File::open(path)?;
+        Ok(BlkFileReader::new(std::io::BufReader::new(file), magic))
+    }
+
+    // Write blocks using the same magic + length framing Bitcoin Core uses
+    pub fn write_blocks(path: &str, magic: [u8; 4], blocks: &[Block]) -> std::io::Result<()> {
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(path)?);
+        for block in blocks {
+            let data = encode::serialize(block);
+            writer.write_all(&magic)?;
+            writer.write_all(&(data.len() as u32).to_le_bytes())?;
+            writer.write_all(&data)?;
+        }
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::Network;
+
+    fn blocks() -> Vec<Block> {
+        vec![genesis_block(Network::Bitcoin), genesis_block(Network::Testnet), genesis_block(Network::Signet)]
+    }
+
+    fn read_all(bytes: &[u8], magic: [u8; 4]) -> Result<Vec<BlkRecord>, Box<dyn std::error::Error>> {
+        BlkFileReader::new(bytes, magic).collect()
+    }
+
+    #[test]
+    fn round_trips_through_a_file() {
+        let path = std::env::temp_dir().join(format!("block_breaker_blk_{}.dat", std::process::id()));
+        let path = path.to_str().unwrap();
+        BlkFile::write_blocks(path, REGTEST_MAGIC, &blocks()).unwrap();
+        let mut bytes = std::fs::read(path).unwrap();
+
+        // magic, little-endian length, then the serialized block
+        let first = encode::serialize(&blocks()[0]);
+        assert_eq!(bytes[..4], REGTEST_MAGIC);
+        assert_eq!(bytes[4..8], (first.len() as u32).to_le_bytes());
+        assert_eq!(bytes[8..8 + first.len()], first);
+        assert_eq!(bytes[8 + first.len()..12 + first.len()], REGTEST_MAGIC);
+
+        // Core preallocates blk files, so readers stop at the zero padding
+        std::fs::OpenOptions::new().append(true).open(path).unwrap().write_all(&[0; 64]).unwrap();
+        let records: Vec<BlkRecord> = BlkFile::open(path, REGTEST_MAGIC).unwrap().collect::<Result<_, _>>().unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(records.iter().map(|r| r.block.clone()).collect::<Vec<_>>(), blocks());
+        assert_eq!(records.iter().map(|r| r.index).collect::<Vec<_>>(), [0, 1, 2]);
+        let mut offset = 0;
+        for record in &records {
+            assert_eq!(record.offset, offset);
+            offset += 8 + encode::serialize(&record.block).len() as u64;
+        }
+
+        bytes.truncate(offset as usize);
+        assert_eq!(read_all(&bytes, REGTEST_MAGIC).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn rejects_bad_framing() {
+        let data = encode::serialize(&blocks()[0]);
+        let mut bytes = MAINNET_MAGIC.to_vec();
+        bytes.extend((data.len() as u32).to_le_bytes());
+        bytes.extend(&data);
+
+        let error = read_all(&bytes, REGTEST_MAGIC).unwrap_err().to_string();
+        assert_eq!(error, "Invalid magic f9beb4d9 at offset 0");
+        assert!(read_all(&bytes[..bytes.len() - 1], MAINNET_MAGIC).is_err());
+        assert!(read_all(&bytes[..6], MAINNET_MAGIC).is_err());
+        assert!(read_all(&[], MAINNET_MAGIC).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,89 @@
+use bitcoin::blockdata::block::{Block, Header};
+
+use crate::blk::BlkFile;
+use crate::presets::CorruptionPreset;
+use crate::processor::{BlockField, BlockProcessor, ProcessingConfig};
+use crate::report::{BatchEntry, MutationReport};
+
+// Simplified interface for common use cases
+pub struct BlockBreaker;
+
+impl BlockBreaker {
+    // Break all fields with default settings
+    pub fn break_all_fields(block: &Block) -> (Block, MutationReport) {
+        let processor = BlockProcessor::with_default_config();
+        processor.process_block(block)
+    }
+
+    // Break only specific fields
+    pub fn break_specific_fields(block: &Block, fields: Vec<BlockField>) -> (Block, MutationReport) {
+        let config = ProcessingConfig {
+            fields_to_modify: fields,
+            ..Default::default()
+        };
+        let processor = BlockProcessor::new(config);
+        processor.process_block(block)
+    }
+
+    // Break with custom configuration
+    pub fn break_with_config(block: &Block, config: ProcessingConfig) -> (Block, MutationReport) {
+        let processor = BlockProcessor::new(config);
+        processor.process_block(block)
+    }
+
+    // Apply a subtle-corruption preset to a block at `height`
+    pub fn break_with_preset(block: &Block, preset: &CorruptionPreset, height: u32) -> (Block, MutationReport) {
+        Self::break_with_config(block, preset.config(block, height))
+    }
+
+    // Break the selected blocks of a blk*.dat file (all when `selected` is empty) and write a new file,
+    // returning the index and report of every broken block
+    pub fn break_blk_file(
+        in_path: &str,
+        out_path: &str,
+        magic: [u8; 4],
+        selected: &[usize],
+        config: ProcessingConfig,
+    ) -> Result<Vec<(usize, MutationReport)>, Box<dyn std::error::Error>> {
+        let processor = BlockProcessor::new(config);
+        let mut blocks = Vec::new();
+        let mut reports = Vec::new();
+        for record in BlkFile::open(in_path, magic)? {
+            let record = record?;
+            if selected.is_empty() || selected.contains(&record.index) {
+                let (block, report) = processor.process_block(&record.block);
+                blocks.push(block);
+                reports.push((record.index, report));
+            } else {
+                blocks.push(record.block);
+            }
+        }
+        BlkFile::write_blocks(out_path, magic, &blocks)?;
+        Ok(reports)
+    }
+
+    // Break a batch of labeled blocks with one processor, so a seeded run is reproducible
+    // across the whole batch. Returns the broken blocks with a summary entry for each.
+    pub fn break_batch(blocks: &[(String, Block)], config: ProcessingConfig) -> Vec<(Block, BatchEntry)> {
+        let processor = BlockProcessor::new(config);
+        blocks
+            .iter()
+            .map(|(label, block)| {
+                let (broken, report) = processor.process_block(block);
+                let entry = BatchEntry::new(label, block.block_hash(), broken.block_hash(), report);
+                (broken, entry)
+            })
+            .collect()
+    }
+
+    // Break header fields and return a minimal block
+    pub fn break_header_fields(header: &Header, fields: Vec<BlockField>) -> (Block, MutationReport) {
+        let config = ProcessingConfig {
+            fields_to_modify: fields,
+            ..Default::default()
+        };
+        let processor = BlockProcessor::new(config);
+        let (modified_header, report) = processor.process_block_header(header);
+        (BlockProcessor::create_minimal_block_from_header(modified_header), report)
+    }
+}
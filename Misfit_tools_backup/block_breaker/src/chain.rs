@@ -0,0 +1,423 @@
+use bitcoin::consensus::Decodable;
+use bitcoin::{blockdata::block::Header, hash_types::BlockHash, pow::Work};
+
+use crate::pow::{ConsensusParams, RETARGET_INTERVAL, TARGET_SPACING};
+use crate::processor::BlockProcessor;
+
+// Timestamp rules used by header chain validation
+pub const MAX_FUTURE_BLOCK_TIME: u32 = 2 * 60 * 60;
+pub const MEDIAN_TIME_SPAN: usize = 11;
+// BIP54 timewarp fix: the first block of a retarget period may be at most this much
+// earlier than its parent
+pub const MAX_TIMEWARP: u32 = 7200;
+// BIP94 (testnet4) enforces the same rule with a tighter bound
+pub const BIP94_MAX_TIMEWARP: u32 = 600;
+
+// Reason a header failed chain validation
+#[derive(Debug, Clone, PartialEq)]
+pub enum HeaderChainError {
+    BrokenLink { expected: BlockHash, found: BlockHash },
+    InvalidPow,
+    TimestampNotAfterMtp { median_time_past: u32, time: u32 },
+    TimestampTooFarInFuture { max_allowed: u32, time: u32 },
+    Timewarp { min_allowed: u32, time: u32 },
+    UnexpectedBits { expected: u32, found: u32 },
+}
+
+// Timestamp placed relative to the median time past of the previous headers
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimestampAttack {
+    BeforeMtp,             // one second before MTP (invalid)
+    ExactlyMtp,            // equal to MTP (invalid, must be strictly greater)
+    JustAfterMtp,          // one second after MTP, the minimal timestamp a time-warp attack uses (valid)
+    FarFuture,             // one second past the two hour future limit (invalid)
+    FutureDrift(u32),      // this many seconds past the two hour future limit (0 is exactly at it)
+    BeforePrevious,        // one second before the parent but still after MTP (valid, non-monotonic)
+    TimewarpPeriodStart,   // one second more than BIP54 allows before the parent, for a period's first block
+}
+
+// First header that failed chain validation
+#[derive(Debug, Clone)]
+pub struct InvalidHeader {
+    pub index: usize,
+    pub height: u32,
+    pub block_hash: BlockHash,
+    pub error: HeaderChainError,
+}
+
+// Result of validating a sequence of headers
+#[derive(Debug, Clone)]
+pub struct HeaderChainReport {
+    pub start_height: u32,
+    pub headers_checked: usize,
+    pub tip_hash: Option<BlockHash>,
+    pub chainwork: Work, // cumulative work of the valid headers
+    pub first_invalid: Option<InvalidHeader>,
+}
+
+// Loading and validation of concatenated 80-byte header files
+pub struct HeaderChain;
+
+impl HeaderChain {
+    // Load a file of concatenated 80-byte headers
+    pub fn load_headers(path: &str) -> Result<Vec<Header>, Box<dyn std::error::Error>> {
+        let bytes = std::fs::read(path)?;
+        if bytes.len() % 80 != 0 {
+            return Err(format!("Invalid header file length: {} is not a multiple of 80", bytes.len()).into());
+        }
+        bytes
+            .chunks(80)
+            .map(|chunk| Ok(Header::consensus_decode(&mut &chunk[..])?))
+            .collect()
+    }
+
+    // Median of the timestamps of up to the last 11 headers
+    pub fn median_time_past(headers: &[Header]) -> u32 {
+        let start = headers.len().saturating_sub(MEDIAN_TIME_SPAN);
+        let mut times: Vec<u32> = headers[start..].iter().map(|h| h.time).collect();
+        times.sort_unstable();
+        times[times.len() / 2]
+    }
+
+    // Timestamp for an attack given up to the last 11 previous headers, or None when the
+    // previous headers are missing or leave no room for it (e.g. parent time right after MTP)
+    pub fn timestamp_for_attack(attack: &TimestampAttack, previous: &[Header], now: u32) -> Option<u32> {
+        let median_time_past = || (!previous.is_empty()).then(|| Self::median_time_past(previous));
+        match attack {
+            TimestampAttack::BeforeMtp => median_time_past().map(|mtp| mtp.saturating_sub(1)),
+            TimestampAttack::ExactlyMtp => median_time_past(),
+            TimestampAttack::JustAfterMtp => median_time_past().map(|mtp| mtp.saturating_add(1)),
+            TimestampAttack::FarFuture => Some(now.saturating_add(MAX_FUTURE_BLOCK_TIME + 1)),
+            TimestampAttack::FutureDrift(seconds) => Some(now.saturating_add(MAX_FUTURE_BLOCK_TIME).saturating_add(*seconds)),
+            TimestampAttack::BeforePrevious => {
+                let time = previous.last()?.time.checked_sub(1)?;
+                (time > median_time_past()?).then_some(time)
+            }
+            TimestampAttack::TimewarpPeriodStart => previous.last()?.time.checked_sub(MAX_TIMEWARP + 1),
+        }
+    }
+
+    // Check a timestamp against the MTP of the previous headers and the future limit
+    pub fn check_timestamp(time: u32, previous: &[Header], now: u32) -> Result<(), HeaderChainError> {
+        if !previous.is_empty() {
+            let median_time_past = Self::median_time_past(previous);
+            if time <= median_time_past {
+                return Err(HeaderChainError::TimestampNotAfterMtp { median_time_past, time });
+            }
+        }
+
+        let max_allowed = now.saturating_add(MAX_FUTURE_BLOCK_TIME);
+        if time > max_allowed {
+            return Err(HeaderChainError::TimestampTooFarInFuture { max_allowed, time });
+        }
+        Ok(())
+    }
+
+    // Cumulative chainwork after each header, starting from `base` (the chainwork of the
+    // parent of the first header, or zero)
+    pub fn cumulative_work(headers: &[Header], base: Work) -> Vec<Work> {
+        headers
+            .iter()
+            .scan(base, |chainwork, header| {
+                *chainwork = BlockProcessor::add_work(*chainwork, BlockProcessor::block_work(header.bits.to_consensus()));
+                Some(*chainwork)
+            })
+            .collect()
+    }
+
+    // Bits expected for the header at `height`, when enough history is loaded to know
+    fn expected_bits(headers: &[Header], index: usize, height: u32, params: &ConsensusParams) -> Option<u32> {
+        let prev = headers.get(index.checked_sub(1)?)?;
+        if !height.is_multiple_of(RETARGET_INTERVAL) {
+            if !params.allow_min_difficulty {
+                return Some(prev.bits.to_consensus());
+            }
+            // Testnet: a block more than 20 minutes after its parent may use the pow limit,
+            // otherwise it has the bits of the last block that was not mined at the limit
+            if headers[index].time > prev.time.saturating_add(2 * TARGET_SPACING) {
+                return Some(params.pow_limit_bits);
+            }
+            let (mut last, mut last_height) = (index - 1, height - 1);
+            let at_limit = |last: usize, last_height: u32| {
+                !last_height.is_multiple_of(RETARGET_INTERVAL) && headers[last].bits.to_consensus() == params.pow_limit_bits
+            };
+            while last > 0 && at_limit(last, last_height) {
+                last -= 1;
+                last_height -= 1;
+            }
+            // The walk ran out of loaded headers before finding that block
+            if at_limit(last, last_height) {
+                return None;
+            }
+            return Some(headers[last].bits.to_consensus());
+        }
+        let first = headers.get(index.checked_sub(RETARGET_INTERVAL as usize)?)?;
+        // BIP94 retargets from the first block of the period, which cannot be a
+        // min-difficulty block
+        let bits = if params.enforce_bip94 { first.bits } else { prev.bits };
+        Some(BlockProcessor::next_work_required_for(params, bits.to_consensus(), first.time, prev.time))
+    }
+
+    // Check a single header against the headers preceding it
+    pub(crate) fn check_header(headers: &[Header], index: usize, height: u32, now: u32, params: &ConsensusParams) -> Result<(), HeaderChainError> {
+        let header = &headers[index];
+
+        if index > 0 {
+            let expected = headers[index - 1].block_hash();
+            if header.prev_blockhash != expected {
+                return Err(HeaderChainError::BrokenLink { expected, found: header.prev_blockhash });
+            }
+        }
+
+        let pow = BlockProcessor::validate_pow(header);
+        if !pow.valid || pow.target.target > BlockProcessor::expand_target(params.pow_limit_bits).target {
+            return Err(HeaderChainError::InvalidPow);
+        }
+
+        Self::check_timestamp(header.time, &headers[..index], now)?;
+
+        if params.enforce_bip94 && height.is_multiple_of(RETARGET_INTERVAL) && index > 0 {
+            let min_allowed = headers[index - 1].time.saturating_sub(BIP94_MAX_TIMEWARP);
+            if header.time < min_allowed {
+                return Err(HeaderChainError::Timewarp { min_allowed, time: header.time });
+            }
+        }
+
+        if let Some(expected) = Self::expected_bits(headers, index, height, params) {
+            let found = header.bits.to_consensus();
+            if found != expected {
+                return Err(HeaderChainError::UnexpectedBits { expected, found });
+            }
+        }
+        Ok(())
+    }
+
+    // Validate linkage, PoW, timestamps and difficulty transitions under a network's rules,
+    // stopping at the first invalid header
+    pub fn validate(headers: &[Header], start_height: u32, params: &ConsensusParams) -> HeaderChainReport {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as u32;
+
+        let mut report = HeaderChainReport {
+            start_height,
+            headers_checked: 0,
+            tip_hash: None,
+            chainwork: Work::from_be_bytes([0u8; 32]),
+            first_invalid: None,
+        };
+
+        for index in 0..headers.len() {
+            let height = start_height + index as u32;
+            if let Err(error) = Self::check_header(headers, index, height, now, params) {
+                report.first_invalid = Some(InvalidHeader {
+                    index,
+                    height,
+                    block_hash: headers[index].block_hash(),
+                    error,
+                });
+                break;
+            }
+            report.headers_checked += 1;
+            report.tip_hash = Some(headers[index].block_hash());
+            report.chainwork = BlockProcessor::add_work(report.chainwork, BlockProcessor::block_work(headers[index].bits.to_consensus()));
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::blockdata::block::Version;
+    use bitcoin::hash_types::TxMerkleNode;
+    use bitcoin::hashes::Hash;
+    use bitcoin::CompactTarget;
+
+    // Mainnet rules with regtest's pow limit, so headers can be mined in a test
+    const EASY: ConsensusParams = ConsensusParams { pow_limit_bits: 0x207fffff, ..ConsensusParams::MAINNET };
+    // Half the pow limit target, for blocks that are not min-difficulty blocks
+    const BITS: u32 = 0x203fffff;
+    const START: u32 = 1_600_000_000;
+
+    fn mine(prev: Option<&Header>, time: u32, bits: u32) -> Header {
+        let mut header = Header {
+            version: Version::from_consensus(4),
+            prev_blockhash: prev.map_or(BlockHash::all_zeros(), |prev| prev.block_hash()),
+            merkle_root: TxMerkleNode::all_zeros(),
+            time,
+            bits: CompactTarget::from_consensus(bits),
+            nonce: 0,
+        };
+        while !BlockProcessor::validate_pow(&header).valid {
+            header.nonce += 1;
+        }
+        header
+    }
+
+    // Headers from height 0, `spacing` seconds apart, all with the same bits
+    fn chain(count: usize, spacing: u32, bits: u32) -> Vec<Header> {
+        let mut headers: Vec<Header> = Vec::with_capacity(count + 1);
+        for i in 0..count as u32 {
+            let header = mine(headers.last(), START + i * spacing, bits);
+            headers.push(header);
+        }
+        headers
+    }
+
+    fn push(headers: &mut Vec<Header>, delay: u32, bits: u32) {
+        let prev = headers.last().unwrap();
+        let header = mine(Some(prev), prev.time + delay, bits);
+        headers.push(header);
+    }
+
+    fn first_error(headers: &[Header], params: &ConsensusParams) -> Option<HeaderChainError> {
+        HeaderChain::validate(headers, 0, params).first_invalid.map(|invalid| invalid.error)
+    }
+
+    #[test]
+    fn rejects_a_broken_link() {
+        let mut headers = chain(3, 600, BITS);
+        let expected = headers[1].block_hash();
+        headers[2] = mine(Some(&headers[0]), headers[2].time, BITS);
+        let invalid = HeaderChain::validate(&headers, 0, &EASY).first_invalid.unwrap();
+        assert_eq!((invalid.index, invalid.height), (2, 2));
+        assert_eq!(invalid.error, HeaderChainError::BrokenLink { expected, found: headers[0].block_hash() });
+    }
+
+    #[test]
+    fn timestamp_must_be_after_median_time_past() {
+        let headers = chain(MEDIAN_TIME_SPAN, 600, BITS);
+        let median_time_past = headers[MEDIAN_TIME_SPAN / 2].time;
+        assert_eq!(HeaderChain::median_time_past(&headers), median_time_past);
+        let now = START + 86_400;
+        for time in [median_time_past - 1, median_time_past] {
+            assert_eq!(HeaderChain::check_timestamp(time, &headers, now), Err(HeaderChainError::TimestampNotAfterMtp { median_time_past, time }));
+        }
+        // Earlier than its parent is fine, as long as it is after the median
+        assert_eq!(HeaderChain::check_timestamp(median_time_past + 1, &headers, now), Ok(()));
+
+        let mut late = headers.clone();
+        late.push(mine(late.last(), median_time_past, BITS));
+        let invalid = HeaderChain::validate(&late, 0, &EASY).first_invalid.unwrap();
+        assert_eq!(invalid.height, MEDIAN_TIME_SPAN as u32);
+    }
+
+    #[test]
+    fn timestamp_may_drift_at_most_two_hours() {
+        let headers = chain(1, 600, BITS);
+        let now = START + 600;
+        let max_allowed = now + MAX_FUTURE_BLOCK_TIME;
+        assert_eq!(HeaderChain::check_timestamp(max_allowed, &headers, now), Ok(()));
+        assert_eq!(HeaderChain::check_timestamp(max_allowed + 1, &headers, now), Err(HeaderChainError::TimestampTooFarInFuture { max_allowed, time: max_allowed + 1 }));
+
+        let far = HeaderChain::timestamp_for_attack(&TimestampAttack::FarFuture, &headers, now).unwrap();
+        let mut future = headers.clone();
+        future.push(mine(future.last(), far, BITS));
+        assert_eq!(
+            HeaderChain::check_header(&future, 1, 1, now, &EASY),
+            Err(HeaderChainError::TimestampTooFarInFuture { max_allowed, time: far })
+        );
+    }
+
+    #[test]
+    fn bits_must_follow_the_retarget() {
+        let mut headers = chain(RETARGET_INTERVAL as usize, 600, BITS);
+        let last = headers.last().unwrap().time;
+        let expected = BlockProcessor::next_work_required_for(&EASY, BITS, START, last);
+        assert_ne!(expected, BITS);
+
+        // Keeping the old bits across the boundary is wrong, and so is the retarget anywhere else
+        let mut unchanged = headers.clone();
+        push(&mut unchanged, 600, BITS);
+        assert_eq!(first_error(&unchanged, &EASY), Some(HeaderChainError::UnexpectedBits { expected, found: BITS }));
+        let mut early = headers[..RETARGET_INTERVAL as usize - 1].to_vec();
+        push(&mut early, 600, expected);
+        assert_eq!(first_error(&early, &EASY), Some(HeaderChainError::UnexpectedBits { expected: BITS, found: expected }));
+
+        push(&mut headers, 600, expected);
+        let report = HeaderChain::validate(&headers, 0, &EASY);
+        assert!(report.first_invalid.is_none());
+        assert_eq!(report.headers_checked, RETARGET_INTERVAL as usize + 1);
+        // Without the period's first header the retarget cannot be checked
+        assert!(HeaderChain::validate(&headers[1..], 1, &EASY).first_invalid.is_none());
+    }
+
+    #[test]
+    fn regtest_does_not_retarget() {
+        let regtest_limit = ConsensusParams::REGTEST.pow_limit_bits;
+        let headers = chain(RETARGET_INTERVAL as usize + 1, 1, regtest_limit);
+        assert_eq!(first_error(&headers, &ConsensusParams::REGTEST), None);
+        // The same fast chain retargets to a quarter of the target where retargeting applies
+        let quartered = BlockProcessor::next_work_required_for(&EASY, regtest_limit, START, START + 2015);
+        assert_eq!(first_error(&headers, &EASY), Some(HeaderChainError::UnexpectedBits { expected: quartered, found: regtest_limit }));
+    }
+
+    #[test]
+    fn pow_above_network_limit_is_invalid() {
+        let headers = chain(1, 600, 0x207fffff);
+        assert_eq!(first_error(&headers, &ConsensusParams::REGTEST), None);
+        assert_eq!(first_error(&headers, &ConsensusParams::SIGNET), Some(HeaderChainError::InvalidPow));
+    }
+
+    #[test]
+    fn testnet_min_difficulty_after_twenty_minutes() {
+        let testnet = ConsensusParams { allow_min_difficulty: true, ..EASY };
+        let limit = testnet.pow_limit_bits;
+        let mut headers = chain(2, 600, BITS);
+
+        // Exactly 20 minutes is not enough
+        let mut early = headers.clone();
+        push(&mut early, 2 * TARGET_SPACING, limit);
+        assert_eq!(first_error(&early, &testnet), Some(HeaderChainError::UnexpectedBits { expected: BITS, found: limit }));
+
+        push(&mut headers, 2 * TARGET_SPACING + 1, limit);
+        assert_eq!(first_error(&headers, &testnet), None);
+        assert_eq!(first_error(&headers, &EASY), Some(HeaderChainError::UnexpectedBits { expected: BITS, found: limit }));
+
+        // The next block on time goes back to the bits before the min-difficulty block
+        let mut limit_again = headers.clone();
+        push(&mut limit_again, 600, limit);
+        assert_eq!(first_error(&limit_again, &testnet), Some(HeaderChainError::UnexpectedBits { expected: BITS, found: limit }));
+        push(&mut headers, 600, BITS);
+        assert_eq!(first_error(&headers, &testnet), None);
+    }
+
+    #[test]
+    fn bip94_retargets_from_the_first_block_of_the_period() {
+        let testnet4 = ConsensusParams { allow_min_difficulty: true, enforce_bip94: true, ..EASY };
+        let limit = testnet4.pow_limit_bits;
+        let mut headers = chain(RETARGET_INTERVAL as usize - 1, 600, BITS);
+        // The period ends with a min-difficulty block
+        push(&mut headers, 2 * TARGET_SPACING + 1, limit);
+        let (first, last) = (headers[0].time, headers.last().unwrap().time);
+        let from_first = BlockProcessor::next_work_required_for(&testnet4, BITS, first, last);
+        let from_last = BlockProcessor::next_work_required_for(&testnet4, limit, first, last);
+        assert_ne!(from_first, from_last);
+
+        let mut wrong = headers.clone();
+        push(&mut wrong, 600, from_last);
+        assert_eq!(first_error(&wrong, &testnet4), Some(HeaderChainError::UnexpectedBits { expected: from_first, found: from_last }));
+        push(&mut headers, 600, from_first);
+        assert_eq!(first_error(&headers, &testnet4), None);
+    }
+
+    #[test]
+    fn bip94_rejects_timewarp_at_period_start() {
+        let testnet4 = ConsensusParams { enforce_bip94: true, ..EASY };
+        let headers = chain(RETARGET_INTERVAL as usize, 600, BITS);
+        let last = headers.last().unwrap();
+        let bits = BlockProcessor::next_work_required_for(&testnet4, BITS, headers[0].time, last.time);
+        let min_allowed = last.time - BIP94_MAX_TIMEWARP;
+
+        let mut at_limit = headers.clone();
+        at_limit.push(mine(Some(last), min_allowed, bits));
+        assert_eq!(first_error(&at_limit, &testnet4), None);
+
+        let mut warped = headers.clone();
+        warped.push(mine(Some(last), min_allowed - 1, bits));
+        assert_eq!(first_error(&warped, &testnet4), Some(HeaderChainError::Timewarp { min_allowed, time: min_allowed - 1 }));
+        assert_eq!(first_error(&warped, &EASY), None);
+    }
+}
@@ -0,0 +1,328 @@
+use bitcoin::{
+    blockdata::{
+        block::Block,
+        script::Builder,
+        transaction::{Transaction, TxOut},
+        witness::Witness,
+    },
+    hash_types::{Txid, WitnessCommitment},
+    hashes::Hash,
+};
+
+use crate::processor::BlockProcessor;
+
+// Consensus limits on the coinbase scriptSig length
+pub const MIN_COINBASE_SCRIPT_SIG: usize = 2;
+pub const MAX_COINBASE_SCRIPT_SIG: usize = 100;
+
+// Subsidy schedule
+pub const INITIAL_SUBSIDY: u64 = 50 * 100_000_000;
+pub const MAINNET_HALVING_INTERVAL: u32 = 210_000;
+pub const REGTEST_HALVING_INTERVAL: u32 = 150;
+
+// OP_RETURN, push 36 bytes, then the BIP141 commitment header
+pub const WITNESS_COMMITMENT_HEADER: [u8; 6] = [0x6a, 0x24, 0xaa, 0x21, 0xa9, 0xed];
+
+// Coinbase txid change produced by an extranonce injection
+#[derive(Debug, Clone)]
+pub struct ExtranonceInjection {
+    pub old_txid: Txid,
+    pub new_txid: Txid,
+    pub old_script_sig: String,
+    pub new_script_sig: String,
+}
+
+// Coinbase output value compared with the subsidy plus fees it may claim
+#[derive(Debug, Clone)]
+pub struct CoinbaseValueCheck {
+    pub height: u32,
+    pub subsidy: u64,
+    pub fees: u64,
+    pub coinbase_value: u64,
+    pub valid: bool, // coinbase_value <= subsidy + fees
+}
+
+impl BlockProcessor {
+    // Block subsidy at a height for a given halving interval
+    pub fn block_subsidy(height: u32, halving_interval: u32) -> u64 {
+        match height / halving_interval {
+            halvings if halvings >= 64 => 0,
+            halvings => INITIAL_SUBSIDY >> halvings,
+        }
+    }
+
+    // Check that the coinbase does not claim more than the subsidy plus the block's fees
+    pub fn check_coinbase_value(block: &Block, height: u32, fees: u64, halving_interval: u32) -> CoinbaseValueCheck {
+        let subsidy = Self::block_subsidy(height, halving_interval);
+        let coinbase_value = block
+            .txdata
+            .first()
+            .filter(|tx| tx.is_coin_base())
+            .map(|tx| tx.output.iter().map(|output| output.value).sum())
+            .unwrap_or(0);
+        CoinbaseValueCheck {
+            height,
+            subsidy,
+            fees,
+            coinbase_value,
+            valid: coinbase_value <= subsidy.saturating_add(fees),
+        }
+    }
+
+    // Replace the BIP34 height push at the start of the coinbase scriptSig and recompute
+    // the merkle root. Returns the old and new scriptSig as hex.
+    pub fn set_coinbase_height(block: &mut Block, height: u32) -> Result<(String, String), String> {
+        let Some(coinbase) = block.txdata.first_mut().filter(|tx| tx.is_coin_base()) else {
+            return Err("Block has no coinbase transaction".to_string());
+        };
+        let original = coinbase.input[0].script_sig.to_bytes();
+        let split = Self::first_push_len(&original).unwrap_or(0);
+
+        let mut script_sig = Builder::new().push_int(height as i64).into_script().to_bytes();
+        script_sig.extend_from_slice(&original[split..]);
+        if script_sig.len() > MAX_COINBASE_SCRIPT_SIG {
+            return Err(format!("Coinbase scriptSig would be {} bytes", script_sig.len()));
+        }
+        coinbase.input[0].script_sig = script_sig.into();
+        let new_script_sig = hex::encode(coinbase.input[0].script_sig.as_bytes());
+
+        if let Some(root) = block.compute_merkle_root() {
+            block.header.merkle_root = root;
+        }
+        Ok((hex::encode(original), new_script_sig))
+    }
+
+    // Add `amount` satoshis to the first coinbase output and recompute the merkle root.
+    // Returns the old and new output values.
+    pub fn inflate_coinbase(block: &mut Block, amount: u64) -> Result<(u64, u64), String> {
+        let Some(coinbase) = block.txdata.first_mut().filter(|tx| tx.is_coin_base()) else {
+            return Err("Block has no coinbase transaction".to_string());
+        };
+        let Some(output) = coinbase.output.first_mut() else {
+            return Err("Coinbase has no outputs".to_string());
+        };
+        let old_value = output.value;
+        output.value = old_value.checked_add(amount).ok_or("Coinbase value overflow")?;
+        let new_value = output.value;
+
+        if let Some(root) = block.compute_merkle_root() {
+            block.header.merkle_root = root;
+        }
+        Ok((old_value, new_value))
+    }
+
+    // Length of the first push in a script (the BIP34 height in a coinbase), if it is a push
+    fn first_push_len(script: &[u8]) -> Option<usize> {
+        let opcode = *script.first()?;
+        let len = match opcode {
+            0x00 | 0x4f | 0x51..=0x60 => 1,
+            0x01..=0x4b => 1 + opcode as usize,
+            0x4c => 2 + *script.get(1)? as usize,
+            0x4d => 3 + u16::from_le_bytes([*script.get(1)?, *script.get(2)?]) as usize,
+            _ => return None,
+        };
+        (len <= script.len()).then_some(len)
+    }
+
+    // Insert an extranonce push right after the coinbase height push and recompute the
+    // coinbase txid and merkle root. The witness commitment does not depend on the coinbase,
+    // whose wtxid is defined as zero, so it stays valid.
+    pub fn inject_coinbase_extranonce(block: &mut Block, extranonce: &[u8]) -> Result<ExtranonceInjection, String> {
+        if extranonce.is_empty() || extranonce.len() > 0x4b {
+            return Err(format!("Extranonce must be 1 to 75 bytes, got {}", extranonce.len()));
+        }
+        let Some(coinbase) = block.txdata.first_mut().filter(|tx| tx.is_coin_base()) else {
+            return Err("Block has no coinbase transaction".to_string());
+        };
+
+        let old_txid = coinbase.txid();
+        let original = coinbase.input[0].script_sig.to_bytes();
+        let split = Self::first_push_len(&original).unwrap_or(0);
+
+        let mut script_sig = original[..split].to_vec();
+        script_sig.push(extranonce.len() as u8);
+        script_sig.extend_from_slice(extranonce);
+        script_sig.extend_from_slice(&original[split..]);
+
+        if script_sig.len() > MAX_COINBASE_SCRIPT_SIG {
+            return Err(format!(
+                "Coinbase scriptSig would be {} bytes, above the {} byte limit",
+                script_sig.len(),
+                MAX_COINBASE_SCRIPT_SIG
+            ));
+        }
+
+        coinbase.input[0].script_sig = script_sig.into();
+        let new_txid = coinbase.txid();
+        let new_script_sig = hex::encode(coinbase.input[0].script_sig.as_bytes());
+
+        if let Some(root) = block.compute_merkle_root() {
+            block.header.merkle_root = root;
+        }
+        Ok(ExtranonceInjection {
+            old_txid,
+            new_txid,
+            old_script_sig: hex::encode(original),
+            new_script_sig,
+        })
+    }
+}
+
+// Witness commitment found in the coinbase compared with the one computed from txdata
+#[derive(Debug, Clone)]
+pub struct WitnessCommitmentCheck {
+    pub found: Option<WitnessCommitment>,
+    pub expected: Option<WitnessCommitment>,
+    pub valid: bool,
+}
+
+impl BlockProcessor {
+    // Index of the last coinbase output carrying a witness commitment
+    pub(crate) fn witness_commitment_output(coinbase: &Transaction) -> Option<usize> {
+        coinbase.output.iter().rposition(|output| {
+            let script = output.script_pubkey.as_bytes();
+            script.len() >= 38 && script[..6] == WITNESS_COMMITMENT_HEADER
+        })
+    }
+
+    // Witness reserved value from the coinbase witness, if well formed
+    fn witness_reserved_value(block: &Block) -> Option<[u8; 32]> {
+        let witness = &block.txdata.first()?.input.first()?.witness;
+        match witness.len() {
+            1 => witness.nth(0)?.try_into().ok(),
+            _ => None,
+        }
+    }
+
+    // Compare the coinbase witness commitment with the one computed from txdata
+    pub fn check_witness_commitment(block: &Block) -> WitnessCommitmentCheck {
+        let found = block.txdata.first().and_then(|coinbase| {
+            let index = Self::witness_commitment_output(coinbase)?;
+            let script = block.txdata[0].output[index].script_pubkey.as_bytes();
+            Some(WitnessCommitment::from_slice(&script[6..38]).expect("32 byte slice"))
+        });
+        let expected = block.witness_root().map(|root| {
+            let reserved = Self::witness_reserved_value(block).unwrap_or([0u8; 32]);
+            Block::compute_witness_commitment(&root, &reserved)
+        });
+        WitnessCommitmentCheck {
+            found,
+            expected,
+            valid: block.check_witness_commitment(),
+        }
+    }
+
+    // Rewrite (or add) the coinbase witness commitment to match txdata, then recompute the
+    // merkle root since the coinbase txid changes with it. Returns the old and new commitments.
+    pub fn fix_witness_commitment(block: &mut Block) -> Result<(Option<WitnessCommitment>, WitnessCommitment), String> {
+        if !block.txdata.first().is_some_and(|tx| tx.is_coin_base()) {
+            return Err("Block has no coinbase transaction".to_string());
+        }
+        let Some(witness_root) = block.witness_root() else {
+            return Err("Block has no transactions".to_string());
+        };
+
+        // A commitment needs a 32-byte reserved value in the coinbase witness
+        let reserved = match Self::witness_reserved_value(block) {
+            Some(reserved) => reserved,
+            None => {
+                block.txdata[0].input[0].witness = Witness::from_slice(&[[0u8; 32]]);
+                [0u8; 32]
+            }
+        };
+
+        let commitment = Block::compute_witness_commitment(&witness_root, &reserved);
+        let mut script = WITNESS_COMMITMENT_HEADER.to_vec();
+        script.extend_from_slice(commitment.as_byte_array());
+
+        let old = Self::check_witness_commitment(block).found;
+        let coinbase = &mut block.txdata[0];
+        match Self::witness_commitment_output(coinbase) {
+            Some(index) => {
+                // Keep any bytes after the commitment, as Bitcoin Core allows them
+                let mut bytes = coinbase.output[index].script_pubkey.to_bytes();
+                bytes[..38].copy_from_slice(&script);
+                coinbase.output[index].script_pubkey = bytes.into();
+            }
+            None => coinbase.output.push(TxOut { value: 0, script_pubkey: script.into() }),
+        }
+
+        if let Some(root) = block.compute_merkle_root() {
+            block.header.merkle_root = root;
+        }
+        Ok((old, commitment))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::Network;
+
+    const COIN: u64 = 100_000_000;
+
+    #[test]
+    fn subsidy_halves_every_interval() {
+        assert_eq!(BlockProcessor::block_subsidy(0, MAINNET_HALVING_INTERVAL), 50 * COIN);
+        assert_eq!(BlockProcessor::block_subsidy(209_999, MAINNET_HALVING_INTERVAL), 50 * COIN);
+        assert_eq!(BlockProcessor::block_subsidy(210_000, MAINNET_HALVING_INTERVAL), 25 * COIN);
+        assert_eq!(BlockProcessor::block_subsidy(840_000, MAINNET_HALVING_INTERVAL), 3 * COIN + COIN / 8);
+        // 33 halvings leave less than a satoshi
+        assert_eq!(BlockProcessor::block_subsidy(6_929_999, MAINNET_HALVING_INTERVAL), 1);
+        assert_eq!(BlockProcessor::block_subsidy(6_930_000, MAINNET_HALVING_INTERVAL), 0);
+        // Shifting by 64 or more is not allowed to wrap around
+        assert_eq!(BlockProcessor::block_subsidy(64 * REGTEST_HALVING_INTERVAL, REGTEST_HALVING_INTERVAL), 0);
+        assert_eq!(BlockProcessor::block_subsidy(u32::MAX, REGTEST_HALVING_INTERVAL), 0);
+    }
+
+    #[test]
+    fn one_satoshi_too_many_is_invalid() {
+        let mut block = genesis_block(Network::Bitcoin);
+        let check = BlockProcessor::check_coinbase_value(&block, 0, 0, MAINNET_HALVING_INTERVAL);
+        assert!(check.valid);
+        assert_eq!(check.coinbase_value, check.subsidy);
+
+        let root = block.header.merkle_root;
+        assert_eq!(BlockProcessor::inflate_coinbase(&mut block, 1), Ok((50 * COIN, 50 * COIN + 1)));
+        assert_ne!(block.header.merkle_root, root);
+        assert_eq!(Some(block.header.merkle_root), block.compute_merkle_root());
+        let check = BlockProcessor::check_coinbase_value(&block, 0, 0, MAINNET_HALVING_INTERVAL);
+        assert!(!check.valid);
+        // Fees cover it
+        assert!(BlockProcessor::check_coinbase_value(&block, 0, 1, MAINNET_HALVING_INTERVAL).valid);
+    }
+
+    #[test]
+    fn encodes_bip34_heights_minimally() {
+        let mut block = genesis_block(Network::Bitcoin);
+        // The genesis scriptSig starts with a 4-byte push; the rest must survive
+        let tail = block.txdata[0].input[0].script_sig.as_bytes()[5..].to_vec();
+        for (height, push) in [
+            (1, &[0x51][..]),
+            (16, &[0x60]),
+            (17, &[0x01, 0x11]),
+            (127, &[0x01, 0x7f]),
+            (128, &[0x02, 0x80, 0x00]),
+            (32_767, &[0x02, 0xff, 0x7f]),
+            (32_768, &[0x03, 0x00, 0x80, 0x00]),
+            (840_000, &[0x03, 0x40, 0xd1, 0x0c]),
+        ] {
+            BlockProcessor::set_coinbase_height(&mut block, height).unwrap();
+            let script_sig = block.txdata[0].input[0].script_sig.as_bytes();
+            assert_eq!(&script_sig[..push.len()], push, "height {}", height);
+            assert_eq!(&script_sig[push.len()..], tail, "height {}", height);
+            assert_eq!(Some(block.header.merkle_root), block.compute_merkle_root());
+        }
+    }
+
+    #[test]
+    fn height_must_fit_the_scriptsig() {
+        let mut block = genesis_block(Network::Bitcoin);
+        block.txdata[0].input[0].script_sig = vec![0x51; MAX_COINBASE_SCRIPT_SIG].into();
+        assert!(BlockProcessor::set_coinbase_height(&mut block, 32_768).is_err());
+        block.txdata.clear();
+        assert!(BlockProcessor::set_coinbase_height(&mut block, 1).is_err());
+        assert!(BlockProcessor::inflate_coinbase(&mut block, 1).is_err());
+    }
+}
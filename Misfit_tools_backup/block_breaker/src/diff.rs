@@ -0,0 +1,112 @@
+use bitcoin::blockdata::{
+    block::{Block, Header},
+    transaction::Transaction,
+};
+use serde::Serialize;
+
+use crate::processor::BlockProcessor;
+use crate::versionbits::{VersionRollingCheck, VERSION_ROLLING_MASK};
+
+// A field that differs between two blocks or headers
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldDiff {
+    pub field: String,
+    pub left: String,
+    pub right: String,
+}
+
+// Field-by-field differences between two blocks
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BlockDiff {
+    pub header: Vec<FieldDiff>,
+    pub transactions: Vec<FieldDiff>,
+    pub changed_transactions: Vec<usize>, // indexes present on both sides that differ
+    pub version_rolling: Option<VersionRollingCheck>, // set when the versions differ
+    pub left_tx_count: usize,
+    pub right_tx_count: usize,
+}
+
+impl BlockDiff {
+    pub fn is_identical(&self) -> bool {
+        self.header.is_empty() && self.transactions.is_empty()
+    }
+}
+
+// Push a diff when the two values differ
+fn compare(diffs: &mut Vec<FieldDiff>, field: &str, left: impl ToString, right: impl ToString) {
+    let (left, right) = (left.to_string(), right.to_string());
+    if left != right {
+        diffs.push(FieldDiff { field: field.to_string(), left, right });
+    }
+}
+
+impl BlockProcessor {
+    // Compare two headers field by field
+    pub fn diff_headers(left: &Header, right: &Header) -> Vec<FieldDiff> {
+        let mut diffs = Vec::new();
+        compare(&mut diffs, "version", format!("0x{:08x}", left.version.to_consensus()), format!("0x{:08x}", right.version.to_consensus()));
+        compare(&mut diffs, "prev_blockhash", left.prev_blockhash, right.prev_blockhash);
+        compare(&mut diffs, "merkle_root", left.merkle_root, right.merkle_root);
+        compare(&mut diffs, "time", left.time, right.time);
+        compare(&mut diffs, "bits", format!("0x{:08x}", left.bits.to_consensus()), format!("0x{:08x}", right.bits.to_consensus()));
+        compare(&mut diffs, "nonce", left.nonce, right.nonce);
+        compare(&mut diffs, "block_hash", left.block_hash(), right.block_hash());
+        diffs
+    }
+
+    // Compare two transactions field by field, prefixing fields with `txdata[index]`
+    pub fn diff_transactions(left: &Transaction, right: &Transaction, index: usize) -> Vec<FieldDiff> {
+        let mut diffs = Vec::new();
+        let prefix = format!("txdata[{}]", index);
+        compare(&mut diffs, &format!("{}.txid", prefix), left.txid(), right.txid());
+        compare(&mut diffs, &format!("{}.wtxid", prefix), left.wtxid(), right.wtxid());
+        compare(&mut diffs, &format!("{}.version", prefix), left.version, right.version);
+        compare(&mut diffs, &format!("{}.lock_time", prefix), left.lock_time.to_consensus_u32(), right.lock_time.to_consensus_u32());
+        compare(&mut diffs, &format!("{}.input_count", prefix), left.input.len(), right.input.len());
+        compare(&mut diffs, &format!("{}.output_count", prefix), left.output.len(), right.output.len());
+
+        for (i, (l, r)) in left.input.iter().zip(&right.input).enumerate() {
+            let field = format!("{}.input[{}]", prefix, i);
+            compare(&mut diffs, &format!("{}.previous_output", field), l.previous_output, r.previous_output);
+            compare(&mut diffs, &format!("{}.script_sig", field), hex::encode(l.script_sig.as_bytes()), hex::encode(r.script_sig.as_bytes()));
+            compare(&mut diffs, &format!("{}.sequence", field), l.sequence.0, r.sequence.0);
+            let witness = |tx_in: &bitcoin::TxIn| tx_in.witness.iter().map(hex::encode).collect::<Vec<_>>().join(" ");
+            compare(&mut diffs, &format!("{}.witness", field), witness(l), witness(r));
+        }
+
+        for (i, (l, r)) in left.output.iter().zip(&right.output).enumerate() {
+            let field = format!("{}.output[{}]", prefix, i);
+            compare(&mut diffs, &format!("{}.value", field), l.value, r.value);
+            compare(&mut diffs, &format!("{}.script_pubkey", field), hex::encode(l.script_pubkey.as_bytes()), hex::encode(r.script_pubkey.as_bytes()));
+        }
+        diffs
+    }
+
+    // Compare two blocks: header fields, then transactions by position. Transactions only
+    // present on one side are reported by txid against an empty value.
+    pub fn diff_blocks(left: &Block, right: &Block) -> BlockDiff {
+        let mut diff = BlockDiff {
+            header: Self::diff_headers(&left.header, &right.header),
+            version_rolling: (left.header.version != right.header.version).then(|| {
+                Self::check_version_rolling(left.header.version.to_consensus(), right.header.version.to_consensus(), VERSION_ROLLING_MASK)
+            }),
+            left_tx_count: left.txdata.len(),
+            right_tx_count: right.txdata.len(),
+            ..Default::default()
+        };
+
+        for index in 0..left.txdata.len().max(right.txdata.len()) {
+            let tx_diffs = match (left.txdata.get(index), right.txdata.get(index)) {
+                (Some(l), Some(r)) => Self::diff_transactions(l, r, index),
+                (Some(l), None) => vec![FieldDiff { field: format!("txdata[{}]", index), left: l.txid().to_string(), right: String::new() }],
+                (None, Some(r)) => vec![FieldDiff { field: format!("txdata[{}]", index), left: String::new(), right: r.txid().to_string() }],
+                (None, None) => vec![],
+            };
+            if !tx_diffs.is_empty() && index < left.txdata.len().min(right.txdata.len()) {
+                diff.changed_transactions.push(index);
+            }
+            diff.transactions.extend(tx_diffs);
+        }
+        diff
+    }
+}
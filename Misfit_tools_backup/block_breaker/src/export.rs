@@ -0,0 +1,63 @@
+use bitcoin::blockdata::block::{Block, Header};
+use serde::Serialize;
+
+use crate::processor::BlockProcessor;
+use crate::report::MutationReport;
+
+// A header with its hash, for JSON export
+#[derive(Debug, Clone, Serialize)]
+pub struct HeaderExport {
+    pub block_hash: String,
+    pub header: Header,
+}
+
+// A decoded block with its hash and transaction ids, for JSON export
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockExport {
+    pub block_hash: String,
+    pub txids: Vec<String>,
+    pub block: Block,
+}
+
+// A block before and after mutation together with the report of what changed
+#[derive(Debug, Clone, Serialize)]
+pub struct MutationExport<T: Serialize> {
+    pub original: T,
+    pub mutated: T,
+    pub report: MutationReport,
+}
+
+impl BlockProcessor {
+    pub fn export_header(header: &Header) -> HeaderExport {
+        HeaderExport {
+            block_hash: header.block_hash().to_string(),
+            header: *header,
+        }
+    }
+
+    pub fn export_block(block: &Block) -> BlockExport {
+        BlockExport {
+            block_hash: block.block_hash().to_string(),
+            txids: block.txdata.iter().map(|tx| tx.txid().to_string()).collect(),
+            block: block.clone(),
+        }
+    }
+
+    // Original and mutated block with the mutation report, as pretty-printed JSON
+    pub fn block_mutation_to_json(original: &Block, mutated: &Block, report: &MutationReport) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(&MutationExport {
+            original: Self::export_block(original),
+            mutated: Self::export_block(mutated),
+            report: report.clone(),
+        })
+    }
+
+    // Original and mutated header with the mutation report, as pretty-printed JSON
+    pub fn header_mutation_to_json(original: &Header, mutated: &Header, report: &MutationReport) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(&MutationExport {
+            original: Self::export_header(original),
+            mutated: Self::export_header(mutated),
+            report: report.clone(),
+        })
+    }
+}
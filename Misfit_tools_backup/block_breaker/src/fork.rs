@@ -0,0 +1,151 @@
+use bitcoin::blockdata::{block::Block, script::ScriptBuf};
+use bitcoin::hash_types::BlockHash;
+use bitcoin::pow::Work;
+use serde::Serialize;
+
+use crate::chain::HeaderChain;
+use crate::miner::REGTEST_BITS;
+use crate::processor::{BlockProcessor, ProcessingConfig};
+use crate::report::MutationReport;
+
+// One side of a fork: how many blocks to mine, at which difficulty, and optionally a
+// block to break (descendants are still mined on top of the broken block)
+#[derive(Debug, Clone)]
+pub struct ForkBranch {
+    pub length: usize,
+    pub bits: u32,
+    pub break_at: Option<(usize, ProcessingConfig)>,
+}
+
+impl ForkBranch {
+    pub fn new(length: usize) -> Self {
+        ForkBranch { length, bits: REGTEST_BITS, break_at: None }
+    }
+}
+
+// Index of a broken block within its branch, with the mutations applied to it
+pub type BrokenBlock = (usize, MutationReport);
+
+// Two competing child chains of the same tip
+#[derive(Debug, Clone)]
+pub struct ForkScenario {
+    pub tip: BlockHash,
+    pub tip_height: u32,
+    pub branches: [Vec<Block>; 2],
+    pub chainwork: [Work; 2], // work added on top of the tip by each branch
+    pub broken: [Option<BrokenBlock>; 2],
+}
+
+// Per-branch summary written alongside a serialized fork
+#[derive(Debug, Clone, Serialize)]
+pub struct ForkBranchSummary {
+    pub length: usize,
+    pub tip_hash: Option<String>,
+    pub chainwork: String,
+    pub broken_at: Option<usize>,
+}
+
+// Summary of a fork scenario, written as fork.json
+#[derive(Debug, Clone, Serialize)]
+pub struct ForkSummary {
+    pub tip: String,
+    pub tip_height: u32,
+    pub branches: Vec<ForkBranchSummary>,
+    pub heavier: Option<usize>, // branch with more work, None on a tie
+}
+
+impl ForkScenario {
+    // Branch with the most work, or None when they tie
+    pub fn heavier(&self) -> Option<usize> {
+        match self.chainwork[0].cmp(&self.chainwork[1]) {
+            std::cmp::Ordering::Greater => Some(0),
+            std::cmp::Ordering::Less => Some(1),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
+
+    pub fn summary(&self) -> ForkSummary {
+        ForkSummary {
+            tip: self.tip.to_string(),
+            tip_height: self.tip_height,
+            branches: (0..2)
+                .map(|i| ForkBranchSummary {
+                    length: self.branches[i].len(),
+                    tip_hash: self.branches[i].last().map(|block| block.block_hash().to_string()),
+                    chainwork: hex::encode(self.chainwork[i].to_be_bytes()),
+                    broken_at: self.broken[i].as_ref().map(|(index, _)| *index),
+                })
+                .collect(),
+            heavier: self.heavier(),
+        }
+    }
+}
+
+impl BlockProcessor {
+    // Mine one branch block by block so a broken block can be built upon
+    fn mine_fork_branch(
+        tip: BlockHash,
+        tip_height: u32,
+        start_time: u32,
+        branch: &ForkBranch,
+        payout_script: &ScriptBuf,
+    ) -> Result<(Vec<Block>, Option<BrokenBlock>), Box<dyn std::error::Error>> {
+        let mut blocks: Vec<Block> = Vec::with_capacity(branch.length);
+        let mut broken = None;
+        for i in 0..branch.length {
+            let parent = blocks.last().map(|block| block.block_hash()).unwrap_or(tip);
+            let mut block = Self::mine_chain(parent, tip_height + i as u32, start_time + i as u32, 1, branch.bits, payout_script)?.remove(0);
+            if let Some((index, config)) = &branch.break_at {
+                if *index == i {
+                    let (mutated, report) = Self::new(config.clone()).process_block(&block);
+                    block = mutated;
+                    broken = Some((i, report));
+                }
+            }
+            blocks.push(block);
+        }
+        Ok((blocks, broken))
+    }
+
+    // Mine two competing branches on top of a tip. The second branch starts one second
+    // later than the first so the branches never share a block, even with equal settings.
+    pub fn simulate_fork(
+        tip: BlockHash,
+        tip_height: u32,
+        start_time: u32,
+        branches: &[ForkBranch; 2],
+        payout_script: &ScriptBuf,
+    ) -> Result<ForkScenario, Box<dyn std::error::Error>> {
+        let (first, first_broken) = Self::mine_fork_branch(tip, tip_height, start_time, &branches[0], payout_script)?;
+        let (second, second_broken) = Self::mine_fork_branch(tip, tip_height, start_time + 1, &branches[1], payout_script)?;
+        let work = |blocks: &[Block]| {
+            let headers: Vec<_> = blocks.iter().map(|block| block.header).collect();
+            HeaderChain::cumulative_work(&headers, Work::from_be_bytes([0u8; 32]))
+                .last()
+                .copied()
+                .unwrap_or(Work::from_be_bytes([0u8; 32]))
+        };
+        Ok(ForkScenario {
+            tip,
+            tip_height,
+            chainwork: [work(&first), work(&second)],
+            branches: [first, second],
+            broken: [first_broken, second_broken],
+        })
+    }
+
+    // Write each branch as hex blocks (branch_a.hex, branch_b.hex) and raw concatenated
+    // headers (branch_a.headers, branch_b.headers) for light clients, plus fork.json
+    pub fn write_fork_scenario(scenario: &ForkScenario, dir: &str) -> Result<ForkSummary, Box<dyn std::error::Error>> {
+        std::fs::create_dir_all(dir)?;
+        for (name, blocks) in ["branch_a", "branch_b"].iter().zip(&scenario.branches) {
+            let lines: Vec<String> = blocks.iter().map(Self::encode_block_to_hex).collect();
+            std::fs::write(format!("{}/{}.hex", dir, name), lines.join("\n") + "\n")?;
+            let headers: Vec<u8> = blocks.iter().flat_map(|block| bitcoin::consensus::encode::serialize(&block.header)).collect();
+            std::fs::write(format!("{}/{}.headers", dir, name), headers)?;
+        }
+        let summary = scenario.summary();
+        std::fs::write(format!("{}/fork.json", dir), serde_json::to_string_pretty(&summary)?)?;
+        Ok(summary)
+    }
+}
@@ -0,0 +1,79 @@
+use bitcoin::blockdata::block::Block;
+use bitcoin::consensus::encode;
+use rand::Rng;
+use serde::Serialize;
+use std::path::Path;
+
+use crate::processor::BlockProcessor;
+
+// A single flipped bit in the serialized block
+#[derive(Debug, Clone, Serialize)]
+pub struct BitFlip {
+    pub byte: usize,
+    pub bit: u8,
+}
+
+// One mutant written to the corpus
+#[derive(Debug, Clone, Serialize)]
+pub struct FuzzMutant {
+    pub file: String,
+    pub flips: Vec<BitFlip>,
+    pub deserializes: bool,          // the mutant still decodes as a block with no trailing bytes
+    pub block_hash: Option<String>,  // hash of the decoded mutant
+}
+
+// Manifest describing a corpus directory
+#[derive(Debug, Clone, Serialize)]
+pub struct FuzzManifest {
+    pub source_hash: String,
+    pub source_size: usize,
+    pub bits_per_mutant: usize,
+    pub mutants: Vec<FuzzMutant>,
+}
+
+impl BlockProcessor {
+    // Flip `count` random bits of a buffer, returning which ones were flipped
+    pub fn flip_random_bits(&self, bytes: &mut [u8], count: usize) -> Vec<BitFlip> {
+        if bytes.is_empty() {
+            return vec![];
+        }
+        let mut rng = self.rng.borrow_mut();
+        (0..count)
+            .map(|_| {
+                let flip = BitFlip { byte: rng.random_range(0..bytes.len()), bit: rng.random_range(0..8) };
+                bytes[flip.byte] ^= 1 << flip.bit;
+                flip
+            })
+            .collect()
+    }
+
+    // Write `mutants` bit-flipped copies of a block into a corpus directory together with
+    // a manifest.json recording the flips and whether each mutant still deserializes
+    pub fn fuzz_block(&self, block: &Block, mutants: usize, bits_per_mutant: usize, corpus_dir: &str) -> Result<FuzzManifest, Box<dyn std::error::Error>> {
+        std::fs::create_dir_all(corpus_dir)?;
+        let original = encode::serialize(block);
+        let mut manifest = FuzzManifest {
+            source_hash: block.block_hash().to_string(),
+            source_size: original.len(),
+            bits_per_mutant,
+            mutants: Vec::with_capacity(mutants),
+        };
+
+        for i in 0..mutants {
+            let mut bytes = original.clone();
+            let flips = self.flip_random_bits(&mut bytes, bits_per_mutant);
+            let decoded = encode::deserialize::<Block>(&bytes).ok();
+            let file = format!("mutant_{:05}.bin", i);
+            std::fs::write(Path::new(corpus_dir).join(&file), &bytes)?;
+            manifest.mutants.push(FuzzMutant {
+                file,
+                flips,
+                deserializes: decoded.is_some(),
+                block_hash: decoded.map(|b| b.block_hash().to_string()),
+            });
+        }
+
+        std::fs::write(Path::new(corpus_dir).join("manifest.json"), serde_json::to_string_pretty(&manifest)?)?;
+        Ok(manifest)
+    }
+}
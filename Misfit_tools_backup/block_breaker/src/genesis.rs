@@ -0,0 +1,135 @@
+use bitcoin::blockdata::{
+    block::{Block, Header, Version},
+    locktime::absolute::LockTime,
+    opcodes::all::OP_CHECKSIG,
+    script::{Builder, PushBytesBuf, ScriptBuf},
+    transaction::{OutPoint, Sequence, Transaction, TxIn, TxOut},
+    witness::Witness,
+};
+use bitcoin::hash_types::{BlockHash, TxMerkleNode};
+use bitcoin::hashes::Hash;
+use bitcoin::pow::CompactTarget;
+use serde::Serialize;
+
+use crate::coinbase::INITIAL_SUBSIDY;
+use crate::grind::{GrindStats, NONCE_SPACE};
+use crate::processor::BlockProcessor;
+
+// Bitcoin's genesis coinbase: every network built from Bitcoin Core's CreateGenesisBlock
+// starts the scriptSig with this number, whatever its own bits
+const GENESIS_SCRIPT_SIG_NUMBER: i64 = 486604799;
+pub const MAINNET_GENESIS_MESSAGE: &str = "The Times 03/Jan/2009 Chancellor on brink of second bailout for banks";
+pub const MAINNET_GENESIS_PUBKEY: &str = "04678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5f";
+pub const MAINNET_GENESIS_TIME: u32 = 1231006505;
+
+// Parameters of a custom network's genesis block
+#[derive(Debug, Clone)]
+pub struct GenesisParams {
+    pub message: String,          // embedded in the coinbase scriptSig
+    pub output_script: ScriptBuf, // scriptPubKey of the (unspendable) genesis output
+    pub time: u32,
+    pub reward: u64,
+    pub bits: u32,
+    pub version: i32,
+}
+
+impl Default for GenesisParams {
+    // Bitcoin mainnet's genesis parameters
+    fn default() -> Self {
+        GenesisParams {
+            message: MAINNET_GENESIS_MESSAGE.to_string(),
+            output_script: GenesisParams::pay_to_pubkey(&hex::decode(MAINNET_GENESIS_PUBKEY).unwrap()).unwrap(),
+            time: MAINNET_GENESIS_TIME,
+            reward: INITIAL_SUBSIDY,
+            bits: 0x1d00ffff,
+            version: 1,
+        }
+    }
+}
+
+impl GenesisParams {
+    // `<pubkey> OP_CHECKSIG`, the output script style of Bitcoin's genesis block
+    pub fn pay_to_pubkey(pubkey: &[u8]) -> Result<ScriptBuf, String> {
+        let push = PushBytesBuf::try_from(pubkey.to_vec()).map_err(|_| "Public key too long".to_string())?;
+        Ok(Builder::new().push_slice(push).push_opcode(OP_CHECKSIG).into_script())
+    }
+}
+
+// A mined genesis block and the values a node's chain parameters need
+#[derive(Debug, Clone, Serialize)]
+pub struct GenesisInfo {
+    pub hash: String,
+    pub merkle_root: String,
+    pub time: u32,
+    pub nonce: u32,
+    pub bits: String,
+    pub version: i32,
+    pub reward: u64,
+    pub message: String,
+    pub output_script: String,
+    pub block: String, // serialized block hex
+    pub grind: GrindStats,
+}
+
+impl BlockProcessor {
+    // Build a genesis block the way Bitcoin Core's CreateGenesisBlock does, with the nonce at zero
+    pub fn genesis_block(params: &GenesisParams) -> Result<Block, String> {
+        let message = PushBytesBuf::try_from(params.message.as_bytes().to_vec()).map_err(|_| "Message too long".to_string())?;
+        let script_sig = Builder::new()
+            .push_int(GENESIS_SCRIPT_SIG_NUMBER)
+            .push_slice([4u8])
+            .push_slice(message)
+            .into_script();
+        let coinbase = Transaction {
+            version: 1,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig,
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut { value: params.reward, script_pubkey: params.output_script.clone() }],
+        };
+        Ok(Block {
+            header: Header {
+                version: Version::from_consensus(params.version),
+                prev_blockhash: BlockHash::all_zeros(),
+                merkle_root: TxMerkleNode::from_raw_hash(coinbase.txid().to_raw_hash()),
+                time: params.time,
+                bits: CompactTarget::from_consensus(params.bits),
+                nonce: 0,
+            },
+            txdata: vec![coinbase],
+        })
+    }
+
+    // Build and mine a genesis block, bumping the timestamp whenever the nonce space runs out
+    pub fn mine_genesis(params: &GenesisParams, threads: usize) -> Result<(Block, GenesisInfo), String> {
+        let mut block = Self::genesis_block(params)?;
+        let mut stats = GrindStats::default();
+        loop {
+            let (found, grind) = Self::grind_nonce_parallel(&mut block.header, threads, NONCE_SPACE);
+            stats.add(&grind);
+            if found {
+                break;
+            }
+            block.header.time = block.header.time.checked_add(1).ok_or("Timestamp space exhausted")?;
+        }
+
+        let info = GenesisInfo {
+            hash: block.block_hash().to_string(),
+            merkle_root: block.header.merkle_root.to_string(),
+            time: block.header.time,
+            nonce: block.header.nonce,
+            bits: format!("0x{:08x}", params.bits),
+            version: params.version,
+            reward: params.reward,
+            message: params.message.clone(),
+            output_script: hex::encode(params.output_script.as_bytes()),
+            block: Self::encode_block_to_hex(&block),
+            grind: stats,
+        };
+        Ok((block, info))
+    }
+}
@@ -0,0 +1,96 @@
+use bitcoin::blockdata::block::Header;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::time::Instant;
+
+use crate::processor::BlockProcessor;
+
+// How often each thread checks whether another one already found a nonce
+const FOUND_CHECK_INTERVAL: u64 = 4096;
+
+// Size of the header's nonce space
+pub const NONCE_SPACE: u64 = 1 << 32;
+
+// Work done while grinding
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GrindStats {
+    pub threads: usize,
+    pub hashes: u64,
+    pub elapsed_secs: f64,
+    pub hashrate: f64,           // hashes per second
+    pub gave_up: Option<String>, // why grinding stopped without a solution
+}
+
+impl GrindStats {
+    // Fold the work of another grind (e.g. after a timestamp roll) into these stats
+    pub fn add(&mut self, other: &GrindStats) {
+        self.threads = other.threads;
+        self.hashes += other.hashes;
+        self.elapsed_secs += other.elapsed_secs;
+        self.hashrate = if self.elapsed_secs > 0.0 { self.hashes as f64 / self.elapsed_secs } else { 0.0 };
+    }
+}
+
+impl BlockProcessor {
+    // Number of grinding threads to use, where 0 means one per available core
+    pub fn grind_threads(requested: usize) -> usize {
+        match requested {
+            0 => std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            n => n,
+        }
+    }
+
+    // Search the first `nonces` nonces across `threads` threads, thread i trying nonces i,
+    // i + threads, ... With one thread this finds the same (lowest) nonce as grind_nonce. With
+    // more, the nonce found first wins, so the result is not deterministic.
+    pub fn grind_nonce_parallel(header: &mut Header, threads: usize, nonces: u64) -> (bool, GrindStats) {
+        let nonces = nonces.min(NONCE_SPACE);
+        let threads = Self::grind_threads(threads);
+        let target = header.target();
+        let found = AtomicBool::new(false);
+        let winning_nonce = AtomicU32::new(0);
+        let hashes = AtomicU64::new(0);
+        let start = Instant::now();
+
+        std::thread::scope(|scope| {
+            for first in 0..threads as u64 {
+                let (found, winning_nonce, hashes) = (&found, &winning_nonce, &hashes);
+                let mut candidate = *header;
+                scope.spawn(move || {
+                    let mut tried = 0u64;
+                    let mut nonce = first;
+                    while nonce < nonces {
+                        if tried.is_multiple_of(FOUND_CHECK_INTERVAL) && found.load(Ordering::Relaxed) {
+                            break;
+                        }
+                        candidate.nonce = nonce as u32;
+                        tried += 1;
+                        if target.is_met_by(candidate.block_hash()) {
+                            if !found.swap(true, Ordering::SeqCst) {
+                                winning_nonce.store(candidate.nonce, Ordering::SeqCst);
+                            }
+                            break;
+                        }
+                        nonce += threads as u64;
+                    }
+                    hashes.fetch_add(tried, Ordering::Relaxed);
+                });
+            }
+        });
+
+        let elapsed_secs = start.elapsed().as_secs_f64();
+        let hashes = hashes.into_inner();
+        let stats = GrindStats {
+            threads,
+            hashes,
+            elapsed_secs,
+            hashrate: if elapsed_secs > 0.0 { hashes as f64 / elapsed_secs } else { 0.0 },
+            gave_up: None,
+        };
+        let solved = found.into_inner();
+        if solved {
+            header.nonce = winning_nonce.into_inner();
+        }
+        (solved, stats)
+    }
+}
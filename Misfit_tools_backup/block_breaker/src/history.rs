@@ -0,0 +1,61 @@
+use bitcoin::blockdata::block::Block;
+
+use crate::processor::{BlockProcessor, ProcessingConfig};
+use crate::report::MutationReport;
+
+// One set of mutations applied on top of the previous state
+#[derive(Debug, Clone)]
+pub struct MutationStep {
+    pub label: String,
+    pub report: MutationReport,
+    pub block: Block, // state after this step
+}
+
+// Ordered history of mutation steps applied to a block, with undo
+#[derive(Debug, Clone)]
+pub struct MutationHistory {
+    original: Block,
+    steps: Vec<MutationStep>,
+}
+
+impl MutationHistory {
+    pub fn new(block: Block) -> Self {
+        MutationHistory { original: block, steps: Vec::new() }
+    }
+
+    pub fn original(&self) -> &Block {
+        &self.original
+    }
+
+    pub fn steps(&self) -> &[MutationStep] {
+        &self.steps
+    }
+
+    // Block after every step applied so far
+    pub fn current(&self) -> &Block {
+        self.steps.last().map(|step| &step.block).unwrap_or(&self.original)
+    }
+
+    // Process the current block with a configuration and record the result as a new step
+    pub fn apply(&mut self, label: &str, config: ProcessingConfig) -> &MutationStep {
+        let (block, report) = BlockProcessor::new(config).process_block(self.current());
+        self.steps.push(MutationStep { label: label.to_string(), report, block });
+        self.steps.last().expect("step was just pushed")
+    }
+
+    // Drop the last `n` steps, returning how many were actually undone
+    pub fn undo(&mut self, n: usize) -> usize {
+        let undone = n.min(self.steps.len());
+        self.steps.truncate(self.steps.len() - undone);
+        undone
+    }
+
+    // Index of the first step after which `is_valid` no longer holds, or None if the
+    // original is already invalid or every step keeps the block valid
+    pub fn first_invalidating_step(&self, is_valid: impl Fn(&Block) -> bool) -> Option<usize> {
+        if !is_valid(&self.original) {
+            return None;
+        }
+        self.steps.iter().position(|step| !is_valid(&step.block))
+    }
+}
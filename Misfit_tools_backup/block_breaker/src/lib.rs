@@ -0,0 +1,34 @@
+pub mod blk;
+pub mod breaker;
+pub mod chain;
+pub mod checkpoints;
+pub mod coinbase;
+pub mod compact;
+pub mod diff;
+pub mod export;
+pub mod fork;
+pub mod fuzz;
+pub mod genesis;
+pub mod grind;
+pub mod history;
+pub mod merkle;
+pub mod miner;
+pub mod oversize;
+pub mod pow;
+pub mod presets;
+pub mod processor;
+pub mod report;
+pub mod rpc;
+pub mod signet;
+pub mod stats;
+pub mod stratum;
+pub mod stream;
+pub mod summary;
+pub mod template;
+pub mod vectors;
+pub mod versionbits;
+
+// The processing API at the crate root; everything else is reached through its module
+pub use breaker::BlockBreaker;
+pub use processor::{BlockField, BlockProcessor, ProcessingConfig, TransactionMutation};
+pub use report::{BatchEntry, BlockConsistency, Mutation, MutationReport};
@@ -1,328 +1,1157 @@
-use bitcoin::consensus::Decodable;
-use bitcoin::{
-    blockdata::{
-        block::{Block, Header, Version},
-    },
-    hash_types::{BlockHash, TxMerkleNode},
-    hashes::Hash,
-    pow::CompactTarget,
-};
-use rand::Rng;
-
-// Enum to specify which fields to modify
-#[derive(Debug, Clone)]
-#[derive(PartialEq)]
-pub enum BlockField {
-    Version,
-    PrevBlockHash,
-    MerkleRoot,
-    Timestamp,
-    Bits,
-    Nonce,
-    All,
-}
-
-// Configuration for block processing
-#[derive(Debug, Clone)]
-pub struct ProcessingConfig {
-    pub fields_to_modify: Vec<BlockField>,
-    pub version_override: Option<i32>,
-    pub timestamp_offset: Option<i64>, // seconds to add/subtract
-    pub randomize_hashes: bool,
-}
-
-impl Default for ProcessingConfig {
-    fn default() -> Self {
-        ProcessingConfig {
-            fields_to_modify: vec![BlockField::All],
-            version_override: None,
-            timestamp_offset: None,
-            randomize_hashes: true,
-        }
-    }
-}
-
-// Block processing implementation
-pub struct BlockProcessor {
-    config: ProcessingConfig,
-}
-
-impl BlockProcessor {
-    pub fn new(config: ProcessingConfig) -> Self {
-        Self { config }
-    }
-
-    pub fn with_default_config() -> Self {
-        Self {
-            config: ProcessingConfig::default(),
-        }
-    }
-
-    // Process the version of the block
-    fn process_version(&self, version: i32) -> i32 {
-        if let Some(override_version) = self.config.version_override {
-            println!("Overriding block version from {} to {}", version, override_version);
-            override_version
-        } else {
-            // Default behavior: set version to maximum valid value
-            let modified_version = 0x3FFFFFFF;
-            println!("Modified block version from {} to {}", version, modified_version);
-            modified_version
-        }
-    }
-
-    // Process the previous block hash
-    fn process_prev_block_hash(&self, hash: &BlockHash) -> BlockHash {
-        if self.config.randomize_hashes {
-            let random_hash = Self::generate_random_block_hash();
-            println!("Modified prev block hash from {} to {}", hash, random_hash);
-            random_hash
-        } else {
-            // Zero out the hash
-            let zero_hash = BlockHash::all_zeros();
-            println!("Zeroed prev block hash from {} to {}", hash, zero_hash);
-            zero_hash
-        }
-    }
-
-    // Process the merkle root
-    fn process_merkle_root(&self, root: &TxMerkleNode) -> TxMerkleNode {
-        if self.config.randomize_hashes {
-            let random_merkle_root = Self::generate_random_merkle_root();
-            println!("Modified merkle root from {} to {}", root, random_merkle_root);
-            random_merkle_root
-        } else {
-            // Zero out the merkle root
-            let zero_root = TxMerkleNode::all_zeros();
-            println!("Zeroed merkle root from {} to {}", root, zero_root);
-            zero_root
-        }
-    }
-
-    // Process the timestamp
-    fn process_timestamp(&self, timestamp: u32) -> u32 {
-        let modified_timestamp = if let Some(offset) = self.config.timestamp_offset {
-            // Apply custom offset
-            (timestamp as i64 + offset).max(0) as u32
-        } else {
-            // Default: add one year (31,536,000 seconds)
-            let current_time = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs() as u32;
-            current_time.saturating_add(31_536_000)
-        };
-        
-        println!("Modified timestamp from {} to {}", timestamp, modified_timestamp);
-        modified_timestamp
+use bitcoin::blockdata::block::{Block, Header};
+use bitcoin::blockdata::script::ScriptBuf;
+use bitcoin::consensus::encode;
+use bitcoin::hash_types::BlockHash;
+use block_breaker::blk::{BlkFile, MAINNET_MAGIC, REGTEST_MAGIC};
+use block_breaker::chain::{HeaderChain, HeaderChainReport, TimestampAttack, MEDIAN_TIME_SPAN};
+use block_breaker::checkpoints::CheckpointReport;
+use block_breaker::coinbase::{CoinbaseValueCheck, INITIAL_SUBSIDY, MAINNET_HALVING_INTERVAL};
+use block_breaker::diff::BlockDiff;
+use block_breaker::fork::ForkBranch;
+use block_breaker::genesis::{GenesisInfo, GenesisParams, MAINNET_GENESIS_MESSAGE, MAINNET_GENESIS_PUBKEY};
+use block_breaker::history::MutationHistory;
+use block_breaker::miner::REGTEST_GENESIS_HASH;
+use block_breaker::pow::ConsensusParams;
+use block_breaker::presets::CorruptionPreset;
+use block_breaker::rpc::{BlockRef, CoreClient};
+use block_breaker::signet::{SignetCheck, SignetSignatureCheck, DEFAULT_SIGNET_CHALLENGE};
+use block_breaker::stats::BlockStats;
+use block_breaker::stream::{HeaderReader, HeaderStreamValidator};
+use block_breaker::summary::TransactionSummary;
+use block_breaker::vectors::MAX_BLOCK_WEIGHT;
+use block_breaker::versionbits::VersionBitMutation;
+use block_breaker::{BatchEntry, BlockBreaker, BlockField, BlockProcessor, MutationReport, ProcessingConfig, TransactionMutation};
+use clap::{Args, Parser, Subcommand};
+
+// Print block header information
+fn print_header_info(header: &Header, label: &str) {
+    println!("\n=== {} ===", label);
+    println!("Version: {}", header.version.to_consensus());
+    println!("Previous Block: {}", header.prev_blockhash);
+    println!("Merkle Root: {}", header.merkle_root);
+    println!("Timestamp: {}", header.time);
+    println!("Bits: 0x{:08x}", header.bits.to_consensus());
+    println!("Nonce: {}", header.nonce);
+    println!("Block Hash: {}", header.block_hash());
+}
+
+// Print proof-of-work validation information
+fn print_pow_info(header: &Header, label: &str) {
+    let pow = BlockProcessor::validate_pow(header);
+    println!("\n=== {} ===", label);
+    println!("Block Hash: {}", pow.block_hash);
+    println!("Bits: 0x{:08x}", pow.bits);
+    println!("Target: {}", hex::encode(pow.target.target));
+    if pow.target.negative {
+        println!("Target is negative");
     }
+    if pow.target.overflow {
+        println!("Target overflows 256 bits");
+    }
+    println!("Meets target: {}", pow.valid);
+}
 
-    // Process the bits (difficulty target)
-    fn process_bits(&self, bits: u32) -> u32 {
-        // XOR with mask to modify difficulty
-        let modified_bits = bits ^ 0x00FFFFFF;
-        println!("Modified bits from 0x{:08x} to 0x{:08x}", bits, modified_bits);
-        modified_bits
+// Print the mutations recorded while processing
+// (to stderr when stdout carries the mutated block)
+fn print_mutation_report(out: &mut dyn std::io::Write, report: &MutationReport) -> std::io::Result<()> {
+    for mutation in &report.mutations {
+        writeln!(out, "Modified {} from {} to {} ({})", mutation.field, mutation.old_value, mutation.new_value, mutation.reason)?;
+    }
+    if let Some(solved) = report.remined {
+        writeln!(out, "Re-mined to meet target: {}", solved)?;
+    }
+    if let Some(stats) = &report.remine_stats {
+        writeln!(out, "Hashed {} nonces on {} threads in {:.2}s ({:.0} H/s)", stats.hashes, stats.threads, stats.elapsed_secs, stats.hashrate)?;
+        if let Some(reason) = &stats.gave_up {
+            writeln!(out, "Gave up re-mining: {}", reason)?;
+        }
     }
+    if let Some(consistency) = &report.consistency {
+        writeln!(out, "Merkle root consistent with txdata: {}", consistency.merkle_root_matches)?;
+        writeln!(out, "Witness commitment valid: {}", consistency.witness_commitment_valid)?;
+    }
+    Ok(())
+}
 
-    // Process the nonce
-    fn process_nonce(&self, nonce: u32) -> u32 {
-        // Bitwise NOT to invert all bits
-        let modified_nonce = !nonce;
-        println!("Modified nonce from {} to {}", nonce, modified_nonce);
-        modified_nonce
+// Print transaction summary information
+fn print_transaction_info(summary: &TransactionSummary) {
+    println!("\n--- Transaction #{} ---", summary.index);
+    println!("Txid: {}", summary.txid);
+    println!("Wtxid: {}", summary.wtxid);
+    println!("Version: {}", summary.version);
+    println!("Locktime: {}", summary.lock_time);
+    println!("Coinbase: {}", summary.is_coinbase);
+    println!("Segwit: {}", summary.is_segwit);
+    println!("Size: {} bytes, vsize: {} vbytes, weight: {} WU", summary.size, summary.vsize, summary.weight);
+    println!("Inputs ({}):", summary.inputs.len());
+    for (i, input) in summary.inputs.iter().enumerate() {
+        println!("  [{}] {} sequence=0x{:08x} witness_items={}", i, input.previous_output, input.sequence, input.witness_items);
+        println!("      scriptSig: {}", input.script_sig);
     }
+    println!("Outputs ({}):", summary.outputs.len());
+    for (i, output) in summary.outputs.iter().enumerate() {
+        println!("  [{}] {} sat ({})", i, output.value, output.script_type);
+        println!("      scriptPubKey: {}", output.script_pubkey);
+    }
+    println!("Total output value: {} sat", summary.total_output_value);
+}
 
-    
-    // Check if a specific field should be processed
-    fn should_process_field(&self, field: &BlockField) -> bool {
-        self.config.fields_to_modify.contains(&BlockField::All) ||
-        self.config.fields_to_modify.contains(field)
+// Print every transaction of a block
+fn print_block_transactions(block: &Block, label: &str) {
+    println!("\n=== {} ({} transactions) ===", label, block.txdata.len());
+    for summary in BlockProcessor::summarize_transactions(block) {
+        print_transaction_info(&summary);
     }
+}
 
-    // Process the entire block header based on configuration
-    pub fn process_block_header(&self, header: &Header) -> Header {
-        let mut modified_header = header.clone();
+// Print a mined genesis block as the values Bitcoin Core's chain parameters take
+fn print_genesis_info(info: &GenesisInfo) {
+    println!("\n=== GENESIS BLOCK ===");
+    println!("Hash: {}", info.hash);
+    println!("Merkle Root: {}", info.merkle_root);
+    println!("Timestamp: {}", info.time);
+    println!("Nonce: {}", info.nonce);
+    println!("Bits: {}", info.bits);
+    println!("Version: {}", info.version);
+    println!("Reward: {} sats", info.reward);
+    println!("Message: {}", info.message);
+    println!("Output script: {}", info.output_script);
+    println!("Hashes: {} at {:.0} H/s", info.grind.hashes, info.grind.hashrate);
+    println!("\n=== CHAINPARAMS ===");
+    println!("genesis = CreateGenesisBlock({}, {}, {}, {}, {});", info.time, info.nonce, info.bits, info.version, info.reward);
+    println!("consensus.hashGenesisBlock = genesis.GetHash();");
+    println!("assert(consensus.hashGenesisBlock == uint256{{\"{}\"}});", info.hash);
+    println!("assert(genesis.hashMerkleRoot == uint256{{\"{}\"}});", info.merkle_root);
+}
 
-        if self.should_process_field(&BlockField::Version) {
-            let new_version = self.process_version(header.version.to_consensus());
-            modified_header.version = Version::from_consensus(new_version);
+// Print the parsed signet solution and its validation
+fn print_signet_check(check: &SignetCheck) {
+    println!("\n=== SIGNET SOLUTION ===");
+    match &check.solution {
+        Some(solution) => {
+            println!("scriptSig: {}", hex::encode(solution.script_sig.as_bytes()));
+            for (i, item) in solution.witness.iter().enumerate() {
+                println!("Witness item {}: {}", i, hex::encode(item));
+            }
         }
+        None => println!("No solution in the witness commitment"),
+    }
+    println!("Signet merkle root: {}", check.signet_merkle_root);
+    println!("to_spend: {}", check.to_spend);
+    println!("to_sign: {}", check.to_sign);
+    match &check.signature {
+        SignetSignatureCheck::Valid => println!("Signature valid"),
+        SignetSignatureCheck::Invalid(reason) => println!("Signature invalid: {}", reason),
+        SignetSignatureCheck::Unsupported => println!("Challenge is not multisig or pay-to-pubkey, signatures not checked"),
+    }
+}
 
-        if self.should_process_field(&BlockField::PrevBlockHash) {
-            modified_header.prev_blockhash = self.process_prev_block_hash(&header.prev_blockhash);
-        }
+// Print header chain validation report
+fn print_header_chain_report(report: &HeaderChainReport) {
+    println!("\n=== HEADER CHAIN VALIDATION ===");
+    println!("Start height: {}", report.start_height);
+    println!("Valid headers: {}", report.headers_checked);
+    if let Some(tip) = &report.tip_hash {
+        println!("Last valid header: {}", tip);
+    }
+    println!("Chainwork: {} (log2 {:.6})", hex::encode(report.chainwork.to_be_bytes()), report.chainwork.log2());
+    match &report.first_invalid {
+        Some(invalid) => println!(
+            "First invalid header: #{} (height {}) {} - {:?}",
+            invalid.index, invalid.height, invalid.block_hash, invalid.error
+        ),
+        None => println!("All headers valid"),
+    }
+}
 
-        if self.should_process_field(&BlockField::MerkleRoot) {
-            modified_header.merkle_root = self.process_merkle_root(&header.merkle_root);
-        }
+// Print checkpoint verification report
+fn print_checkpoint_report(report: &CheckpointReport) {
+    println!("\n=== CHECKPOINTS ===");
+    println!("Checkpoints in range: {}", report.checkpoints_in_range);
+    if let Some(height) = report.last_match {
+        println!("Last matching checkpoint: {}", height);
+    }
+    match &report.first_mismatch {
+        Some(mismatch) => println!(
+            "Chain diverges at or before height {}: expected {}, found {}",
+            mismatch.height, mismatch.expected, mismatch.found
+        ),
+        None => println!("All checkpoints in range match"),
+    }
+}
 
-        if self.should_process_field(&BlockField::Timestamp) {
-            modified_header.time = self.process_timestamp(header.time);
-        }
+// Print block statistics
+fn print_block_stats(stats: &BlockStats) {
+    println!("\n=== BLOCK STATS ===");
+    println!("Block Hash: {}", stats.block_hash);
+    println!("Size: {} bytes (stripped {})", stats.size, stats.stripped_size);
+    println!("Weight: {} WU", stats.weight);
+    println!("Transactions: {} ({} segwit)", stats.tx_count, stats.segwit_tx_count);
+    println!("Legacy sigops: {}", stats.legacy_sigops);
+    println!("Total output value: {} sat", stats.total_output_value);
+    println!("Coinbase output value: {} sat", stats.coinbase_output_value);
+    if let Some(fees) = stats.total_fees {
+        println!("Total fees: {} sat ({} inputs without prevout)", fees, stats.missing_prevouts);
+    }
+    if let Some(f) = &stats.feerates {
+        println!(
+            "Feerates (sat/vB): min {:.2} p10 {:.2} p25 {:.2} median {:.2} p75 {:.2} p90 {:.2} max {:.2}",
+            f.min, f.p10, f.p25, f.median, f.p75, f.p90, f.max
+        );
+    }
+}
 
-        if self.should_process_field(&BlockField::Bits) {
-            let new_bits = self.process_bits(header.bits.to_consensus());
-            modified_header.bits = CompactTarget::from_consensus(new_bits);
-        }
+// Print the coinbase subsidy check
+fn print_coinbase_value_check(check: &CoinbaseValueCheck) {
+    println!("\n=== COINBASE VALUE ===");
+    println!("Height: {}", check.height);
+    println!("Subsidy: {} sat", check.subsidy);
+    println!("Fees: {} sat", check.fees);
+    println!("Coinbase value: {} sat", check.coinbase_value);
+    println!("Valid: {}", check.valid);
+}
 
-        if self.should_process_field(&BlockField::Nonce) {
-            modified_header.nonce = self.process_nonce(header.nonce);
-        }
-        
-        println!("Processed block header successfully");
-        modified_header
+// Print the differences between two blocks
+fn print_block_diff(diff: &BlockDiff) {
+    println!("\n=== BLOCK DIFF ===");
+    if diff.is_identical() {
+        println!("Blocks are identical");
+        return;
+    }
+    println!("Header fields changed: {}", diff.header.len());
+    if let Some(rolling) = &diff.version_rolling {
+        println!(
+            "Version change 0x{:08x} is {} the BIP320 rolling mask",
+            rolling.changed_bits,
+            if rolling.valid { "within" } else { "outside" }
+        );
     }
-    
-    // Helper method to generate a random block hash
-    fn generate_random_block_hash() -> BlockHash {
-        let mut rng = rand::rng();
-        let random_bytes: [u8; 32] = std::array::from_fn(|_| rng.random());
-        BlockHash::from_slice(&random_bytes).expect("Failed to create BlockHash from random bytes")
+    println!("Transactions: {} vs {} ({} changed)", diff.left_tx_count, diff.right_tx_count, diff.changed_transactions.len());
+    for field_diff in diff.header.iter().chain(&diff.transactions) {
+        println!("  {}: {} -> {}", field_diff.field, field_diff.left, field_diff.right);
     }
-    
-    // Helper method to generate a random merkle root
-    fn generate_random_merkle_root() -> TxMerkleNode {
-        let mut rng = rand::rng();
-        let random_bytes: [u8; 32] = std::array::from_fn(|_| rng.random());
-        TxMerkleNode::from_slice(&random_bytes).expect("Failed to create TxMerkleNode from random bytes")
+}
+
+// Print a batch summary table
+fn print_batch_summary(entries: &[BatchEntry]) {
+    println!("\n=== BATCH SUMMARY ({} blocks) ===", entries.len());
+    println!("{:<20} {:<64} {:>9}  fields", "block", "broken hash", "mutations");
+    for entry in entries {
+        println!("{:<20} {:<64} {:>9}  {}", entry.label, entry.broken_hash, entry.report.mutations.len(), entry.fields_changed.join(","));
     }
-    
-    // Process an entire block
-    pub fn process_block(&self, block: &Block) -> Block {
-        let modified_header = self.process_block_header(&block.header);
-        
-        Block {
-            header: modified_header,
-            txdata: block.txdata.clone(),
+}
+
+// Command line interface
+#[derive(Parser)]
+#[command(name = "block_breaker", about = "Break, inspect and mine Bitcoin blocks for validation testing")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Fetch a block from Bitcoin Core by height or hash")]
+    Fetch {
+        #[arg(help = "Block height or hash")]
+        block: BlockRef,
+        #[arg(long)]
+        out: Option<String>,
+        #[command(flatten)]
+        node: NodeArgs,
+    },
+    #[command(about = "Apply mutations to a block or an 80-byte header")]
+    Break(BreakArgs),
+    #[command(about = "Print header, proof-of-work and transaction details")]
+    Inspect {
+        input: String,
+        #[arg(long, help = "Print the decoded block as JSON")]
+        json: bool,
+    },
+    #[command(about = "Print size, weight, sigop, value and fee statistics")]
+    Stats {
+        input: String,
+        #[arg(long, help = "JSON object of \"txid:vout\" -> satoshis used to compute fees")]
+        prevouts: Option<String>,
+        #[arg(long, help = "Block height, to check the coinbase against the mainnet subsidy")]
+        height: Option<u32>,
+    },
+    #[command(about = "Validate a file of concatenated 80-byte headers")]
+    Headers {
+        input: String,
+        #[arg(long, default_value_t = 0)]
+        start_height: u32,
+        #[arg(long, default_value = "mainnet", value_parser = parse_network, help = "Difficulty rules: mainnet, testnet3, testnet4, signet or regtest")]
+        network: ConsensusParams,
+        #[arg(long, help = "JSON object of height -> block hash to check the chain against")]
+        checkpoints: Option<String>,
+        #[arg(long, conflicts_with = "checkpoints", help = "Check against the historical mainnet checkpoints")]
+        mainnet_checkpoints: bool,
+    },
+    #[command(about = "Stream 80-byte headers from a file or stdin, validating or mutating each one")]
+    Stream {
+        #[arg(long = "in", help = "Header file (stdin otherwise)")]
+        input: Option<String>,
+        #[arg(long, help = "Output file (stdout otherwise)")]
+        out: Option<String>,
+        #[arg(long, help = "Validate each header against the ones before it instead of mutating")]
+        validate: bool,
+        #[arg(long, default_value_t = 0)]
+        start_height: u32,
+        #[arg(long, default_value = "mainnet", value_parser = parse_network, help = "Difficulty rules used with --validate: mainnet, testnet3, testnet4, signet or regtest")]
+        network: ConsensusParams,
+        #[arg(long, help = "Write mutated headers as hex lines instead of raw bytes")]
+        hex: bool,
+        #[command(flatten)]
+        mutations: MutationArgs,
+    },
+    #[command(about = "Break every block of a mainnet blk*.dat file")]
+    Blk {
+        input: String,
+        #[arg(long, help = "Output file (defaults to <input>.broken)")]
+        out: Option<String>,
+        #[command(flatten)]
+        mutations: MutationArgs,
+    },
+    #[command(about = "Break every block of a directory or a node height range")]
+    Batch {
+        #[arg(long, required_unless_present = "range", help = "Directory of hex, .bin or .dat block files")]
+        dir: Option<String>,
+        #[arg(long, conflicts_with = "dir", help = "Height range START..END (end exclusive) fetched from Bitcoin Core")]
+        range: Option<String>,
+        #[arg(long, help = "Directory for the broken blocks, their reports and summary.json")]
+        out_dir: String,
+        #[command(flatten)]
+        node: NodeArgs,
+        #[command(flatten)]
+        mutations: MutationArgs,
+    },
+    #[command(about = "Assemble a block from a getblocktemplate response")]
+    Gbt {
+        template: String,
+        #[arg(long, default_value = "51", help = "Coinbase payout scriptPubKey as hex (OP_TRUE by default)")]
+        payout: String,
+        #[arg(long, default_value = "")]
+        extranonce: String,
+        #[arg(long = "break", help = "Break the assembled block with the default mutations")]
+        break_block: bool,
+        #[arg(long)]
+        out: Option<String>,
+    },
+    #[command(about = "Build the candidate header of a stratum mining.notify job")]
+    Stratum {
+        #[arg(help = "File holding the mining.notify message or its params array")]
+        job: String,
+        #[arg(long, default_value = "")]
+        extranonce1: String,
+        #[arg(long, default_value = "")]
+        extranonce2: String,
+        #[arg(long, help = "Write the 80-byte header to a file (hex on stdout otherwise)")]
+        out: Option<String>,
+    },
+    #[command(about = "Mine regtest blocks on top of a tip")]
+    Mine {
+        #[arg(long, default_value = REGTEST_GENESIS_HASH)]
+        tip: String,
+        #[arg(long, default_value_t = 0, help = "Height of the tip")]
+        height: u32,
+        #[arg(long, default_value_t = 1)]
+        count: usize,
+        #[arg(long, default_value = "51", help = "Coinbase payout scriptPubKey as hex (OP_TRUE by default)")]
+        payout: String,
+        #[arg(long, help = "Timestamp of the first block (defaults to now)")]
+        time: Option<u32>,
+        #[arg(long, help = "Output file: blk*.dat framing for .dat, one hex block per line otherwise")]
+        out: Option<String>,
+    },
+    #[command(about = "Mine two competing child chains of a tip for reorg testing")]
+    Fork {
+        #[arg(help = "Tip header or block")]
+        tip: String,
+        #[arg(long, default_value_t = 0, help = "Height of the tip")]
+        height: u32,
+        #[arg(long, default_value_t = 1)]
+        a_length: usize,
+        #[arg(long, default_value_t = 2)]
+        b_length: usize,
+        #[arg(long, default_value = "207fffff", value_parser = parse_hex_u32)]
+        a_bits: u32,
+        #[arg(long, default_value = "207fffff", value_parser = parse_hex_u32)]
+        b_bits: u32,
+        #[arg(long, help = "Break this block of branch b with the mutation flags, mining descendants on top of it")]
+        break_b: Option<usize>,
+        #[arg(long, default_value = "51", help = "Coinbase payout scriptPubKey as hex (OP_TRUE by default)")]
+        payout: String,
+        #[arg(long, help = "Timestamp of the first block (defaults to one second after the tip)")]
+        time: Option<u32>,
+        #[arg(long)]
+        out_dir: String,
+        #[command(flatten)]
+        mutations: MutationArgs,
+    },
+    #[command(about = "Interactively apply and undo mutations on a block, reading commands from stdin")]
+    Explore {
+        #[arg(long = "in")]
+        input: String,
+    },
+    #[command(about = "Build and mine a genesis block for a custom network")]
+    Genesis {
+        #[arg(long, default_value = MAINNET_GENESIS_MESSAGE, help = "Message embedded in the coinbase scriptSig")]
+        message: String,
+        #[arg(long, default_value = MAINNET_GENESIS_PUBKEY, help = "Public key paid by the genesis output as hex")]
+        pubkey: String,
+        #[arg(long, conflicts_with = "pubkey", help = "Genesis output scriptPubKey as hex, instead of paying a pubkey")]
+        script: Option<String>,
+        #[arg(long, help = "Timestamp (defaults to now)")]
+        time: Option<u32>,
+        #[arg(long, default_value_t = INITIAL_SUBSIDY, help = "Output value in satoshis")]
+        reward: u64,
+        #[arg(long, default_value = "207fffff", value_parser = parse_hex_u32, help = "Compact difficulty target as hex")]
+        bits: u32,
+        #[arg(long, default_value_t = 1)]
+        version: i32,
+        #[arg(long, default_value_t = 1, help = "Grinding threads (0 uses every core)")]
+        threads: usize,
+        #[arg(long, help = "Print the parameters as JSON")]
+        json: bool,
+        #[arg(long, help = "Write the genesis block to a file")]
+        out: Option<String>,
+    },
+    #[command(about = "Generate labeled invalid regtest blocks with a JSON manifest")]
+    Vectors {
+        #[arg(long)]
+        out_dir: String,
+        #[arg(long, default_value_t = 1_700_000_000, help = "Timestamp of the first parent block")]
+        time: u32,
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+    },
+    #[command(about = "Pad a block with filler transactions past the 4M weight unit limit")]
+    Oversize {
+        input: String,
+        #[arg(long, default_value_t = 1, help = "Weight units to exceed the limit by")]
+        overshoot: usize,
+        #[arg(long, help = "Grind the nonce after padding")]
+        remine: bool,
+        #[arg(long)]
+        out: Option<String>,
+    },
+    #[command(about = "Parse and validate the BIP325 signet solution of a block")]
+    Signet {
+        input: String,
+        #[arg(long, default_value = DEFAULT_SIGNET_CHALLENGE, help = "Signet challenge script as hex")]
+        challenge: String,
+    },
+    #[command(about = "Compare two blocks or headers field by field")]
+    Diff {
+        left: String,
+        right: String,
+        #[arg(long, help = "Print the differences as JSON")]
+        json: bool,
+    },
+    #[command(about = "Write random bit-flip mutants of a block into a corpus directory")]
+    Fuzz {
+        input: String,
+        #[arg(long, default_value_t = 100)]
+        count: usize,
+        #[arg(long, default_value_t = 1, help = "Bits flipped per mutant")]
+        bits: usize,
+        #[arg(long, default_value = "corpus")]
+        corpus: String,
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+}
+
+#[derive(Args)]
+struct NodeArgs {
+    #[arg(long, default_value = "http://127.0.0.1:8332")]
+    rpc_url: String,
+    #[arg(long)]
+    rpc_user: Option<String>,
+    #[arg(long)]
+    rpc_password: Option<String>,
+    #[arg(long, help = "Path to bitcoind's .cookie file")]
+    rpc_cookie: Option<String>,
+    #[arg(long, help = "Use the REST interface instead of JSON-RPC")]
+    rest: bool,
+}
+
+impl NodeArgs {
+    // Build a client from the flags, preferring REST, then user/password, then the cookie file
+    fn client(&self) -> Result<CoreClient, Box<dyn std::error::Error>> {
+        match (&self.rpc_user, &self.rpc_password, &self.rpc_cookie) {
+            _ if self.rest => Ok(CoreClient::rest(&self.rpc_url)),
+            (Some(user), Some(password), _) => Ok(CoreClient::new(&self.rpc_url, user, password)),
+            (_, _, Some(cookie)) => CoreClient::from_cookie(&self.rpc_url, cookie),
+            _ => Err("Pass --rest, --rpc-user and --rpc-password, or --rpc-cookie".into()),
         }
     }
+}
+
+#[derive(Args)]
+struct BreakArgs {
+    #[arg(long = "in", help = "Block or header as hex, or raw bytes for .bin/.dat files; - or no --in reads stdin")]
+    input: Option<String>,
+    #[arg(long, conflicts_with = "input", help = "Fetch the block to break from Bitcoin Core by height or hash")]
+    fetch: Option<BlockRef>,
+    #[command(flatten)]
+    node: NodeArgs,
+    #[arg(long, help = "Output file (hex on stdout otherwise, or with -)")]
+    out: Option<String>,
+    #[arg(long, help = "Write raw bytes instead of hex to stdout")]
+    raw: bool,
+    #[arg(long, help = "JSON report file (defaults to <out>.report.json when --out is given)")]
+    report: Option<String>,
+    #[arg(long, help = "JSON file with the decoded original and mutated block and the report")]
+    json: Option<String>,
+    #[arg(long, help = "Apply a subtle-corruption preset instead of the mutation flags")]
+    preset: Option<String>,
+    #[arg(long, requires = "preset", help = "Block height, for presets that depend on it")]
+    height: Option<u32>,
+    #[command(flatten)]
+    mutations: MutationArgs,
+}
+
+#[derive(Args)]
+struct MutationArgs {
+    #[arg(long, value_delimiter = ',', default_value = "all", help = "Header fields to break: version, prev-hash, merkle-root, time, bits, nonce, all or none")]
+    fields: Vec<String>,
+    #[arg(long = "version", help = "Version to set instead of the maximum")]
+    version_override: Option<i32>,
+    #[arg(long, allow_hyphen_values = true, help = "Seconds to add to the timestamp instead of one year from now")]
+    timestamp_offset: Option<i64>,
+    #[arg(long, help = "before-mtp, exactly-mtp, just-after-mtp, far-future, future-drift:SECS, before-previous or timewarp-period-start")]
+    timestamp_attack: Option<String>,
+    #[arg(long, help = "Header file whose last 11 headers give the median time past")]
+    prev_headers: Option<String>,
+    #[arg(long, help = "Seed for reproducible random hashes")]
+    seed: Option<u64>,
+    #[arg(long, help = "Zero hashes instead of randomizing them")]
+    zero_hashes: bool,
+    #[arg(long = "tx", help = "drop:N, duplicate:N, corrupt-witness:N or swap:A:B (repeatable)")]
+    transaction_mutations: Vec<String>,
+    #[arg(long = "set-bit")]
+    set_bits: Vec<u8>,
+    #[arg(long = "clear-bit")]
+    clear_bits: Vec<u8>,
+    #[arg(long, value_parser = parse_hex_u32, help = "Hex 16-bit value to place in the BIP320 version-rolling bits")]
+    roll_version: Option<u32>,
+    #[arg(long, value_parser = parse_hex_u32, help = "Hex bits to flip outside the version-rolling mask")]
+    roll_outside_mask: Option<u32>,
+    #[arg(long, help = "Coinbase extranonce to inject, as hex")]
+    extranonce: Option<String>,
+    #[arg(long)]
+    coinbase_height: Option<u32>,
+    #[arg(long)]
+    inflate_coinbase: bool,
+    #[arg(long)]
+    fix_witness_commitment: bool,
+    #[arg(long, help = "Flip a bit in the first signature of the BIP325 signet solution")]
+    corrupt_signet_signature: bool,
+    #[arg(long)]
+    fix_merkle_root: bool,
+    #[arg(long)]
+    remine: bool,
+    #[arg(long)]
+    roll_timestamp: bool,
+    #[arg(long)]
+    roll_extranonce: bool,
+    #[arg(long, default_value_t = 1, help = "Threads used to re-mine, 0 for one per core")]
+    threads: usize,
+    #[arg(long, default_value_t = ProcessingConfig::default().remine_max_hashes, help = "Hashes to try while re-mining, across all rolls, before giving up")]
+    max_hashes: u64,
+}
+
+// Parse a header field name
+fn parse_field(name: &str) -> Result<Option<BlockField>, String> {
+    match name {
+        "version" => Ok(Some(BlockField::Version)),
+        "prev-hash" => Ok(Some(BlockField::PrevBlockHash)),
+        "merkle-root" => Ok(Some(BlockField::MerkleRoot)),
+        "time" => Ok(Some(BlockField::Timestamp)),
+        "bits" => Ok(Some(BlockField::Bits)),
+        "nonce" => Ok(Some(BlockField::Nonce)),
+        "all" => Ok(Some(BlockField::All)),
+        "none" => Ok(None),
+        _ => Err(format!("Unknown field: {}", name)),
+    }
+}
+
+// Parse a transaction mutation such as drop:1 or swap:1:2
+fn parse_transaction_mutation(spec: &str) -> Result<TransactionMutation, Box<dyn std::error::Error>> {
+    let parts: Vec<&str> = spec.split(':').collect();
+    match parts.as_slice() {
+        ["drop", index] => Ok(TransactionMutation::Drop(index.parse()?)),
+        ["duplicate", index] => Ok(TransactionMutation::Duplicate(index.parse()?)),
+        ["corrupt-witness", index] => Ok(TransactionMutation::CorruptWitness(index.parse()?)),
+        ["swap", a, b] => Ok(TransactionMutation::Swap(a.parse()?, b.parse()?)),
+        _ => Err(format!("Unknown transaction mutation: {}", spec).into()),
+    }
+}
 
-    // Utility method to decode block header from hex string
-    pub fn decode_header_from_hex(hex_string: &str) -> Result<Header, Box<dyn std::error::Error>> {
-        let bytes = hex::decode(hex_string)?;
-        if bytes.len() != 80 {
-            return Err(format!("Invalid header length: expected 80 bytes, got {}", bytes.len()).into());
+// Parse a hex number, with or without a 0x prefix
+fn parse_hex_u32(s: &str) -> Result<u32, String> {
+    u32::from_str_radix(s.trim_start_matches("0x"), 16).map_err(|e| format!("Invalid hex number {}: {}", s, e))
+}
+
+// Parse a timestamp attack name
+fn parse_timestamp_attack(name: &str) -> Result<TimestampAttack, String> {
+    match name {
+        "before-mtp" => Ok(TimestampAttack::BeforeMtp),
+        "exactly-mtp" => Ok(TimestampAttack::ExactlyMtp),
+        "just-after-mtp" => Ok(TimestampAttack::JustAfterMtp),
+        "far-future" => Ok(TimestampAttack::FarFuture),
+        "before-previous" => Ok(TimestampAttack::BeforePrevious),
+        "timewarp-period-start" => Ok(TimestampAttack::TimewarpPeriodStart),
+        _ if name.starts_with("future-drift:") => name["future-drift:".len()..]
+            .parse()
+            .map(TimestampAttack::FutureDrift)
+            .map_err(|_| format!("Invalid drift in {}", name)),
+        _ => Err(format!("Unknown timestamp attack: {}", name)),
+    }
+}
+
+// Parse a network name into its difficulty rules
+fn parse_network(name: &str) -> Result<ConsensusParams, String> {
+    match name {
+        "mainnet" => Ok(ConsensusParams::MAINNET),
+        "testnet3" => Ok(ConsensusParams::TESTNET3),
+        "testnet4" => Ok(ConsensusParams::TESTNET4),
+        "signet" => Ok(ConsensusParams::SIGNET),
+        "regtest" => Ok(ConsensusParams::REGTEST),
+        _ => Err(format!("Unknown network: {}", name)),
+    }
+}
+
+// Parse a corruption preset name
+fn parse_preset(name: &str) -> Result<CorruptionPreset, String> {
+    match name {
+        "duplicate-txid" => Ok(CorruptionPreset::DuplicateTxid),
+        "bad-coinbase-height" => Ok(CorruptionPreset::BadCoinbaseHeight),
+        "inflated-coinbase" => Ok(CorruptionPreset::InflatedCoinbase),
+        "bad-witness-commitment" => Ok(CorruptionPreset::BadWitnessCommitment),
+        _ => Err(format!("Unknown preset: {}", name)),
+    }
+}
+
+impl MutationArgs {
+    // Build the processing configuration from the flags
+    fn to_config(&self) -> Result<ProcessingConfig, Box<dyn std::error::Error>> {
+        let mut fields_to_modify = Vec::new();
+        for name in &self.fields {
+            fields_to_modify.extend(parse_field(name)?);
         }
-        let header = Header::consensus_decode(&mut &bytes[..])?;
-        Ok(header)
+        let previous_headers = match &self.prev_headers {
+            Some(path) => {
+                let headers = HeaderChain::load_headers(path)?;
+                headers[headers.len().saturating_sub(MEDIAN_TIME_SPAN)..].to_vec()
+            }
+            None => vec![],
+        };
+        let version_bit_mutations = self
+            .set_bits
+            .iter()
+            .map(|bit| VersionBitMutation::Set(*bit))
+            .chain(self.clear_bits.iter().map(|bit| VersionBitMutation::Clear(*bit)))
+            .chain(self.roll_version.map(u16::try_from).transpose()?.map(VersionBitMutation::Roll))
+            .chain(self.roll_outside_mask.map(VersionBitMutation::RollOutsideMask))
+            .collect();
+
+        Ok(ProcessingConfig {
+            fields_to_modify,
+            version_override: self.version_override,
+            timestamp_offset: self.timestamp_offset,
+            randomize_hashes: !self.zero_hashes,
+            fix_merkle_root: self.fix_merkle_root,
+            remine: self.remine,
+            remine_roll_timestamp: self.roll_timestamp,
+            remine_roll_extranonce: self.roll_extranonce,
+            transaction_mutations: self
+                .transaction_mutations
+                .iter()
+                .map(|spec| parse_transaction_mutation(spec))
+                .collect::<Result<_, _>>()?,
+            seed: self.seed,
+            coinbase_extranonce: self.extranonce.as_deref().map(hex::decode).transpose()?,
+            fix_witness_commitment: self.fix_witness_commitment,
+            version_bit_mutations,
+            timestamp_attack: self.timestamp_attack.as_deref().map(parse_timestamp_attack).transpose()?,
+            previous_headers,
+            inflate_coinbase: self.inflate_coinbase,
+            coinbase_height: self.coinbase_height,
+            remine_threads: self.threads,
+            remine_max_hashes: self.max_hashes,
+            corrupt_signet_signature: self.corrupt_signet_signature,
+        })
     }
+}
+
+// Commands accepted by the explore prompt
+#[derive(Parser)]
+#[command(no_binary_name = true)]
+enum ExploreCommand {
+    #[command(about = "Apply mutations, taking the same flags as break")]
+    Apply(Box<MutationArgs>),
+    #[command(about = "Undo the last N steps")]
+    Undo {
+        #[arg(default_value_t = 1)]
+        n: usize,
+    },
+    #[command(about = "List the steps applied so far")]
+    History,
+    #[command(about = "Check the current block and find the first step that made it invalid")]
+    Check,
+    #[command(about = "Write the current block to a file")]
+    Write { path: String },
+    Quit,
+}
+
+// Proof of work, merkle root and witness commitment all hold
+fn block_is_valid(block: &Block) -> bool {
+    let consistency = BlockProcessor::check_consistency(block);
+    BlockProcessor::validate_pow(&block.header).valid && consistency.merkle_root_matches && consistency.witness_commitment_valid
+}
 
-    // Utility method to decode block from hex string
-    pub fn decode_block_from_hex(hex_string: &str) -> Result<Block, Box<dyn std::error::Error>> {
-        let bytes = hex::decode(hex_string)?;
-        let block = Block::consensus_decode(&mut &bytes[..])?;
-        Ok(block)
+// Read explore commands from stdin until EOF or quit
+fn run_explore(input: &str) -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::BufRead;
+
+    let mut history = MutationHistory::new(read_block(input)?);
+    println!("Loaded block {} (valid: {})", history.current().block_hash(), block_is_valid(history.current()));
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.is_empty() {
+            continue;
+        }
+        let command = match ExploreCommand::try_parse_from(&words) {
+            Ok(command) => command,
+            Err(e) => {
+                println!("{}", e);
+                continue;
+            }
+        };
+        match command {
+            ExploreCommand::Apply(mutations) => {
+                let step = history.apply(&words[1..].join(" "), mutations.to_config()?);
+                print_mutation_report(&mut std::io::stdout(), &step.report)?;
+                println!("Step {}: {} (valid: {})", history.steps().len(), history.current().block_hash(), block_is_valid(history.current()));
+            }
+            ExploreCommand::Undo { n } => {
+                let undone = history.undo(n);
+                println!("Undid {} steps, now at {} (valid: {})", undone, history.current().block_hash(), block_is_valid(history.current()));
+            }
+            ExploreCommand::History => {
+                for (i, step) in history.steps().iter().enumerate() {
+                    println!("{:>3}. [{}] {} mutations -> {}", i + 1, step.label, step.report.mutations.len(), step.block.block_hash());
+                }
+            }
+            ExploreCommand::Check => {
+                println!("Current block valid: {}", block_is_valid(history.current()));
+                match history.first_invalidating_step(block_is_valid) {
+                    Some(index) => println!("First invalidating step: {} [{}]", index + 1, history.steps()[index].label),
+                    None => println!("No step invalidated the block"),
+                }
+            }
+            ExploreCommand::Write { path } => {
+                BlockProcessor::write_block_to_file(history.current(), &path)?;
+                println!("Wrote current block to {}", path);
+            }
+            ExploreCommand::Quit => break,
+        }
     }
+    Ok(())
+}
+
+// Validate or mutate a stream of headers without holding the whole chain in memory
+fn run_stream(input: Option<&str>, out: Option<&str>, validate: bool, start_height: u32, network: ConsensusParams, hex_output: bool, mutations: &MutationArgs) -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::Write;
+
+    let reader: Box<dyn std::io::Read> = match input {
+        Some(path) => Box::new(std::io::BufReader::new(std::fs::File::open(path)?)),
+        None => Box::new(std::io::stdin().lock()),
+    };
+    let mut writer: Box<dyn Write> = match out {
+        Some(path) => Box::new(std::io::BufWriter::new(std::fs::File::create(path)?)),
+        None => Box::new(std::io::BufWriter::new(std::io::stdout().lock())),
+    };
 
-    // Create a minimal block from a header (for testing purposes)
-    pub fn create_minimal_block_from_header(header: Header) -> Block {
-        Block {
-            header,
-            txdata: vec![], // Empty transaction list
+    if validate {
+        let mut validator = HeaderStreamValidator::new(start_height, network);
+        for header in HeaderReader::new(reader) {
+            let header = header?;
+            let height = validator.next_height();
+            match validator.push(header) {
+                Ok(_) => writeln!(writer, "{} {} ok", height, header.block_hash())?,
+                Err(error) => writeln!(writer, "{} {} invalid {:?}", height, header.block_hash(), error)?,
+            }
+        }
+    } else {
+        let processor = BlockProcessor::new(mutations.to_config()?);
+        for header in HeaderReader::new(reader) {
+            let (mutated, _) = processor.process_block_header(&header?);
+            if hex_output {
+                writeln!(writer, "{}", BlockProcessor::encode_header_to_hex(&mutated))?;
+            } else {
+                writer.write_all(&encode::serialize(&mutated))?;
+            }
         }
     }
+    writer.flush()?;
+    Ok(())
+}
 
-    // Print block header information
-    pub fn print_header_info(header: &Header, label: &str) {
-        println!("\n=== {} ===", label);
-        println!("Version: {}", header.version.to_consensus());
-        println!("Previous Block: {}", header.prev_blockhash);
-        println!("Merkle Root: {}", header.merkle_root);
-        println!("Timestamp: {}", header.time);
-        println!("Bits: 0x{:08x}", header.bits.to_consensus());
-        println!("Nonce: {}", header.nonce);
-        println!("Block Hash: {}", header.block_hash());
+// Block or header read from an input file
+enum Input {
+    Header(Header),
+    Block(Block),
+}
+
+// Read a block or header from raw bytes (.bin/.dat) or hex, telling them apart by size.
+// A path of "-" reads stdin, which may carry either hex or raw bytes.
+fn read_input(path: &str) -> Result<Input, Box<dyn std::error::Error>> {
+    use std::io::Read;
+    let bytes = if path == "-" {
+        let mut data = Vec::new();
+        std::io::stdin().lock().read_to_end(&mut data)?;
+        if data.is_empty() {
+            return Err("Nothing to read on stdin".into());
+        }
+        match std::str::from_utf8(&data).map(str::trim) {
+            Ok(text) if text.bytes().all(|b| b.is_ascii_hexdigit()) => hex::decode(text)?,
+            _ => data,
+        }
+    } else if path.ends_with(".bin") || path.ends_with(".dat") {
+        std::fs::read(path)?
+    } else {
+        hex::decode(std::fs::read_to_string(path)?.trim())?
+    };
+    if bytes.len() == 80 {
+        Ok(Input::Header(encode::deserialize(&bytes)?))
+    } else {
+        Ok(Input::Block(encode::deserialize(&bytes)?))
     }
 }
 
-// Simplified interface for common use cases
-pub struct BlockBreaker;
+// Read an input as a block, wrapping a bare header in a minimal block
+fn read_block(path: &str) -> Result<Block, Box<dyn std::error::Error>> {
+    Ok(match read_input(path)? {
+        Input::Header(header) => BlockProcessor::create_minimal_block_from_header(header),
+        Input::Block(block) => block,
+    })
+}
 
-impl BlockBreaker {
-    // Break all fields with default settings
-    pub fn break_all_fields(block: &Block) -> Block {
-        let processor = BlockProcessor::with_default_config();
-        processor.process_block(block)
+// Load labeled blocks for a batch, from a directory (sorted by file name) or a node height range
+fn load_batch(dir: Option<&str>, range: Option<&str>, node: &NodeArgs) -> Result<Vec<(String, Block)>, Box<dyn std::error::Error>> {
+    if let Some(range) = range {
+        let (start, end) = range.split_once("..").ok_or("Range must look like START..END")?;
+        let client = node.client()?;
+        return (start.parse::<u32>()?..end.parse::<u32>()?)
+            .map(|height| Ok((height.to_string(), client.get_block(&BlockRef::Height(height))?)))
+            .collect();
     }
+    let mut paths: Vec<_> = std::fs::read_dir(dir.ok_or("Pass --dir or --range")?)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    paths.sort();
+    paths
+        .iter()
+        .filter(|path| path.is_file())
+        .map(|path| {
+            let label = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+            Ok((label, read_block(&path.to_string_lossy())?))
+        })
+        .collect()
+}
 
-    // Break only specific fields
-    pub fn break_specific_fields(block: &Block, fields: Vec<BlockField>) -> Block {
-        let config = ProcessingConfig {
-            fields_to_modify: fields,
-            ..Default::default()
-        };
-        let processor = BlockProcessor::new(config);
-        processor.process_block(block)
+// Write a block to `out`, or print it as hex
+fn output_block(block: &Block, out: Option<&str>) -> std::io::Result<()> {
+    match out {
+        Some(path) if path != "-" => BlockProcessor::write_block_to_file(block, path),
+        _ => {
+            println!("{}", BlockProcessor::encode_block_to_hex(block));
+            Ok(())
+        }
     }
+}
 
-    // Break with custom configuration
-    pub fn break_with_config(block: &Block, config: ProcessingConfig) -> Block {
-        let processor = BlockProcessor::new(config);
-        processor.process_block(block)
+// Write a serialized block or header to a file (raw bytes for .bin/.dat, hex otherwise),
+// or to stdout as hex, or as raw bytes with `raw`
+fn output_bytes(bytes: &[u8], out: Option<&str>, raw: bool) -> std::io::Result<()> {
+    use std::io::Write;
+    match out {
+        Some(path) if path.ends_with(".bin") || path.ends_with(".dat") => std::fs::write(path, bytes),
+        Some(path) if path != "-" => std::fs::write(path, hex::encode(bytes) + "\n"),
+        _ if raw => {
+            let mut stdout = std::io::stdout().lock();
+            stdout.write_all(bytes)?;
+            stdout.flush()
+        }
+        _ => {
+            println!("{}", hex::encode(bytes));
+            Ok(())
+        }
     }
+}
 
-    // Break header fields and return a minimal block
-    pub fn break_header_fields(header: &Header, fields: Vec<BlockField>) -> Block {
-        let config = ProcessingConfig {
-            fields_to_modify: fields,
-            ..Default::default()
-        };
-        let processor = BlockProcessor::new(config);
-        let modified_header = processor.process_block_header(header);
-        BlockProcessor::create_minimal_block_from_header(modified_header)
+// Break a block or header according to the flags
+fn run_break(args: &BreakArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let input = match (&args.fetch, &args.input) {
+        (Some(block), _) => Input::Block(args.node.client()?.get_block(block)?),
+        (None, Some(path)) => read_input(path)?,
+        (None, None) if atty::isnt(atty::Stream::Stdin) => read_input("-")?,
+        (None, None) => return Err("Pass --in, --fetch or pipe a block or header on stdin".into()),
+    };
+    let out = args.out.as_deref().filter(|path| *path != "-");
+    let (report, json) = match (&input, &args.preset) {
+        (Input::Block(block), Some(preset)) => {
+            let (broken, report) = BlockBreaker::break_with_preset(block, &parse_preset(preset)?, args.height.unwrap_or(0));
+            output_bytes(&encode::serialize(&broken), out, args.raw)?;
+            let json = BlockProcessor::block_mutation_to_json(block, &broken, &report)?;
+            (report, json)
+        }
+        (Input::Header(_), Some(_)) => return Err("Presets need a full block".into()),
+        (Input::Block(block), None) => {
+            let (broken, report) = BlockBreaker::break_with_config(block, args.mutations.to_config()?);
+            output_bytes(&encode::serialize(&broken), out, args.raw)?;
+            let json = BlockProcessor::block_mutation_to_json(block, &broken, &report)?;
+            (report, json)
+        }
+        (Input::Header(header), None) => {
+            let (broken, report) = BlockProcessor::new(args.mutations.to_config()?).process_block_header(header);
+            output_bytes(&encode::serialize(&broken), out, args.raw)?;
+            let json = BlockProcessor::header_mutation_to_json(header, &broken, &report)?;
+            (report, json)
+        }
+    };
+
+    if let Some(path) = &args.json {
+        std::fs::write(path, json)?;
+    }
+
+    let report_path = args.report.clone().or_else(|| out.map(|out| format!("{}.report.json", out)));
+    if let Some(path) = &report_path {
+        BlockProcessor::write_report_to_file(&report, path)?;
+    }
+    // Keep stdout to the mutated output when no file was requested, so it can be piped
+    match out {
+        Some(out) => {
+            print_mutation_report(&mut std::io::stdout(), &report)?;
+            println!("Wrote broken output to {}", out);
+        }
+        None => print_mutation_report(&mut std::io::stderr(), &report)?,
     }
+    Ok(())
 }
 
-// Example usage and tests
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Sample block header hex (Bitcoin Genesis Block header)
-    let header_hex = "0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d1dac2b7c";
-    
-    // Decode the header
-    let original_header = BlockProcessor::decode_header_from_hex(header_hex)?;
-    
-    // Create a minimal block from the header for processing
-    let original_block = BlockProcessor::create_minimal_block_from_header(original_header.clone());
-    
-    // Print original block info
-    BlockProcessor::print_header_info(&original_header, "ORIGINAL BLOCK HEADER");
-    
-    // Example 1: Break all fields
-    println!("\n{}" , "=".repeat(50).as_str());
-    println!("EXAMPLE 1: Breaking all fields");
-    let broken_all = BlockBreaker::break_all_fields(&original_block);
-    BlockProcessor::print_header_info(&broken_all.header, "ALL FIELDS BROKEN");
-    
-    // Example 2: Break only specific fields
-    println!("\n{}" , "=".repeat(50).as_str());
-    println!("EXAMPLE 2: Breaking only version and nonce");
-    let broken_specific = BlockBreaker::break_specific_fields(
-        &original_block,
-        vec![BlockField::Version, BlockField::Nonce]
-    );
-    BlockProcessor::print_header_info(&broken_specific.header, "VERSION & NONCE BROKEN");
-    
-    // Example 3: Custom configuration
-    println!("\n{}" , "=".repeat(50).as_str());
-    println!("EXAMPLE 3: Custom configuration");
-    let custom_config = ProcessingConfig {
-        fields_to_modify: vec![BlockField::Timestamp, BlockField::Bits],
-        version_override: Some(2),
-        timestamp_offset: Some(-86400), // Subtract one day
-        randomize_hashes: false,
-    };
-    let broken_custom = BlockBreaker::break_with_config(&original_block, custom_config);
-    BlockProcessor::print_header_info(&broken_custom.header, "CUSTOM CONFIGURATION");
-    
-    // Example 4: Working directly with headers
-    println!("\n{}" , "=".repeat(50).as_str());
-    println!("EXAMPLE 4: Working directly with header");
-    let broken_header_block = BlockBreaker::break_header_fields(
-        &original_header,
-        vec![BlockField::MerkleRoot, BlockField::PrevBlockHash]
-    );
-    BlockProcessor::print_header_info(&broken_header_block.header, "HEADER FIELDS BROKEN");
-    
+    match Cli::parse().command {
+        Command::Fetch { block, out, node } => output_block(&node.client()?.get_block(&block)?, out.as_deref())?,
+        Command::Break(args) => run_break(&args)?,
+        Command::Inspect { input, json: true } => match read_input(&input)? {
+            Input::Header(header) => println!("{}", serde_json::to_string_pretty(&BlockProcessor::export_header(&header))?),
+            Input::Block(block) => println!("{}", serde_json::to_string_pretty(&BlockProcessor::export_block(&block))?),
+        },
+        Command::Inspect { input, json: false } => match read_input(&input)? {
+            Input::Header(header) => {
+                print_header_info(&header, "HEADER");
+                print_pow_info(&header, "PROOF OF WORK");
+            }
+            Input::Block(block) => {
+                print_header_info(&block.header, "BLOCK HEADER");
+                print_pow_info(&block.header, "PROOF OF WORK");
+                print_block_transactions(&block, "TRANSACTIONS");
+            }
+        },
+        Command::Stats { input, prevouts, height } => {
+            let block = read_block(&input)?;
+            let prevouts = prevouts.map(|path| BlockProcessor::load_prevout_values(&path)).transpose()?;
+            let stats = BlockProcessor::block_stats(&block, prevouts.as_ref());
+            print_block_stats(&stats);
+            if let Some(height) = height {
+                let fees = stats.total_fees.unwrap_or(0);
+                print_coinbase_value_check(&BlockProcessor::check_coinbase_value(&block, height, fees, MAINNET_HALVING_INTERVAL));
+            }
+        }
+        Command::Headers { input, start_height, network, checkpoints, mainnet_checkpoints } => {
+            let headers = HeaderChain::load_headers(&input)?;
+            print_header_chain_report(&HeaderChain::validate(&headers, start_height, &network));
+            let checkpoints = match checkpoints {
+                Some(path) => Some(HeaderChain::load_checkpoints(&path)?),
+                None => mainnet_checkpoints.then(HeaderChain::mainnet_checkpoints),
+            };
+            if let Some(checkpoints) = checkpoints {
+                print_checkpoint_report(&HeaderChain::check_checkpoints(&headers, start_height, &checkpoints));
+            }
+        }
+        Command::Stream { input, out, validate, start_height, network, hex, mutations } => {
+            run_stream(input.as_deref(), out.as_deref(), validate, start_height, network, hex, &mutations)?;
+        }
+        Command::Blk { input, out, mutations } => {
+            let out = out.unwrap_or_else(|| format!("{}.broken", input));
+            let reports = BlockBreaker::break_blk_file(&input, &out, MAINNET_MAGIC, &[], mutations.to_config()?)?;
+            for (index, report) in &reports {
+                println!("\nBlock #{}", index);
+                print_mutation_report(&mut std::io::stdout(), report)?;
+            }
+            println!("Broke {} blocks from {} into {}", reports.len(), input, out);
+        }
+        Command::Batch { dir, range, out_dir, node, mutations } => {
+            let blocks = load_batch(dir.as_deref(), range.as_deref(), &node)?;
+            std::fs::create_dir_all(&out_dir)?;
+            let results = BlockBreaker::break_batch(&blocks, mutations.to_config()?);
+            for (broken, entry) in &results {
+                let path = format!("{}/{}.hex", out_dir, entry.label);
+                BlockProcessor::write_block_to_file(broken, &path)?;
+                BlockProcessor::write_report_to_file(&entry.report, &format!("{}.report.json", path))?;
+            }
+            let entries: Vec<BatchEntry> = results.into_iter().map(|(_, entry)| entry).collect();
+            std::fs::write(format!("{}/summary.json", out_dir), serde_json::to_string_pretty(&entries)?)?;
+            print_batch_summary(&entries);
+        }
+        Command::Gbt { template, payout, extranonce, break_block, out } => {
+            let template = BlockProcessor::load_block_template(&template)?;
+            let mut block = BlockProcessor::assemble_block(&template, hex::decode(payout)?.into(), &hex::decode(extranonce)?)?;
+            if break_block {
+                let (broken, report) = BlockBreaker::break_all_fields(&block);
+                if out.is_some() {
+                    print_mutation_report(&mut std::io::stdout(), &report)?;
+                }
+                block = broken;
+            }
+            output_block(&block, out.as_deref())?;
+        }
+        Command::Stratum { job, extranonce1, extranonce2, out } => {
+            let job = BlockProcessor::parse_mining_notify(&std::fs::read_to_string(&job)?)?;
+            let (header, coinbase) = BlockProcessor::stratum_header(&job, &hex::decode(extranonce1)?, &hex::decode(extranonce2)?)?;
+            match &out {
+                Some(path) => {
+                    BlockProcessor::write_header_to_file(&header, path)?;
+                    print_header_info(&header, &format!("STRATUM JOB {}", job.job_id));
+                    println!("Coinbase txid: {}", coinbase.txid());
+                }
+                None => println!("{}", BlockProcessor::encode_header_to_hex(&header)),
+            }
+        }
+        Command::Mine { tip, height, count, payout, time, out } => {
+            let tip: BlockHash = tip.parse()?;
+            let start_time = match time {
+                Some(time) => time,
+                None => std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as u32,
+            };
+            let blocks = BlockProcessor::mine_regtest_chain(tip, height, start_time, count, &hex::decode(payout)?.into())?;
+            match &out {
+                Some(path) if path.ends_with(".dat") => BlkFile::write_blocks(path, REGTEST_MAGIC, &blocks)?,
+                Some(path) => {
+                    let lines: Vec<String> = blocks.iter().map(BlockProcessor::encode_block_to_hex).collect();
+                    std::fs::write(path, lines.join("\n") + "\n")?;
+                }
+                None => blocks.iter().for_each(|block| println!("{}", BlockProcessor::encode_block_to_hex(block))),
+            }
+            if let Some(path) = &out {
+                for (i, block) in blocks.iter().enumerate() {
+                    println!("Mined block {} at height {}", block.block_hash(), height + 1 + i as u32);
+                }
+                println!("Wrote {} blocks to {}", blocks.len(), path);
+            }
+        }
+        Command::Fork { tip, height, a_length, b_length, a_bits, b_bits, break_b, payout, time, out_dir, mutations } => {
+            let tip = match read_input(&tip)? {
+                Input::Header(header) => header,
+                Input::Block(block) => block.header,
+            };
+            let branches = [
+                ForkBranch { bits: a_bits, ..ForkBranch::new(a_length) },
+                ForkBranch {
+                    bits: b_bits,
+                    break_at: break_b.map(|index| mutations.to_config().map(|config| (index, config))).transpose()?,
+                    ..ForkBranch::new(b_length)
+                },
+            ];
+            let start_time = time.unwrap_or(tip.time + 1);
+            let scenario = BlockProcessor::simulate_fork(tip.block_hash(), height, start_time, &branches, &hex::decode(payout)?.into())?;
+            let summary = BlockProcessor::write_fork_scenario(&scenario, &out_dir)?;
+            for (name, branch) in ["a", "b"].iter().zip(&summary.branches) {
+                println!(
+                    "Branch {}: {} blocks, tip {}, chainwork {}",
+                    name,
+                    branch.length,
+                    branch.tip_hash.as_deref().unwrap_or("-"),
+                    branch.chainwork
+                );
+            }
+            if let Some((index, report)) = &scenario.broken[1] {
+                println!("Broke branch b block {}:", index);
+                print_mutation_report(&mut std::io::stdout(), report)?;
+            }
+            match summary.heavier {
+                Some(0) => println!("Branch a has more work"),
+                Some(_) => println!("Branch b has more work"),
+                None => println!("Branches have equal work"),
+            }
+            println!("Wrote fork scenario to {}", out_dir);
+        }
+        Command::Explore { input } => run_explore(&input)?,
+        Command::Genesis { message, pubkey, script, time, reward, bits, version, threads, json, out } => {
+            let output_script = match script {
+                Some(script) => hex::decode(script)?.into(),
+                None => GenesisParams::pay_to_pubkey(&hex::decode(pubkey)?)?,
+            };
+            let time = match time {
+                Some(time) => time,
+                None => std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as u32,
+            };
+            let params = GenesisParams { message, output_script, time, reward, bits, version };
+            let (block, info) = BlockProcessor::mine_genesis(&params, threads)?;
+            if let Some(path) = &out {
+                BlockProcessor::write_block_to_file(&block, path)?;
+            }
+            if json {
+                println!("{}", serde_json::to_string_pretty(&info)?);
+            } else {
+                print_genesis_info(&info);
+            }
+        }
+        Command::Vectors { out_dir, time, seed } => {
+            let manifest = BlockBreaker::write_test_vectors(&out_dir, time, seed)?;
+            for vector in &manifest.vectors {
+                println!("{:<24} {:<26} {}", vector.name, vector.rule, vector.block_hash);
+            }
+            println!("Wrote {} vectors on top of {} to {}", manifest.vectors.len(), manifest.parent_tip, out_dir);
+        }
+        Command::Oversize { input, overshoot, remine, out } => {
+            let mut block = read_block(&input)?;
+            let report = BlockProcessor::pad_to_weight(&mut block, overshoot);
+            if remine && !BlockProcessor::grind_nonce(&mut block.header) {
+                eprintln!("Nonce space exhausted without meeting the target");
+            }
+            if out.is_some() {
+                println!(
+                    "Added {} filler transactions: weight {} -> {} (limit {}, target {})",
+                    report.filler_transactions, report.original_weight, report.weight, MAX_BLOCK_WEIGHT, report.target_weight
+                );
+            }
+            output_block(&block, out.as_deref())?;
+        }
+        Command::Signet { input, challenge } => {
+            let challenge = ScriptBuf::from_bytes(hex::decode(challenge)?);
+            print_signet_check(&BlockProcessor::check_signet_block(&read_block(&input)?, &challenge)?);
+        }
+        Command::Diff { left, right, json } => {
+            let diff = BlockProcessor::diff_blocks(&read_block(&left)?, &read_block(&right)?);
+            if json {
+                println!("{}", serde_json::to_string_pretty(&diff)?);
+            } else {
+                print_block_diff(&diff);
+            }
+        }
+        Command::Fuzz { input, count, bits, corpus, seed } => {
+            let processor = BlockProcessor::new(ProcessingConfig { seed, ..Default::default() });
+            let manifest = processor.fuzz_block(&read_block(&input)?, count, bits, &corpus)?;
+            let clean = manifest.mutants.iter().filter(|mutant| mutant.deserializes).count();
+            println!("Wrote {} mutants to {} ({} still deserialize)", manifest.mutants.len(), corpus, clean);
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,110 @@
+use bitcoin::{
+    blockdata::block::{Block, Header},
+    hash_types::{TxMerkleNode, Txid},
+    hashes::{sha256d, Hash},
+};
+
+use crate::processor::BlockProcessor;
+
+// Result of comparing the header merkle root with the one computed from txdata
+#[derive(Debug, Clone)]
+pub struct MerkleRootCheck {
+    pub header_root: TxMerkleNode,
+    pub computed_root: Option<TxMerkleNode>, // None when the block has no transactions
+    pub matches: bool,
+}
+
+// SPV-style inclusion proof of a txid in a block
+#[derive(Debug, Clone)]
+pub struct MerkleProof {
+    pub txid: Txid,
+    pub position: usize,              // index of the transaction in txdata
+    pub branch: Vec<TxMerkleNode>,    // sibling hashes from leaf to root
+    pub merkle_root: TxMerkleNode,
+}
+
+impl MerkleProof {
+    // Fold the branch back up to the root and compare it with the expected root
+    pub fn verify(&self, expected_root: &TxMerkleNode) -> bool {
+        let mut current = TxMerkleNode::from_raw_hash(self.txid.to_raw_hash());
+        let mut position = self.position;
+        for sibling in &self.branch {
+            current = if position & 1 == 0 {
+                BlockProcessor::merkle_parent(&current, sibling)
+            } else {
+                BlockProcessor::merkle_parent(sibling, &current)
+            };
+            position >>= 1;
+        }
+        current == *expected_root && self.merkle_root == *expected_root
+    }
+}
+
+impl BlockProcessor {
+    // Recompute the merkle root from txdata and compare it with the header
+    pub fn check_merkle_root(block: &Block) -> MerkleRootCheck {
+        let computed_root = block.compute_merkle_root();
+        MerkleRootCheck {
+            header_root: block.header.merkle_root,
+            computed_root,
+            matches: computed_root == Some(block.header.merkle_root),
+        }
+    }
+
+    // Overwrite the header merkle root with the one computed from txdata
+    pub fn fix_merkle_root(block: &mut Block) -> bool {
+        match block.compute_merkle_root() {
+            Some(root) => {
+                block.header.merkle_root = root;
+                true
+            }
+            // A block without transactions has no merkle root
+            None => false,
+        }
+    }
+
+    // Hash two merkle nodes together into their parent
+    pub(crate) fn merkle_parent(left: &TxMerkleNode, right: &TxMerkleNode) -> TxMerkleNode {
+        let mut data = [0u8; 64];
+        data[..32].copy_from_slice(left.as_byte_array());
+        data[32..].copy_from_slice(right.as_byte_array());
+        TxMerkleNode::from_raw_hash(sha256d::Hash::hash(&data))
+    }
+
+    // Build the merkle branch proving that txid is included in the block
+    pub fn merkle_proof(block: &Block, txid: &Txid) -> Option<MerkleProof> {
+        let position = block.txdata.iter().position(|tx| tx.txid() == *txid)?;
+        let mut level: Vec<TxMerkleNode> = block
+            .txdata
+            .iter()
+            .map(|tx| TxMerkleNode::from_raw_hash(tx.txid().to_raw_hash()))
+            .collect();
+
+        let mut branch = Vec::new();
+        let mut index = position;
+        while level.len() > 1 {
+            // Odd levels duplicate their last node, as in Bitcoin Core
+            if level.len() % 2 == 1 {
+                level.push(*level.last().unwrap());
+            }
+            branch.push(level[index ^ 1]);
+            level = level
+                .chunks(2)
+                .map(|pair| Self::merkle_parent(&pair[0], &pair[1]))
+                .collect();
+            index /= 2;
+        }
+
+        Some(MerkleProof {
+            txid: *txid,
+            position,
+            branch,
+            merkle_root: level[0],
+        })
+    }
+
+    // Verify a merkle proof against a block header
+    pub fn verify_merkle_proof(proof: &MerkleProof, header: &Header) -> bool {
+        proof.verify(&header.merkle_root)
+    }
+}
@@ -0,0 +1,64 @@
+use bitcoin::blockdata::{block::Block, script::ScriptBuf};
+use bitcoin::hash_types::BlockHash;
+
+use crate::coinbase::REGTEST_HALVING_INTERVAL;
+use crate::processor::BlockProcessor;
+use crate::template::BlockTemplate;
+
+// Regtest consensus parameters
+pub const REGTEST_BITS: u32 = 0x207fffff;
+pub const REGTEST_GENESIS_HASH: &str = "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206";
+
+impl BlockProcessor {
+    // Assemble a template into a block and grind it until it meets its target, bumping the
+    // extranonce whenever the nonce space runs out
+    pub fn mine_template(template: &BlockTemplate, payout_script: &ScriptBuf) -> Result<Block, Box<dyn std::error::Error>> {
+        for extranonce in 0u32.. {
+            let mut block = Self::assemble_block(template, payout_script.clone(), &extranonce.to_le_bytes())?;
+            if Self::grind_nonce(&mut block.header) {
+                return Ok(block);
+            }
+        }
+        Err("Extranonce space exhausted".into())
+    }
+
+    // Mine `count` empty regtest blocks on top of a tip, paying every coinbase to the
+    // payout script. Timestamps start at `start_time` and increase by one second per block
+    // so each block is after the median time past of the ones before it.
+    pub fn mine_regtest_chain(
+        tip: BlockHash,
+        tip_height: u32,
+        start_time: u32,
+        count: usize,
+        payout_script: &ScriptBuf,
+    ) -> Result<Vec<Block>, Box<dyn std::error::Error>> {
+        Self::mine_chain(tip, tip_height, start_time, count, REGTEST_BITS, payout_script)
+    }
+
+    // Mine `count` empty blocks like mine_regtest_chain, but at a fixed difficulty `bits`
+    pub fn mine_chain(
+        tip: BlockHash,
+        tip_height: u32,
+        start_time: u32,
+        count: usize,
+        bits: u32,
+        payout_script: &ScriptBuf,
+    ) -> Result<Vec<Block>, Box<dyn std::error::Error>> {
+        let mut blocks: Vec<Block> = Vec::with_capacity(count);
+        for i in 0..count {
+            let height = tip_height + 1 + i as u32;
+            let template = BlockTemplate {
+                version: 0x2000_0000,
+                previousblockhash: blocks.last().map(|b| b.block_hash()).unwrap_or(tip).to_string(),
+                transactions: vec![],
+                coinbasevalue: Self::block_subsidy(height, REGTEST_HALVING_INTERVAL),
+                bits: format!("{:08x}", bits),
+                curtime: start_time + i as u32,
+                height,
+                default_witness_commitment: None,
+            };
+            blocks.push(Self::mine_template(&template, payout_script)?);
+        }
+        Ok(blocks)
+    }
+}
@@ -0,0 +1,78 @@
+use bitcoin::blockdata::{
+    block::Block,
+    locktime::absolute::LockTime,
+    script::ScriptBuf,
+    transaction::{OutPoint, Sequence, Transaction, TxIn, TxOut},
+    witness::Witness,
+};
+use bitcoin::hash_types::Txid;
+use bitcoin::hashes::Hash;
+use serde::Serialize;
+
+use crate::processor::BlockProcessor;
+use crate::vectors::MAX_BLOCK_WEIGHT;
+
+// Largest OP_RETURN script in a single filler transaction
+const MAX_FILLER_SCRIPT_SIZE: usize = 100_000;
+
+// Outcome of padding a block past the weight limit
+#[derive(Debug, Clone, Serialize)]
+pub struct OversizeReport {
+    pub original_weight: usize,
+    pub weight: usize,
+    pub target_weight: usize,
+    pub filler_transactions: usize,
+}
+
+impl BlockProcessor {
+    // Non-witness transaction spending a made-up outpoint into one OP_RETURN output
+    // of `script_size` bytes. `index` keeps filler txids distinct.
+    fn filler_transaction(index: u32, script_size: usize) -> Transaction {
+        let mut txid = [0u8; 32];
+        txid[..4].copy_from_slice(&index.to_le_bytes());
+        let mut script = vec![0x6a];
+        script.resize(script_size.max(1), 0);
+        Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint { txid: Txid::from_byte_array(txid), vout: 0 },
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut { value: 0, script_pubkey: script.into() }],
+        }
+    }
+
+    // Append filler transactions until the block weighs at least MAX_BLOCK_WEIGHT + overshoot
+    // (an overshoot of 0 still exceeds the limit by one weight unit). An existing witness
+    // commitment and the merkle root are recomputed; the header is not re-mined.
+    pub fn pad_to_weight(block: &mut Block, overshoot: usize) -> OversizeReport {
+        let original_weight = block.weight().to_wu() as usize;
+        let target_weight = MAX_BLOCK_WEIGHT + overshoot.max(1);
+        let mut filler_transactions = 0;
+        while (block.weight().to_wu() as usize) < target_weight {
+            let remaining = (target_weight - block.weight().to_wu() as usize).div_ceil(4);
+            // Size the last filler to land on the target, allowing for the script length varint
+            let estimate = remaining.clamp(1, MAX_FILLER_SCRIPT_SIZE);
+            let overhead = Self::filler_transaction(0, estimate).size() - estimate;
+            let script_size = remaining.saturating_sub(overhead).clamp(1, MAX_FILLER_SCRIPT_SIZE);
+            block.txdata.push(Self::filler_transaction(filler_transactions as u32, script_size));
+            filler_transactions += 1;
+        }
+
+        if Self::check_witness_commitment(block).found.is_some() {
+            // Fillers have no witness, but they still change the witness merkle root
+            let _ = Self::fix_witness_commitment(block);
+        }
+        Self::fix_merkle_root(block);
+
+        OversizeReport {
+            original_weight,
+            weight: block.weight().to_wu() as usize,
+            target_weight,
+            filler_transactions,
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use bitcoin::{
+    blockdata::block::Header,
+    hash_types::BlockHash,
+    hashes::Hash,
+    pow::{Target, Work},
+};
+
+use crate::processor::BlockProcessor;
+
+// Mainnet consensus parameters used by difficulty validation
+pub const POW_LIMIT_BITS: u32 = 0x1d00ffff;
+pub const RETARGET_INTERVAL: u32 = 2016;
+pub const TARGET_TIMESPAN: u64 = 14 * 24 * 60 * 60;
+pub const TARGET_SPACING: u32 = 10 * 60;
+
+// Difficulty rules that differ between networks, as in Bitcoin Core's chainparams
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConsensusParams {
+    pub pow_limit_bits: u32,
+    pub no_retargeting: bool,       // bits never change (regtest)
+    pub allow_min_difficulty: bool, // a block more than 20 minutes after its parent may use the pow limit (testnets)
+    pub enforce_bip94: bool,        // retarget from the period's first block and reject timewarps (testnet4)
+}
+
+impl ConsensusParams {
+    pub const MAINNET: ConsensusParams = ConsensusParams {
+        pow_limit_bits: POW_LIMIT_BITS,
+        no_retargeting: false,
+        allow_min_difficulty: false,
+        enforce_bip94: false,
+    };
+    pub const TESTNET3: ConsensusParams = ConsensusParams { allow_min_difficulty: true, ..Self::MAINNET };
+    pub const TESTNET4: ConsensusParams = ConsensusParams { allow_min_difficulty: true, enforce_bip94: true, ..Self::MAINNET };
+    // Default signet; custom signets keep the same rules
+    pub const SIGNET: ConsensusParams = ConsensusParams { pow_limit_bits: 0x1e0377ae, ..Self::MAINNET };
+    pub const REGTEST: ConsensusParams = ConsensusParams {
+        pow_limit_bits: 0x207fffff,
+        no_retargeting: true,
+        allow_min_difficulty: true,
+        enforce_bip94: false,
+    };
+}
+
+impl Default for ConsensusParams {
+    fn default() -> Self {
+        Self::MAINNET
+    }
+}
+
+// Target expanded from the compact `bits` encoding
+#[derive(Debug, Clone)]
+pub struct ExpandedTarget {
+    pub target: [u8; 32], // big-endian, same order as the displayed block hash
+    pub negative: bool,   // sign bit set in the mantissa
+    pub overflow: bool,   // exponent pushes the mantissa beyond 256 bits
+}
+
+// Result of checking a header's hash against the target encoded in its bits
+#[derive(Debug, Clone)]
+pub struct PowValidation {
+    pub block_hash: BlockHash,
+    pub bits: u32,
+    pub target: ExpandedTarget,
+    pub valid: bool,
+}
+
+impl BlockProcessor {
+    // Expand compact bits into a 256-bit target, following Bitcoin Core's SetCompact
+    pub fn expand_target(bits: u32) -> ExpandedTarget {
+        let size = (bits >> 24) as usize;
+        let mut word = bits & 0x007fffff;
+        let mut target = [0u8; 32];
+
+        if size <= 3 {
+            word >>= 8 * (3 - size);
+            target[28..].copy_from_slice(&word.to_be_bytes());
+        } else {
+            // Mantissa bytes land at big-endian positions 32 - size .. 35 - size
+            for (i, byte) in word.to_be_bytes()[1..].iter().enumerate() {
+                if let Some(pos) = (32 + i).checked_sub(size) {
+                    if pos < 32 {
+                        target[pos] = *byte;
+                    }
+                }
+            }
+        }
+
+        ExpandedTarget {
+            target,
+            negative: word != 0 && bits & 0x00800000 != 0,
+            overflow: word != 0
+                && (size > 34 || (word > 0xff && size > 33) || (word > 0xffff && size > 32)),
+        }
+    }
+
+    // Check that the header hash meets the target encoded in its own bits
+    pub fn validate_pow(header: &Header) -> PowValidation {
+        let block_hash = header.block_hash();
+        let bits = header.bits.to_consensus();
+        let target = Self::expand_target(bits);
+
+        let mut hash_be = block_hash.to_byte_array();
+        hash_be.reverse();
+
+        let target_is_zero = target.target.iter().all(|b| *b == 0);
+        let valid = !target.negative && !target.overflow && !target_is_zero && hash_be <= target.target;
+
+        PowValidation { block_hash, bits, target, valid }
+    }
+
+    // Work implied by compact bits, following Bitcoin Core's GetBlockProof: zero for
+    // negative, overflowing or zero targets
+    pub fn block_work(bits: u32) -> Work {
+        let target = Self::expand_target(bits);
+        if target.negative || target.overflow || target.target.iter().all(|b| *b == 0) {
+            return Work::from_be_bytes([0u8; 32]);
+        }
+        Target::from_be_bytes(target.target).to_work()
+    }
+
+    // Add two amounts of work, saturating instead of overflowing on absurd targets
+    pub fn add_work(a: Work, b: Work) -> Work {
+        let max = Work::from_be_bytes([0xff; 32]);
+        if b > max - a {
+            max
+        } else {
+            a + b
+        }
+    }
+
+    // Compress a big-endian 256-bit target into compact bits, following Bitcoin Core's GetCompact
+    pub fn compact_from_target(target: &[u8; 32]) -> u32 {
+        let mut size = 32 - target.iter().take_while(|b| **b == 0).count();
+        let mut compact = if size <= 3 {
+            let mut word = [0u8; 4];
+            word[4 - size..].copy_from_slice(&target[32 - size..]);
+            u32::from_be_bytes(word) << (8 * (3 - size))
+        } else {
+            let start = 32 - size;
+            u32::from_be_bytes([0, target[start], target[start + 1], target[start + 2]])
+        };
+        // Keep the mantissa positive by moving the sign bit into the exponent
+        if compact & 0x00800000 != 0 {
+            compact >>= 8;
+            size += 1;
+        }
+        compact | (size as u32) << 24
+    }
+
+    // Difficulty retarget at the end of a 2016-block period, with mainnet rules
+    pub fn next_work_required(prev_bits: u32, first_time: u32, last_time: u32) -> u32 {
+        Self::next_work_required_for(&ConsensusParams::MAINNET, prev_bits, first_time, last_time)
+    }
+
+    // Difficulty retarget at the end of a 2016-block period. `bits` is the last block's,
+    // or the first block's under BIP94.
+    pub fn next_work_required_for(params: &ConsensusParams, bits: u32, first_time: u32, last_time: u32) -> u32 {
+        if params.no_retargeting {
+            return bits;
+        }
+        let actual_timespan = (last_time as i64 - first_time as i64)
+            .clamp(TARGET_TIMESPAN as i64 / 4, TARGET_TIMESPAN as i64 * 4) as u64;
+
+        // Four spare high bytes keep target * actual_timespan from overflowing
+        let mut wide = [0u8; 36];
+        wide[4..].copy_from_slice(&Self::expand_target(bits).target);
+
+        // target *= actual_timespan
+        let mut carry = 0u64;
+        for byte in wide.iter_mut().rev() {
+            let value = *byte as u64 * actual_timespan + carry;
+            *byte = value as u8;
+            carry = value >> 8;
+        }
+
+        // target /= TARGET_TIMESPAN
+        let mut remainder = 0u64;
+        for byte in wide.iter_mut() {
+            let value = (remainder << 8) | *byte as u64;
+            *byte = (value / TARGET_TIMESPAN) as u8;
+            remainder = value % TARGET_TIMESPAN;
+        }
+
+        let pow_limit = Self::expand_target(params.pow_limit_bits).target;
+        let mut target: [u8; 32] = wide[4..].try_into().unwrap();
+        if wide[..4].iter().any(|b| *b != 0) || target > pow_limit {
+            target = pow_limit;
+        }
+        Self::compact_from_target(&target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::{CompactTarget, Network};
+
+    // Big-endian target with `value` in its low bytes
+    fn target(value: u64) -> [u8; 32] {
+        let mut target = [0u8; 32];
+        target[24..].copy_from_slice(&value.to_be_bytes());
+        target
+    }
+
+    #[test]
+    fn expands_genesis_bits() {
+        let expanded = BlockProcessor::expand_target(0x1d00ffff);
+        let mut expected = [0u8; 32];
+        expected[4..6].copy_from_slice(&[0xff, 0xff]);
+        assert_eq!(expanded.target, expected);
+        assert!(!expanded.negative && !expanded.overflow);
+        assert_eq!(BlockProcessor::compact_from_target(&expanded.target), 0x1d00ffff);
+    }
+
+    // Bitcoin Core's SetCompact/GetCompact cases
+    #[test]
+    fn compact_encoding_round_trips() {
+        for bits in [0, 0x00123456, 0x01003456, 0x02000056, 0x03000000, 0x04000000, 0x00923456, 0x01803456, 0x02800056, 0x03800000, 0x04800000] {
+            let expanded = BlockProcessor::expand_target(bits);
+            assert_eq!(expanded.target, [0u8; 32], "{:08x}", bits);
+            assert!(!expanded.negative && !expanded.overflow, "{:08x}", bits);
+        }
+        for (bits, value, compact) in [
+            (0x01123456, 0x12, 0x01120000),
+            (0x02123456, 0x1234, 0x02123400),
+            (0x03123456, 0x123456, 0x03123456),
+            (0x04123456, 0x12345600, 0x04123456),
+            (0x05009234, 0x92340000, 0x05009234),
+        ] {
+            let expanded = BlockProcessor::expand_target(bits);
+            assert_eq!(expanded.target, target(value), "{:08x}", bits);
+            assert_eq!(BlockProcessor::compact_from_target(&expanded.target), compact);
+        }
+        let mut high = [0u8; 32];
+        high[..3].copy_from_slice(&[0x12, 0x34, 0x56]);
+        assert_eq!(BlockProcessor::expand_target(0x20123456).target, high);
+        assert_eq!(BlockProcessor::compact_from_target(&high), 0x20123456);
+    }
+
+    #[test]
+    fn flags_negative_and_overflowing_bits() {
+        let negative = BlockProcessor::expand_target(0x04923456);
+        assert!(negative.negative && !negative.overflow);
+        assert_eq!(negative.target, target(0x12345600));
+        let negative = BlockProcessor::expand_target(0x01fedcba);
+        assert!(negative.negative);
+        assert_eq!(negative.target, target(0x7e));
+
+        assert!(BlockProcessor::expand_target(0xff123456).overflow);
+        // One byte past 256 bits overflows only when the mantissa reaches it
+        assert!(!BlockProcessor::expand_target(0x21001234).overflow);
+        assert!(BlockProcessor::expand_target(0x21010000).overflow);
+        assert!(BlockProcessor::block_work(0xff123456) == Work::from_be_bytes([0u8; 32]));
+        assert!(BlockProcessor::block_work(0x04923456) == Work::from_be_bytes([0u8; 32]));
+    }
+
+    #[test]
+    fn validates_proof_of_work() {
+        let mut header = genesis_block(Network::Bitcoin).header;
+        assert!(BlockProcessor::validate_pow(&header).valid);
+        header.nonce += 1;
+        assert!(!BlockProcessor::validate_pow(&header).valid);
+
+        // A hash meeting an easy target fails once the target is negative, overflows or is zero
+        header.bits = CompactTarget::from_consensus(0x207fffff);
+        assert!(BlockProcessor::grind_nonce(&mut header));
+        assert!(BlockProcessor::validate_pow(&header).valid);
+        for bits in [0x20ffffff, 0xff123456, 0] {
+            header.bits = CompactTarget::from_consensus(bits);
+            assert!(!BlockProcessor::validate_pow(&header).valid, "{:08x}", bits);
+        }
+    }
+
+    // Bitcoin Core's pow_tests: timestamps of the first and last blocks of real mainnet periods
+    #[test]
+    fn retargets_like_mainnet() {
+        // Heights 30240..32255, retargeting at 32256
+        assert_eq!(BlockProcessor::next_work_required(0x1d00ffff, 1261130161, 1262152739), 0x1d00d86a);
+        // Heights 0..2015: slower than two weeks, but already at the pow limit
+        assert_eq!(BlockProcessor::next_work_required(0x1d00ffff, 1231006505, 1233061996), 0x1d00ffff);
+    }
+
+    #[test]
+    fn clamps_retarget_to_a_factor_of_four() {
+        // Heights 66528..68543 took under half a week: the target shrinks only fourfold
+        assert_eq!(BlockProcessor::next_work_required(0x1c05a3f4, 1279008237, 1279297671), 0x1c0168fd);
+        // Heights 46368..48383 took over eight weeks: the target grows only fourfold
+        assert_eq!(BlockProcessor::next_work_required(0x1c387f6f, 1263163443, 1269211443), 0x1d00e1fd);
+        // Any further out gives the same
+        assert_eq!(BlockProcessor::next_work_required(0x1c05a3f4, 1279008237, 1279008237), 0x1c0168fd);
+        assert_eq!(BlockProcessor::next_work_required(0x1c387f6f, 1263163443, u32::MAX), 0x1d00e1fd);
+    }
+}
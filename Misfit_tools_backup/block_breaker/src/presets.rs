@@ -0,0 +1,64 @@
+use bitcoin::blockdata::block::Block;
+
+use crate::processor::{ProcessingConfig, TransactionMutation};
+
+// Mutation presets that keep the header valid (re-mined, consistent merkle root) and break
+// exactly one deeper rule, for testing the order in which nodes run their checks
+#[derive(Debug, Clone, PartialEq)]
+pub enum CorruptionPreset {
+    DuplicateTxid,        // last transaction repeated; with an odd count the merkle root is unchanged (CVE-2012-2459)
+    BadCoinbaseHeight,    // BIP34 height in the coinbase is off by one
+    InflatedCoinbase,     // coinbase claims one satoshi more than subsidy plus fees
+    BadWitnessCommitment, // witness data no longer matches the coinbase commitment
+}
+
+impl CorruptionPreset {
+    pub const ALL: [CorruptionPreset; 4] = [
+        CorruptionPreset::DuplicateTxid,
+        CorruptionPreset::BadCoinbaseHeight,
+        CorruptionPreset::InflatedCoinbase,
+        CorruptionPreset::BadWitnessCommitment,
+    ];
+
+    // Rule the preset violates, as Bitcoin Core reports it
+    pub fn rule(&self) -> &'static str {
+        match self {
+            CorruptionPreset::DuplicateTxid => "bad-txns-duplicate",
+            CorruptionPreset::BadCoinbaseHeight => "bad-cb-height",
+            CorruptionPreset::InflatedCoinbase => "bad-cb-amount",
+            CorruptionPreset::BadWitnessCommitment => "bad-witness-merkle-match",
+        }
+    }
+
+    // Processing configuration producing the preset for a block at `height`
+    pub fn config(&self, block: &Block, height: u32) -> ProcessingConfig {
+        let base = ProcessingConfig {
+            fields_to_modify: vec![],
+            remine: true,
+            remine_roll_timestamp: true,
+            ..Default::default()
+        };
+        let last = block.txdata.len().saturating_sub(1);
+        match self {
+            CorruptionPreset::DuplicateTxid => ProcessingConfig {
+                transaction_mutations: vec![TransactionMutation::Duplicate(last)],
+                // An odd count keeps the original root; an even one needs it recomputed
+                fix_merkle_root: true,
+                ..base
+            },
+            CorruptionPreset::BadCoinbaseHeight => ProcessingConfig {
+                coinbase_height: Some(height.wrapping_add(1)),
+                ..base
+            },
+            CorruptionPreset::InflatedCoinbase => ProcessingConfig {
+                inflate_coinbase: true,
+                ..base
+            },
+            CorruptionPreset::BadWitnessCommitment => ProcessingConfig {
+                transaction_mutations: vec![TransactionMutation::CorruptWitness(last.max(1))],
+                fix_merkle_root: true,
+                ..base
+            },
+        }
+    }
+}
@@ -0,0 +1,639 @@
+use bitcoin::consensus::{encode, Decodable};
+use bitcoin::{
+    blockdata::{
+        block::{Block, Header, Version},
+        transaction::Transaction,
+        witness::Witness,
+    },
+    hash_types::{BlockHash, TxMerkleNode},
+    hashes::Hash,
+    pow::CompactTarget,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::cell::RefCell;
+
+use crate::chain::{HeaderChain, TimestampAttack};
+use crate::coinbase::{MAX_COINBASE_SCRIPT_SIG, MIN_COINBASE_SCRIPT_SIG};
+use crate::grind::{GrindStats, NONCE_SPACE};
+use crate::report::{BlockConsistency, MutationReport};
+use crate::versionbits::VersionBitMutation;
+
+// Enum to specify which fields to modify
+#[derive(Debug, Clone)]
+#[derive(PartialEq)]
+pub enum BlockField {
+    Version,
+    PrevBlockHash,
+    MerkleRoot,
+    Timestamp,
+    Bits,
+    Nonce,
+    All,
+}
+
+// Transaction-level mutations applied to block.txdata
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransactionMutation {
+    Drop(usize),           // remove the transaction at index
+    Duplicate(usize),      // insert a copy right after the transaction at index
+    CorruptWitness(usize), // flip the first witness byte (or add a bogus witness item)
+    Swap(usize, usize),    // exchange the positions of two transactions
+}
+
+// Configuration for block processing
+#[derive(Debug, Clone)]
+pub struct ProcessingConfig {
+    pub fields_to_modify: Vec<BlockField>,
+    pub version_override: Option<i32>,
+    pub timestamp_offset: Option<i64>, // seconds to add/subtract
+    pub randomize_hashes: bool,
+    pub fix_merkle_root: bool, // recompute merkle root from txdata after mutation
+    pub remine: bool,                 // grind the nonce so the mutated block meets its target
+    pub remine_roll_timestamp: bool,  // bump the timestamp when the nonce space is exhausted
+    pub remine_roll_extranonce: bool, // bump a coinbase extranonce when the nonce space is exhausted
+    pub transaction_mutations: Vec<TransactionMutation>,
+    pub seed: Option<u64>, // seed for reproducible random hashes; None draws from the OS
+    pub coinbase_extranonce: Option<Vec<u8>>, // extranonce pushed after the coinbase height
+    pub fix_witness_commitment: bool, // recompute the coinbase witness commitment after mutation
+    pub version_bit_mutations: Vec<VersionBitMutation>, // applied after the version field
+    pub timestamp_attack: Option<TimestampAttack>, // takes precedence over timestamp_offset
+    pub previous_headers: Vec<Header>, // up to the last 11 headers before the block, for MTP
+    pub inflate_coinbase: bool, // add 1 satoshi to the first coinbase output
+    pub coinbase_height: Option<u32>, // rewrite the BIP34 height in the coinbase scriptSig
+    pub remine_threads: usize, // threads used to grind the nonce; 0 uses every core
+    pub remine_max_hashes: u64, // hashes re-mining may try across all rolls before giving up
+    pub corrupt_signet_signature: bool, // flip a bit in the first BIP325 solution signature
+}
+
+impl Default for ProcessingConfig {
+    fn default() -> Self {
+        ProcessingConfig {
+            fields_to_modify: vec![BlockField::All],
+            version_override: None,
+            timestamp_offset: None,
+            randomize_hashes: true,
+            fix_merkle_root: false,
+            remine: false,
+            remine_roll_timestamp: false,
+            remine_roll_extranonce: false,
+            transaction_mutations: vec![],
+            seed: None,
+            coinbase_extranonce: None,
+            fix_witness_commitment: false,
+            version_bit_mutations: vec![],
+            timestamp_attack: None,
+            previous_headers: vec![],
+            inflate_coinbase: false,
+            coinbase_height: None,
+            remine_threads: 1,
+            remine_max_hashes: 16 * NONCE_SPACE,
+            corrupt_signet_signature: false,
+        }
+    }
+}
+
+// Block processing implementation
+pub struct BlockProcessor {
+    pub(crate) config: ProcessingConfig,
+    pub(crate) rng: RefCell<StdRng>,
+}
+
+impl BlockProcessor {
+    pub fn new(config: ProcessingConfig) -> Self {
+        let rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_os_rng(),
+        };
+        Self { config, rng: RefCell::new(rng) }
+    }
+
+    pub fn with_default_config() -> Self {
+        Self::new(ProcessingConfig::default())
+    }
+
+    // Process the version of the block
+    fn process_version(&self, version: i32, report: &mut MutationReport) -> i32 {
+        if let Some(override_version) = self.config.version_override {
+            report.record("version", version, override_version, "version override");
+            override_version
+        } else {
+            // Default behavior: set version to maximum valid value
+            let modified_version = 0x3FFFFFFF;
+            report.record("version", version, modified_version, "maximum version");
+            modified_version
+        }
+    }
+
+    // Process the previous block hash
+    fn process_prev_block_hash(&self, hash: &BlockHash, report: &mut MutationReport) -> BlockHash {
+        if self.config.randomize_hashes {
+            let random_hash = self.generate_random_block_hash();
+            report.record("prev_blockhash", hash, random_hash, "random hash");
+            random_hash
+        } else {
+            // Zero out the hash
+            let zero_hash = BlockHash::all_zeros();
+            report.record("prev_blockhash", hash, zero_hash, "zeroed hash");
+            zero_hash
+        }
+    }
+
+    // Process the merkle root
+    fn process_merkle_root(&self, root: &TxMerkleNode, report: &mut MutationReport) -> TxMerkleNode {
+        if self.config.randomize_hashes {
+            let random_merkle_root = self.generate_random_merkle_root();
+            report.record("merkle_root", root, random_merkle_root, "random hash");
+            random_merkle_root
+        } else {
+            // Zero out the merkle root
+            let zero_root = TxMerkleNode::all_zeros();
+            report.record("merkle_root", root, zero_root, "zeroed hash");
+            zero_root
+        }
+    }
+
+    // Process the timestamp
+    fn process_timestamp(&self, timestamp: u32, report: &mut MutationReport) -> u32 {
+        let current_time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as u32;
+
+        if let Some(attack) = &self.config.timestamp_attack {
+            return match HeaderChain::timestamp_for_attack(attack, &self.config.previous_headers, current_time) {
+                Some(modified_timestamp) => {
+                    report.record("time", timestamp, modified_timestamp, &format!("{:?}", attack));
+                    modified_timestamp
+                }
+                None => {
+                    report.record("time", timestamp, timestamp, &format!("skipped {:?}: cannot be placed after the previous headers", attack));
+                    timestamp
+                }
+            };
+        }
+
+        if let Some(offset) = self.config.timestamp_offset {
+            // Apply custom offset
+            let modified_timestamp = (timestamp as i64 + offset).max(0) as u32;
+            report.record("time", timestamp, modified_timestamp, "timestamp offset");
+            modified_timestamp
+        } else {
+            // Default: add one year (31,536,000 seconds)
+            let modified_timestamp = current_time.saturating_add(31_536_000);
+            report.record("time", timestamp, modified_timestamp, "one year in the future");
+            modified_timestamp
+        }
+    }
+
+    // Process the bits (difficulty target)
+    fn process_bits(&self, bits: u32, report: &mut MutationReport) -> u32 {
+        // XOR with mask to modify difficulty
+        let modified_bits = bits ^ 0x00FFFFFF;
+        report.record("bits", format!("0x{:08x}", bits), format!("0x{:08x}", modified_bits), "mantissa xor 0x00ffffff");
+        modified_bits
+    }
+
+    // Process the nonce
+    fn process_nonce(&self, nonce: u32, report: &mut MutationReport) -> u32 {
+        // Bitwise NOT to invert all bits
+        let modified_nonce = !nonce;
+        report.record("nonce", nonce, modified_nonce, "bitwise not");
+        modified_nonce
+    }
+
+    // Check if a specific field should be processed
+    fn should_process_field(&self, field: &BlockField) -> bool {
+        self.config.fields_to_modify.contains(&BlockField::All) ||
+        self.config.fields_to_modify.contains(field)
+    }
+
+    // Process the entire block header based on configuration
+    pub fn process_block_header(&self, header: &Header) -> (Header, MutationReport) {
+        let mut modified_header = *header;
+        let mut report = MutationReport::default();
+
+        if self.should_process_field(&BlockField::Version) {
+            let new_version = self.process_version(header.version.to_consensus(), &mut report);
+            modified_header.version = Version::from_consensus(new_version);
+        }
+
+        self.process_version_bits(&mut modified_header, &mut report);
+
+        if self.should_process_field(&BlockField::PrevBlockHash) {
+            modified_header.prev_blockhash = self.process_prev_block_hash(&header.prev_blockhash, &mut report);
+        }
+
+        if self.should_process_field(&BlockField::MerkleRoot) {
+            modified_header.merkle_root = self.process_merkle_root(&header.merkle_root, &mut report);
+        }
+
+        if self.should_process_field(&BlockField::Timestamp) {
+            modified_header.time = self.process_timestamp(header.time, &mut report);
+        }
+
+        if self.should_process_field(&BlockField::Bits) {
+            let new_bits = self.process_bits(header.bits.to_consensus(), &mut report);
+            modified_header.bits = CompactTarget::from_consensus(new_bits);
+        }
+
+        if self.should_process_field(&BlockField::Nonce) {
+            modified_header.nonce = self.process_nonce(header.nonce, &mut report);
+        }
+        
+        (modified_header, report)
+    }
+
+    // Helper method to generate a random block hash
+    fn generate_random_block_hash(&self) -> BlockHash {
+        let random_bytes: [u8; 32] = self.rng.borrow_mut().random();
+        BlockHash::from_slice(&random_bytes).expect("Failed to create BlockHash from random bytes")
+    }
+
+    // Helper method to generate a random merkle root
+    fn generate_random_merkle_root(&self) -> TxMerkleNode {
+        let random_bytes: [u8; 32] = self.rng.borrow_mut().random();
+        TxMerkleNode::from_slice(&random_bytes).expect("Failed to create TxMerkleNode from random bytes")
+    }
+
+    // Process an entire block
+    pub fn process_block(&self, block: &Block) -> (Block, MutationReport) {
+        let (modified_header, mut report) = self.process_block_header(&block.header);
+        
+        let mut modified_block = Block {
+            header: modified_header,
+            txdata: block.txdata.clone(),
+        };
+
+        for mutation in &self.config.transaction_mutations {
+            Self::apply_transaction_mutation(&mut modified_block.txdata, mutation, &mut report);
+        }
+
+        if let Some(extranonce) = &self.config.coinbase_extranonce {
+            let old_root = modified_block.header.merkle_root;
+            match Self::inject_coinbase_extranonce(&mut modified_block, extranonce) {
+                Ok(injection) => {
+                    report.record("txdata[0].script_sig", injection.old_script_sig, injection.new_script_sig, "coinbase extranonce injected");
+                    report.record("txdata[0].txid", injection.old_txid, injection.new_txid, "coinbase extranonce injected");
+                    report.record("merkle_root", old_root, modified_block.header.merkle_root, "recomputed after coinbase change");
+                }
+                Err(reason) => report.record("txdata[0].script_sig", hex::encode(extranonce), "", &format!("skipped: {}", reason)),
+            }
+        }
+
+        if let Some(height) = self.config.coinbase_height {
+            let old_root = modified_block.header.merkle_root;
+            match Self::set_coinbase_height(&mut modified_block, height) {
+                Ok((old_script_sig, new_script_sig)) => {
+                    report.record("txdata[0].script_sig", old_script_sig, new_script_sig, &format!("coinbase height set to {}", height));
+                    report.record("merkle_root", old_root, modified_block.header.merkle_root, "recomputed after coinbase change");
+                }
+                Err(reason) => report.record("txdata[0].script_sig", "", "", &format!("skipped: {}", reason)),
+            }
+        }
+
+        if self.config.inflate_coinbase {
+            let old_root = modified_block.header.merkle_root;
+            match Self::inflate_coinbase(&mut modified_block, 1) {
+                Ok((old_value, new_value)) => {
+                    report.record("txdata[0].output[0].value", old_value, new_value, "coinbase inflated by 1 satoshi");
+                    report.record("merkle_root", old_root, modified_block.header.merkle_root, "recomputed after coinbase change");
+                }
+                Err(reason) => report.record("txdata[0].output[0].value", "", "", &format!("skipped: {}", reason)),
+            }
+        }
+
+        if self.config.fix_witness_commitment {
+            let old_root = modified_block.header.merkle_root;
+            match Self::fix_witness_commitment(&mut modified_block) {
+                Ok((old, new)) => {
+                    let old = old.map(|c| c.to_string()).unwrap_or_default();
+                    report.record("witness_commitment", old, new, "recomputed from txdata");
+                    report.record("merkle_root", old_root, modified_block.header.merkle_root, "recomputed after coinbase change");
+                }
+                Err(reason) => report.record("witness_commitment", "", "", &format!("skipped: {}", reason)),
+            }
+        }
+
+        if self.config.corrupt_signet_signature {
+            let old_root = modified_block.header.merkle_root;
+            match Self::corrupt_signet_signature(&mut modified_block) {
+                Some(location) => {
+                    report.record("signet_solution", "", location, "signet signature corrupted");
+                    report.record("merkle_root", old_root, modified_block.header.merkle_root, "recomputed after coinbase change");
+                }
+                None => report.record("signet_solution", "", "", "skipped: no signet signature in the witness commitment"),
+            }
+        }
+
+        if self.config.fix_merkle_root {
+            let old_root = modified_block.header.merkle_root;
+            if Self::fix_merkle_root(&mut modified_block) {
+                report.record("merkle_root", old_root, modified_block.header.merkle_root, "recomputed from txdata");
+            }
+        }
+
+        if self.config.remine {
+            let before = modified_block.header;
+            let (solved, stats) = self.remine_block_with_stats(&mut modified_block);
+            if before.merkle_root != modified_block.header.merkle_root {
+                report.record("merkle_root", before.merkle_root, modified_block.header.merkle_root, "coinbase extranonce rolled");
+            }
+            if before.time != modified_block.header.time {
+                report.record("time", before.time, modified_block.header.time, "timestamp rolled while re-mining");
+            }
+            report.record("nonce", before.nonce, modified_block.header.nonce, "re-mined to meet target");
+            report.remined = Some(solved);
+            report.remine_stats = Some(stats);
+        }
+
+        report.consistency = Some(Self::check_consistency(&modified_block));
+        (modified_block, report)
+    }
+
+    // Search the whole nonce space for a hash that meets the header's own target
+    pub fn grind_nonce(header: &mut Header) -> bool {
+        let target = header.target();
+        for nonce in 0..=u32::MAX {
+            header.nonce = nonce;
+            if target.is_met_by(header.block_hash()) {
+                return true;
+            }
+        }
+        false
+    }
+
+    // Write a 4-byte extranonce into the coinbase scriptSig and recompute the merkle root. The
+    // first roll appends a push and returns where its bytes start; later rolls overwrite them
+    // there. The block is left as it was when there is no coinbase or the scriptSig would fall
+    // outside the length consensus allows.
+    fn set_coinbase_extranonce(block: &mut Block, extranonce: u32, offset: Option<usize>) -> Result<usize, String> {
+        let Some(coinbase) = block.txdata.first_mut().filter(|tx| tx.is_coin_base()) else {
+            return Err("Block has no coinbase transaction".to_string());
+        };
+
+        let mut script_sig = coinbase.input[0].script_sig.to_bytes();
+        let offset = match offset {
+            Some(offset) => offset,
+            None => {
+                script_sig.extend([0x04, 0, 0, 0, 0]); // push 4 bytes
+                script_sig.len() - 4
+            }
+        };
+        if !(MIN_COINBASE_SCRIPT_SIG..=MAX_COINBASE_SCRIPT_SIG).contains(&script_sig.len()) {
+            return Err(format!(
+                "Coinbase scriptSig would be {} bytes, outside the {}-{} bytes allowed",
+                script_sig.len(),
+                MIN_COINBASE_SCRIPT_SIG,
+                MAX_COINBASE_SCRIPT_SIG
+            ));
+        }
+        let Some(bytes) = script_sig.get_mut(offset..offset + 4) else {
+            return Err(format!("No extranonce at offset {} of the coinbase scriptSig", offset));
+        };
+        bytes.copy_from_slice(&extranonce.to_le_bytes());
+        coinbase.input[0].script_sig = script_sig.into();
+
+        if let Some(root) = block.compute_merkle_root() {
+            block.header.merkle_root = root;
+        }
+        Ok(offset)
+    }
+
+    // Re-mine the block after mutation so it still satisfies its target
+    pub fn remine_block(&self, block: &mut Block) -> bool {
+        self.remine_block_with_stats(block).0
+    }
+
+    // Re-mine the block with the configured number of threads, returning the work done. Gives
+    // up once remine_max_hashes have been tried, or when nothing is left to roll.
+    pub fn remine_block_with_stats(&self, block: &mut Block) -> (bool, GrindStats) {
+        let mut extranonce: u32 = 0;
+        let mut extranonce_offset = None;
+        let mut stats = GrindStats::default();
+        loop {
+            let budget = self.config.remine_max_hashes.saturating_sub(stats.hashes);
+            let (solved, grind) = Self::grind_nonce_parallel(&mut block.header, self.config.remine_threads, budget);
+            stats.add(&grind);
+            if solved {
+                return (true, stats);
+            }
+
+            let gave_up = if stats.hashes >= self.config.remine_max_hashes {
+                format!("tried the maximum of {} hashes", self.config.remine_max_hashes)
+            } else if self.config.remine_roll_timestamp && block.header.time < u32::MAX {
+                block.header.time += 1;
+                continue;
+            } else if self.config.remine_roll_extranonce && extranonce < u32::MAX {
+                match Self::set_coinbase_extranonce(block, extranonce + 1, extranonce_offset) {
+                    Ok(offset) => {
+                        extranonce_offset = Some(offset);
+                        extranonce += 1;
+                        continue;
+                    }
+                    Err(reason) => format!("could not roll the extranonce: {}", reason),
+                }
+            } else {
+                "nonce space exhausted with nothing left to roll".to_string()
+            };
+            stats.gave_up = Some(gave_up);
+            return (false, stats);
+        }
+    }
+
+    // Apply a single transaction-level mutation to txdata
+    fn apply_transaction_mutation(txdata: &mut Vec<Transaction>, mutation: &TransactionMutation, report: &mut MutationReport) {
+        match *mutation {
+            TransactionMutation::Drop(index) if index < txdata.len() => {
+                let tx = txdata.remove(index);
+                report.record(&format!("txdata[{}]", index), tx.txid(), "", "transaction dropped");
+            }
+            TransactionMutation::Duplicate(index) if index < txdata.len() => {
+                let tx = txdata[index].clone();
+                report.record(&format!("txdata[{}]", index + 1), "", tx.txid(), "transaction duplicated");
+                txdata.insert(index + 1, tx);
+            }
+            TransactionMutation::CorruptWitness(index) if txdata.get(index).is_some_and(|tx| !tx.input.is_empty()) => {
+                let old_wtxid = txdata[index].wtxid();
+                let input = &mut txdata[index].input[0];
+                let mut items = input.witness.to_vec();
+                match items.first_mut().and_then(|item| item.first_mut()) {
+                    Some(byte) => *byte ^= 0xff,
+                    None => items.push(vec![0xde, 0xad, 0xbe, 0xef]),
+                }
+                input.witness = Witness::from_slice(&items);
+                report.record(&format!("txdata[{}].wtxid", index), old_wtxid, txdata[index].wtxid(), "witness corrupted");
+            }
+            TransactionMutation::Swap(a, b) if a < txdata.len() && b < txdata.len() => {
+                txdata.swap(a, b);
+                report.record(&format!("txdata[{}]", a), txdata[b].txid(), txdata[a].txid(), "transactions swapped");
+                report.record(&format!("txdata[{}]", b), txdata[a].txid(), txdata[b].txid(), "transactions swapped");
+            }
+            _ => report.record("txdata", format!("{:?}", mutation), "", "skipped: index out of range"),
+        }
+    }
+
+    // Check merkle root and witness commitment against txdata
+    pub fn check_consistency(block: &Block) -> BlockConsistency {
+        BlockConsistency {
+            merkle_root_matches: Self::check_merkle_root(block).matches,
+            witness_commitment_valid: block.check_witness_commitment(),
+        }
+    }
+
+    // Utility method to decode block header from hex string
+    pub fn decode_header_from_hex(hex_string: &str) -> Result<Header, Box<dyn std::error::Error>> {
+        let bytes = hex::decode(hex_string)?;
+        if bytes.len() != 80 {
+            return Err(format!("Invalid header length: expected 80 bytes, got {}", bytes.len()).into());
+        }
+        let header = Header::consensus_decode(&mut &bytes[..])?;
+        Ok(header)
+    }
+
+    // Utility method to decode block from hex string
+    pub fn decode_block_from_hex(hex_string: &str) -> Result<Block, Box<dyn std::error::Error>> {
+        let bytes = hex::decode(hex_string)?;
+        let block = Block::consensus_decode(&mut &bytes[..])?;
+        Ok(block)
+    }
+
+    // Utility method to encode block header to hex string
+    pub fn encode_header_to_hex(header: &Header) -> String {
+        encode::serialize_hex(header)
+    }
+
+    // Utility method to encode block to hex string
+    pub fn encode_block_to_hex(block: &Block) -> String {
+        encode::serialize_hex(block)
+    }
+
+    // Write a block to a file, as raw bytes for .bin/.dat paths and as hex otherwise
+    pub fn write_block_to_file(block: &Block, path: &str) -> std::io::Result<()> {
+        if path.ends_with(".bin") || path.ends_with(".dat") {
+            std::fs::write(path, encode::serialize(block))
+        } else {
+            std::fs::write(path, Self::encode_block_to_hex(block) + "\n")
+        }
+    }
+
+    // Write a header to a file, as raw bytes for .bin/.dat paths and as hex otherwise
+    pub fn write_header_to_file(header: &Header, path: &str) -> std::io::Result<()> {
+        if path.ends_with(".bin") || path.ends_with(".dat") {
+            std::fs::write(path, encode::serialize(header))
+        } else {
+            std::fs::write(path, Self::encode_header_to_hex(header) + "\n")
+        }
+    }
+
+    // Create a minimal block from a header (for testing purposes)
+    pub fn create_minimal_block_from_header(header: Header) -> Block {
+        Block {
+            header,
+            txdata: vec![], // Empty transaction list
+        }
+    }
+
+    // Write a mutation report as JSON
+    pub fn write_report_to_file(report: &MutationReport, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::write(path, report.to_json()? + "\n")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::Network;
+
+    fn config(fields: Vec<BlockField>) -> ProcessingConfig {
+        ProcessingConfig { fields_to_modify: fields, seed: Some(7), ..Default::default() }
+    }
+
+    fn fields(report: &MutationReport) -> Vec<&str> {
+        report.mutations.iter().map(|mutation| mutation.field.as_str()).collect()
+    }
+
+    #[test]
+    fn header_mutation_touches_only_selected_fields() {
+        let header = genesis_block(Network::Bitcoin).header;
+        let (mutated, report) = BlockProcessor::new(config(vec![BlockField::PrevBlockHash, BlockField::Nonce])).process_block_header(&header);
+        assert_ne!(mutated.prev_blockhash, header.prev_blockhash);
+        assert_ne!(mutated.nonce, header.nonce);
+        assert_eq!((mutated.version, mutated.merkle_root, mutated.time, mutated.bits), (header.version, header.merkle_root, header.time, header.bits));
+        assert_eq!(fields(&report), ["prev_blockhash", "nonce"]);
+        assert_eq!(report.mutations[0].old_value, header.prev_blockhash.to_string());
+        assert_eq!(report.mutations[0].new_value, mutated.prev_blockhash.to_string());
+    }
+
+    #[test]
+    fn seeded_mutation_is_reproducible() {
+        let header = genesis_block(Network::Bitcoin).header;
+        let first = BlockProcessor::new(config(vec![BlockField::All])).process_block_header(&header).0;
+        let second = BlockProcessor::new(config(vec![BlockField::All])).process_block_header(&header).0;
+        assert_eq!(first, second);
+        let other = BlockProcessor::new(ProcessingConfig { seed: Some(8), ..config(vec![BlockField::All]) }).process_block_header(&header).0;
+        assert_ne!(first, other);
+    }
+
+    #[test]
+    fn transaction_mutations_break_and_fix_the_merkle_root() {
+        let block = genesis_block(Network::Bitcoin);
+        let mutations = vec![TransactionMutation::Duplicate(0), TransactionMutation::Swap(0, 1), TransactionMutation::Drop(5)];
+        let processor = BlockProcessor::new(ProcessingConfig { transaction_mutations: mutations.clone(), ..config(vec![]) });
+        let (mutated, report) = processor.process_block(&block);
+        assert_eq!(mutated.header, block.header);
+        assert_eq!(mutated.txdata.len(), 2);
+        assert_eq!(fields(&report), ["txdata[1]", "txdata[0]", "txdata[1]", "txdata"]);
+        assert!(report.mutations[3].reason.starts_with("skipped"));
+        assert!(!report.consistency.unwrap().merkle_root_matches);
+
+        let processor = BlockProcessor::new(ProcessingConfig { transaction_mutations: mutations, fix_merkle_root: true, ..config(vec![]) });
+        let (fixed, report) = processor.process_block(&block);
+        assert_eq!(fixed.header.merkle_root, fixed.compute_merkle_root().unwrap());
+        assert!(report.consistency.unwrap().merkle_root_matches);
+    }
+
+    #[test]
+    fn extranonce_roll_overwrites_its_push() {
+        let mut block = genesis_block(Network::Bitcoin);
+        let original = block.txdata[0].input[0].script_sig.to_bytes();
+        let offset = BlockProcessor::set_coinbase_extranonce(&mut block, 1, None).unwrap();
+        assert_eq!(offset, original.len() + 1);
+        let root = block.header.merkle_root;
+        assert_eq!(BlockProcessor::set_coinbase_extranonce(&mut block, 0x0102_0304, Some(offset)), Ok(offset));
+        let script_sig = block.txdata[0].input[0].script_sig.to_bytes();
+        assert_eq!(script_sig, [&original[..], &[0x04, 0x04, 0x03, 0x02, 0x01]].concat());
+        assert_ne!(block.header.merkle_root, root);
+        assert_eq!(Some(block.header.merkle_root), block.compute_merkle_root());
+    }
+
+    #[test]
+    fn extranonce_roll_keeps_the_coinbase_valid() {
+        let mut block = genesis_block(Network::Bitcoin);
+        block.txdata[0].input[0].script_sig = vec![0x51; MAX_COINBASE_SCRIPT_SIG - 4].into();
+        let before = block.clone();
+        assert!(BlockProcessor::set_coinbase_extranonce(&mut block, 1, None).is_err());
+        assert_eq!(block, before);
+        assert!(BlockProcessor::set_coinbase_extranonce(&mut block, 1, Some(MAX_COINBASE_SCRIPT_SIG - 5)).is_err());
+
+        block.txdata.clear();
+        assert!(BlockProcessor::set_coinbase_extranonce(&mut block, 1, None).is_err());
+    }
+
+    #[test]
+    fn remining_stops_at_the_hash_budget() {
+        let mut block = genesis_block(Network::Bitcoin);
+        block.header.nonce = 0;
+        let processor = BlockProcessor::new(ProcessingConfig { remine_roll_timestamp: true, remine_max_hashes: 3000, ..config(vec![]) });
+        let (solved, stats) = processor.remine_block_with_stats(&mut block);
+        assert!(!solved);
+        assert_eq!(stats.hashes, 3000);
+        assert!(stats.gave_up.unwrap().contains("maximum"));
+        assert_eq!(block.header.time, genesis_block(Network::Bitcoin).header.time);
+
+        // An easy target is met well within it
+        block.header.bits = CompactTarget::from_consensus(0x207fffff);
+        assert!(processor.remine_block(&mut block));
+    }
+}
@@ -0,0 +1,73 @@
+use serde::Serialize;
+
+use crate::grind::GrindStats;
+
+// A single change applied to a block, with the values before and after
+#[derive(Debug, Clone, Serialize)]
+pub struct Mutation {
+    pub field: String,
+    pub old_value: String,
+    pub new_value: String,
+    pub reason: String,
+}
+
+// Everything process_block_header/process_block changed, in order
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MutationReport {
+    pub mutations: Vec<Mutation>,
+    pub consistency: Option<BlockConsistency>,
+    pub remined: Option<bool>,
+    pub remine_stats: Option<GrindStats>,
+}
+
+impl MutationReport {
+    // Record a change to a field
+    pub fn record(&mut self, field: &str, old_value: impl ToString, new_value: impl ToString, reason: &str) {
+        self.mutations.push(Mutation {
+            field: field.to_string(),
+            old_value: old_value.to_string(),
+            new_value: new_value.to_string(),
+            reason: reason.to_string(),
+        });
+    }
+
+    // Serialize the report as pretty-printed JSON
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+// One row of a batch summary: which block was broken and what changed
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchEntry {
+    pub label: String,
+    pub original_hash: String,
+    pub broken_hash: String,
+    pub fields_changed: Vec<String>, // distinct fields, in the order they were first changed
+    pub report: MutationReport,
+}
+
+impl BatchEntry {
+    pub fn new(label: &str, original_hash: impl ToString, broken_hash: impl ToString, report: MutationReport) -> Self {
+        let mut fields_changed: Vec<String> = Vec::new();
+        for mutation in &report.mutations {
+            if !fields_changed.contains(&mutation.field) {
+                fields_changed.push(mutation.field.clone());
+            }
+        }
+        BatchEntry {
+            label: label.to_string(),
+            original_hash: original_hash.to_string(),
+            broken_hash: broken_hash.to_string(),
+            fields_changed,
+            report,
+        }
+    }
+}
+
+// Merkle root and witness commitment consistency of a block
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockConsistency {
+    pub merkle_root_matches: bool,
+    pub witness_commitment_valid: bool,
+}
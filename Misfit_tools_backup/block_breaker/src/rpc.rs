@@ -0,0 +1,112 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bitcoin::blockdata::block::Block;
+use bitcoin::consensus::encode;
+use bitcoin::hash_types::BlockHash;
+use serde_json::{json, Value};
+use std::str::FromStr;
+
+// Block to fetch from a node, by height or by hash
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockRef {
+    Height(u32),
+    Hash(BlockHash),
+}
+
+impl FromStr for BlockRef {
+    type Err = String;
+
+    // A 64 character hex string is a hash, anything else must be a height
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() == 64 {
+            BlockHash::from_str(s).map(BlockRef::Hash).map_err(|e| format!("Invalid block hash {}: {}", s, e))
+        } else {
+            s.parse().map(BlockRef::Height).map_err(|_| format!("Expected a block height or hash, got {}", s))
+        }
+    }
+}
+
+// Minimal Bitcoin Core client over JSON-RPC, or over the REST interface when `rest` is set
+#[derive(Debug, Clone)]
+pub struct CoreClient {
+    pub url: String,
+    pub auth: Option<String>, // "user:password", sent as HTTP basic auth to the RPC server
+    pub rest: bool,
+}
+
+impl CoreClient {
+    pub fn new(url: &str, user: &str, password: &str) -> Self {
+        CoreClient {
+            url: url.trim_end_matches('/').to_string(),
+            auth: Some(format!("{}:{}", user, password)),
+            rest: false,
+        }
+    }
+
+    // Authenticate with the .cookie file bitcoind writes into its data directory
+    pub fn from_cookie(url: &str, cookie_path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(CoreClient {
+            url: url.trim_end_matches('/').to_string(),
+            auth: Some(std::fs::read_to_string(cookie_path)?.trim().to_string()),
+            rest: false,
+        })
+    }
+
+    // Use the unauthenticated REST interface (bitcoind -rest)
+    pub fn rest(url: &str) -> Self {
+        CoreClient {
+            url: url.trim_end_matches('/').to_string(),
+            auth: None,
+            rest: true,
+        }
+    }
+
+    // Send a JSON-RPC request and return its result
+    pub fn call(&self, method: &str, params: Value) -> Result<Value, Box<dyn std::error::Error>> {
+        let mut request = ureq::post(&self.url);
+        if let Some(auth) = &self.auth {
+            request = request.set("Authorization", &format!("Basic {}", STANDARD.encode(auth)));
+        }
+        let body = json!({ "jsonrpc": "1.0", "id": "block_breaker", "method": method, "params": params });
+        // Bitcoin Core answers RPC errors with an HTTP error status and a JSON body
+        let response: Value = match request.send_json(body) {
+            Ok(response) => response.into_json()?,
+            Err(ureq::Error::Status(_, response)) => response.into_json()?,
+            Err(e) => return Err(e.into()),
+        };
+        match response.get("error") {
+            Some(error) if !error.is_null() => Err(format!("{} failed: {}", method, error).into()),
+            _ => Ok(response.get("result").cloned().unwrap_or(Value::Null)),
+        }
+    }
+
+    // GET a REST endpoint returning hex
+    fn rest_hex(&self, path: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let body = ureq::get(&format!("{}/rest/{}", self.url, path)).call()?.into_string()?;
+        Ok(hex::decode(body.trim())?)
+    }
+
+    // Hash of the block at a height on the node's active chain
+    pub fn get_block_hash(&self, height: u32) -> Result<BlockHash, Box<dyn std::error::Error>> {
+        if self.rest {
+            let bytes = self.rest_hex(&format!("blockhashbyheight/{}.hex", height))?;
+            return Ok(encode::deserialize(&bytes)?);
+        }
+        let hash = self.call("getblockhash", json!([height]))?;
+        Ok(BlockHash::from_str(hash.as_str().ok_or("getblockhash returned no hash")?)?)
+    }
+
+    // Fetch a raw block (`getblock <hash> 0`) by height or hash
+    pub fn get_block(&self, block: &BlockRef) -> Result<Block, Box<dyn std::error::Error>> {
+        let hash = match block {
+            BlockRef::Height(height) => self.get_block_hash(*height)?,
+            BlockRef::Hash(hash) => *hash,
+        };
+        let bytes = if self.rest {
+            self.rest_hex(&format!("block/{}.hex", hash))?
+        } else {
+            let raw = self.call("getblock", json!([hash.to_string(), 0]))?;
+            hex::decode(raw.as_str().ok_or("getblock returned no hex")?)?
+        };
+        Ok(encode::deserialize(&bytes)?)
+    }
+}
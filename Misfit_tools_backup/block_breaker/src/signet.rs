@@ -0,0 +1,449 @@
+use bitcoin::blockdata::{
+    block::Block,
+    locktime::absolute::LockTime,
+    opcodes::all::{OP_CHECKMULTISIG, OP_CHECKSIG, OP_PUSHBYTES_0, OP_PUSHNUM_1, OP_PUSHNUM_16, OP_RETURN},
+    script::{Builder, Instruction, PushBytesBuf, Script, ScriptBuf},
+    transaction::{OutPoint, Sequence, Transaction, TxIn, TxOut},
+    witness::Witness,
+};
+use bitcoin::consensus::{encode, Decodable};
+use bitcoin::hash_types::{TxMerkleNode, Txid};
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::{ecdsa, Message, PublicKey, Secp256k1};
+use bitcoin::sighash::SighashCache;
+
+use crate::processor::BlockProcessor;
+
+// BIP325 marker at the start of the signet solution push in the witness commitment output
+pub const SIGNET_HEADER: [u8; 4] = [0xec, 0xc7, 0xda, 0xa2];
+
+// Challenge of the default public signet, a 1-of-2 bare multisig
+pub const DEFAULT_SIGNET_CHALLENGE: &str = "512103ad5e0edad18cb1f0fc0d28a3d4f1f3e445640337489abb10404f2d8e086be430210359ef5021964fe22d6f8e05b2463c9540ce96883fe3b278760f048f5189f2e6c452ae";
+
+// Block solution: the scriptSig and witness spending the challenge
+#[derive(Debug, Clone)]
+pub struct SignetSolution {
+    pub script_sig: ScriptBuf,
+    pub witness: Witness,
+}
+
+// Outcome of checking the solution's signatures against the challenge
+#[derive(Debug, Clone, PartialEq)]
+pub enum SignetSignatureCheck {
+    Valid,
+    Invalid(String),
+    Unsupported, // challenge is not bare multisig or pay-to-pubkey, signatures not checked
+}
+
+// Result of validating a block's signet solution
+#[derive(Debug, Clone)]
+pub struct SignetCheck {
+    pub solution: Option<SignetSolution>, // None when the commitment carries no signet push
+    pub signet_merkle_root: TxMerkleNode, // merkle root with the solution cleared from the coinbase
+    pub to_spend: Txid,
+    pub to_sign: Txid,
+    pub signature: SignetSignatureCheck,
+}
+
+impl BlockProcessor {
+    // Re-serialize a script, letting `replace` rewrite the data of any push
+    fn rewrite_pushes(script: &Script, mut replace: impl FnMut(&[u8]) -> Option<Vec<u8>>) -> Option<ScriptBuf> {
+        let mut builder = Builder::new();
+        for instruction in script.instructions() {
+            builder = match instruction.ok()? {
+                Instruction::PushBytes(push) => match replace(push.as_bytes()).unwrap_or_else(|| push.as_bytes().to_vec()) {
+                    data if data.is_empty() => builder.push_opcode(OP_PUSHBYTES_0),
+                    data => builder.push_slice(PushBytesBuf::try_from(data).ok()?),
+                },
+                Instruction::Op(opcode) => builder.push_opcode(opcode),
+            };
+        }
+        Some(builder.into_script())
+    }
+
+    // Split a witness commitment script into the script with the solution cleared (the
+    // header push kept, as in Bitcoin Core) and the solution bytes after the header
+    fn split_signet_commitment(script: &Script) -> Option<(ScriptBuf, Vec<u8>)> {
+        let mut solution = None;
+        let cleared = Self::rewrite_pushes(script, |data| {
+            if solution.is_none() && data.len() > SIGNET_HEADER.len() && data.starts_with(&SIGNET_HEADER) {
+                solution = Some(data[SIGNET_HEADER.len()..].to_vec());
+                Some(SIGNET_HEADER.to_vec())
+            } else {
+                None
+            }
+        })?;
+        Some((cleared, solution?))
+    }
+
+    // Decode a solution: serialized scriptSig followed by the witness stack, nothing after
+    pub fn parse_signet_solution(bytes: &[u8]) -> Result<SignetSolution, String> {
+        let mut reader = bytes;
+        let script_sig = ScriptBuf::consensus_decode(&mut reader).map_err(|e| format!("Invalid solution scriptSig: {}", e))?;
+        let witness = Witness::consensus_decode(&mut reader).map_err(|e| format!("Invalid solution witness: {}", e))?;
+        if !reader.is_empty() {
+            return Err(format!("{} trailing bytes after the signet solution", reader.len()));
+        }
+        Ok(SignetSolution { script_sig, witness })
+    }
+
+    // Solution embedded in the block, with the coinbase it was cleared from
+    fn extract_signet_solution(block: &Block) -> Result<(Transaction, Option<SignetSolution>), String> {
+        let coinbase = block.txdata.first().filter(|tx| tx.is_coin_base()).ok_or("Block has no coinbase transaction")?;
+        let index = Self::witness_commitment_output(coinbase).ok_or("Signet blocks need a witness commitment")?;
+        let mut cleared = coinbase.clone();
+        match Self::split_signet_commitment(&coinbase.output[index].script_pubkey) {
+            Some((script, bytes)) => {
+                cleared.output[index].script_pubkey = script;
+                Ok((cleared, Some(Self::parse_signet_solution(&bytes)?)))
+            }
+            None => Ok((cleared, None)),
+        }
+    }
+
+    // The BIP325 virtual transactions: to_spend commits to the block and pays the challenge,
+    // to_sign spends it with the block's solution. Also returns the signet merkle root.
+    pub fn signet_transactions(block: &Block, challenge: &Script) -> Result<(Transaction, Transaction, TxMerkleNode), String> {
+        let (cleared, solution) = Self::extract_signet_solution(block)?;
+        let txids = std::iter::once(cleared.txid()).chain(block.txdata[1..].iter().map(|tx| tx.txid()));
+        let signet_merkle_root = bitcoin::merkle_tree::calculate_root(txids.map(|txid| txid.to_raw_hash()))
+            .map(TxMerkleNode::from_raw_hash)
+            .ok_or("Block has no transactions")?;
+
+        // nVersion, hashPrevBlock, signet merkle root and nTime
+        let mut block_data = Vec::with_capacity(72);
+        block_data.extend_from_slice(&block.header.version.to_consensus().to_le_bytes());
+        block_data.extend_from_slice(block.header.prev_blockhash.as_byte_array());
+        block_data.extend_from_slice(signet_merkle_root.as_byte_array());
+        block_data.extend_from_slice(&block.header.time.to_le_bytes());
+
+        let to_spend = Transaction {
+            version: 0,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: Builder::new()
+                    .push_opcode(OP_PUSHBYTES_0)
+                    .push_slice(PushBytesBuf::try_from(block_data).unwrap())
+                    .into_script(),
+                sequence: Sequence::ZERO,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut { value: 0, script_pubkey: challenge.to_owned() }],
+        };
+        let solution = solution.unwrap_or(SignetSolution { script_sig: ScriptBuf::new(), witness: Witness::new() });
+        let to_sign = Transaction {
+            version: 0,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint { txid: to_spend.txid(), vout: 0 },
+                script_sig: solution.script_sig,
+                sequence: Sequence::ZERO,
+                witness: solution.witness,
+            }],
+            output: vec![TxOut { value: 0, script_pubkey: Builder::new().push_opcode(OP_RETURN).into_script() }],
+        };
+        Ok((to_spend, to_sign, signet_merkle_root))
+    }
+
+    // Threshold and keys of a bare multisig (`OP_m <keys> OP_n OP_CHECKMULTISIG`) or
+    // pay-to-pubkey challenge; multisig solutions carry a leading dummy element
+    fn signet_challenge_keys(challenge: &Script) -> Option<(usize, Vec<PublicKey>, bool)> {
+        let instructions: Vec<Instruction> = challenge.instructions().collect::<Result<_, _>>().ok()?;
+        let key = |instruction: &Instruction| PublicKey::from_slice(instruction.push_bytes()?.as_bytes()).ok();
+        let small_int = |instruction: &Instruction| match instruction {
+            Instruction::Op(op) if (OP_PUSHNUM_1.to_u8()..=OP_PUSHNUM_16.to_u8()).contains(&op.to_u8()) => {
+                Some((op.to_u8() - OP_PUSHNUM_1.to_u8() + 1) as usize)
+            }
+            _ => None,
+        };
+        match instructions.as_slice() {
+            [pubkey, Instruction::Op(OP_CHECKSIG)] => Some((1, vec![key(pubkey)?], false)),
+            [m, keys @ .., n, Instruction::Op(OP_CHECKMULTISIG)] => {
+                let (m, n) = (small_int(m)?, small_int(n)?);
+                let keys: Vec<PublicKey> = keys.iter().map(key).collect::<Option<_>>()?;
+                (keys.len() == n && m <= n).then_some((m, keys, true))
+            }
+            _ => None,
+        }
+    }
+
+    // Check the scriptSig signatures of to_sign against the challenge, matching signatures
+    // to keys in order as OP_CHECKMULTISIG does
+    fn check_signet_signatures(to_sign: &Transaction, challenge: &Script) -> SignetSignatureCheck {
+        let Some((required, keys, multisig)) = Self::signet_challenge_keys(challenge) else {
+            return SignetSignatureCheck::Unsupported;
+        };
+        let Ok(pushes) = to_sign.input[0].script_sig.instructions().collect::<Result<Vec<Instruction>, _>>() else {
+            return SignetSignatureCheck::Invalid("Unparseable solution scriptSig".to_string());
+        };
+        let signatures: Vec<&[u8]> = pushes
+            .iter()
+            .skip(usize::from(multisig))
+            .filter_map(|instruction| instruction.push_bytes().map(|push| push.as_bytes()))
+            .collect();
+        if signatures.len() != required {
+            return SignetSignatureCheck::Invalid(format!("Expected {} signatures, found {}", required, signatures.len()));
+        }
+
+        let secp = Secp256k1::verification_only();
+        let mut keys = keys.iter();
+        for (i, signature) in signatures.iter().enumerate() {
+            let Some((sighash_type, der)) = signature.split_last() else {
+                return SignetSignatureCheck::Invalid(format!("Signature {} is empty", i));
+            };
+            let Ok(mut sig) = ecdsa::Signature::from_der(der) else {
+                return SignetSignatureCheck::Invalid(format!("Signature {} is not valid DER", i));
+            };
+            // Consensus accepts high-S signatures, libsecp256k1 only verifies normalized ones
+            sig.normalize_s();
+            let Ok(sighash) = SighashCache::new(to_sign).legacy_signature_hash(0, challenge, *sighash_type as u32) else {
+                return SignetSignatureCheck::Invalid(format!("Cannot compute the sighash of signature {}", i));
+            };
+            let message = Message::from_slice(sighash.as_byte_array()).unwrap();
+            if !keys.any(|key| secp.verify_ecdsa(&message, &sig, key).is_ok()) {
+                return SignetSignatureCheck::Invalid(format!("Signature {} matches no remaining key", i));
+            }
+        }
+        SignetSignatureCheck::Valid
+    }
+
+    // Parse and validate a block's signet solution against the challenge script
+    pub fn check_signet_block(block: &Block, challenge: &Script) -> Result<SignetCheck, String> {
+        let (_, solution) = Self::extract_signet_solution(block)?;
+        let (to_spend, to_sign, signet_merkle_root) = Self::signet_transactions(block, challenge)?;
+        Ok(SignetCheck {
+            solution,
+            signet_merkle_root,
+            to_spend: to_spend.txid(),
+            to_sign: to_sign.txid(),
+            signature: Self::check_signet_signatures(&to_sign, challenge),
+        })
+    }
+
+    // Flip the last bit of S in a DER signature with trailing sighash byte, which keeps it
+    // valid DER. Returns false when the item is not such a signature.
+    fn corrupt_der_signature(item: &mut [u8]) -> bool {
+        let is_signature = item.len() >= 9 && ecdsa::Signature::from_der(&item[..item.len() - 1]).is_ok();
+        if is_signature {
+            item[item.len() - 2] ^= 0x01;
+        }
+        is_signature
+    }
+
+    // Corrupt the first signature of the signet solution (scriptSig pushes first, then the
+    // witness), write the solution back into the witness commitment output and recompute the
+    // merkle root. The signet merkle root is unaffected, so only the signature check fails.
+    // Returns where the corrupted signature sits.
+    pub fn corrupt_signet_signature(block: &mut Block) -> Option<String> {
+        let coinbase = block.txdata.first_mut()?;
+        let index = Self::witness_commitment_output(coinbase)?;
+        let (_, bytes) = Self::split_signet_commitment(&coinbase.output[index].script_pubkey)?;
+        let mut solution = Self::parse_signet_solution(&bytes).ok()?;
+
+        let mut location = None;
+        let mut push_index = 0;
+        solution.script_sig = Self::rewrite_pushes(&solution.script_sig, |data| {
+            let mut item = data.to_vec();
+            push_index += 1;
+            (location.is_none() && Self::corrupt_der_signature(&mut item)).then(|| {
+                location = Some(format!("scriptSig push {}", push_index - 1));
+                item
+            })
+        })?;
+        if location.is_none() {
+            let mut items = solution.witness.to_vec();
+            let i = items.iter_mut().position(|item| Self::corrupt_der_signature(item))?;
+            solution.witness = Witness::from_slice(&items);
+            location = Some(format!("witness item {}", i));
+        }
+
+        let mut payload = SIGNET_HEADER.to_vec();
+        payload.extend(encode::serialize(&solution.script_sig));
+        payload.extend(encode::serialize(&solution.witness));
+        let mut replaced = false;
+        coinbase.output[index].script_pubkey = Self::rewrite_pushes(&coinbase.output[index].script_pubkey, |data| {
+            (!replaced && data.len() > SIGNET_HEADER.len() && data.starts_with(&SIGNET_HEADER)).then(|| {
+                replaced = true;
+                payload.clone()
+            })
+        })?;
+        Self::fix_merkle_root(block);
+        location
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::blockdata::opcodes::all::OP_PUSHNUM_2;
+    use bitcoin::secp256k1::SecretKey;
+    use bitcoin::Network;
+
+    use crate::coinbase::WITNESS_COMMITMENT_HEADER;
+
+    // No real signet block is available offline, so the fixtures are blocks signed here
+    // with fixed keys against a 1-of-2 multisig challenge shaped like the default signet's
+
+    fn keys() -> [SecretKey; 2] {
+        [SecretKey::from_slice(&[1; 32]).unwrap(), SecretKey::from_slice(&[2; 32]).unwrap()]
+    }
+
+    fn challenge() -> ScriptBuf {
+        let secp = Secp256k1::new();
+        let [first, second] = keys();
+        Builder::new()
+            .push_opcode(OP_PUSHNUM_1)
+            .push_slice(first.public_key(&secp).serialize())
+            .push_slice(second.public_key(&secp).serialize())
+            .push_opcode(OP_PUSHNUM_2)
+            .push_opcode(OP_CHECKMULTISIG)
+            .into_script()
+    }
+
+    // Genesis block with a witness commitment output carrying the given signet push
+    fn with_signet_push(mut block: Block, push: Vec<u8>) -> Block {
+        let coinbase = &mut block.txdata[0];
+        let mut commitment = WITNESS_COMMITMENT_HEADER[2..].to_vec();
+        commitment.extend([0; 32]);
+        let script_pubkey = Builder::new()
+            .push_opcode(OP_RETURN)
+            .push_slice(PushBytesBuf::try_from(commitment).unwrap())
+            .push_slice(PushBytesBuf::try_from(push).unwrap())
+            .into_script();
+        match BlockProcessor::witness_commitment_output(coinbase) {
+            Some(index) => coinbase.output[index].script_pubkey = script_pubkey,
+            None => coinbase.output.push(TxOut { value: 0, script_pubkey }),
+        }
+        BlockProcessor::fix_merkle_root(&mut block);
+        block
+    }
+
+    fn with_solution(block: Block, solution: &SignetSolution) -> Block {
+        let mut push = SIGNET_HEADER.to_vec();
+        push.extend(encode::serialize(&solution.script_sig));
+        push.extend(encode::serialize(&solution.witness));
+        with_signet_push(block, push)
+    }
+
+    // Sign the block with the second key, as a multisig solution with its dummy element
+    fn signed_block() -> Block {
+        let block = with_signet_push(genesis_block(Network::Bitcoin), SIGNET_HEADER.to_vec());
+        let challenge = challenge();
+        let (_, to_sign, _) = BlockProcessor::signet_transactions(&block, &challenge).unwrap();
+        let sighash = SighashCache::new(&to_sign).legacy_signature_hash(0, &challenge, 1).unwrap();
+        let message = Message::from_slice(sighash.as_byte_array()).unwrap();
+        let mut signature = Secp256k1::new().sign_ecdsa(&message, &keys()[1]).serialize_der().to_vec();
+        signature.push(1);
+        let script_sig = Builder::new()
+            .push_opcode(OP_PUSHBYTES_0)
+            .push_slice(PushBytesBuf::try_from(signature).unwrap())
+            .into_script();
+        with_solution(block, &SignetSolution { script_sig, witness: Witness::new() })
+    }
+
+    #[test]
+    fn default_challenge_is_one_of_two_multisig() {
+        let challenge = ScriptBuf::from_hex(DEFAULT_SIGNET_CHALLENGE).unwrap();
+        let (required, keys, multisig) = BlockProcessor::signet_challenge_keys(&challenge).unwrap();
+        assert_eq!((required, keys.len(), multisig), (1, 2, true));
+    }
+
+    #[test]
+    fn parses_and_verifies_a_signed_block() {
+        let block = signed_block();
+        let check = BlockProcessor::check_signet_block(&block, &challenge()).unwrap();
+        assert_eq!(check.signature, SignetSignatureCheck::Valid);
+        let solution = check.solution.unwrap();
+        let pushes: Vec<Instruction> = solution.script_sig.instructions().collect::<Result<_, _>>().unwrap();
+        assert_eq!(pushes.len(), 2);
+        assert!(solution.witness.is_empty());
+
+        // The solution is left out of the signet merkle root but not the block's
+        let unsigned = with_signet_push(block.clone(), SIGNET_HEADER.to_vec());
+        assert_ne!(unsigned.header.merkle_root, block.header.merkle_root);
+        let unsigned_check = BlockProcessor::check_signet_block(&unsigned, &challenge()).unwrap();
+        assert!(unsigned_check.solution.is_none());
+        assert_eq!(unsigned_check.signet_merkle_root, check.signet_merkle_root);
+        assert_eq!(unsigned_check.to_spend, check.to_spend);
+        assert!(matches!(unsigned_check.signature, SignetSignatureCheck::Invalid(_)));
+
+        // Any other challenge rejects the signature
+        let other = Builder::new()
+            .push_slice(keys()[0].public_key(&Secp256k1::new()).serialize())
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        assert!(matches!(BlockProcessor::check_signet_block(&block, &other).unwrap().signature, SignetSignatureCheck::Invalid(_)));
+        assert_eq!(
+            BlockProcessor::check_signet_block(&block, &Builder::new().push_opcode(OP_PUSHNUM_1).into_script()).unwrap().signature,
+            SignetSignatureCheck::Unsupported
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_solutions() {
+        let solution = SignetSolution { script_sig: ScriptBuf::from(vec![0x51]), witness: Witness::from_slice(&[[1u8, 2]]) };
+        let mut bytes = encode::serialize(&solution.script_sig);
+        bytes.extend(encode::serialize(&solution.witness));
+        let parsed = BlockProcessor::parse_signet_solution(&bytes).unwrap();
+        assert_eq!((parsed.script_sig, parsed.witness), (solution.script_sig, solution.witness));
+
+        assert!(BlockProcessor::parse_signet_solution(&bytes[..bytes.len() - 1]).is_err());
+        bytes.push(0);
+        assert_eq!(BlockProcessor::parse_signet_solution(&bytes).unwrap_err(), "1 trailing bytes after the signet solution");
+
+        let mut push = SIGNET_HEADER.to_vec();
+        push.push(0x05);
+        let block = with_signet_push(genesis_block(Network::Bitcoin), push);
+        assert!(BlockProcessor::check_signet_block(&block, &challenge()).is_err());
+        assert!(BlockProcessor::check_signet_block(&genesis_block(Network::Bitcoin), &challenge()).is_err());
+    }
+
+    #[test]
+    fn corrupting_the_signature_changes_nothing_else() {
+        let block = signed_block();
+        let before = BlockProcessor::check_signet_block(&block, &challenge()).unwrap();
+        let mut corrupted = block.clone();
+        assert_eq!(BlockProcessor::corrupt_signet_signature(&mut corrupted).as_deref(), Some("scriptSig push 1"));
+
+        let after = BlockProcessor::check_signet_block(&corrupted, &challenge()).unwrap();
+        assert!(matches!(after.signature, SignetSignatureCheck::Invalid(_)));
+        assert_eq!(after.signet_merkle_root, before.signet_merkle_root);
+        assert_eq!(after.to_spend, before.to_spend);
+
+        // One bit of one push differs, and the block merkle root follows the coinbase
+        let (old, new) = (before.solution.unwrap().script_sig, after.solution.unwrap().script_sig);
+        assert_eq!(old.len(), new.len());
+        let differing: Vec<u8> = old.as_bytes().iter().zip(new.as_bytes()).map(|(a, b)| a ^ b).filter(|x| *x != 0).collect();
+        assert_eq!(differing, [1]);
+        assert_eq!(corrupted.txdata[1..], block.txdata[1..]);
+        assert_eq!(corrupted.txdata[0].input, block.txdata[0].input);
+        assert!(corrupted.check_merkle_root());
+        let mut header = corrupted.header;
+        header.merkle_root = block.header.merkle_root;
+        assert_eq!(header, block.header);
+    }
+
+    #[test]
+    fn corrupts_witness_signatures_when_the_scriptsig_has_none() {
+        let mut signature = Secp256k1::new()
+            .sign_ecdsa(&Message::from_slice(&[7; 32]).unwrap(), &keys()[0])
+            .serialize_der()
+            .to_vec();
+        signature.push(1);
+        let pubkey = keys()[0].public_key(&Secp256k1::new()).serialize().to_vec();
+        let solution = SignetSolution { script_sig: ScriptBuf::new(), witness: Witness::from_slice(&[signature.clone(), pubkey.clone()]) };
+        let mut block = with_solution(genesis_block(Network::Bitcoin), &solution);
+        assert_eq!(BlockProcessor::corrupt_signet_signature(&mut block).as_deref(), Some("witness item 0"));
+
+        let corrupted = BlockProcessor::check_signet_block(&block, &challenge()).unwrap().solution.unwrap();
+        assert!(corrupted.script_sig.is_empty());
+        assert_eq!(corrupted.witness.nth(1), Some(&pubkey[..]));
+        assert_ne!(corrupted.witness.nth(0), Some(&signature[..]));
+
+        // Nothing to corrupt without a signature
+        let mut unsigned = with_signet_push(genesis_block(Network::Bitcoin), SIGNET_HEADER.to_vec());
+        assert_eq!(BlockProcessor::corrupt_signet_signature(&mut unsigned), None);
+    }
+}
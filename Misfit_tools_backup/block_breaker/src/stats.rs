@@ -0,0 +1,139 @@
+use bitcoin::blockdata::{
+    block::Block,
+    opcodes::all::{OP_CHECKMULTISIG, OP_CHECKMULTISIGVERIFY, OP_CHECKSIG, OP_CHECKSIGVERIFY},
+    script::{Instruction, Script},
+    transaction::OutPoint,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::processor::BlockProcessor;
+
+// Previous output values keyed by outpoint, used to compute fees
+pub type PrevoutValues = HashMap<OutPoint, u64>;
+
+// Feerate percentiles in sat/vB over the transactions whose fee is known
+#[derive(Debug, Clone, Serialize)]
+pub struct FeerateDistribution {
+    pub min: f64,
+    pub p10: f64,
+    pub p25: f64,
+    pub median: f64,
+    pub p75: f64,
+    pub p90: f64,
+    pub max: f64,
+}
+
+// Aggregate statistics for a decoded block
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockStats {
+    pub block_hash: String,
+    pub size: usize,
+    pub stripped_size: usize,
+    pub weight: u64,
+    pub tx_count: usize,
+    pub segwit_tx_count: usize,
+    pub legacy_sigops: usize,
+    pub total_output_value: u64,
+    pub coinbase_output_value: u64,
+    pub total_fees: Option<u64>,      // only set when a prevout source is given
+    pub missing_prevouts: usize,      // inputs whose value was not in the prevout source
+    pub feerates: Option<FeerateDistribution>,
+}
+
+impl BlockProcessor {
+    // Count sigops the legacy way: 1 per CHECKSIG, 20 per CHECKMULTISIG
+    pub fn legacy_sigop_count(script: &Script) -> usize {
+        let mut count = 0;
+        for instruction in script.instructions() {
+            match instruction {
+                Ok(Instruction::Op(op)) if op == OP_CHECKSIG || op == OP_CHECKSIGVERIFY => count += 1,
+                Ok(Instruction::Op(op)) if op == OP_CHECKMULTISIG || op == OP_CHECKMULTISIGVERIFY => count += 20,
+                Ok(_) => {}
+                // Bitcoin Core stops counting at the first parse error
+                Err(_) => break,
+            }
+        }
+        count
+    }
+
+    // Load prevout values from a JSON object of "txid:vout" -> satoshis
+    pub fn load_prevout_values(path: &str) -> Result<PrevoutValues, Box<dyn std::error::Error>> {
+        let raw: HashMap<String, u64> = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        raw.into_iter()
+            .map(|(outpoint, value)| Ok((OutPoint::from_str(&outpoint)?, value)))
+            .collect()
+    }
+
+    // Value at a given percentile of sorted samples (nearest rank)
+    fn percentile(sorted: &[f64], percent: usize) -> f64 {
+        let rank = (sorted.len() * percent).div_ceil(100).max(1);
+        sorted[rank - 1]
+    }
+
+    // Compute size, weight, sigop, value and (given prevouts) fee statistics
+    pub fn block_stats(block: &Block, prevouts: Option<&PrevoutValues>) -> BlockStats {
+        let mut legacy_sigops = 0;
+        let mut total_output_value = 0u64;
+        let mut total_fees = prevouts.map(|_| 0u64);
+        let mut missing_prevouts = 0;
+        let mut feerates = Vec::new();
+
+        for tx in &block.txdata {
+            let output_value: u64 = tx.output.iter().map(|output| output.value).sum();
+            total_output_value = total_output_value.saturating_add(output_value);
+            legacy_sigops += tx.input.iter().map(|input| Self::legacy_sigop_count(&input.script_sig)).sum::<usize>();
+            legacy_sigops += tx.output.iter().map(|output| Self::legacy_sigop_count(&output.script_pubkey)).sum::<usize>();
+
+            let Some(prevouts) = prevouts.filter(|_| !tx.is_coin_base()) else {
+                continue;
+            };
+            let input_values: Vec<Option<&u64>> = tx.input.iter().map(|input| prevouts.get(&input.previous_output)).collect();
+            let missing = input_values.iter().filter(|value| value.is_none()).count();
+            if missing > 0 {
+                missing_prevouts += missing;
+                continue;
+            }
+            let input_value: u64 = input_values.into_iter().flatten().sum();
+            let fee = input_value.saturating_sub(output_value);
+            total_fees = total_fees.map(|total| total + fee);
+            feerates.push(fee as f64 / tx.vsize() as f64);
+        }
+
+        feerates.sort_by(|a, b| a.total_cmp(b));
+        let feerates = (!feerates.is_empty()).then(|| FeerateDistribution {
+            min: feerates[0],
+            p10: Self::percentile(&feerates, 10),
+            p25: Self::percentile(&feerates, 25),
+            median: Self::percentile(&feerates, 50),
+            p75: Self::percentile(&feerates, 75),
+            p90: Self::percentile(&feerates, 90),
+            max: feerates[feerates.len() - 1],
+        });
+
+        BlockStats {
+            block_hash: block.block_hash().to_string(),
+            size: block.size(),
+            stripped_size: block.strippedsize(),
+            weight: block.weight().to_wu(),
+            tx_count: block.txdata.len(),
+            segwit_tx_count: block
+                .txdata
+                .iter()
+                .filter(|tx| tx.input.iter().any(|input| !input.witness.is_empty()))
+                .count(),
+            legacy_sigops,
+            total_output_value,
+            coinbase_output_value: block
+                .txdata
+                .first()
+                .filter(|tx| tx.is_coin_base())
+                .map(|tx| tx.output.iter().map(|output| output.value).sum())
+                .unwrap_or(0),
+            total_fees,
+            missing_prevouts,
+            feerates,
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use bitcoin::blockdata::{
+    block::{Header, Version},
+    transaction::Transaction,
+};
+use bitcoin::consensus::encode;
+use bitcoin::hash_types::{BlockHash, TxMerkleNode};
+use bitcoin::hashes::Hash;
+use bitcoin::pow::CompactTarget;
+use serde_json::Value;
+
+use crate::processor::BlockProcessor;
+
+// A stratum v1 `mining.notify` job
+#[derive(Debug, Clone)]
+pub struct StratumJob {
+    pub job_id: String,
+    pub prev_blockhash: BlockHash,
+    pub coinb1: Vec<u8>,
+    pub coinb2: Vec<u8>,
+    pub merkle_branch: Vec<TxMerkleNode>,
+    pub version: i32,
+    pub bits: u32,
+    pub time: u32,
+    pub clean_jobs: bool,
+}
+
+// Decode a 32-byte hex value given in internal byte order
+fn parse_hash_hex(value: &Value) -> Result<[u8; 32], Box<dyn std::error::Error>> {
+    let bytes = hex::decode(value.as_str().ok_or("Expected a hex string")?)?;
+    Ok(bytes.try_into().map_err(|_| "Expected 32 bytes")?)
+}
+
+// Decode a big-endian u32 given as hex, as stratum sends version, nbits and ntime
+fn parse_u32_hex(value: &Value) -> Result<u32, Box<dyn std::error::Error>> {
+    Ok(u32::from_str_radix(value.as_str().ok_or("Expected a hex string")?, 16)?)
+}
+
+impl BlockProcessor {
+    // Parse a `mining.notify` message, either the full JSON-RPC notification or its params array
+    pub fn parse_mining_notify(json: &str) -> Result<StratumJob, Box<dyn std::error::Error>> {
+        let value: Value = serde_json::from_str(json)?;
+        let params = value.get("params").unwrap_or(&value).as_array().ok_or("Expected a params array")?;
+        if params.len() < 9 {
+            return Err(format!("mining.notify needs 9 params, got {}", params.len()).into());
+        }
+
+        // Stratum sends prevhash as 4-byte words, each byte-swapped from the header order
+        let mut prev_blockhash = parse_hash_hex(&params[1])?;
+        prev_blockhash.chunks_mut(4).for_each(|word| word.reverse());
+
+        Ok(StratumJob {
+            job_id: params[0].as_str().ok_or("Expected a job id")?.to_string(),
+            prev_blockhash: BlockHash::from_byte_array(prev_blockhash),
+            coinb1: hex::decode(params[2].as_str().ok_or("Expected coinb1")?)?,
+            coinb2: hex::decode(params[3].as_str().ok_or("Expected coinb2")?)?,
+            merkle_branch: params[4]
+                .as_array()
+                .ok_or("Expected a merkle branch array")?
+                .iter()
+                .map(|node| Ok(TxMerkleNode::from_byte_array(parse_hash_hex(node)?)))
+                .collect::<Result<_, Box<dyn std::error::Error>>>()?,
+            version: parse_u32_hex(&params[5])? as i32,
+            bits: parse_u32_hex(&params[6])?,
+            time: parse_u32_hex(&params[7])?,
+            clean_jobs: params[8].as_bool().unwrap_or(false),
+        })
+    }
+
+    // Serialized coinbase: coinb1 + extranonce1 + extranonce2 + coinb2
+    pub fn stratum_coinbase(job: &StratumJob, extranonce1: &[u8], extranonce2: &[u8]) -> Vec<u8> {
+        [&job.coinb1[..], extranonce1, extranonce2, &job.coinb2[..]].concat()
+    }
+
+    // Build the candidate header for a job and extranonces, folding the coinbase txid up
+    // the merkle branch. Returns the header (nonce 0) and the decoded coinbase.
+    pub fn stratum_header(job: &StratumJob, extranonce1: &[u8], extranonce2: &[u8]) -> Result<(Header, Transaction), Box<dyn std::error::Error>> {
+        let coinbase: Transaction = encode::deserialize(&Self::stratum_coinbase(job, extranonce1, extranonce2))?;
+        let merkle_root = job
+            .merkle_branch
+            .iter()
+            .fold(TxMerkleNode::from_raw_hash(coinbase.txid().to_raw_hash()), |root, node| Self::merkle_parent(&root, node));
+        let header = Header {
+            version: Version::from_consensus(job.version),
+            prev_blockhash: job.prev_blockhash,
+            merkle_root,
+            time: job.time,
+            bits: CompactTarget::from_consensus(job.bits),
+            nonce: 0,
+        };
+        Ok((header, coinbase))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Job building on the mainnet genesis block; the expected values were computed
+    // independently from the raw bytes
+    const NOTIFY: &str = r#"{"id": null, "method": "mining.notify", "params": ["4f", "0a8ce26f72b3f1b646a2a6c14ff763ae65831e939c085ae10019d66800000000", "01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff110340d10c", "042f62622fffffffff01205fa01200000000015100000000", ["aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa", "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb"], "20000000", "207fffff", "66a0b1c2", true]}"#;
+    const EXTRANONCE1: &str = "f000000f";
+    const EXTRANONCE2: &str = "00000001";
+    const NONCE: u32 = 0x12345678;
+    const COINBASE_TXID: &str = "967818dda490366ab38ae5755c3036f60df35d82746af1e6634595cf254f65c7";
+    const MERKLE_ROOT: &str = "467fb3b0315e84f5759a70a1d9c31bcaa602d4b4cc31678b3bedec5624e418d8";
+    const HEADER: &str = "000000206fe28c0ab6f1b372c1a6a246ae63f74f931e8365e15a089c68d6190000000000d818e42456eced3b8b6731ccb4d402a6ca1bc3d9a1709a75f5845e31b0b37f46c2b1a066ffff7f2078563412";
+    const HASH: &str = "ce25585478b966c592562de314819fcb0e4d7b88c582a93be9f7a2e6a79b3e2e";
+
+    #[test]
+    fn parses_the_notify_params() {
+        let job = BlockProcessor::parse_mining_notify(NOTIFY).unwrap();
+        assert_eq!(job.job_id, "4f");
+        // The word-swapped prevhash comes back as the genesis hash
+        assert_eq!(job.prev_blockhash.to_string(), "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f");
+        assert_eq!((job.version, job.bits, job.time), (0x20000000, 0x207fffff, 0x66a0b1c2));
+        assert_eq!(job.merkle_branch.len(), 2);
+        assert!(job.clean_jobs);
+
+        // The bare params array is accepted too
+        let value: Value = serde_json::from_str(NOTIFY).unwrap();
+        let params = BlockProcessor::parse_mining_notify(&value["params"].to_string()).unwrap();
+        assert_eq!(params.prev_blockhash, job.prev_blockhash);
+        assert!(BlockProcessor::parse_mining_notify(r#"["4f", "00"]"#).is_err());
+    }
+
+    #[test]
+    fn builds_the_header_from_the_job_and_extranonces() {
+        let job = BlockProcessor::parse_mining_notify(NOTIFY).unwrap();
+        let (extranonce1, extranonce2) = (hex::decode(EXTRANONCE1).unwrap(), hex::decode(EXTRANONCE2).unwrap());
+        let (mut header, coinbase) = BlockProcessor::stratum_header(&job, &extranonce1, &extranonce2).unwrap();
+        assert_eq!(coinbase.txid().to_string(), COINBASE_TXID);
+        assert_eq!(&coinbase.input[0].script_sig.as_bytes()[4..12], [extranonce1.clone(), extranonce2.clone()].concat());
+        assert_eq!(header.merkle_root.to_string(), MERKLE_ROOT);
+        assert_eq!(header.nonce, 0);
+
+        header.nonce = NONCE;
+        assert_eq!(encode::serialize_hex(&header), HEADER);
+        assert_eq!(header.block_hash().to_string(), HASH);
+
+        // A different extranonce2 changes only the merkle root
+        let (other, _) = BlockProcessor::stratum_header(&job, &extranonce1, &[0, 0, 0, 2]).unwrap();
+        assert_ne!(other.merkle_root, header.merkle_root);
+        assert_eq!(other.prev_blockhash, header.prev_blockhash);
+    }
+}
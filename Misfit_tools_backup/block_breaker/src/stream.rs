@@ -0,0 +1,82 @@
+use bitcoin::blockdata::block::Header;
+use bitcoin::consensus::Decodable;
+use std::collections::VecDeque;
+use std::io::Read;
+
+use crate::chain::{HeaderChain, HeaderChainError};
+use crate::pow::{ConsensusParams, RETARGET_INTERVAL};
+
+// Iterator over concatenated 80-byte headers from any reader
+pub struct HeaderReader<R: Read> {
+    reader: R,
+}
+
+impl<R: Read> HeaderReader<R> {
+    pub fn new(reader: R) -> Self {
+        HeaderReader { reader }
+    }
+}
+
+impl<R: Read> Iterator for HeaderReader<R> {
+    type Item = Result<Header, Box<dyn std::error::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut bytes = [0u8; 80];
+        let mut filled = 0;
+        while filled < bytes.len() {
+            match self.reader.read(&mut bytes[filled..]) {
+                Ok(0) if filled == 0 => return None,
+                Ok(0) => return Some(Err(format!("Truncated header: {} of 80 bytes", filled).into())),
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Some(Err(e.into())),
+            }
+        }
+        Some(Header::consensus_decode(&mut &bytes[..]).map_err(|e| e.into()))
+    }
+}
+
+// Validates headers one at a time, keeping only the window needed for MTP and retargeting
+pub struct HeaderStreamValidator {
+    window: VecDeque<Header>,
+    height: u32,
+    now: u32,
+    params: ConsensusParams,
+}
+
+impl HeaderStreamValidator {
+    pub fn new(start_height: u32, params: ConsensusParams) -> Self {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as u32;
+        HeaderStreamValidator {
+            window: VecDeque::with_capacity(RETARGET_INTERVAL as usize + 1),
+            height: start_height,
+            now,
+            params,
+        }
+    }
+
+    // Height the next pushed header is validated at
+    pub fn next_height(&self) -> u32 {
+        self.height
+    }
+
+    // Validate the next header against the window. Invalid headers are not kept, so the
+    // stream can continue with a replacement for them.
+    pub fn push(&mut self, header: Header) -> Result<u32, HeaderChainError> {
+        if self.window.len() > RETARGET_INTERVAL as usize {
+            self.window.pop_front();
+        }
+        self.window.push_back(header);
+        let headers = self.window.make_contiguous();
+        let height = self.height;
+        if let Err(error) = HeaderChain::check_header(headers, headers.len() - 1, height, self.now, &self.params) {
+            self.window.pop_back();
+            return Err(error);
+        }
+        self.height += 1;
+        Ok(height)
+    }
+}
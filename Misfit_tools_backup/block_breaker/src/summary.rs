@@ -0,0 +1,120 @@
+use bitcoin::{
+    blockdata::{
+        block::Block,
+        script::Script,
+        transaction::{OutPoint, Transaction},
+    },
+    hash_types::{Txid, Wtxid},
+};
+
+use crate::processor::BlockProcessor;
+
+// Summary of a single transaction input
+#[derive(Debug, Clone)]
+pub struct InputSummary {
+    pub previous_output: OutPoint,
+    pub script_sig: String,
+    pub sequence: u32,
+    pub witness_items: usize,
+}
+
+// Summary of a single transaction output
+#[derive(Debug, Clone)]
+pub struct OutputSummary {
+    pub value: u64,
+    pub script_pubkey: String,
+    pub script_type: &'static str,
+}
+
+// Summary of a transaction decoded from block.txdata
+#[derive(Debug, Clone)]
+pub struct TransactionSummary {
+    pub index: usize,
+    pub txid: Txid,
+    pub wtxid: Wtxid,
+    pub version: i32,
+    pub lock_time: u32,
+    pub is_coinbase: bool,
+    pub is_segwit: bool,
+    pub size: usize,
+    pub vsize: usize,
+    pub weight: u64,
+    pub inputs: Vec<InputSummary>,
+    pub outputs: Vec<OutputSummary>,
+    pub total_output_value: u64,
+}
+
+impl BlockProcessor {
+    // Classify a scriptPubKey by its standard template
+    fn script_type(script: &Script) -> &'static str {
+        if script.is_p2pkh() {
+            "p2pkh"
+        } else if script.is_p2sh() {
+            "p2sh"
+        } else if script.is_v0_p2wpkh() {
+            "p2wpkh"
+        } else if script.is_v0_p2wsh() {
+            "p2wsh"
+        } else if script.is_v1_p2tr() {
+            "p2tr"
+        } else if script.is_p2pk() {
+            "p2pk"
+        } else if script.is_op_return() {
+            "op_return"
+        } else if script.is_witness_program() {
+            "witness_unknown"
+        } else {
+            "nonstandard"
+        }
+    }
+
+    // Decode a single transaction into a summary
+    pub fn summarize_transaction(index: usize, tx: &Transaction) -> TransactionSummary {
+        let inputs: Vec<InputSummary> = tx
+            .input
+            .iter()
+            .map(|input| InputSummary {
+                previous_output: input.previous_output,
+                script_sig: hex::encode(input.script_sig.as_bytes()),
+                sequence: input.sequence.0,
+                witness_items: input.witness.len(),
+            })
+            .collect();
+
+        let outputs: Vec<OutputSummary> = tx
+            .output
+            .iter()
+            .map(|output| OutputSummary {
+                value: output.value,
+                script_pubkey: hex::encode(output.script_pubkey.as_bytes()),
+                script_type: Self::script_type(&output.script_pubkey),
+            })
+            .collect();
+
+        TransactionSummary {
+            index,
+            txid: tx.txid(),
+            wtxid: tx.wtxid(),
+            version: tx.version,
+            lock_time: tx.lock_time.to_consensus_u32(),
+            is_coinbase: tx.is_coin_base(),
+            is_segwit: tx.input.iter().any(|input| !input.witness.is_empty()),
+            size: tx.size(),
+            vsize: tx.vsize(),
+            weight: tx.weight().to_wu(),
+            total_output_value: outputs.iter().map(|output| output.value).sum(),
+            inputs,
+            outputs,
+        }
+    }
+
+    // Decode every transaction in the block into summaries
+    pub fn summarize_transactions(block: &Block) -> Vec<TransactionSummary> {
+        block
+            .txdata
+            .iter()
+            .enumerate()
+            .map(|(index, tx)| Self::summarize_transaction(index, tx))
+            .collect()
+    }
+}
@@ -0,0 +1,103 @@
+use bitcoin::blockdata::{
+    block::{Block, Header, Version},
+    locktime::absolute::LockTime,
+    opcodes::all::OP_PUSHBYTES_0,
+    script::{Builder, PushBytesBuf, ScriptBuf},
+    transaction::{OutPoint, Sequence, Transaction, TxIn, TxOut},
+    witness::Witness,
+};
+use bitcoin::consensus::encode;
+use bitcoin::hash_types::{BlockHash, TxMerkleNode};
+use bitcoin::hashes::Hash;
+use bitcoin::pow::CompactTarget;
+use serde::Deserialize;
+use std::str::FromStr;
+
+use crate::processor::BlockProcessor;
+
+// A transaction entry of a getblocktemplate response
+#[derive(Debug, Clone, Deserialize)]
+pub struct TemplateTransaction {
+    pub data: String, // serialized transaction hex
+    #[serde(default)]
+    pub fee: Option<u64>,
+}
+
+// The fields of a Bitcoin Core getblocktemplate response needed to assemble a block
+#[derive(Debug, Clone, Deserialize)]
+pub struct BlockTemplate {
+    pub version: i32,
+    pub previousblockhash: String,
+    pub transactions: Vec<TemplateTransaction>,
+    pub coinbasevalue: u64,
+    pub bits: String, // compact target as hex, e.g. "1d00ffff"
+    pub curtime: u32,
+    pub height: u32,
+    #[serde(default)]
+    pub default_witness_commitment: Option<String>,
+}
+
+impl BlockProcessor {
+    // Load a getblocktemplate response from a JSON file, accepting either the bare
+    // template or a full JSON-RPC reply with the template under "result"
+    pub fn load_block_template(path: &str) -> Result<BlockTemplate, Box<dyn std::error::Error>> {
+        let value: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        let value = match value.get("result") {
+            Some(result) => result.clone(),
+            None => value,
+        };
+        Ok(serde_json::from_value(value)?)
+    }
+
+    // Build the coinbase paying `coinbasevalue` to the payout script. The scriptSig starts
+    // with the BIP34 height push, followed by the extranonce (or OP_0 when it is empty).
+    pub fn build_coinbase(height: u32, value: u64, payout_script: ScriptBuf, extranonce: &[u8]) -> Result<Transaction, String> {
+        let builder = Builder::new().push_int(height as i64);
+        let builder = if extranonce.is_empty() {
+            builder.push_opcode(OP_PUSHBYTES_0)
+        } else {
+            let push = PushBytesBuf::try_from(extranonce.to_vec()).map_err(|_| "Extranonce too long".to_string())?;
+            builder.push_slice(push)
+        };
+        Ok(Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: builder.into_script(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut { value, script_pubkey: payout_script }],
+        })
+    }
+
+    // Assemble a candidate block from a template: coinbase, template transactions,
+    // witness commitment when any transaction carries witness data, and merkle root.
+    // The nonce is left at zero; the block still has to be mined.
+    pub fn assemble_block(template: &BlockTemplate, payout_script: ScriptBuf, extranonce: &[u8]) -> Result<Block, Box<dyn std::error::Error>> {
+        let mut txdata = vec![Self::build_coinbase(template.height, template.coinbasevalue, payout_script, extranonce)?];
+        for tx in &template.transactions {
+            txdata.push(encode::deserialize(&hex::decode(&tx.data)?)?);
+        }
+
+        let mut block = Block {
+            header: Header {
+                version: Version::from_consensus(template.version),
+                prev_blockhash: BlockHash::from_str(&template.previousblockhash)?,
+                merkle_root: TxMerkleNode::all_zeros(),
+                time: template.curtime,
+                bits: CompactTarget::from_consensus(u32::from_str_radix(&template.bits, 16)?),
+                nonce: 0,
+            },
+            txdata,
+        };
+
+        let has_witness = block.txdata.iter().any(|tx| tx.input.iter().any(|input| !input.witness.is_empty()));
+        if has_witness || template.default_witness_commitment.is_some() {
+            Self::fix_witness_commitment(&mut block)?;
+        }
+        block.header.merkle_root = block.compute_merkle_root().ok_or("Block has no transactions")?;
+        Ok(block)
+    }
+}
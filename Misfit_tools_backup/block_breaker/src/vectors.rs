@@ -0,0 +1,222 @@
+use bitcoin::blockdata::{
+    block::Block,
+    locktime::absolute::LockTime,
+    script::ScriptBuf,
+    transaction::{OutPoint, Sequence, Transaction, TxIn, TxOut},
+    witness::Witness,
+};
+use bitcoin::pow::CompactTarget;
+use serde::Serialize;
+
+use crate::breaker::BlockBreaker;
+use crate::chain::{TimestampAttack, MEDIAN_TIME_SPAN};
+use crate::coinbase::REGTEST_HALVING_INTERVAL;
+use crate::miner::{REGTEST_BITS, REGTEST_GENESIS_HASH};
+use crate::presets::CorruptionPreset;
+use crate::processor::{BlockField, BlockProcessor, ProcessingConfig};
+use crate::template::BlockTemplate;
+
+// Regtest blocks mined below the invalid blocks, so every vector has a parent and an MTP
+pub const VECTOR_PARENT_CHAIN_LENGTH: usize = 11;
+
+// Consensus weight limit, used to build the oversized block
+pub const MAX_BLOCK_WEIGHT: usize = 4_000_000;
+
+// One invalid block and the rule it breaks
+#[derive(Debug, Clone, Serialize)]
+pub struct TestVector {
+    pub name: String,
+    pub rule: String, // reject reason Bitcoin Core reports
+    pub description: String,
+    pub height: u32,
+    pub block_hash: String,
+    pub file: String,
+}
+
+// An invalid block together with its label
+pub type LabeledBlock = (Block, TestVector);
+
+// Manifest describing a generated set of test vectors
+#[derive(Debug, Clone, Serialize)]
+pub struct TestVectorManifest {
+    pub network: String,
+    pub parent_chain_file: String, // valid regtest blocks to submit first, one hex block per line
+    pub parent_tip: String,
+    pub vectors: Vec<TestVector>,
+}
+
+impl BlockBreaker {
+    // Mine a regtest parent chain and a valid candidate on top of it, then derive one
+    // invalid block per rule from the candidate. Every vector is re-mined, so it fails
+    // only on the rule it is labeled with.
+    pub fn generate_test_vectors(start_time: u32, seed: u64) -> Result<(Vec<Block>, Vec<LabeledBlock>), Box<dyn std::error::Error>> {
+        let payout = vec![0x51].into();
+        let parents = BlockProcessor::mine_regtest_chain(REGTEST_GENESIS_HASH.parse()?, 0, start_time, VECTOR_PARENT_CHAIN_LENGTH, &payout)?;
+        let tip = parents.last().expect("parent chain is not empty");
+        let height = VECTOR_PARENT_CHAIN_LENGTH as u32 + 1;
+        let candidate = BlockProcessor::mine_template(
+            &BlockTemplate {
+                version: 0x2000_0000,
+                previousblockhash: tip.block_hash().to_string(),
+                transactions: vec![],
+                coinbasevalue: BlockProcessor::block_subsidy(height, REGTEST_HALVING_INTERVAL),
+                bits: format!("{:08x}", REGTEST_BITS),
+                curtime: tip.header.time + 1,
+                height,
+                default_witness_commitment: None,
+            },
+            &payout,
+        )?;
+        let previous_headers: Vec<_> = parents[parents.len().saturating_sub(MEDIAN_TIME_SPAN)..].iter().map(|b| b.header).collect();
+
+        let mut vectors = Vec::new();
+        let mut push = |name: &str, rule: &str, description: &str, block: Block| {
+            let vector = TestVector {
+                name: name.to_string(),
+                rule: rule.to_string(),
+                description: description.to_string(),
+                height,
+                block_hash: block.block_hash().to_string(),
+                file: format!("{}.hex", name),
+            };
+            vectors.push((block, vector));
+        };
+        let remined = |config: ProcessingConfig| {
+            let config = ProcessingConfig { seed: Some(seed), remine: true, remine_roll_timestamp: true, ..config };
+            Self::break_with_config(&candidate, config).0
+        };
+
+        let mut high_hash = candidate.clone();
+        while BlockProcessor::validate_pow(&high_hash.header).valid {
+            high_hash.header.nonce = high_hash.header.nonce.wrapping_add(1);
+        }
+        push("bad-pow", "high-hash", "Block hash does not meet the target encoded in bits", high_hash);
+
+        push(
+            "bad-merkle-root",
+            "bad-txnmrklroot",
+            "Header merkle root does not match the transactions",
+            remined(ProcessingConfig { fields_to_modify: vec![BlockField::MerkleRoot], ..Default::default() }),
+        );
+        push(
+            "unknown-parent",
+            "prev-blk-not-found",
+            "Previous block hash points to a block that does not exist",
+            remined(ProcessingConfig { fields_to_modify: vec![BlockField::PrevBlockHash], ..Default::default() }),
+        );
+
+        let mut bad_bits = candidate.clone();
+        bad_bits.header.bits = CompactTarget::from_consensus(0x2000ffff);
+        BlockProcessor::grind_nonce(&mut bad_bits.header);
+        push("bad-bits", "bad-diffbits", "Bits differ from the required regtest difficulty although the hash meets them", bad_bits);
+
+        push(
+            "timestamp-at-mtp",
+            "time-too-old",
+            "Timestamp equals the median time past of the previous 11 blocks",
+            remined(ProcessingConfig {
+                fields_to_modify: vec![BlockField::Timestamp],
+                timestamp_attack: Some(TimestampAttack::ExactlyMtp),
+                previous_headers: previous_headers.clone(),
+                ..Default::default()
+            }),
+        );
+
+        let mut too_new = candidate.clone();
+        too_new.header.time = u32::MAX;
+        BlockProcessor::grind_nonce(&mut too_new.header);
+        push("timestamp-far-future", "time-too-new", "Timestamp is far beyond the two hour future limit", too_new);
+
+        push(
+            "obsolete-version",
+            "bad-version(0x00000001)",
+            "Version 1 after BIP34, BIP66 and BIP65 are active",
+            remined(ProcessingConfig {
+                fields_to_modify: vec![BlockField::Version],
+                version_override: Some(1),
+                ..Default::default()
+            }),
+        );
+
+        // Pad the coinbase with large OP_RETURN outputs until the stripped size alone exceeds the weight limit
+        let mut oversized = candidate.clone();
+        while oversized.strippedsize() * 4 <= MAX_BLOCK_WEIGHT {
+            let mut script = vec![0x6a];
+            script.resize(10_000, 0);
+            oversized.txdata[0].output.push(TxOut { value: 0, script_pubkey: script.into() });
+        }
+        BlockProcessor::fix_merkle_root(&mut oversized);
+        BlockProcessor::grind_nonce(&mut oversized.header);
+        push("oversized", "bad-blk-length", "Serialized size without witness data exceeds the block weight limit", oversized);
+
+        let preset = |preset: CorruptionPreset, block: &Block| {
+            let config = ProcessingConfig { seed: Some(seed), ..preset.config(block, height) };
+            Self::break_with_config(block, config).0
+        };
+        push(
+            "duplicate-txid",
+            CorruptionPreset::DuplicateTxid.rule(),
+            "Last transaction repeated, leaving the merkle root unchanged",
+            preset(CorruptionPreset::DuplicateTxid, &candidate),
+        );
+        push(
+            "bad-coinbase-height",
+            CorruptionPreset::BadCoinbaseHeight.rule(),
+            "Coinbase height push is one more than the block height",
+            preset(CorruptionPreset::BadCoinbaseHeight, &candidate),
+        );
+        push(
+            "inflated-coinbase",
+            CorruptionPreset::InflatedCoinbase.rule(),
+            "Coinbase pays one satoshi more than the subsidy plus fees",
+            preset(CorruptionPreset::InflatedCoinbase, &candidate),
+        );
+
+        // The witness commitment is checked before inputs are looked up, so a witness spend
+        // of the first parent coinbase is enough even though it is immature
+        let mut segwit = candidate.clone();
+        segwit.txdata.push(Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint { txid: parents[0].txdata[0].txid(), vout: 0 },
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::from_slice(&[[0x01]]),
+            }],
+            output: vec![TxOut { value: 0, script_pubkey: ScriptBuf::new_op_return(&[]) }],
+        });
+        BlockProcessor::fix_witness_commitment(&mut segwit)?;
+        push(
+            "bad-witness-commitment",
+            CorruptionPreset::BadWitnessCommitment.rule(),
+            "Witness data no longer matches the coinbase witness commitment",
+            preset(CorruptionPreset::BadWitnessCommitment, &segwit),
+        );
+
+        Ok((parents, vectors))
+    }
+
+    // Generate the test vectors into a directory: the parent chain, one hex file per vector
+    // and manifest.json
+    pub fn write_test_vectors(dir: &str, start_time: u32, seed: u64) -> Result<TestVectorManifest, Box<dyn std::error::Error>> {
+        std::fs::create_dir_all(dir)?;
+        let (parents, vectors) = Self::generate_test_vectors(start_time, seed)?;
+
+        let parent_chain_file = "parent_chain.hex".to_string();
+        let lines: Vec<String> = parents.iter().map(BlockProcessor::encode_block_to_hex).collect();
+        std::fs::write(format!("{}/{}", dir, parent_chain_file), lines.join("\n") + "\n")?;
+        for (block, vector) in &vectors {
+            BlockProcessor::write_block_to_file(block, &format!("{}/{}", dir, vector.file))?;
+        }
+
+        let manifest = TestVectorManifest {
+            network: "regtest".to_string(),
+            parent_chain_file,
+            parent_tip: parents.last().map(|b| b.block_hash().to_string()).unwrap_or_default(),
+            vectors: vectors.into_iter().map(|(_, vector)| vector).collect(),
+        };
+        std::fs::write(format!("{}/manifest.json", dir), serde_json::to_string_pretty(&manifest)?)?;
+        Ok(manifest)
+    }
+}
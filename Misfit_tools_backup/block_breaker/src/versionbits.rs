@@ -0,0 +1,126 @@
+use bitcoin::blockdata::block::{Header, Version};
+use serde::Serialize;
+
+use crate::processor::BlockProcessor;
+use crate::report::MutationReport;
+
+// BIP9 version bits layout: top three bits 001, then 29 signaling bits
+pub const VERSIONBITS_TOP_MASK: i32 = 0xE000_0000u32 as i32;
+pub const VERSIONBITS_TOP_BITS: i32 = 0x2000_0000;
+pub const VERSIONBITS_NUM_BITS: u8 = 29;
+
+// BIP320 general purpose bits that miners (stratum version rolling, ASICBoost) may change freely
+pub const VERSION_ROLLING_MASK: i32 = 0x1fff_e000;
+pub const VERSION_ROLLING_SHIFT: u32 = 13;
+
+// Mainnet deployments that signaled through version bits
+pub const KNOWN_DEPLOYMENTS: [(u8, &str); 3] = [(0, "csv"), (1, "segwit"), (2, "taproot")];
+
+// Set or clear a single version bit
+#[derive(Debug, Clone, PartialEq)]
+pub enum VersionBitMutation {
+    Set(u8),
+    Clear(u8),
+    SetTopBits,   // force the 001 BIP9 prefix
+    ClearTopBits, // zero the top three bits so BIP9 signaling is off
+    Roll(u16),    // put a 16-bit value into the BIP320 rollable bits, as a version-rolling miner would
+    RollOutsideMask(u32), // flip the given bits, keeping only those outside the rollable mask
+}
+
+// How a rolled version differs from the version the job was issued with
+#[derive(Debug, Clone, Serialize)]
+pub struct VersionRollingCheck {
+    pub original: i32,
+    pub rolled: i32,
+    pub changed_bits: u32,
+    pub outside_mask: u32, // changed bits a stratum server would reject
+    pub valid: bool,
+}
+
+// Deployment bits signaled by a header version
+#[derive(Debug, Clone, Serialize)]
+pub struct VersionBitsInfo {
+    pub version: i32,
+    pub uses_versionbits: bool, // top bits are 001
+    pub signaled_bits: Vec<u8>,
+    pub deployments: Vec<String>, // names for known bits, "bit N" otherwise
+}
+
+impl BlockProcessor {
+    // Name of the deployment behind a version bit
+    pub fn deployment_name(bit: u8) -> String {
+        KNOWN_DEPLOYMENTS
+            .iter()
+            .find(|(known, _)| *known == bit)
+            .map(|(_, name)| name.to_string())
+            .unwrap_or_else(|| format!("bit {}", bit))
+    }
+
+    // Decode which BIP9 bits a version signals
+    pub fn decode_version_bits(version: i32) -> VersionBitsInfo {
+        let uses_versionbits = version & VERSIONBITS_TOP_MASK == VERSIONBITS_TOP_BITS;
+        let signaled_bits: Vec<u8> = if uses_versionbits {
+            (0..VERSIONBITS_NUM_BITS).filter(|bit| version & (1 << bit) != 0).collect()
+        } else {
+            vec![]
+        };
+        VersionBitsInfo {
+            version,
+            uses_versionbits,
+            deployments: signaled_bits.iter().map(|bit| Self::deployment_name(*bit)).collect(),
+            signaled_bits,
+        }
+    }
+
+    // Check that a rolled version only changed bits inside the given rolling mask
+    pub fn check_version_rolling(original: i32, rolled: i32, mask: i32) -> VersionRollingCheck {
+        let changed_bits = (original ^ rolled) as u32;
+        let outside_mask = changed_bits & !(mask as u32);
+        VersionRollingCheck {
+            original,
+            rolled,
+            changed_bits,
+            outside_mask,
+            valid: outside_mask == 0,
+        }
+    }
+
+    // Apply a version bit mutation, or None if the bit is out of range
+    pub fn apply_version_bit_mutation(version: i32, mutation: &VersionBitMutation) -> Option<i32> {
+        match mutation {
+            VersionBitMutation::Set(bit) if *bit < VERSIONBITS_NUM_BITS => Some(version | (1 << bit)),
+            VersionBitMutation::Clear(bit) if *bit < VERSIONBITS_NUM_BITS => Some(version & !(1 << bit)),
+            VersionBitMutation::SetTopBits => Some((version & !VERSIONBITS_TOP_MASK) | VERSIONBITS_TOP_BITS),
+            VersionBitMutation::ClearTopBits => Some(version & !VERSIONBITS_TOP_MASK),
+            VersionBitMutation::Roll(value) => {
+                Some((version & !VERSION_ROLLING_MASK) | (((*value as i32) << VERSION_ROLLING_SHIFT) & VERSION_ROLLING_MASK))
+            }
+            VersionBitMutation::RollOutsideMask(bits) if *bits as i32 & !VERSION_ROLLING_MASK != 0 => {
+                Some(version ^ (*bits as i32 & !VERSION_ROLLING_MASK))
+            }
+            _ => None,
+        }
+    }
+
+    // Apply the configured version bit mutations to a header
+    pub(crate) fn process_version_bits(&self, header: &mut Header, report: &mut MutationReport) {
+        for mutation in &self.config.version_bit_mutations {
+            let version = header.version.to_consensus();
+            let reason = match mutation {
+                VersionBitMutation::Set(bit) => format!("set {}", Self::deployment_name(*bit)),
+                VersionBitMutation::Clear(bit) => format!("clear {}", Self::deployment_name(*bit)),
+                VersionBitMutation::SetTopBits => "set BIP9 top bits".to_string(),
+                VersionBitMutation::ClearTopBits => "clear BIP9 top bits".to_string(),
+                VersionBitMutation::Roll(value) => format!("rolled 0x{:04x} into BIP320 bits", value),
+                VersionBitMutation::RollOutsideMask(bits) => format!("flipped 0x{:08x} outside the rolling mask", *bits as i32 & !VERSION_ROLLING_MASK),
+            };
+            match Self::apply_version_bit_mutation(version, mutation) {
+                Some(new_version) => {
+                    report.record("version", format!("0x{:08x}", version), format!("0x{:08x}", new_version), &reason);
+                    header.version = Version::from_consensus(new_version);
+                }
+                None => report.record("version", format!("0x{:08x}", version), "", &format!("skipped: {} has no bits to change", reason)),
+            }
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use bitcoin::hashes::Hash;
+use block_breaker::{BlockField, BlockProcessor, ProcessingConfig};
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+mod strategies;
+use strategies::{arb_block, arb_header, arb_header_field, arb_transaction_mutation};
+
+// Whether the header already holds the fixed value a single-field mutation writes
+fn unchanged_by_mutation(header: &bitcoin::block::Header, field: &BlockField) -> bool {
+    match field {
+        BlockField::Version => header.version.to_consensus() == 0x3FFFFFFF,
+        _ => false,
+    }
+}
+
+proptest! {
+    #[test]
+    fn single_field_header_mutation_changes_block_hash(header in arb_header(), field in arb_header_field(), seed in any::<u64>()) {
+        prop_assume!(!unchanged_by_mutation(&header, &field));
+        let config = ProcessingConfig { fields_to_modify: vec![field], seed: Some(seed), ..Default::default() };
+        let (mutated, report) = BlockProcessor::new(config).process_block_header(&header);
+        prop_assert_eq!(report.mutations.len(), 1);
+        prop_assert_ne!(mutated.block_hash(), header.block_hash());
+    }
+
+    #[test]
+    fn header_hex_round_trips(header in arb_header()) {
+        let decoded = BlockProcessor::decode_header_from_hex(&BlockProcessor::encode_header_to_hex(&header)).unwrap();
+        prop_assert_eq!(decoded, header);
+    }
+
+    #[test]
+    fn block_hex_round_trips(block in arb_block(4)) {
+        let decoded = BlockProcessor::decode_block_from_hex(&BlockProcessor::encode_block_to_hex(&block)).unwrap();
+        prop_assert_eq!(decoded, block);
+    }
+
+    #[test]
+    fn fix_merkle_root_restores_consistency(block in arb_block(6), root in any::<[u8; 32]>()) {
+        let mut block = block;
+        block.header.merkle_root = bitcoin::hash_types::TxMerkleNode::from_byte_array(root);
+        prop_assert!(BlockProcessor::fix_merkle_root(&mut block));
+        prop_assert!(BlockProcessor::check_consistency(&block).merkle_root_matches);
+    }
+
+    #[test]
+    fn transaction_mutations_with_fix_keep_merkle_root_consistent(
+        block in arb_block(6),
+        mutations in vec(arb_transaction_mutation(7), 1..4),
+    ) {
+        let config = ProcessingConfig {
+            fields_to_modify: vec![],
+            transaction_mutations: mutations,
+            fix_merkle_root: true,
+            ..Default::default()
+        };
+        let (mutated, _) = BlockProcessor::new(config).process_block(&block);
+        prop_assert_eq!(BlockProcessor::check_consistency(&mutated).merkle_root_matches, !mutated.txdata.is_empty());
+    }
+
+    #[test]
+    fn merkle_proofs_verify_for_every_transaction(block in arb_block(8)) {
+        for tx in &block.txdata {
+            let proof = BlockProcessor::merkle_proof(&block, &tx.txid()).unwrap();
+            prop_assert!(BlockProcessor::verify_merkle_proof(&proof, &block.header));
+        }
+    }
+
+    #[test]
+    fn diff_of_block_with_itself_is_identical(block in arb_block(4)) {
+        prop_assert!(BlockProcessor::diff_blocks(&block, &block).is_identical());
+    }
+}
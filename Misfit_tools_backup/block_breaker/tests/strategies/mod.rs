@@ -0,0 +1,110 @@
+use bitcoin::blockdata::{
+    block::{Block, Header, Version},
+    script::ScriptBuf,
+    transaction::{OutPoint, Sequence, Transaction, TxIn, TxOut},
+    witness::Witness,
+};
+use bitcoin::hash_types::{BlockHash, TxMerkleNode, Txid};
+use bitcoin::hashes::Hash;
+use bitcoin::pow::CompactTarget;
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+use block_breaker::{BlockField, TransactionMutation};
+
+// Proptest strategies for headers, transactions and blocks
+
+pub fn arb_header() -> impl Strategy<Value = Header> {
+    (any::<i32>(), any::<[u8; 32]>(), any::<[u8; 32]>(), any::<u32>(), any::<u32>(), any::<u32>()).prop_map(
+        |(version, prev_blockhash, merkle_root, time, bits, nonce)| Header {
+            version: Version::from_consensus(version),
+            prev_blockhash: BlockHash::from_byte_array(prev_blockhash),
+            merkle_root: TxMerkleNode::from_byte_array(merkle_root),
+            time,
+            bits: CompactTarget::from_consensus(bits),
+            nonce,
+        },
+    )
+}
+
+fn arb_script() -> impl Strategy<Value = ScriptBuf> {
+    vec(any::<u8>(), 0..40).prop_map(ScriptBuf::from_bytes)
+}
+
+fn arb_witness() -> impl Strategy<Value = Witness> {
+    vec(vec(any::<u8>(), 0..40), 0..3).prop_map(|items| Witness::from_slice(&items))
+}
+
+pub fn arb_txout() -> impl Strategy<Value = TxOut> {
+    (0..=21_000_000 * 100_000_000u64, arb_script()).prop_map(|(value, script_pubkey)| TxOut { value, script_pubkey })
+}
+
+pub fn arb_txin() -> impl Strategy<Value = TxIn> {
+    (any::<[u8; 32]>(), any::<u32>(), arb_script(), any::<u32>(), arb_witness()).prop_map(
+        |(txid, vout, script_sig, sequence, witness)| TxIn {
+            previous_output: OutPoint { txid: Txid::from_byte_array(txid), vout },
+            script_sig,
+            sequence: Sequence(sequence),
+            witness,
+        },
+    )
+}
+
+pub fn arb_transaction() -> impl Strategy<Value = Transaction> {
+    (1..=2i32, any::<u32>(), vec(arb_txin(), 1..4), vec(arb_txout(), 1..4)).prop_map(|(version, lock_time, input, output)| {
+        Transaction {
+            version,
+            lock_time: bitcoin::absolute::LockTime::from_consensus(lock_time),
+            input,
+            output,
+        }
+    })
+}
+
+// Coinbase spending the null outpoint with a 2 to 100 byte scriptSig
+pub fn arb_coinbase() -> impl Strategy<Value = Transaction> {
+    (vec(any::<u8>(), 2..100), vec(arb_txout(), 1..3)).prop_map(|(script_sig, output)| Transaction {
+        version: 2,
+        lock_time: bitcoin::absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig: ScriptBuf::from_bytes(script_sig),
+            sequence: Sequence::MAX,
+            witness: Witness::new(),
+        }],
+        output,
+    })
+}
+
+// Block of a coinbase followed by up to `max_transactions` transactions, with the header
+// merkle root computed from txdata
+pub fn arb_block(max_transactions: usize) -> impl Strategy<Value = Block> {
+    (arb_header(), arb_coinbase(), vec(arb_transaction(), 0..=max_transactions)).prop_map(|(header, coinbase, rest)| {
+        let mut block = Block { header, txdata: std::iter::once(coinbase).chain(rest).collect() };
+        block.header.merkle_root = block.compute_merkle_root().unwrap();
+        block
+    })
+}
+
+// A single header field, excluding `BlockField::All`
+pub fn arb_header_field() -> impl Strategy<Value = BlockField> {
+    prop_oneof![
+        Just(BlockField::Version),
+        Just(BlockField::PrevBlockHash),
+        Just(BlockField::MerkleRoot),
+        Just(BlockField::Timestamp),
+        Just(BlockField::Bits),
+        Just(BlockField::Nonce),
+    ]
+}
+
+// Transaction mutation with indices below `len`, which may still land out of range
+// after earlier mutations shrink txdata
+pub fn arb_transaction_mutation(len: usize) -> impl Strategy<Value = TransactionMutation> {
+    prop_oneof![
+        (0..len).prop_map(TransactionMutation::Drop),
+        (0..len).prop_map(TransactionMutation::Duplicate),
+        (0..len).prop_map(TransactionMutation::CorruptWitness),
+        (0..len, 0..len).prop_map(|(a, b)| TransactionMutation::Swap(a, b)),
+    ]
+}
@@ -0,0 +1,201 @@
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::sha3::sha3_256;
+
+// BIP155 network ids carried in addrv2 entries
+const NET_IPV4: u8 = 1;
+const NET_IPV6: u8 = 2;
+const NET_TORV2: u8 = 3;
+const NET_TORV3: u8 = 4;
+const NET_I2P: u8 = 5;
+const NET_CJDNS: u8 = 6;
+
+const TORV3_VERSION: u8 = 3;
+
+// RFC 4648 base32, lowercase as onion and I2P names are written
+const BASE32_ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+// Where a peer can be reached: an IP endpoint, an overlay network address only a proxy or
+// router can connect to, or a CJDNS address reached over its fc00::/8 tunnel
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PeerAddr {
+    Ip(SocketAddr),
+    Onion { host: String, port: u16 }, // host is the lowercase name ending in .onion
+    I2p { host: String, port: u16 },   // host is the lowercase name ending in .b32.i2p
+    Cjdns { ip: Ipv6Addr, port: u16 },
+}
+
+impl PeerAddr {
+    pub fn port(&self) -> u16 {
+        match self {
+            PeerAddr::Ip(addr) => addr.port(),
+            PeerAddr::Onion { port, .. } | PeerAddr::I2p { port, .. } | PeerAddr::Cjdns { port, .. } => *port,
+        }
+    }
+
+    // Parse "host.onion" or "host.b32.i2p" with an optional ":port"; None for other names
+    pub fn parse_name(name: &str, default_port: u16) -> Option<PeerAddr> {
+        let (host, port) = match name.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().ok()?),
+            None => (name, default_port),
+        };
+        let host = host.to_ascii_lowercase();
+        let is_label = |label: &str| !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric());
+        if host.strip_suffix(".b32.i2p").is_some_and(is_label) {
+            return Some(PeerAddr::I2p { host, port });
+        }
+        if host.strip_suffix(".onion").is_some_and(is_label) {
+            return Some(PeerAddr::Onion { host, port });
+        }
+        None
+    }
+
+    // Address length for each BIP155 network this code knows, whether or not it is still used
+    pub fn bip155_length(network: u8) -> Option<usize> {
+        match network {
+            NET_IPV4 => Some(4),
+            NET_IPV6 | NET_CJDNS => Some(16),
+            NET_TORV2 => Some(10),
+            NET_TORV3 | NET_I2P => Some(32),
+            _ => None,
+        }
+    }
+
+    // Decode an addrv2 address of the length bip155_length gives. None for networks we
+    // cannot use: unknown ones and the retired Tor v2.
+    pub fn from_bip155(network: u8, bytes: &[u8], port: u16) -> Option<PeerAddr> {
+        match network {
+            NET_IPV4 => Some(PeerAddr::Ip(SocketAddr::new(IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(bytes).ok()?)), port))),
+            NET_IPV6 => {
+                let ip = Ipv6Addr::from(<[u8; 16]>::try_from(bytes).ok()?);
+                // Embedded IPv4 must use its own network id
+                ip.to_ipv4_mapped().is_none().then_some(PeerAddr::Ip(SocketAddr::new(IpAddr::V6(ip), port)))
+            }
+            NET_TORV3 => {
+                let pubkey: [u8; 32] = bytes.try_into().ok()?;
+                let mut name = pubkey.to_vec();
+                name.extend(onion_checksum(&pubkey));
+                name.push(TORV3_VERSION);
+                Some(PeerAddr::Onion { host: format!("{}.onion", base32_encode(&name)), port })
+            }
+            NET_I2P => Some(PeerAddr::I2p { host: format!("{}.b32.i2p", base32_encode(bytes)), port }),
+            NET_CJDNS => {
+                let ip = Ipv6Addr::from(<[u8; 16]>::try_from(bytes).ok()?);
+                (ip.octets()[0] == 0xFC).then_some(PeerAddr::Cjdns { ip, port })
+            }
+            _ => None,
+        }
+    }
+
+    // Network id and address bytes for addrv2; None for names that are not valid Tor v3 or
+    // I2P addresses
+    pub fn to_bip155(&self) -> Option<(u8, Vec<u8>)> {
+        match self {
+            PeerAddr::Ip(addr) => match addr.ip() {
+                IpAddr::V4(v4) => Some((NET_IPV4, v4.octets().to_vec())),
+                IpAddr::V6(v6) => Some((NET_IPV6, v6.octets().to_vec())),
+            },
+            PeerAddr::Onion { host, .. } => {
+                let name = base32_decode(host.strip_suffix(".onion")?)?;
+                let (pubkey, rest) = name.split_first_chunk::<32>()?;
+                (rest.len() == 3 && rest[..2] == onion_checksum(pubkey) && rest[2] == TORV3_VERSION).then(|| (NET_TORV3, pubkey.to_vec()))
+            }
+            PeerAddr::I2p { host, .. } => {
+                let hash = base32_decode(host.strip_suffix(".b32.i2p")?)?;
+                (hash.len() == 32).then_some((NET_I2P, hash))
+            }
+            PeerAddr::Cjdns { ip, .. } => Some((NET_CJDNS, ip.octets().to_vec())),
+        }
+    }
+}
+
+impl From<SocketAddr> for PeerAddr {
+    fn from(addr: SocketAddr) -> Self {
+        PeerAddr::Ip(addr)
+    }
+}
+
+impl fmt::Display for PeerAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PeerAddr::Ip(addr) => write!(f, "{}", addr),
+            PeerAddr::Onion { host, port } | PeerAddr::I2p { host, port } => write!(f, "{}:{}", host, port),
+            PeerAddr::Cjdns { ip, port } => write!(f, "[{}]:{}", ip, port),
+        }
+    }
+}
+
+impl FromStr for PeerAddr {
+    type Err = String;
+
+    // The Display form, with the port required. IPv6 addresses in fc00::/8 read back as
+    // CJDNS, the only network that uses that range.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(addr) = s.parse::<SocketAddr>() {
+            return Ok(match addr.ip() {
+                IpAddr::V6(ip) if ip.octets()[0] == 0xFC => PeerAddr::Cjdns { ip, port: addr.port() },
+                _ => PeerAddr::Ip(addr),
+            });
+        }
+        match s.rsplit_once(':') {
+            Some((_, port)) if port.parse::<u16>().is_ok() => PeerAddr::parse_name(s, 0).ok_or_else(|| format!("invalid peer address {}", s)),
+            _ => Err(format!("peer address {} has no port", s)),
+        }
+    }
+}
+
+// Stored as its Display string, which for IP endpoints matches how SocketAddr serializes
+impl Serialize for PeerAddr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for PeerAddr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+// First two bytes of SHA3-256(".onion checksum" || pubkey || version)
+fn onion_checksum(pubkey: &[u8; 32]) -> [u8; 2] {
+    let digest = sha3_256(&[b".onion checksum", &pubkey[..], &[TORV3_VERSION]].concat());
+    [digest[0], digest[1]]
+}
+
+fn base32_encode(data: &[u8]) -> String {
+    let mut out = String::new();
+    let (mut buffer, mut bits) = (0u32, 0);
+    for &byte in data {
+        buffer = buffer << 8 | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32_ALPHABET[(buffer >> bits) as usize & 31] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[(buffer << (5 - bits)) as usize & 31] as char);
+    }
+    out
+}
+
+// Decode unpadded base32, dropping the leftover bits of the final character
+fn base32_decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let (mut buffer, mut bits) = (0u32, 0);
+    for c in text.bytes() {
+        let value = BASE32_ALPHABET.iter().position(|&a| a == c.to_ascii_lowercase())? as u32;
+        buffer = buffer << 5 | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Some(out)
+}
@@ -0,0 +1,573 @@
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+
+use rand::seq::{IndexedRandom, SliceRandom};
+use rand::Rng;
+use sha2::{Digest, Sha256};
+
+use crate::address::PeerAddr;
+use crate::message::AddrEntry;
+
+// Address manager modelled on Bitcoin Core's addrman: addresses we only heard about live in
+// the "new" table, addresses we connected to move to the "tried" table. Bucket placement is
+// keyed by a secret and by network group, so one source cannot flood either table.
+
+const NEW_BUCKET_COUNT: usize = 1024;
+const TRIED_BUCKET_COUNT: usize = 256;
+const BUCKET_SIZE: usize = 64;
+
+// New buckets one source group can reach, and tried buckets one address group can reach
+const NEW_BUCKETS_PER_SOURCE_GROUP: u64 = 64;
+const TRIED_BUCKETS_PER_GROUP: u64 = 8;
+
+// Most new buckets a single address may occupy
+const NEW_BUCKETS_PER_ADDRESS: u32 = 8;
+
+// Quality thresholds, in seconds
+const HORIZON: u64 = 30 * 24 * 60 * 60; // addresses not seen for this long are terrible
+const MIN_FAIL: u64 = 7 * 24 * 60 * 60; // window without success for MAX_FAILURES
+const REPLACEMENT: u64 = 4 * 60 * 60;   // a tried entry that worked this recently is kept
+const TEST_WINDOW: u64 = 40 * 60;       // how long a collision waits for its test
+const RETRIES: u32 = 3;                 // failures before a never-connected address is terrible
+const MAX_FAILURES: u32 = 10;           // failures before any address is terrible
+
+// Most tried-table collisions waiting to be resolved
+const MAX_TRIED_COLLISIONS: usize = 10;
+
+// Buckets select draws before falling back to a scan of the table. Each rejected candidate
+// raises the chance factor 1.2 times, which takes even the least likely address within
+// about 45 candidates, so only a table too sparse for random draws to find its entries
+// gets this far.
+const SELECT_DRAWS: usize = 4 * NEW_BUCKET_COUNT;
+
+// An address with everything addrman tracks about it
+#[derive(Debug, Clone)]
+struct AddrInfo {
+    addr: PeerAddr,
+    services: u64,
+    timestamp: u32,    // last time the network saw it, per addr messages
+    source: PeerAddr,  // peer that told us about it
+    last_try: u64,
+    last_success: u64,
+    attempts: u32,     // failed attempts since the last success
+    ref_count: u32,    // new buckets referencing it
+    in_tried: bool,
+}
+
+impl AddrInfo {
+    // Not worth keeping: stale, from the future, or failing repeatedly
+    fn is_terrible(&self, now: u64) -> bool {
+        let timestamp = self.timestamp as u64;
+        if self.last_try != 0 && now.saturating_sub(self.last_try) <= 60 {
+            return false; // tried in the last minute; give it a chance
+        }
+        timestamp > now + 10 * 60
+            || timestamp == 0
+            || now.saturating_sub(timestamp) > HORIZON
+            || (self.last_success == 0 && self.attempts >= RETRIES)
+            || (now.saturating_sub(self.last_success) > MIN_FAIL && self.attempts >= MAX_FAILURES)
+    }
+
+    // Relative chance of being selected, lower for recently tried and failing addresses
+    fn chance(&self, now: u64) -> f64 {
+        let mut chance = 1.0;
+        if now.saturating_sub(self.last_try) < 10 * 60 {
+            chance *= 0.01;
+        }
+        chance * 0.66f64.powi(self.attempts.min(8) as i32)
+    }
+}
+
+#[derive(Debug)]
+pub struct AddrMan {
+    key: [u8; 32],
+    infos: HashMap<u64, AddrInfo>,
+    ids: HashMap<PeerAddr, u64>,
+    next_id: u64,
+    new_table: Vec<[Option<u64>; BUCKET_SIZE]>,
+    tried_table: Vec<[Option<u64>; BUCKET_SIZE]>,
+    new_count: usize,
+    tried_count: usize,
+    tried_collisions: HashSet<u64>, // new addresses whose tried slot is taken
+}
+
+impl Default for AddrMan {
+    fn default() -> Self {
+        AddrMan {
+            key: rand::random(),
+            infos: HashMap::new(),
+            ids: HashMap::new(),
+            next_id: 0,
+            new_table: vec![[None; BUCKET_SIZE]; NEW_BUCKET_COUNT],
+            tried_table: vec![[None; BUCKET_SIZE]; TRIED_BUCKET_COUNT],
+            new_count: 0,
+            tried_count: 0,
+            tried_collisions: HashSet::new(),
+        }
+    }
+}
+
+impl AddrMan {
+    pub fn addresses(&self) -> impl Iterator<Item = &PeerAddr> {
+        self.ids.keys()
+    }
+
+    // Add addresses learned from source. Returns how many were not known before.
+    pub fn add(&mut self, entries: impl IntoIterator<Item = AddrEntry>, source: &PeerAddr, now: u64) -> usize {
+        entries
+            .into_iter()
+            .filter(|entry| self.add_one(entry.addr.clone(), entry.services, entry.timestamp, source, now))
+            .count()
+    }
+
+    // Add an address we were told about out of band, such as a seed, as seen just now
+    pub fn add_seed(&mut self, addr: PeerAddr, now: u64) -> bool {
+        let source = addr.clone();
+        self.add_one(addr, 0, now as u32, &source, now)
+    }
+
+    fn add_one(&mut self, addr: PeerAddr, services: u64, timestamp: u32, source: &PeerAddr, now: u64) -> bool {
+        // I2P has no ports; anywhere else port 0 cannot be connected to
+        if addr.port() == 0 && !matches!(addr, PeerAddr::I2p { .. }) {
+            return false;
+        }
+
+        let (id, is_new) = match self.ids.get(&addr) {
+            Some(&id) => {
+                let info = self.infos.get_mut(&id).unwrap();
+                info.timestamp = info.timestamp.max(timestamp);
+                info.services |= services;
+                if info.in_tried || info.ref_count >= NEW_BUCKETS_PER_ADDRESS {
+                    return false;
+                }
+                // Each extra bucket is half as likely as the last, so a widely gossiped
+                // address does not crowd out the rest
+                if rand::rng().random_range(0..1u64 << info.ref_count) != 0 {
+                    return false;
+                }
+                (id, false)
+            }
+            None => {
+                let id = self.next_id;
+                self.next_id += 1;
+                let info = AddrInfo {
+                    addr: addr.clone(),
+                    services,
+                    timestamp,
+                    source: source.clone(),
+                    last_try: 0,
+                    last_success: 0,
+                    attempts: 0,
+                    ref_count: 0,
+                    in_tried: false,
+                };
+                self.infos.insert(id, info);
+                self.ids.insert(addr.clone(), id);
+                self.new_count += 1;
+                (id, true)
+            }
+        };
+
+        let bucket = self.new_bucket(&addr, source);
+        let position = self.bucket_position(&addr, true, bucket);
+        match self.new_table[bucket][position] {
+            Some(existing) if existing == id => {}
+            Some(existing) => {
+                // Collision: replace the occupant only if it is worse off than we are
+                let occupant = &self.infos[&existing];
+                let newcomer_refs = self.infos[&id].ref_count;
+                if occupant.is_terrible(now) || (occupant.ref_count > 1 && newcomer_refs == 0) {
+                    self.clear_new(bucket, position);
+                    self.place_new(id, bucket, position);
+                } else if newcomer_refs == 0 {
+                    self.delete(id);
+                }
+            }
+            None => self.place_new(id, bucket, position),
+        }
+        is_new && self.ids.contains_key(&addr)
+    }
+
+    // Note a connection attempt; failures lower the address's selection chance
+    pub fn attempt(&mut self, addr: &PeerAddr, count_failure: bool, now: u64) {
+        if let Some(info) = self.ids.get(addr).and_then(|id| self.infos.get_mut(id)) {
+            info.last_try = now;
+            if count_failure {
+                info.attempts += 1;
+            }
+        }
+    }
+
+    // Note a successful connection, moving the address to the tried table. When its tried
+    // slot is taken the move waits for resolve_collisions.
+    pub fn good(&mut self, addr: &PeerAddr, now: u64) {
+        let Some(&id) = self.ids.get(addr) else { return };
+        let info = self.infos.get_mut(&id).unwrap();
+        info.last_try = now;
+        info.last_success = now;
+        info.attempts = 0;
+        if info.in_tried {
+            return;
+        }
+
+        let bucket = self.tried_bucket(addr);
+        let position = self.bucket_position(addr, false, bucket);
+        if self.tried_table[bucket][position].is_some() {
+            if self.tried_collisions.len() < MAX_TRIED_COLLISIONS {
+                self.tried_collisions.insert(id);
+            }
+            return;
+        }
+        self.make_tried(id);
+    }
+
+    // Tried address whose slot a pending collision wants; connecting to it and reporting the
+    // outcome lets resolve_collisions decide which of the two stays
+    pub fn select_tried_collision(&self) -> Option<PeerAddr> {
+        let id = *self.tried_collisions.iter().next()?;
+        let addr = &self.infos[&id].addr;
+        let bucket = self.tried_bucket(addr);
+        let position = self.bucket_position(addr, false, bucket);
+        self.tried_table[bucket][position].map(|occupant| self.infos[&occupant].addr.clone())
+    }
+
+    // Settle pending collisions: the old tried entry stays if it worked recently, and is
+    // evicted if it failed its test or was never tested within TEST_WINDOW
+    pub fn resolve_collisions(&mut self, now: u64) {
+        for id in self.tried_collisions.clone() {
+            let Some(info) = self.infos.get(&id) else {
+                self.tried_collisions.remove(&id);
+                continue;
+            };
+            if info.in_tried {
+                self.tried_collisions.remove(&id);
+                continue;
+            }
+            let bucket = self.tried_bucket(&info.addr);
+            let position = self.bucket_position(&info.addr, false, bucket);
+            let evict = match self.tried_table[bucket][position] {
+                None => true,
+                Some(occupant) => {
+                    let old = &self.infos[&occupant];
+                    if now.saturating_sub(old.last_success) < REPLACEMENT {
+                        false
+                    } else if now.saturating_sub(old.last_try) < REPLACEMENT {
+                        // Tried recently without success; wait a minute for the attempt to finish
+                        if now.saturating_sub(old.last_try) <= 60 {
+                            continue;
+                        }
+                        true
+                    } else if now.saturating_sub(info.last_success) > TEST_WINDOW {
+                        true
+                    } else {
+                        continue;
+                    }
+                }
+            };
+            if evict {
+                self.make_tried(id);
+            }
+            self.tried_collisions.remove(&id);
+        }
+    }
+
+    // Addresses to answer a getaddr with, as Bitcoin Core does: a random sample of at most
+    // max_pct percent of what we know, capped at max_count, leaving out terrible entries
+    pub fn get_addr(&self, max_count: usize, max_pct: usize, now: u64) -> Vec<AddrEntry> {
+        let mut infos: Vec<&AddrInfo> = self.infos.values().collect();
+        infos.shuffle(&mut rand::rng());
+        infos
+            .into_iter()
+            .filter(|info| !info.is_terrible(now))
+            .take(max_count.min(self.infos.len() * max_pct / 100))
+            .map(|info| AddrEntry { timestamp: info.timestamp, services: info.services, addr: info.addr.clone() })
+            .collect()
+    }
+
+    // Pick an address to connect to, favouring tried and healthy addresses the way
+    // Bitcoin Core does. With new_only, only the new table is considered.
+    pub fn select(&self, new_only: bool, now: u64) -> Option<PeerAddr> {
+        if self.infos.is_empty() || (new_only && self.new_count == 0) {
+            return None;
+        }
+        let use_tried = !new_only && self.tried_count > 0 && (self.new_count == 0 || rand::random::<bool>());
+        let table = if use_tried { &self.tried_table } else { &self.new_table };
+
+        let mut rng = rand::rng();
+        let mut chance_factor = 1.0;
+        for _ in 0..SELECT_DRAWS {
+            let bucket = &table[rng.random_range(0..table.len())];
+            let start = rng.random_range(0..BUCKET_SIZE);
+            let Some(id) = (0..BUCKET_SIZE).find_map(|i| bucket[(start + i) % BUCKET_SIZE]) else {
+                continue;
+            };
+            let info = &self.infos[&id];
+            if rng.random::<f64>() < chance_factor * info.chance(now) {
+                return Some(info.addr.clone());
+            }
+            chance_factor *= 1.2;
+        }
+        let ids: Vec<u64> = table.iter().flatten().flatten().copied().collect();
+        ids.choose(&mut rng).map(|id| self.infos[id].addr.clone())
+    }
+
+    fn place_new(&mut self, id: u64, bucket: usize, position: usize) {
+        self.new_table[bucket][position] = Some(id);
+        self.infos.get_mut(&id).unwrap().ref_count += 1;
+    }
+
+    // Empty a new-table slot, forgetting its address once no bucket references it
+    fn clear_new(&mut self, bucket: usize, position: usize) {
+        if let Some(id) = self.new_table[bucket][position].take() {
+            let info = self.infos.get_mut(&id).unwrap();
+            info.ref_count -= 1;
+            if info.ref_count == 0 {
+                self.delete(id);
+            }
+        }
+    }
+
+    fn delete(&mut self, id: u64) {
+        if let Some(info) = self.infos.remove(&id) {
+            self.ids.remove(&info.addr);
+            self.new_count -= 1;
+        }
+    }
+
+    // Move a new address into its tried slot, pushing any occupant back to the new table
+    fn make_tried(&mut self, id: u64) {
+        let addr = self.infos[&id].addr.clone();
+        for bucket in 0..NEW_BUCKET_COUNT {
+            let position = self.bucket_position(&addr, true, bucket);
+            if self.new_table[bucket][position] == Some(id) {
+                self.new_table[bucket][position] = None;
+            }
+        }
+        self.infos.get_mut(&id).unwrap().ref_count = 0;
+        self.new_count -= 1;
+
+        let bucket = self.tried_bucket(&addr);
+        let position = self.bucket_position(&addr, false, bucket);
+        if let Some(evicted) = self.tried_table[bucket][position].take() {
+            let (evicted_addr, source) = {
+                let info = self.infos.get_mut(&evicted).unwrap();
+                info.in_tried = false;
+                (info.addr.clone(), info.source.clone())
+            };
+            self.tried_count -= 1;
+            self.new_count += 1;
+            let new_bucket = self.new_bucket(&evicted_addr, &source);
+            let new_position = self.bucket_position(&evicted_addr, true, new_bucket);
+            self.clear_new(new_bucket, new_position);
+            self.place_new(evicted, new_bucket, new_position);
+        }
+
+        self.tried_table[bucket][position] = Some(id);
+        self.infos.get_mut(&id).unwrap().in_tried = true;
+        self.tried_count += 1;
+    }
+
+    // Keyed hash truncated to 64 bits, as Bitcoin Core's cheap hash
+    fn hash(&self, parts: &[&[u8]]) -> u64 {
+        let mut hasher = Sha256::new().chain_update(self.key);
+        for part in parts {
+            hasher.update(part);
+        }
+        let digest = Sha256::digest(hasher.finalize());
+        u64::from_le_bytes(digest[..8].try_into().unwrap())
+    }
+
+    fn tried_bucket(&self, addr: &PeerAddr) -> usize {
+        let slot = self.hash(&[&addr_key(addr)]) % TRIED_BUCKETS_PER_GROUP;
+        (self.hash(&[&group(addr), &slot.to_le_bytes()]) % TRIED_BUCKET_COUNT as u64) as usize
+    }
+
+    fn new_bucket(&self, addr: &PeerAddr, source: &PeerAddr) -> usize {
+        let source_group = group(source);
+        let slot = self.hash(&[&group(addr), &source_group]) % NEW_BUCKETS_PER_SOURCE_GROUP;
+        (self.hash(&[&source_group, &slot.to_le_bytes()]) % NEW_BUCKET_COUNT as u64) as usize
+    }
+
+    fn bucket_position(&self, addr: &PeerAddr, new: bool, bucket: usize) -> usize {
+        let table = if new { b"N" } else { b"K" };
+        (self.hash(&[table, &(bucket as u64).to_le_bytes(), &addr_key(addr)]) % BUCKET_SIZE as u64) as usize
+    }
+}
+
+// Host and port as hashed into bucket choices
+fn addr_key(addr: &PeerAddr) -> Vec<u8> {
+    let mut key = match addr {
+        PeerAddr::Ip(ip) => match ip.ip() {
+            IpAddr::V4(v4) => v4.to_ipv6_mapped().octets().to_vec(),
+            IpAddr::V6(v6) => v6.octets().to_vec(),
+        },
+        PeerAddr::Cjdns { ip, .. } => ip.octets().to_vec(),
+        PeerAddr::Onion { host, .. } | PeerAddr::I2p { host, .. } => host.as_bytes().to_vec(),
+    };
+    key.extend(addr.port().to_be_bytes());
+    key
+}
+
+// Network group, tagged with its BIP155 network id: the /16 of an IPv4 address, the /32 of
+// an IPv6 address, the first 12 bits after the fc prefix for CJDNS, and the first base32
+// character (five bits) of onion and I2P names
+fn group(addr: &PeerAddr) -> Vec<u8> {
+    let ip = match addr {
+        PeerAddr::Ip(ip) => ip.ip(),
+        PeerAddr::Onion { host, .. } => return vec![4, host.as_bytes()[0]],
+        PeerAddr::I2p { host, .. } => return vec![5, host.as_bytes()[0]],
+        PeerAddr::Cjdns { ip, .. } => return vec![6, ip.octets()[1], ip.octets()[2] & 0xF0],
+    };
+    let ip = match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(IpAddr::V6(v6)),
+        v4 => v4,
+    };
+    match ip {
+        IpAddr::V4(v4) => [&[1u8][..], &v4.octets()[..2]].concat(),
+        IpAddr::V6(v6) => [&[2u8][..], &v6.octets()[..4]].concat(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn ip(a: u8, b: u8, c: u8, d: u8) -> PeerAddr {
+        PeerAddr::Ip(SocketAddr::new(Ipv4Addr::new(a, b, c, d).into(), 8333))
+    }
+
+    fn entry(addr: PeerAddr) -> AddrEntry {
+        AddrEntry { timestamp: NOW as u32, services: 1, addr }
+    }
+
+    // A fixed key, so bucket placement is the same on every run
+    fn addrman() -> AddrMan {
+        AddrMan { key: [7; 32], ..AddrMan::default() }
+    }
+
+    fn occupied(table: &[[Option<u64>; BUCKET_SIZE]]) -> Vec<usize> {
+        table.iter().enumerate().filter(|(_, bucket)| bucket.iter().any(Option::is_some)).map(|(i, _)| i).collect()
+    }
+
+    #[test]
+    fn limits_the_buckets_a_group_reaches() {
+        let mut addrman = addrman();
+        // Addresses from many groups, all told to us by one source: they land in at most
+        // the source group's share of new buckets
+        let source = ip(203, 0, 113, 1);
+        let entries = (0..600u32).map(|i| entry(ip(1 + (i % 200) as u8, (i / 200) as u8, 1, 1)));
+        assert!(addrman.add(entries, &source, NOW) > 300);
+        assert!(occupied(&addrman.new_table).len() <= NEW_BUCKETS_PER_SOURCE_GROUP as usize);
+
+        // Placement depends only on the address, the source and the key
+        let addr = ip(198, 51, 100, 7);
+        assert_eq!(addrman.new_bucket(&addr, &source), addrman.new_bucket(&addr, &source));
+        assert_ne!(addrman.new_bucket(&addr, &source), AddrMan::default().new_bucket(&addr, &source));
+
+        // Addresses of one /16 promoted to tried share at most TRIED_BUCKETS_PER_GROUP buckets
+        let mut addrman = self::addrman();
+        for i in 0..64 {
+            let addr = ip(10, 1, i, 1);
+            addrman.add_seed(addr.clone(), NOW);
+            addrman.good(&addr, NOW);
+        }
+        assert!(addrman.tried_count > 32);
+        assert!(occupied(&addrman.tried_table).len() <= TRIED_BUCKETS_PER_GROUP as usize);
+    }
+
+    #[test]
+    fn select_finds_a_lone_unlikely_address() {
+        let mut addrman = addrman();
+        assert_eq!(addrman.select(false, NOW), None);
+        let addr = ip(192, 0, 2, 1);
+        addrman.add_seed(addr.clone(), NOW);
+        // Failing and just tried: a chance of about 1 in 3000
+        for _ in 0..8 {
+            addrman.attempt(&addr, true, NOW);
+        }
+        for _ in 0..20 {
+            assert_eq!(addrman.select(false, NOW), Some(addr.clone()));
+        }
+    }
+
+    #[test]
+    fn good_addresses_move_to_tried() {
+        let mut addrman = addrman();
+        let addr = ip(192, 0, 2, 1);
+        addrman.add_seed(addr.clone(), NOW);
+        assert_eq!((addrman.new_count, addrman.tried_count), (1, 0));
+        assert_eq!(addrman.select(true, NOW), Some(addr.clone()));
+
+        addrman.good(&addr, NOW);
+        assert_eq!((addrman.new_count, addrman.tried_count), (0, 1));
+        assert!(addrman.new_table.iter().flatten().all(Option::is_none));
+        assert_eq!(addrman.select(true, NOW), None);
+        assert_eq!(addrman.select(false, NOW), Some(addr.clone()));
+
+        // A tried address heard about again stays put
+        assert_eq!(addrman.add([entry(addr.clone())], &ip(203, 0, 113, 1), NOW), 0);
+        assert_eq!((addrman.new_count, addrman.tried_count), (0, 1));
+    }
+
+    // Two addresses of one group wanting the same tried slot, the first already there
+    fn collision() -> (AddrMan, PeerAddr, PeerAddr) {
+        let mut addrman = addrman();
+        let mut slots: HashMap<(usize, usize), PeerAddr> = HashMap::new();
+        for i in 0..=255 {
+            let addr = ip(10, 2, i, 1);
+            let bucket = addrman.tried_bucket(&addr);
+            let slot = (bucket, addrman.bucket_position(&addr, false, bucket));
+            if let Some(first) = slots.insert(slot, addr.clone()) {
+                for addr in [&first, &addr] {
+                    addrman.add_seed(addr.clone(), NOW);
+                }
+                addrman.good(&first, NOW);
+                return (addrman, first, addr);
+            }
+        }
+        unreachable!("512 slots hold 256 addresses without a collision");
+    }
+
+    #[test]
+    fn collision_keeps_a_recently_good_entry() {
+        let (mut addrman, old, newcomer) = collision();
+        addrman.good(&newcomer, NOW + 60);
+        assert_eq!(addrman.select_tried_collision(), Some(old.clone()));
+
+        // The occupant answers its test
+        addrman.good(&old, NOW + 120);
+        addrman.resolve_collisions(NOW + 120);
+        assert_eq!(addrman.select_tried_collision(), None);
+        assert!(addrman.infos[&addrman.ids[&old]].in_tried);
+        assert!(!addrman.infos[&addrman.ids[&newcomer]].in_tried);
+        assert_eq!((addrman.new_count, addrman.tried_count), (1, 1));
+    }
+
+    #[test]
+    fn collision_evicts_an_entry_failing_its_test() {
+        let (mut addrman, old, newcomer) = collision();
+        let later = NOW + REPLACEMENT + 1;
+        addrman.good(&newcomer, later);
+        assert_eq!(addrman.select_tried_collision(), Some(old.clone()));
+
+        // Still waiting on the test
+        addrman.resolve_collisions(later);
+        assert_eq!(addrman.select_tried_collision(), Some(old.clone()));
+
+        // The occupant fails it, and once the attempt is over the newcomer takes its place
+        addrman.attempt(&old, true, later + 10);
+        addrman.resolve_collisions(later + 30);
+        assert_eq!(addrman.select_tried_collision(), Some(old.clone()));
+        addrman.resolve_collisions(later + 100);
+        assert_eq!(addrman.select_tried_collision(), None);
+        assert!(addrman.infos[&addrman.ids[&newcomer]].in_tried);
+        assert!(!addrman.infos[&addrman.ids[&old]].in_tried);
+        assert_eq!((addrman.new_count, addrman.tried_count), (1, 1));
+        // The evicted entry went back to the new table rather than being forgotten
+        assert!(addrman.new_table.iter().flatten().any(|slot| *slot == Some(addrman.ids[&old])));
+    }
+}
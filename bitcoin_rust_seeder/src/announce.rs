@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use bitcoin::hashes::Hash;
+use bitcoin::BlockHash;
+use serde::Serialize;
+
+use crate::address::PeerAddr;
+use crate::message::{GetHeadersMessage, InvType, Message, MessageError};
+use crate::peer::{ConnectConfig, Peer, PROTOCOL_VERSION};
+use crate::shutdown;
+
+// Block announcement watching: stay connected to several peers, ask each for header
+// announcements (BIP130) and timestamp every block as each peer announces it, so the spread
+// between the first and last announcement shows how blocks propagate
+
+// First protocol version that understands sendheaders
+const SEND_HEADERS_VERSION: i32 = 70012;
+
+// How often peer threads look for a shutdown request
+const SHUTDOWN_POLL: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnnouncedVia {
+    Headers,
+    Inv,
+}
+
+// Something that happened on one of the watched connections
+#[derive(Debug, Clone)]
+pub enum WatchEvent {
+    Connected { peer: PeerAddr, user_agent: String, height: i32 },
+    Announced { peer: PeerAddr, hash: BlockHash, via: AnnouncedVia, block_time: Option<u32>, received_ms: u64 },
+    Closed { peer: PeerAddr, error: Option<String> }, // error is None when the watch ended
+}
+
+// One peer's announcement of a block
+#[derive(Debug, Clone, Serialize)]
+pub struct Announcement {
+    pub peer: PeerAddr,
+    pub via: AnnouncedVia,
+    pub received_ms: u64, // unix time in milliseconds
+    pub delay_ms: u64,    // after the block's first announcement
+}
+
+// Every announcement of one block, first one first
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockAnnouncements {
+    pub hash: BlockHash,
+    pub block_time: Option<u32>, // from the header, once a peer announced it with one
+    pub first_seen_ms: u64,
+    pub announcements: Vec<Announcement>,
+}
+
+// Per peer view of a watch
+#[derive(Debug, Clone, Serialize)]
+pub struct PeerAnnouncements {
+    pub peer: PeerAddr,
+    pub connected: bool,
+    pub blocks: usize,       // distinct blocks announced
+    pub first: usize,        // blocks this peer announced before anyone else
+    pub via_headers: usize,
+    pub mean_delay_ms: Option<u64>,
+    pub error: Option<String>,
+}
+
+// What a watch saw: blocks in the order they were first announced, and each peer's part
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchReport {
+    pub blocks: Vec<BlockAnnouncements>,
+    pub peers: Vec<PeerAnnouncements>,
+}
+
+#[derive(Debug, Default)]
+struct AnnouncementLog {
+    blocks: Vec<BlockAnnouncements>,
+    index: HashMap<BlockHash, usize>,
+}
+
+impl AnnouncementLog {
+    // Record one announcement, returning its delay after the block's first; a peer
+    // repeating itself, over inv and then headers say, counts once
+    fn record(&mut self, peer: &PeerAddr, hash: BlockHash, via: AnnouncedVia, block_time: Option<u32>, received_ms: u64) -> Option<u64> {
+        let blocks = &mut self.blocks;
+        let position = *self.index.entry(hash).or_insert_with(|| {
+            blocks.push(BlockAnnouncements { hash, block_time: None, first_seen_ms: received_ms, announcements: Vec::new() });
+            blocks.len() - 1
+        });
+        let block = &mut self.blocks[position];
+        block.block_time = block.block_time.or(block_time);
+        if block.announcements.iter().any(|announcement| &announcement.peer == peer) {
+            return None;
+        }
+        let delay_ms = received_ms.saturating_sub(block.first_seen_ms);
+        block.announcements.push(Announcement { peer: peer.clone(), via, received_ms, delay_ms });
+        Some(delay_ms)
+    }
+
+    fn peer_summary(&self, peer: &PeerAddr, connected: bool, error: Option<String>) -> PeerAnnouncements {
+        let announcements: Vec<&Announcement> = self.blocks.iter().flat_map(|block| &block.announcements).filter(|announcement| &announcement.peer == peer).collect();
+        PeerAnnouncements {
+            peer: peer.clone(),
+            connected,
+            blocks: announcements.len(),
+            first: announcements.iter().filter(|announcement| announcement.delay_ms == 0).count(),
+            via_headers: announcements.iter().filter(|announcement| announcement.via == AnnouncedVia::Headers).count(),
+            mean_delay_ms: (!announcements.is_empty())
+                .then(|| announcements.iter().map(|announcement| announcement.delay_ms).sum::<u64>() / announcements.len() as u64),
+            error,
+        }
+    }
+}
+
+// Watch the peers for the given duration, or until shutdown is requested, passing every
+// event to on_event as it happens, announcements with their delay after the block's first
+pub fn watch_announcements(
+    peers: &[PeerAddr],
+    config: &ConnectConfig,
+    duration: Duration,
+    mut on_event: impl FnMut(&WatchEvent, Option<u64>),
+) -> WatchReport {
+    let deadline = Instant::now() + duration;
+    let (events, received) = mpsc::channel();
+    let mut log = AnnouncementLog::default();
+    let mut closed = HashMap::new();
+    let mut connected = Vec::new();
+
+    thread::scope(|scope| {
+        for peer in peers {
+            let events = events.clone();
+            scope.spawn(move || {
+                let error = watch_peer(peer, config, deadline, &events).err().map(|e| e.to_string());
+                let _ = events.send(WatchEvent::Closed { peer: peer.clone(), error });
+            });
+        }
+        drop(events);
+
+        for event in received {
+            let mut delay_ms = None;
+            match &event {
+                WatchEvent::Connected { peer, .. } => connected.push(peer.clone()),
+                WatchEvent::Announced { peer, hash, via, block_time, received_ms } => {
+                    delay_ms = log.record(peer, *hash, *via, *block_time, *received_ms);
+                    if delay_ms.is_none() {
+                        continue;
+                    }
+                }
+                WatchEvent::Closed { peer, error } => {
+                    closed.insert(peer.clone(), error.clone());
+                }
+            }
+            on_event(&event, delay_ms);
+        }
+    });
+
+    let peers = peers.iter().map(|peer| log.peer_summary(peer, connected.contains(peer), closed.remove(peer).flatten())).collect();
+    WatchReport { blocks: log.blocks, peers }
+}
+
+// Connect, ask for header announcements and report blocks until the deadline. A peer that
+// announces with inv does so because it does not know we have the parent; asking for the
+// headers after the announced block tells it we have its tip, and later blocks then come as
+// headers, as they do between Core nodes.
+fn watch_peer(addr: &PeerAddr, config: &ConnectConfig, deadline: Instant, events: &mpsc::Sender<WatchEvent>) -> Result<(), MessageError> {
+    let mut peer = Peer::open(addr, config)?;
+    let version = peer.version.clone().unwrap();
+    let _ = events.send(WatchEvent::Connected { peer: addr.clone(), user_agent: version.user_agent, height: version.start_height });
+    if version.version >= SEND_HEADERS_VERSION {
+        peer.send(&Message::SendHeaders)?;
+    }
+
+    while Instant::now() < deadline && !shutdown::requested() {
+        let Some(message) = peer.receive_before(deadline.min(Instant::now() + SHUTDOWN_POLL))? else {
+            continue;
+        };
+        let received_ms = unix_millis();
+        match message {
+            Message::Headers(headers) => {
+                for header in headers {
+                    let hash = header.block_hash();
+                    let _ = events.send(WatchEvent::Announced { peer: addr.clone(), hash, via: AnnouncedVia::Headers, block_time: Some(header.time), received_ms });
+                }
+            }
+            Message::Inv(items) => {
+                let blocks: Vec<BlockHash> = items
+                    .iter()
+                    .filter(|item| matches!(item.kind, InvType::Block | InvType::WitnessBlock))
+                    .map(|item| BlockHash::from_byte_array(item.hash))
+                    .collect();
+                for hash in &blocks {
+                    let _ = events.send(WatchEvent::Announced { peer: addr.clone(), hash: *hash, via: AnnouncedVia::Inv, block_time: None, received_ms });
+                }
+                if let Some(tip) = blocks.last() {
+                    peer.send(&Message::GetHeaders(GetHeadersMessage {
+                        version: PROTOCOL_VERSION as u32,
+                        locator: vec![*tip],
+                        stop_hash: BlockHash::all_zeros(),
+                    }))?;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64
+}
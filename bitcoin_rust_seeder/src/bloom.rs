@@ -0,0 +1,103 @@
+use std::f64::consts::LN_2;
+
+use bitcoin::script::Instruction;
+use bitcoin::Script;
+
+// BIP37 bloom filters, loaded into a peer so it only relays transactions we care about
+
+// Largest filter and hash count peers accept
+pub const MAX_BLOOM_FILTER_SIZE: usize = 36_000;
+pub const MAX_HASH_FUNCS: u32 = 50;
+
+// Largest element filteradd may carry, the script push limit
+pub const MAX_FILTER_ADD_SIZE: usize = 520;
+
+// How the peer updates the filter when a transaction output matches
+pub const BLOOM_UPDATE_NONE: u8 = 0;
+pub const BLOOM_UPDATE_ALL: u8 = 1;
+pub const BLOOM_UPDATE_P2PUBKEY_ONLY: u8 = 2;
+
+// Seed multiplier separating the hash functions
+const HASH_SEED_STEP: u32 = 0xFBA4C795;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BloomFilter {
+    pub data: Vec<u8>,
+    pub hash_funcs: u32,
+    pub tweak: u32, // random per filter, so filters of different clients hash differently
+    pub flags: u8,  // one of the BLOOM_UPDATE values
+}
+
+impl BloomFilter {
+    // Size a filter for the expected number of elements and false positive rate, within
+    // the limits peers enforce
+    pub fn new(elements: usize, fp_rate: f64, tweak: u32, flags: u8) -> Self {
+        let elements = elements.max(1) as f64;
+        let bits = -elements * fp_rate.ln() / (LN_2 * LN_2);
+        let size = ((bits / 8.0) as usize).clamp(1, MAX_BLOOM_FILTER_SIZE);
+        let hash_funcs = ((size * 8) as f64 / elements * LN_2) as u32;
+        BloomFilter { data: vec![0; size], hash_funcs: hash_funcs.clamp(1, MAX_HASH_FUNCS), tweak, flags }
+    }
+
+    pub fn insert(&mut self, element: &[u8]) {
+        for i in 0..self.hash_funcs {
+            let bit = self.bit_index(i, element);
+            self.data[bit / 8] |= 1 << (bit % 8);
+        }
+    }
+
+    pub fn contains(&self, element: &[u8]) -> bool {
+        !self.data.is_empty() && (0..self.hash_funcs).all(|i| {
+            let bit = self.bit_index(i, element);
+            self.data[bit / 8] & (1 << (bit % 8)) != 0
+        })
+    }
+
+    // Whether any data push in the script is in the filter, which is how peers match
+    // output scripts
+    pub fn matches_script(&self, script: &Script) -> bool {
+        script_pushes(script).iter().any(|push| self.contains(push))
+    }
+
+    fn bit_index(&self, i: u32, element: &[u8]) -> usize {
+        murmur3(i.wrapping_mul(HASH_SEED_STEP).wrapping_add(self.tweak), element) as usize % (self.data.len() * 8)
+    }
+}
+
+// Data pushes of a script; these are the elements a filter must hold to match it, e.g.
+// the key hash of a P2PKH script or the witness program of a segwit one
+pub fn script_pushes(script: &Script) -> Vec<Vec<u8>> {
+    script
+        .instructions()
+        .filter_map(|instruction| match instruction {
+            Ok(Instruction::PushBytes(push)) if !push.is_empty() => Some(push.as_bytes().to_vec()),
+            _ => None,
+        })
+        .collect()
+}
+
+// MurmurHash3 (x86, 32-bit), the hash BIP37 specifies
+fn murmur3(seed: u32, data: &[u8]) -> u32 {
+    const C1: u32 = 0xCC9E2D51;
+    const C2: u32 = 0x1B873593;
+
+    let mut h = seed;
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        let k = u32::from_le_bytes(chunk.try_into().unwrap()).wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        h = (h ^ k).rotate_left(13).wrapping_mul(5).wrapping_add(0xE6546B64);
+    }
+
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        let k = tail.iter().rev().fold(0u32, |k, &byte| k << 8 | byte as u32);
+        h ^= k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+    }
+
+    h ^= data.len() as u32;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85EBCA6B);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xC2B2AE35);
+    h ^ (h >> 16)
+}
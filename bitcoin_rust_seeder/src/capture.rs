@@ -0,0 +1,95 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::address::PeerAddr;
+use crate::message::Command;
+
+// Message capture: every message sent to or received from a peer, appended to a file per
+// connection as it happens, so a session can be replayed through the codec offline.
+//
+// Each record is the time in microseconds since the epoch (u64), the direction (one byte,
+// 0 received, 1 sent), the 12-byte command, the payload length (u32) and the payload, with
+// integers little-endian. Received payloads are recorded before they are decoded, so
+// messages the codec rejects are kept as the peer sent them.
+
+const RECORD_HEADER_SIZE: usize = 8 + 1 + 12 + 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Received,
+    Sent,
+}
+
+// An open capture file for one connection
+pub struct Capture {
+    file: File,
+}
+
+impl Capture {
+    // Start a capture named after the peer and the time, e.g. 1.2.3.4_8333-1700000000123.dat
+    pub fn create(dir: &Path, peer: &PeerAddr) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let name: String = peer.to_string().chars().map(|c| if c.is_ascii_alphanumeric() || c == '.' { c } else { '_' }).collect();
+        let path = dir.join(format!("{}-{}.dat", name.trim_matches('_'), unix_micros() / 1000));
+        Ok(Capture { file: File::create(path)? })
+    }
+
+    // Append one message; each record is written whole, so a capture cut short by a crash
+    // ends on a record boundary
+    pub fn record(&mut self, direction: Direction, command: &Command, payload: &[u8]) -> io::Result<()> {
+        let mut record = Vec::with_capacity(RECORD_HEADER_SIZE + payload.len());
+        record.extend(unix_micros().to_le_bytes());
+        record.push(match direction {
+            Direction::Received => 0,
+            Direction::Sent => 1,
+        });
+        record.extend(command.to_bytes());
+        record.extend((payload.len() as u32).to_le_bytes());
+        record.extend(payload);
+        self.file.write_all(&record)
+    }
+}
+
+// One captured message
+#[derive(Debug, Clone)]
+pub struct CaptureRecord {
+    pub time_us: u64,
+    pub direction: Direction,
+    pub command: [u8; 12], // raw, since captures may hold commands the codec rejects
+    pub payload: Vec<u8>,
+}
+
+pub fn read_capture(path: &Path) -> Result<Vec<CaptureRecord>, Box<dyn Error>> {
+    let bytes = fs::read(path)?;
+    let mut records = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let header = bytes
+            .get(offset..offset + RECORD_HEADER_SIZE)
+            .ok_or_else(|| format!("capture truncated in the record at byte {}", offset))?;
+        let direction = match header[8] {
+            0 => Direction::Received,
+            1 => Direction::Sent,
+            other => return Err(format!("unknown direction {} in the record at byte {}", other, offset).into()),
+        };
+        let length = u32::from_le_bytes(header[21..25].try_into().unwrap()) as usize;
+        let payload = bytes
+            .get(offset + RECORD_HEADER_SIZE..offset + RECORD_HEADER_SIZE + length)
+            .ok_or_else(|| format!("capture truncated in the record at byte {}", offset))?;
+        records.push(CaptureRecord {
+            time_us: u64::from_le_bytes(header[..8].try_into().unwrap()),
+            direction,
+            command: header[9..21].try_into().unwrap(),
+            payload: payload.to_vec(),
+        });
+        offset += RECORD_HEADER_SIZE + length;
+    }
+    Ok(records)
+}
+
+fn unix_micros() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_micros() as u64
+}
@@ -0,0 +1,304 @@
+// ChaCha20 and Poly1305 (RFC 8439) plus the forward-secure wrappers BIP324 builds on them
+
+// Messages encrypted under one key before the forward-secure ciphers rekey
+pub const REKEY_INTERVAL: u64 = 224;
+
+pub const TAG_SIZE: usize = 16;
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+// One 64-byte keystream block
+pub fn chacha20_block(key: &[u8; 32], nonce: &[u8; 12], counter: u32) -> [u8; 64] {
+    let mut state = [0u32; 16];
+    state[..4].copy_from_slice(&[0x61707865, 0x3320646e, 0x79622d32, 0x6b206574]);
+    for i in 0..8 {
+        state[4 + i] = u32::from_le_bytes(key[4 * i..4 * i + 4].try_into().unwrap());
+    }
+    state[12] = counter;
+    for i in 0..3 {
+        state[13 + i] = u32::from_le_bytes(nonce[4 * i..4 * i + 4].try_into().unwrap());
+    }
+
+    let mut working = state;
+    for _ in 0..10 {
+        quarter_round(&mut working, 0, 4, 8, 12);
+        quarter_round(&mut working, 1, 5, 9, 13);
+        quarter_round(&mut working, 2, 6, 10, 14);
+        quarter_round(&mut working, 3, 7, 11, 15);
+        quarter_round(&mut working, 0, 5, 10, 15);
+        quarter_round(&mut working, 1, 6, 11, 12);
+        quarter_round(&mut working, 2, 7, 8, 13);
+        quarter_round(&mut working, 3, 4, 9, 14);
+    }
+
+    let mut block = [0u8; 64];
+    for i in 0..16 {
+        block[4 * i..4 * i + 4].copy_from_slice(&working[i].wrapping_add(state[i]).to_le_bytes());
+    }
+    block
+}
+
+// XOR data with the keystream starting at block counter
+pub fn chacha20_xor(key: &[u8; 32], nonce: &[u8; 12], counter: u32, data: &mut [u8]) {
+    for (i, chunk) in data.chunks_mut(64).enumerate() {
+        let block = chacha20_block(key, nonce, counter.wrapping_add(i as u32));
+        chunk.iter_mut().zip(block).for_each(|(byte, k)| *byte ^= k);
+    }
+}
+
+// Poly1305 one-time authenticator over the message
+pub fn poly1305(key: &[u8; 32], message: &[u8]) -> [u8; TAG_SIZE] {
+    const MASK_130: u128 = (1 << 2) - 1; // bits 128 and 129 live in the third limb
+
+    let clamped = u128::from_le_bytes(key[..16].try_into().unwrap()) & 0x0ffffffc0ffffffc0ffffffc0fffffff;
+    let r = [clamped as u64, (clamped >> 64) as u64];
+    let s = u128::from_le_bytes(key[16..].try_into().unwrap());
+
+    // Accumulator as three 64-bit limbs, kept below a few multiples of 2^130
+    let mut h = [0u64; 3];
+    for chunk in message.chunks(16) {
+        let mut block = [0u8; 17];
+        block[..chunk.len()].copy_from_slice(chunk);
+        block[chunk.len()] = 1;
+        let n0 = u64::from_le_bytes(block[..8].try_into().unwrap());
+        let n1 = u64::from_le_bytes(block[8..16].try_into().unwrap());
+
+        let v = h[0] as u128 + n0 as u128;
+        h[0] = v as u64;
+        let v = h[1] as u128 + n1 as u128 + (v >> 64);
+        h[1] = v as u64;
+        h[2] += block[16] as u64 + (v >> 64) as u64;
+
+        // h * r as five limbs
+        let mut product = [0u64; 5];
+        for (i, hi) in h.iter().enumerate() {
+            let mut carry = 0u128;
+            for (j, rj) in r.iter().enumerate() {
+                let v = product[i + j] as u128 + *hi as u128 * *rj as u128 + carry;
+                product[i + j] = v as u64;
+                carry = v >> 64;
+            }
+            let v = product[i + 2] as u128 + carry;
+            product[i + 2] = v as u64;
+            if i + 3 < 5 {
+                product[i + 3] += (v >> 64) as u64;
+            }
+        }
+
+        // Reduce modulo 2^130 - 5: the part above bit 130 folds back multiplied by 5
+        let high = [
+            (product[2] >> 2) | (product[3] << 62),
+            (product[3] >> 2) | (product[4] << 62),
+            product[4] >> 2,
+        ];
+        let mut carry = 0u128;
+        let low = [product[0], product[1], product[2] & MASK_130 as u64];
+        for i in 0..3 {
+            let v = low[i] as u128 + high[i] as u128 * 5 + carry;
+            h[i] = v as u64;
+            carry = v >> 64;
+        }
+    }
+
+    // Fully reduce, then subtract p = 2^130 - 5 if h is still at least p
+    while h[2] >> 2 != 0 {
+        let mut carry = (h[2] >> 2) as u128 * 5;
+        h[2] &= MASK_130 as u64;
+        for limb in h.iter_mut() {
+            let v = *limb as u128 + carry;
+            *limb = v as u64;
+            carry = v >> 64;
+        }
+    }
+    let value = h[0] as u128 | (h[1] as u128) << 64;
+    let at_least_p = h[2] == 3 && value >= u128::MAX - 4;
+    let value = if at_least_p { value.wrapping_add(5) } else { value };
+
+    value.wrapping_add(s).to_le_bytes()
+}
+
+// AEAD_CHACHA20_POLY1305 tag over the additional data and ciphertext
+fn aead_tag(key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], ciphertext: &[u8]) -> [u8; TAG_SIZE] {
+    let poly_key: [u8; 32] = chacha20_block(key, nonce, 0)[..32].try_into().unwrap();
+    let mut data = Vec::with_capacity(aad.len() + ciphertext.len() + 32);
+    for part in [aad, ciphertext] {
+        data.extend(part);
+        data.resize(data.len().next_multiple_of(16), 0);
+    }
+    data.extend((aad.len() as u64).to_le_bytes());
+    data.extend((ciphertext.len() as u64).to_le_bytes());
+    poly1305(&poly_key, &data)
+}
+
+// Encrypt plaintext, returning ciphertext followed by the tag
+pub fn aead_encrypt(key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let mut out = plaintext.to_vec();
+    chacha20_xor(key, nonce, 1, &mut out);
+    let tag = aead_tag(key, nonce, aad, &out);
+    out.extend(tag);
+    out
+}
+
+// Check the tag and decrypt; None when authentication fails
+pub fn aead_decrypt(key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], ciphertext: &[u8]) -> Option<Vec<u8>> {
+    let (body, tag) = ciphertext.split_at(ciphertext.len().checked_sub(TAG_SIZE)?);
+    let expected = aead_tag(key, nonce, aad, body);
+    // Constant-time comparison
+    if expected.iter().zip(tag).fold(0u8, |diff, (a, b)| diff | (a ^ b)) != 0 {
+        return None;
+    }
+    let mut out = body.to_vec();
+    chacha20_xor(key, nonce, 1, &mut out);
+    Some(out)
+}
+
+fn bip324_nonce(low: u32, high: u64) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[..4].copy_from_slice(&low.to_le_bytes());
+    nonce[4..].copy_from_slice(&high.to_le_bytes());
+    nonce
+}
+
+// Stream cipher for packet lengths: one continuous keystream, rekeyed from itself every
+// REKEY_INTERVAL chunks
+pub struct FsChaCha20 {
+    key: [u8; 32],
+    chunk_counter: u64,
+    block_counter: u32,
+    keystream: Vec<u8>,
+}
+
+impl FsChaCha20 {
+    pub fn new(key: [u8; 32]) -> Self {
+        FsChaCha20 { key, chunk_counter: 0, block_counter: 0, keystream: Vec::new() }
+    }
+
+    fn keystream_bytes(&mut self, n: usize) -> Vec<u8> {
+        while self.keystream.len() < n {
+            let nonce = bip324_nonce(0, self.chunk_counter / REKEY_INTERVAL);
+            self.keystream.extend(chacha20_block(&self.key, &nonce, self.block_counter));
+            self.block_counter += 1;
+        }
+        self.keystream.drain(..n).collect()
+    }
+
+    // Encryption and decryption are the same XOR
+    pub fn crypt(&mut self, chunk: &mut [u8]) {
+        let keystream = self.keystream_bytes(chunk.len());
+        chunk.iter_mut().zip(keystream).for_each(|(byte, k)| *byte ^= k);
+        if (self.chunk_counter + 1).is_multiple_of(REKEY_INTERVAL) {
+            self.key = self.keystream_bytes(32).try_into().unwrap();
+            self.block_counter = 0;
+            self.keystream.clear();
+        }
+        self.chunk_counter += 1;
+    }
+}
+
+// AEAD for packet contents: the nonce counts packets, and the key is replaced every
+// REKEY_INTERVAL packets
+pub struct FsChaCha20Poly1305 {
+    key: [u8; 32],
+    packet_counter: u64,
+}
+
+impl FsChaCha20Poly1305 {
+    pub fn new(key: [u8; 32]) -> Self {
+        FsChaCha20Poly1305 { key, packet_counter: 0 }
+    }
+
+    fn nonce(&self) -> [u8; 12] {
+        bip324_nonce((self.packet_counter % REKEY_INTERVAL) as u32, self.packet_counter / REKEY_INTERVAL)
+    }
+
+    fn next_packet(&mut self) {
+        if (self.packet_counter + 1).is_multiple_of(REKEY_INTERVAL) {
+            let nonce = bip324_nonce(0xFFFFFFFF, self.packet_counter / REKEY_INTERVAL);
+            self.key = aead_encrypt(&self.key, &nonce, &[], &[0u8; 32])[..32].try_into().unwrap();
+        }
+        self.packet_counter += 1;
+    }
+
+    pub fn encrypt(&mut self, aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        let ciphertext = aead_encrypt(&self.key, &self.nonce(), aad, plaintext);
+        self.next_packet();
+        ciphertext
+    }
+
+    pub fn decrypt(&mut self, aad: &[u8], ciphertext: &[u8]) -> Option<Vec<u8>> {
+        let plaintext = aead_decrypt(&self.key, &self.nonce(), aad, ciphertext);
+        self.next_packet();
+        plaintext
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+    }
+
+    const SUNSCREEN: &[u8] = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+
+    fn rfc_key() -> [u8; 32] {
+        std::array::from_fn(|i| i as u8)
+    }
+
+    // RFC 8439 section 2.3.2
+    #[test]
+    fn block_function() {
+        let nonce = hex("000000090000004a00000000").try_into().unwrap();
+        let expected = hex("10f1e7e4d13b5915500fdd1fa32071c4c7d1f4c733c068030422aa9ac3d46c4ed2826446079faa0914c2d705d98b02a2b5129cd1de164eb9cbd083e8a2503c4e");
+        assert_eq!(chacha20_block(&rfc_key(), &nonce, 1).to_vec(), expected);
+    }
+
+    // RFC 8439 section 2.4.2
+    #[test]
+    fn encryption() {
+        let nonce = hex("000000000000004a00000000").try_into().unwrap();
+        let mut data = SUNSCREEN.to_vec();
+        chacha20_xor(&rfc_key(), &nonce, 1, &mut data);
+        assert_eq!(
+            data,
+            hex("6e2e359a2568f98041ba0728dd0d6981e97e7aec1d4360c20a27afccfd9fae0bf91b65c5524733ab8f593dabcd62b3571639d624e65152ab8f530c359f0861d807ca0dbf500d6a6156a38e088a22b65e52bc514d16ccf806818ce91ab77937365af90bbf74a35be6b40b8eedf2785e42874d")
+        );
+    }
+
+    // RFC 8439 section 2.5.2
+    #[test]
+    fn poly1305_tag() {
+        let key = hex("85d6be7857556d337f4452fe42d506a80103808afb0db2fd4abff6af4149f51b").try_into().unwrap();
+        assert_eq!(poly1305(&key, b"Cryptographic Forum Research Group").to_vec(), hex("a8061dc1305136c6c22b8baf0c0127a9"));
+    }
+
+    // RFC 8439 section 2.8.2
+    #[test]
+    fn aead() {
+        let key: [u8; 32] = std::array::from_fn(|i| 0x80 + i as u8);
+        let nonce = hex("070000004041424344454647").try_into().unwrap();
+        let aad = hex("50515253c0c1c2c3c4c5c6c7");
+        let sealed = aead_encrypt(&key, &nonce, &aad, SUNSCREEN);
+        assert_eq!(
+            sealed,
+            hex("d31a8d34648e60db7b86afbc53ef7ec2a4aded51296e08fea9e2b5a736ee62d63dbea45e8ca9671282fafb69da92728b1a71de0a9e060b2905d6a5b67ecd3b3692ddbd7f2d778b8c9803aee328091b58fab324e4fad675945585808b4831d7bc3ff4def08e4b7a9de576d26586cec64b61161ae10b594f09e26a7e902ecbd0600691")
+        );
+        assert_eq!(aead_decrypt(&key, &nonce, &aad, &sealed).as_deref(), Some(SUNSCREEN));
+
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert_eq!(aead_decrypt(&key, &nonce, &aad, &tampered), None);
+        assert_eq!(aead_decrypt(&key, &nonce, b"other", &sealed), None);
+        assert_eq!(aead_decrypt(&key, &nonce, &aad, &sealed[..TAG_SIZE - 1]), None);
+    }
+}
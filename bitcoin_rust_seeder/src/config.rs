@@ -0,0 +1,93 @@
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, Command};
+use toml_edit::{DocumentMut, Item, Value};
+
+// Configuration file for the crawl and daemon commands. Keys are the commands' long options
+// with underscores for dashes, plus seeds for the seed list:
+//
+//     network = "signet"
+//     concurrency = 32
+//     connect_timeout = 3
+//     store = "peers.json"
+//
+//     [networks.signet]
+//     seeds = ["seed.signet.bitcoin.sprovoost.nl"]
+//     store = "signet-peers.json"
+//
+// Keys in the [networks.<name>] table of the network being crawled take precedence over the
+// top-level ones, and options given on the command line over both. Paths are taken relative
+// to the working directory, as on the command line. Crawling several networks at once with
+// --networks applies each network's table to its own part of the crawl.
+
+pub fn load(path: &Path) -> Result<DocumentMut, Box<dyn Error>> {
+    let text = fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    Ok(text.parse::<DocumentMut>().map_err(|e| format!("{}: {}", path.display(), e))?)
+}
+
+// The network a file selects, when the command line does not
+pub fn network(document: &DocumentMut) -> Option<&str> {
+    document.get("network").and_then(Item::as_str)
+}
+
+// Turn the file's settings for a network into command-line arguments for the subcommand,
+// leaving out every option the command line already sets, so they can be appended to the
+// real arguments and parsed together
+pub fn file_args(document: &DocumentMut, network: &str, command: &Command, matches: &ArgMatches) -> Result<Vec<OsString>, Box<dyn Error>> {
+    let mut settings: Vec<(&str, &Item)> = Vec::new();
+    let network_table = document.get("networks").and_then(|networks| networks.get(network)).and_then(Item::as_table_like);
+    if let Some(table) = network_table {
+        settings.extend(table.iter());
+    }
+    for (key, item) in document.iter() {
+        if key == "networks" {
+            continue;
+        }
+        if !settings.iter().any(|(set, _)| *set == key) {
+            settings.push((key, item));
+        }
+    }
+
+    let mut args = Vec::new();
+    for (key, item) in settings {
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_id() == key && key != "config")
+            .ok_or_else(|| format!("unknown setting {} for {}", key, command.get_name()))?;
+        if matches.value_source(key) == Some(ValueSource::CommandLine) {
+            continue;
+        }
+        let values = match item.as_value() {
+            Some(Value::Array(array)) => array.iter().map(|value| setting_text(key, value)).collect::<Result<Vec<_>, _>>()?,
+            Some(value) => vec![setting_text(key, value)?],
+            None => return Err(format!("setting {} is not a value", key).into()),
+        };
+        let long = arg.get_long().map(|long| format!("--{}", long));
+        for value in values {
+            match (&long, arg.get_action()) {
+                (Some(long), ArgAction::SetTrue) => match value.as_str() {
+                    "true" => args.push(OsString::from(long)),
+                    "false" => {}
+                    _ => return Err(format!("setting {} must be true or false", key).into()),
+                },
+                (Some(long), _) => args.extend([OsString::from(long), OsString::from(value)]),
+                (None, _) => args.push(OsString::from(value)),
+            }
+        }
+    }
+    Ok(args)
+}
+
+fn setting_text(key: &str, value: &Value) -> Result<String, String> {
+    match value {
+        Value::String(s) => Ok(s.value().clone()),
+        Value::Integer(n) => Ok(n.value().to_string()),
+        Value::Float(x) => Ok(x.value().to_string()),
+        Value::Boolean(b) => Ok(b.value().to_string()),
+        _ => Err(format!("setting {} must be a string, number, boolean or array of them", key)),
+    }
+}
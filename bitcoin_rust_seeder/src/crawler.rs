@@ -0,0 +1,365 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::address::PeerAddr;
+use crate::addrman::AddrMan;
+use crate::discovery::DiscoveredPeers;
+use crate::message::{AddrEntry, MessageError, SendCmpctMessage, VersionMessage};
+use crate::misbehavior::{BanList, Misbehavior};
+use crate::peer::{ConnectConfig, FailureKind, Features, Peer, PingStats};
+use crate::ratelimit::{SubnetLimit, SubnetLimiter};
+use crate::shutdown;
+use crate::tips::{check_tip, BestChain, TipStatus};
+
+// Crawl settings
+#[derive(Debug, Clone)]
+pub struct CrawlConfig {
+    pub connection: ConnectConfig,
+    pub concurrency: usize,                 // peers visited at the same time
+    pub addr_timeout: Duration,             // time spent waiting for addr replies
+    pub max_peers: usize,                   // stop after visiting this many peers
+    pub retries: u32,                       // extra attempts after a timeout or disconnect
+    pub retry_backoff: Duration,            // wait before the first retry, doubling for each one after
+    pub pings: u32,                         // pings sent to measure latency once addresses are in
+    pub ping_interval: Duration,            // time between pings
+    pub best_chain: Option<Arc<BestChain>>, // reference chain to place peers' tips against
+    pub stale_blocks: u32,                  // blocks behind the reference tip still counted as synced
+    pub subnet_limit: SubnetLimit,          // spacing of connections within a subnet
+}
+
+impl Default for CrawlConfig {
+    fn default() -> Self {
+        CrawlConfig {
+            connection: ConnectConfig::default(),
+            concurrency: 32,
+            addr_timeout: Duration::from_secs(15),
+            max_peers: 1000,
+            retries: 2,
+            retry_backoff: Duration::from_secs(1),
+            pings: 3,
+            ping_interval: Duration::from_millis(500),
+            best_chain: None,
+            stale_blocks: 6,
+            subnet_limit: SubnetLimit::default(),
+        }
+    }
+}
+
+// Outcome of visiting one peer
+#[derive(Debug, Clone)]
+pub struct CrawlResult {
+    pub addr: PeerAddr,
+    pub version: Option<VersionMessage>, // None when the handshake did not complete
+    pub addresses_received: usize,
+    pub v2: bool, // connected over the BIP324 transport
+    pub handshake_latency: Option<Duration>, // connect plus version handshake
+    pub fee_filter: Option<u64>, // minimum relay feerate the peer announced, in sat/kvB
+    pub compact_blocks: Option<SendCmpctMessage>,
+    pub features: Features,
+    pub addr_error: Option<String>, // what cut the getaddr answer short; the visit still counts
+    pub ping: Option<PingStats>, // None when no ping was answered
+    pub ping_error: Option<String>, // what cut the pings short; the visit still counts
+    pub tip: Option<TipStatus>, // None without a reference chain or an answer to getheaders
+    pub tip_error: Option<String>, // why the tip could not be checked; the visit still counts
+    pub attempts: u32, // connections made, counting retries
+    pub misbehavior: Vec<Misbehavior>, // protocol violations, over all attempts
+    pub failure: Option<FailureKind>,
+    pub error: Option<String>,
+}
+
+// Everything learned during a crawl
+#[derive(Debug, Default)]
+pub struct CrawlReport {
+    pub results: Vec<CrawlResult>,
+    pub discovered: DiscoveredPeers,
+}
+
+impl CrawlReport {
+    pub fn reachable(&self) -> usize {
+        self.results.iter().filter(|result| result.version.is_some()).count()
+    }
+
+    // How many reachable peers support each feature, in a fixed order for printing
+    pub fn capabilities(&self) -> Vec<(&'static str, usize)> {
+        let reachable: Vec<&CrawlResult> = self.results.iter().filter(|result| result.version.is_some()).collect();
+        let count = |has: &dyn Fn(&CrawlResult) -> bool| reachable.iter().filter(|result| has(result)).count();
+        vec![
+            ("v2 transport", count(&|result| result.v2)),
+            ("wtxidrelay", count(&|result| result.features.wtxid_relay)),
+            ("sendaddrv2", count(&|result| result.features.addr_v2)),
+            ("sendheaders", count(&|result| result.features.send_headers)),
+            ("cmpct v1", count(&|result| result.compact_blocks.is_some_and(|sendcmpct| sendcmpct.version == 1))),
+            ("cmpct v2", count(&|result| result.compact_blocks.is_some_and(|sendcmpct| sendcmpct.version == 2))),
+            ("feefilter", count(&|result| result.fee_filter.is_some())),
+        ]
+    }
+}
+
+// How often idle workers look for a shutdown request
+const SHUTDOWN_POLL: Duration = Duration::from_millis(200);
+
+// Selection attempts before falling back to scanning for any unvisited address
+const SELECT_ATTEMPTS: usize = 64;
+
+// One network's part of a crawl: where it starts, how its peers are visited and which are
+// skipped
+pub struct NetworkCrawl<'a> {
+    pub seeds: &'a [PeerAddr],
+    pub config: &'a CrawlConfig,
+    pub bans: &'a BanList,
+}
+
+// One network's work, shared by the crawl workers
+struct CrawlState {
+    addrman: AddrMan,
+    visited: HashSet<PeerAddr>,
+    in_flight: usize,
+    report: CrawlReport,
+    limiter: SubnetLimiter,
+}
+
+impl CrawlState {
+    // Next address to visit: first any tried entry a collision is waiting on, then whatever
+    // addrman selects. Each address is visited at most once per crawl, and addresses on
+    // networks we cannot reach, banned, or in a subnet connected to too recently are passed
+    // over.
+    fn next_address(&mut self, now: u64, connection: &ConnectConfig, bans: &BanList) -> Option<PeerAddr> {
+        self.addrman.resolve_collisions(now);
+        let started = Instant::now();
+        let eligible = |addr: &PeerAddr| {
+            !self.visited.contains(addr) && connection.reaches(addr) && !bans.is_banned(addr, now) && self.limiter.is_ready(addr, started)
+        };
+        if let Some(addr) = self.addrman.select_tried_collision().filter(|addr| eligible(addr)) {
+            return Some(addr);
+        }
+        if !self.addrman.addresses().any(&eligible) {
+            return None;
+        }
+        (0..SELECT_ATTEMPTS)
+            .filter_map(|_| self.addrman.select(false, now))
+            .find(|addr| eligible(addr))
+            .or_else(|| self.addrman.addresses().find(|addr| eligible(addr)).cloned())
+    }
+
+    // Whether addresses are left to visit once their subnets allow it
+    fn has_throttled(&self, now: u64, connection: &ConnectConfig, bans: &BanList) -> bool {
+        self.addrman.addresses().any(|addr| !self.visited.contains(addr) && connection.reaches(addr) && !bans.is_banned(addr, now))
+    }
+
+    // Whether nothing is left to start: the visit limit is reached, or no visit is in
+    // progress that could turn up more addresses and none are waiting on their subnets
+    fn is_done(&self, network: &NetworkCrawl) -> bool {
+        self.visited.len() >= network.config.max_peers || (self.in_flight == 0 && !self.has_throttled(unix_time(), &network.config.connection, network.bans))
+    }
+}
+
+// Crawl several networks at once, returning a report for each in the same order. Every
+// network has its own address manager, visit limit, bans and subnet spacing, while the
+// workers visiting peers are shared: there are as many as the largest concurrency of any
+// network, and a free worker goes to the network with the fewest visits in progress, so
+// each gets a fair share of them. Peers are picked through an address manager, as Bitcoin
+// Core picks outbound connections. A shutdown request stops new visits and retries and cuts
+// short the pings of visits in progress. Banned addresses are never visited. Workers are
+// threads doing blocking I/O, one visit each at a time; running visits as async tasks, so
+// thousands can be in flight at once, is still to be done.
+pub fn crawl(networks: &[NetworkCrawl]) -> Vec<CrawlReport> {
+    let now = unix_time();
+    let states: Vec<CrawlState> = networks
+        .iter()
+        .map(|network| {
+            let mut state = CrawlState {
+                addrman: AddrMan::default(),
+                visited: HashSet::new(),
+                in_flight: 0,
+                report: CrawlReport::default(),
+                limiter: SubnetLimiter::new(network.config.subnet_limit),
+            };
+            for seed in network.seeds {
+                state.addrman.add_seed(seed.clone(), now);
+            }
+            state
+        })
+        .collect();
+    let states = Mutex::new(states);
+    let work_changed = Condvar::new();
+    let workers = networks.iter().map(|network| network.config.concurrency).max().unwrap_or(0).max(1);
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| crawl_worker(&states, &work_changed, networks));
+        }
+    });
+
+    states.into_inner().unwrap().into_iter().map(|state| state.report).collect()
+}
+
+fn crawl_worker(states: &Mutex<Vec<CrawlState>>, work_changed: &Condvar, networks: &[NetworkCrawl]) {
+    loop {
+        // Take the next address, waiting while other workers may still queue more
+        let (index, addr) = {
+            let mut guard = states.lock().unwrap();
+            loop {
+                if shutdown::requested() {
+                    return;
+                }
+                if let Some(next) = next_visit(&mut guard, networks) {
+                    break next;
+                }
+                if guard.iter().zip(networks).all(|(state, network)| state.is_done(network)) {
+                    return;
+                }
+                guard = work_changed.wait_timeout(guard, SHUTDOWN_POLL).unwrap().0;
+            }
+        };
+
+        let (result, entries) = visit_peer(&addr, networks[index].config, states, index);
+
+        let now = unix_time();
+        let mut guard = states.lock().unwrap();
+        let state = &mut guard[index];
+        if result.version.is_some() {
+            state.addrman.good(&addr, now);
+        } else {
+            state.addrman.attempt(&addr, true, now);
+        }
+        state.addrman.add(entries.iter().cloned(), &addr, now);
+        state.report.discovered.add(entries);
+        state.report.results.push(result);
+        state.in_flight -= 1;
+        work_changed.notify_all();
+    }
+}
+
+// Claim the next peer to visit and the index of its network, trying the networks with the
+// fewest visits in progress first
+fn next_visit(states: &mut [CrawlState], networks: &[NetworkCrawl]) -> Option<(usize, PeerAddr)> {
+    let mut order: Vec<usize> = (0..states.len()).collect();
+    order.sort_by_key(|&index| states[index].in_flight);
+    for index in order {
+        let (state, network) = (&mut states[index], &networks[index]);
+        if state.visited.len() >= network.config.max_peers {
+            continue;
+        }
+        if let Some(addr) = state.next_address(unix_time(), &network.config.connection, network.bans) {
+            state.limiter.reserve(&addr, Instant::now());
+            state.visited.insert(addr.clone());
+            state.in_flight += 1;
+            return Some((index, addr));
+        }
+    }
+    None
+}
+
+// Visit a peer, retrying with exponential backoff while the handshake fails in ways that
+// may clear up. A retry also waits its turn in the peer's subnet, so retries to a subnet
+// spread out rather than pile up.
+fn visit_peer(addr: &PeerAddr, config: &CrawlConfig, states: &Mutex<Vec<CrawlState>>, index: usize) -> (CrawlResult, Vec<AddrEntry>) {
+    let mut attempt = 1;
+    let mut misbehavior = Vec::new();
+    loop {
+        let (mut result, entries) = visit_once(addr, config);
+        result.attempts = attempt;
+        misbehavior.append(&mut result.misbehavior);
+        result.misbehavior = misbehavior.clone();
+        let retry = result.version.is_none() && result.failure.is_some_and(FailureKind::is_transient);
+        if !retry || attempt > config.retries || shutdown::requested() {
+            return (result, entries);
+        }
+        let earliest = Instant::now() + config.retry_backoff * 2u32.pow(attempt - 1);
+        let start = states.lock().unwrap()[index].limiter.reserve(addr, earliest);
+        while !shutdown::requested() && Instant::now() < start {
+            thread::sleep(start.saturating_duration_since(Instant::now()).min(SHUTDOWN_POLL));
+        }
+        attempt += 1;
+    }
+}
+
+// Connect, handshake and ask for addresses
+fn visit_once(addr: &PeerAddr, config: &CrawlConfig) -> (CrawlResult, Vec<AddrEntry>) {
+    let mut result = CrawlResult {
+        addr: addr.clone(),
+        version: None,
+        addresses_received: 0,
+        v2: false,
+        handshake_latency: None,
+        fee_filter: None,
+        compact_blocks: None,
+        features: Features::default(),
+        addr_error: None,
+        ping: None,
+        ping_error: None,
+        tip: None,
+        tip_error: None,
+        attempts: 1,
+        misbehavior: Vec::new(),
+        failure: None,
+        error: None,
+    };
+    let outcome = (|| -> Result<Vec<AddrEntry>, MessageError> {
+        let started = Instant::now();
+        let mut peer = Peer::open(addr, &config.connection)?;
+        result.handshake_latency = Some(started.elapsed());
+        result.v2 = peer.is_v2();
+        result.version = peer.version.clone();
+        // feefilter and sendcmpct follow the handshake, so they arrive while we harvest
+        let (entries, error) = peer.harvest_addresses(config.addr_timeout);
+        result.fee_filter = peer.fee_filter;
+        result.compact_blocks = peer.compact_blocks;
+        result.features = peer.features;
+        result.misbehavior.append(&mut peer.misbehavior);
+        // Past the handshake the visit succeeded; the connection is likely gone after an
+        // error, so the tip and pings are skipped
+        if let Some(e) = error {
+            result.misbehavior.extend(Misbehavior::from_error(&e));
+            result.addr_error = Some(e.to_string());
+            return Ok(entries);
+        }
+        if let Some(chain) = &config.best_chain {
+            // Like latency, a tip is a bonus; the connection is likely gone after an error, so
+            // pinging is skipped
+            let tip = check_tip(&mut peer, chain, config.stale_blocks, config.connection.io_timeout);
+            result.misbehavior.append(&mut peer.misbehavior);
+            match tip {
+                Ok(tip) => result.tip = tip,
+                Err(e) => {
+                    result.misbehavior.extend(Misbehavior::from_error(&e));
+                    result.tip_error = Some(e.to_string());
+                    return Ok(entries);
+                }
+            }
+        }
+        if shutdown::requested() {
+            return Ok(entries);
+        }
+        // Latency is a bonus: losing the connection while pinging keeps the addresses and
+        // any round trips already measured
+        let (ping, error) = peer.measure_latency(config.pings, config.ping_interval, config.connection.io_timeout);
+        result.misbehavior.append(&mut peer.misbehavior);
+        result.ping = ping;
+        if let Some(e) = error {
+            result.misbehavior.extend(Misbehavior::from_error(&e));
+            result.ping_error = Some(e.to_string());
+        }
+        Ok(entries)
+    })();
+
+    match outcome {
+        Ok(entries) => {
+            result.addresses_received = entries.len();
+            (result, entries)
+        }
+        Err(e) => {
+            result.misbehavior.extend(Misbehavior::from_error(&e));
+            result.failure = Some(FailureKind::classify(&e));
+            result.error = Some(e.to_string());
+            (result, Vec::new())
+        }
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
@@ -0,0 +1,36 @@
+use std::collections::HashMap;
+use crate::address::PeerAddr;
+use crate::message::AddrEntry;
+
+// Peers learned from addr messages, keyed by address
+#[derive(Debug, Default)]
+pub struct DiscoveredPeers {
+    peers: HashMap<PeerAddr, AddrEntry>,
+}
+
+impl DiscoveredPeers {
+    // Merge addr entries, keeping the most recent timestamp per address. Returns how many
+    // addresses were new.
+    pub fn add(&mut self, entries: impl IntoIterator<Item = AddrEntry>) -> usize {
+        let mut added = 0;
+        for entry in entries {
+            match self.peers.get_mut(&entry.addr) {
+                Some(known) if known.timestamp >= entry.timestamp => {}
+                Some(known) => *known = entry,
+                None => {
+                    self.peers.insert(entry.addr.clone(), entry);
+                    added += 1;
+                }
+            }
+        }
+        added
+    }
+
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &AddrEntry> {
+        self.peers.values()
+    }
+}
@@ -0,0 +1,499 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use rand::seq::IndexedRandom;
+
+use crate::services;
+
+// Minimal authoritative DNS responder for the seed domain: answers A and AAAA queries with
+// a random sample of good peers, as sipa's bitcoin-seeder does. A query for x<hex>.<domain>
+// only gets peers offering every service bit in <hex>, e.g. x9 for NODE_NETWORK and
+// NODE_WITNESS.
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const TYPE_OPT: u16 = 41;
+const CLASS_IN: u16 = 1;
+
+const RCODE_FORMERR: u8 = 1;
+const RCODE_NXDOMAIN: u8 = 3;
+const RCODE_NOTIMP: u8 = 4;
+const RCODE_REFUSED: u8 = 5;
+// Upper bits of the EDNS extended rcode 16, BADVERS, carried in the OPT record
+const EXTENDED_RCODE_BADVERS: u8 = 1;
+
+// Replies are kept within the classic 512-byte UDP limit, unless the query's EDNS OPT record
+// allows more, up to the size DNS flag day 2020 settled on to avoid fragmentation
+const MAX_UDP_SIZE: usize = 512;
+const MAX_EDNS_UDP_SIZE: usize = 1232;
+const OPT_RECORD_SIZE: usize = 11;
+
+// What the responder serves
+#[derive(Debug, Clone)]
+pub struct DnsConfig {
+    pub domain: String, // zone answered for, without the trailing dot
+    pub ttl: u32,
+    pub max_records: usize,     // addresses per reply
+    pub required_services: u64, // service bits every peer served for the bare domain must offer
+}
+
+impl Default for DnsConfig {
+    fn default() -> Self {
+        DnsConfig { domain: String::new(), ttl: 60, max_records: 20, required_services: 0 }
+    }
+}
+
+// A parsed single-question query
+#[derive(Debug, Clone)]
+struct Question {
+    id: u16,
+    flags: u16,
+    name: Vec<String>, // lowercase labels
+    qtype: u16,
+    qclass: u16,
+    raw: Vec<u8>,      // the question section as received, echoed in the reply
+    edns: Option<Edns>,
+}
+
+// The EDNS OPT record of a query (RFC 6891)
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Edns {
+    udp_size: u16, // largest reply the client accepts
+    version: u8,
+}
+
+pub struct DnsServer {
+    config: DnsConfig,
+    ipv4: Vec<(Ipv4Addr, u64)>, // with the services each peer offers
+    ipv6: Vec<(Ipv6Addr, u64)>,
+}
+
+impl DnsServer {
+    pub fn new(config: DnsConfig) -> Self {
+        DnsServer { config, ipv4: Vec::new(), ipv6: Vec::new() }
+    }
+
+    // Replace the addresses handed out, each with its service bits
+    pub fn set_peers(&mut self, peers: impl IntoIterator<Item = (IpAddr, u64)>) {
+        self.ipv4.clear();
+        self.ipv6.clear();
+        for (ip, services) in peers {
+            match ip {
+                IpAddr::V4(v4) => self.ipv4.push((v4, services)),
+                IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+                    Some(v4) => self.ipv4.push((v4, services)),
+                    None => self.ipv6.push((v6, services)),
+                },
+            }
+        }
+    }
+
+    pub fn peer_count(&self) -> usize {
+        self.ipv4.len() + self.ipv6.len()
+    }
+
+    // Reply to one request packet; None for packets not worth answering
+    pub fn respond(&self, packet: &[u8]) -> Option<Vec<u8>> {
+        let question = match parse_question(packet) {
+            Ok(question) => question,
+            Err(Some(id)) => return Some(error_reply(id, 0, &[], RCODE_FORMERR)),
+            Err(None) => return None,
+        };
+        let error = |rcode| Some(with_opt(error_reply(question.id, question.flags, &question.raw, rcode), question.edns, 0));
+        // Only EDNS version 0 and standard queries (opcode 0) are supported
+        if question.edns.is_some_and(|edns| edns.version != 0) {
+            let reply = error_reply(question.id, question.flags, &question.raw, 0);
+            return Some(with_opt(reply, question.edns, EXTENDED_RCODE_BADVERS));
+        }
+        if (question.flags >> 11) & 0xF != 0 {
+            return error(RCODE_NOTIMP);
+        }
+        let zone: Vec<String> = self.config.domain.trim_end_matches('.').split('.').map(str::to_ascii_lowercase).collect();
+        if !question.name.ends_with(&zone) || question.qclass != CLASS_IN {
+            return error(RCODE_REFUSED);
+        }
+        let required = match &question.name[..question.name.len() - zone.len()] {
+            [] => self.config.required_services,
+            [label] => match label.strip_prefix('x').and_then(|hex| u64::from_str_radix(hex, 16).ok()) {
+                Some(required) => required,
+                None => return error(RCODE_NXDOMAIN),
+            },
+            _ => return error(RCODE_NXDOMAIN),
+        };
+
+        let mut rng = rand::rng();
+        let records: Vec<Vec<u8>> = match question.qtype {
+            TYPE_A => {
+                let ipv4: Vec<&Ipv4Addr> = self.ipv4.iter().filter(|(_, services)| services::has(*services, required)).map(|(ip, _)| ip).collect();
+                ipv4.choose_multiple(&mut rng, self.config.max_records).map(|ip| ip.octets().to_vec()).collect()
+            }
+            TYPE_AAAA => {
+                let ipv6: Vec<&Ipv6Addr> = self.ipv6.iter().filter(|(_, services)| services::has(*services, required)).map(|(ip, _)| ip).collect();
+                ipv6.choose_multiple(&mut rng, self.config.max_records).map(|ip| ip.octets().to_vec()).collect()
+            }
+            _ => Vec::new(), // the name exists, it just has no records of this type
+        };
+
+        // An OPT record in the reply takes room from the answers
+        let max_size = match question.edns {
+            Some(edns) => (edns.udp_size as usize).clamp(MAX_UDP_SIZE, MAX_EDNS_UDP_SIZE) - OPT_RECORD_SIZE,
+            None => MAX_UDP_SIZE,
+        };
+        let mut reply = header(question.id, question.flags, 0, 1, 0);
+        reply.extend(&question.raw);
+        let mut answers = 0u16;
+        for data in records {
+            // Name as a pointer to the question at offset 12, then type, class, TTL and data
+            let mut record = vec![0xC0, 12];
+            record.extend(question.qtype.to_be_bytes());
+            record.extend(CLASS_IN.to_be_bytes());
+            record.extend(self.config.ttl.to_be_bytes());
+            record.extend((data.len() as u16).to_be_bytes());
+            record.extend(data);
+            if reply.len() + record.len() > max_size {
+                break;
+            }
+            reply.extend(record);
+            answers += 1;
+        }
+        reply[6..8].copy_from_slice(&answers.to_be_bytes());
+        Some(with_opt(reply, question.edns, 0))
+    }
+}
+
+// Add our OPT record to a reply when the query carried one
+fn with_opt(mut reply: Vec<u8>, edns: Option<Edns>, extended_rcode: u8) -> Vec<u8> {
+    if edns.is_none() {
+        return reply;
+    }
+    reply.push(0); // root name
+    reply.extend(TYPE_OPT.to_be_bytes());
+    reply.extend((MAX_EDNS_UDP_SIZE as u16).to_be_bytes());
+    reply.extend([extended_rcode, 0, 0, 0]); // version 0, no flags
+    reply.extend([0, 0]); // no options
+    reply[10..12].copy_from_slice(&1u16.to_be_bytes());
+    reply
+}
+
+// Header for a reply: QR and AA set, opcode and RD copied from the query
+fn header(id: u16, query_flags: u16, rcode: u8, questions: u16, answers: u16) -> Vec<u8> {
+    let flags = 0x8000 | 0x0400 | (query_flags & 0x7900) | rcode as u16;
+    let mut out = Vec::with_capacity(MAX_UDP_SIZE);
+    out.extend(id.to_be_bytes());
+    out.extend(flags.to_be_bytes());
+    out.extend(questions.to_be_bytes());
+    out.extend(answers.to_be_bytes());
+    out.extend([0, 0, 0, 0]); // no authority or additional records
+    out
+}
+
+fn error_reply(id: u16, query_flags: u16, question: &[u8], rcode: u8) -> Vec<u8> {
+    let mut reply = header(id, query_flags, rcode, !question.is_empty() as u16, 0);
+    reply.extend(question);
+    reply
+}
+
+// Parse a query carrying exactly one question, and an EDNS OPT record if it has one. Errors
+// carry the query id when the header was readable, so a FORMERR can be sent back; responses
+// and garbage are dropped.
+fn parse_question(packet: &[u8]) -> Result<Question, Option<u16>> {
+    if packet.len() < 12 {
+        return Err(None);
+    }
+    let id = u16::from_be_bytes([packet[0], packet[1]]);
+    let flags = u16::from_be_bytes([packet[2], packet[3]]);
+    if flags & 0x8000 != 0 {
+        return Err(None); // a response, not a query
+    }
+    let questions = u16::from_be_bytes([packet[4], packet[5]]);
+    if questions != 1 {
+        return Err(Some(id));
+    }
+
+    let mut pos = 12;
+    let mut name = Vec::new();
+    loop {
+        let length = *packet.get(pos).ok_or(Some(id))? as usize;
+        pos += 1;
+        if length == 0 {
+            break;
+        }
+        // Compression pointers have no business in a question
+        if length > 63 {
+            return Err(Some(id));
+        }
+        let label = packet.get(pos..pos + length).ok_or(Some(id))?;
+        name.push(String::from_utf8_lossy(label).to_ascii_lowercase());
+        pos += length;
+    }
+    let fixed = packet.get(pos..pos + 4).ok_or(Some(id))?;
+    let qtype = u16::from_be_bytes([fixed[0], fixed[1]]);
+    let qclass = u16::from_be_bytes([fixed[2], fixed[3]]);
+    let raw = packet[12..pos + 4].to_vec();
+    pos += 4;
+
+    // A query has no answer or authority records. Of the additional records only an OPT
+    // record matters; there may be at most one, with the root as its name.
+    if packet[6..10] != [0, 0, 0, 0] {
+        return Err(Some(id));
+    }
+    let mut edns = None;
+    for _ in 0..u16::from_be_bytes([packet[10], packet[11]]) {
+        let name_end = skip_name(packet, pos).ok_or(Some(id))?;
+        let fixed = packet.get(name_end..name_end + 10).ok_or(Some(id))?;
+        let rtype = u16::from_be_bytes([fixed[0], fixed[1]]);
+        let data_len = u16::from_be_bytes([fixed[8], fixed[9]]) as usize;
+        if rtype == TYPE_OPT {
+            if edns.is_some() || name_end != pos + 1 {
+                return Err(Some(id));
+            }
+            edns = Some(Edns { udp_size: u16::from_be_bytes([fixed[2], fixed[3]]), version: fixed[5] });
+        }
+        pos = name_end + 10 + data_len;
+        if pos > packet.len() {
+            return Err(Some(id));
+        }
+    }
+    Ok(Question { id, flags, name, qtype, qclass, raw, edns })
+}
+
+// Position just past the name starting at pos, which may end in a compression pointer
+fn skip_name(packet: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let length = *packet.get(pos)? as usize;
+        match length {
+            0 => return Some(pos + 1),
+            1..=63 => pos += 1 + length,
+            0xC0..=0xFF => return (pos + 2 <= packet.len()).then_some(pos + 2),
+            _ => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOMAIN: &str = "seed.example.com";
+
+    // A standard query with recursion desired for name
+    fn query(id: u16, name: &str, qtype: u16) -> Vec<u8> {
+        let mut packet = id.to_be_bytes().to_vec();
+        packet.extend([0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+        for label in name.split('.') {
+            packet.push(label.len() as u8);
+            packet.extend(label.as_bytes());
+        }
+        packet.push(0);
+        packet.extend(qtype.to_be_bytes());
+        packet.extend(CLASS_IN.to_be_bytes());
+        packet
+    }
+
+    // The query with an EDNS OPT record advertising udp_size
+    fn with_edns(mut packet: Vec<u8>, udp_size: u16, version: u8) -> Vec<u8> {
+        packet[10..12].copy_from_slice(&1u16.to_be_bytes());
+        packet.push(0);
+        packet.extend(TYPE_OPT.to_be_bytes());
+        packet.extend(udp_size.to_be_bytes());
+        packet.extend([0, version, 0x80, 0]); // DNSSEC OK set
+        packet.extend([0, 4, 0, 10, 0, 0]); // an empty cookie option
+        packet
+    }
+
+    fn server(peers: impl IntoIterator<Item = (IpAddr, u64)>, max_records: usize) -> DnsServer {
+        let mut server = DnsServer::new(DnsConfig { domain: DOMAIN.to_string(), max_records, ..DnsConfig::default() });
+        server.set_peers(peers);
+        server
+    }
+
+    fn rcode(reply: &[u8]) -> u8 {
+        reply[3] & 0x0F
+    }
+
+    fn answer_count(reply: &[u8]) -> usize {
+        u16::from_be_bytes([reply[6], reply[7]]) as usize
+    }
+
+    // Address data of every answer, which all follow the question
+    fn answers(reply: &[u8], question_len: usize) -> Vec<Vec<u8>> {
+        let mut pos = 12 + question_len;
+        let mut out = Vec::new();
+        for _ in 0..answer_count(reply) {
+            assert_eq!(&reply[pos..pos + 2], [0xC0, 12]);
+            let length = u16::from_be_bytes([reply[pos + 10], reply[pos + 11]]) as usize;
+            out.push(reply[pos + 12..pos + 12 + length].to_vec());
+            pos += 12 + length;
+        }
+        assert_eq!(pos, reply.len());
+        out
+    }
+
+    fn peers() -> Vec<(IpAddr, u64)> {
+        vec![
+            ("192.0.2.1".parse().unwrap(), 0x409),
+            ("192.0.2.2".parse().unwrap(), 0x1),
+            ("::ffff:192.0.2.3".parse().unwrap(), 0x9),
+            ("2001:db8::1".parse().unwrap(), 0x9),
+        ]
+    }
+
+    #[test]
+    fn parses_a_question() {
+        let packet = query(0xBEEF, "X9.Seed.Example.com", TYPE_AAAA);
+        let question = parse_question(&packet).unwrap();
+        assert_eq!((question.id, question.flags, question.qtype, question.qclass), (0xBEEF, 0x0100, TYPE_AAAA, CLASS_IN));
+        assert_eq!(question.name, ["x9", "seed", "example", "com"]);
+        assert_eq!(question.raw, &packet[12..]);
+    }
+
+    #[test]
+    fn answers_a_queries() {
+        let packet = query(7, DOMAIN, TYPE_A);
+        let reply = server(peers(), 20).respond(&packet).unwrap();
+        assert_eq!(&reply[..2], [0, 7]);
+        assert_eq!(reply[2] & 0x85, 0x85); // QR, AA and RD echoed
+        assert_eq!(rcode(&reply), 0);
+        assert_eq!(&reply[12..packet.len()], &packet[12..]);
+        let mut found = answers(&reply, packet.len() - 12);
+        found.sort();
+        assert_eq!(found, [[192, 0, 2, 1], [192, 0, 2, 2], [192, 0, 2, 3]]);
+    }
+
+    #[test]
+    fn answers_aaaa_queries() {
+        let packet = query(7, DOMAIN, TYPE_AAAA);
+        let reply = server(peers(), 20).respond(&packet).unwrap();
+        assert_eq!(rcode(&reply), 0);
+        let ip: Ipv6Addr = "2001:db8::1".parse().unwrap();
+        assert_eq!(answers(&reply, packet.len() - 12), [ip.octets().to_vec()]);
+    }
+
+    #[test]
+    fn filters_by_service_subdomain() {
+        let packet = query(7, "x400.seed.example.com", TYPE_A);
+        let reply = server(peers(), 20).respond(&packet).unwrap();
+        assert_eq!(answers(&reply, packet.len() - 12), [[192, 0, 2, 1]]);
+        let reply = server(peers(), 20).respond(&query(7, "www.seed.example.com", TYPE_A)).unwrap();
+        assert_eq!((rcode(&reply), answer_count(&reply)), (RCODE_NXDOMAIN, 0));
+    }
+
+    #[test]
+    fn refuses_names_outside_the_zone() {
+        for name in ["example.com", "seed.example.org", "evilseed.example.com"] {
+            let reply = server(peers(), 20).respond(&query(7, name, TYPE_A)).unwrap();
+            assert_eq!((rcode(&reply), answer_count(&reply)), (RCODE_REFUSED, 0), "{}", name);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_queries() {
+        let server = server(peers(), 20);
+
+        // Not exactly one question
+        for count in [0u16, 2] {
+            let mut packet = query(9, DOMAIN, TYPE_A);
+            packet[4..6].copy_from_slice(&count.to_be_bytes());
+            assert!(matches!(parse_question(&packet), Err(Some(9))));
+            let reply = server.respond(&packet).unwrap();
+            assert_eq!((&reply[..2], rcode(&reply), reply.len()), (&[0, 9][..], RCODE_FORMERR, 12));
+        }
+
+        // A label longer than 63 bytes
+        let long = format!("{}.{}", "a".repeat(64), DOMAIN);
+        assert!(matches!(parse_question(&query(9, &long, TYPE_A)), Err(Some(9))));
+        assert_eq!(rcode(&server.respond(&query(9, &long, TYPE_A)).unwrap()), RCODE_FORMERR);
+
+        // Cut off inside the header, the name and the type and class
+        let packet = query(9, DOMAIN, TYPE_A);
+        assert!(server.respond(&packet[..11]).is_none());
+        for end in [13, 20, packet.len() - 1] {
+            assert!(matches!(parse_question(&packet[..end]), Err(Some(9))));
+            assert_eq!(rcode(&server.respond(&packet[..end]).unwrap()), RCODE_FORMERR);
+        }
+
+        // Responses are dropped rather than answered
+        let mut packet = query(9, DOMAIN, TYPE_A);
+        packet[2] |= 0x80;
+        assert!(server.respond(&packet).is_none());
+
+        // Compression pointers, even to the header, and the reserved label types
+        for label_type in [0xC0, 0x80, 0x40] {
+            let mut packet = query(9, "", TYPE_A);
+            packet.splice(12..13, [label_type, 12]);
+            assert!(matches!(parse_question(&packet), Err(Some(9))));
+            assert_eq!(rcode(&server.respond(&packet).unwrap()), RCODE_FORMERR);
+        }
+
+        // Answer records in a query
+        let mut packet = query(9, DOMAIN, TYPE_A);
+        packet[7] = 1;
+        assert!(matches!(parse_question(&packet), Err(Some(9))));
+    }
+
+    #[test]
+    fn answers_edns_queries_with_an_opt_record() {
+        let plain = query(7, DOMAIN, TYPE_A);
+        let packet = with_edns(plain.clone(), 4096, 0);
+        let question = parse_question(&packet).unwrap();
+        assert_eq!(question.edns, Some(Edns { udp_size: 4096, version: 0 }));
+        assert_eq!(question.raw, &plain[12..]);
+
+        let reply = server(peers(), 20).respond(&packet).unwrap();
+        assert_eq!((rcode(&reply), answer_count(&reply)), (0, 3));
+        assert_eq!(&reply[10..12], [0, 1]);
+        let opt = &reply[reply.len() - OPT_RECORD_SIZE..];
+        assert_eq!(opt, [0, 0, 41, 0x04, 0xD0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(answers(&reply[..reply.len() - OPT_RECORD_SIZE], plain.len() - 12).len(), 3);
+
+        // Errors carry it too
+        let reply = server(peers(), 20).respond(&with_edns(query(7, "example.org", TYPE_A), 4096, 0)).unwrap();
+        assert_eq!((rcode(&reply), reply[11]), (RCODE_REFUSED, 1));
+    }
+
+    #[test]
+    fn edns_allows_larger_replies() {
+        let many = || (0..100u16).map(|i| (IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, i)), 1));
+        let plain = query(7, DOMAIN, TYPE_AAAA);
+        for (udp_size, limit) in [(0, MAX_UDP_SIZE), (1000, 1000), (4096, MAX_EDNS_UDP_SIZE)] {
+            let reply = server(many(), 100).respond(&with_edns(plain.clone(), udp_size, 0)).unwrap();
+            assert!(reply.len() <= limit, "{}", udp_size);
+            assert_eq!(answer_count(&reply), (limit - OPT_RECORD_SIZE - plain.len()) / 28, "{}", udp_size);
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_edns_versions_and_malformed_opt_records() {
+        let server = server(peers(), 20);
+        let reply = server.respond(&with_edns(query(7, DOMAIN, TYPE_A), 4096, 1)).unwrap();
+        // BADVERS is 16: zero in the header, 1 in the OPT record's extended rcode
+        assert_eq!((rcode(&reply), answer_count(&reply)), (0, 0));
+        assert_eq!(reply[reply.len() - 6], EXTENDED_RCODE_BADVERS);
+
+        // Two OPT records
+        let mut packet = with_edns(query(7, DOMAIN, TYPE_A), 4096, 0);
+        let opt = packet[packet.len() - 17..].to_vec();
+        packet.extend(opt);
+        packet[11] = 2;
+        assert!(matches!(parse_question(&packet), Err(Some(7))));
+
+        // An OPT record not owned by the root, and one cut short
+        let mut packet = query(7, DOMAIN, TYPE_A);
+        packet[11] = 1;
+        packet.extend([0xC0, 12, 0, 41, 0x10, 0, 0, 0, 0, 0, 0, 0]);
+        assert!(matches!(parse_question(&packet), Err(Some(7))));
+        let packet = with_edns(query(7, DOMAIN, TYPE_A), 4096, 0);
+        assert!(matches!(parse_question(&packet[..packet.len() - 1]), Err(Some(7))));
+        assert_eq!(rcode(&server.respond(&packet[..packet.len() - 1]).unwrap()), RCODE_FORMERR);
+    }
+
+    #[test]
+    fn keeps_replies_within_512_bytes() {
+        let many = (0..100u16).map(|i| (IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, i)), 1));
+        let packet = query(7, DOMAIN, TYPE_AAAA);
+        let reply = server(many, 100).respond(&packet).unwrap();
+        assert!(reply.len() <= MAX_UDP_SIZE);
+        // Each AAAA record takes 28 bytes after the header and question
+        assert_eq!(answer_count(&reply), (MAX_UDP_SIZE - packet.len()) / 28);
+        assert_eq!(answers(&reply, packet.len() - 12).len(), answer_count(&reply));
+    }
+}
@@ -0,0 +1,377 @@
+use secp256k1::{ecdh, Parity, PublicKey, Secp256k1, SecretKey, XOnlyPublicKey};
+
+// ElligatorSwift encoding of secp256k1 public keys (BIP324): 64 bytes (u, t) that look
+// uniformly random and decode to the X coordinate xswiftec(u, t)
+
+// Field modulus p = 2^256 - 2^32 - 977, as little-endian 64-bit limbs
+const P: [u64; 4] = [0xFFFFFFFEFFFFFC2F, 0xFFFFFFFFFFFFFFFF, 0xFFFFFFFFFFFFFFFF, 0xFFFFFFFFFFFFFFFF];
+
+// 2^256 mod p
+const P_COMPLEMENT: u64 = 0x1000003D1;
+
+// Exponents for inversion (p - 2) and square roots ((p + 1) / 4)
+const P_MINUS_2: [u64; 4] = [0xFFFFFFFEFFFFFC2D, 0xFFFFFFFFFFFFFFFF, 0xFFFFFFFFFFFFFFFF, 0xFFFFFFFFFFFFFFFF];
+const SQRT_EXPONENT: [u64; 4] = [0xFFFFFFFFBFFFFF0C, 0xFFFFFFFFFFFFFFFF, 0xFFFFFFFFFFFFFFFF, 0x3FFFFFFFFFFFFFFF];
+
+// sqrt(-3), the root returned by exponentiation
+const MINUS_3_SQRT: Fe = Fe([0x7D8D27AE1CD5F852, 0xC61F6D15DA14ECD4, 0x233770C2A797962C, 0x0A2D2BA93507F1DF]);
+
+// Element of the secp256k1 base field, always fully reduced
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Fe([u64; 4]);
+
+impl Fe {
+    const ZERO: Fe = Fe([0, 0, 0, 0]);
+
+    fn from_u64(n: u64) -> Fe {
+        Fe([n, 0, 0, 0])
+    }
+
+    // Big-endian bytes, reduced modulo p
+    fn from_bytes(bytes: &[u8; 32]) -> Fe {
+        let mut limbs = [0u64; 4];
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            *limb = u64::from_be_bytes(bytes[24 - 8 * i..32 - 8 * i].try_into().unwrap());
+        }
+        Fe(limbs).reduce_once()
+    }
+
+    fn to_bytes(self) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        for (i, limb) in self.0.iter().enumerate() {
+            bytes[24 - 8 * i..32 - 8 * i].copy_from_slice(&limb.to_be_bytes());
+        }
+        bytes
+    }
+
+    fn is_zero(self) -> bool {
+        self == Fe::ZERO
+    }
+
+    // Subtract p once if the value is at least p
+    fn reduce_once(self) -> Fe {
+        let mut out = [0u64; 4];
+        let mut borrow = false;
+        for i in 0..4 {
+            let (d, b1) = self.0[i].overflowing_sub(P[i]);
+            let (d, b2) = d.overflowing_sub(borrow as u64);
+            out[i] = d;
+            borrow = b1 || b2;
+        }
+        if borrow { self } else { Fe(out) }
+    }
+
+    // Add a small value, folding a carry out of 2^256 back in as 2^256 mod p
+    fn add_small(limbs: [u64; 4], mut carry: u128) -> Fe {
+        let mut out = limbs;
+        while carry != 0 {
+            for limb in out.iter_mut() {
+                let v = *limb as u128 + carry;
+                *limb = v as u64;
+                carry = v >> 64;
+            }
+            carry *= P_COMPLEMENT as u128;
+        }
+        Fe(out).reduce_once()
+    }
+
+    fn add(self, other: Fe) -> Fe {
+        let mut out = [0u64; 4];
+        let mut carry = 0u128;
+        for (i, limb) in out.iter_mut().enumerate() {
+            let v = self.0[i] as u128 + other.0[i] as u128 + carry;
+            *limb = v as u64;
+            carry = v >> 64;
+        }
+        Fe::add_small(out, carry * P_COMPLEMENT as u128)
+    }
+
+    fn neg(self) -> Fe {
+        if self.is_zero() {
+            return self;
+        }
+        let mut out = [0u64; 4];
+        let mut borrow = false;
+        for i in 0..4 {
+            let (d, b1) = P[i].overflowing_sub(self.0[i]);
+            let (d, b2) = d.overflowing_sub(borrow as u64);
+            out[i] = d;
+            borrow = b1 || b2;
+        }
+        Fe(out)
+    }
+
+    fn sub(self, other: Fe) -> Fe {
+        self.add(other.neg())
+    }
+
+    fn mul(self, other: Fe) -> Fe {
+        // 512-bit product, then fold the high half back in as hi * 2^256 = hi * (2^256 mod p)
+        let mut wide = [0u64; 8];
+        for i in 0..4 {
+            let mut carry = 0u128;
+            for j in 0..4 {
+                let v = wide[i + j] as u128 + self.0[i] as u128 * other.0[j] as u128 + carry;
+                wide[i + j] = v as u64;
+                carry = v >> 64;
+            }
+            wide[i + 4] = carry as u64;
+        }
+        let mut out = [0u64; 4];
+        let mut carry = 0u128;
+        for i in 0..4 {
+            let v = wide[i] as u128 + wide[i + 4] as u128 * P_COMPLEMENT as u128 + carry;
+            out[i] = v as u64;
+            carry = v >> 64;
+        }
+        Fe::add_small(out, carry * P_COMPLEMENT as u128)
+    }
+
+    fn square(self) -> Fe {
+        self.mul(self)
+    }
+
+    fn pow(self, exponent: &[u64; 4]) -> Fe {
+        let mut result = Fe::from_u64(1);
+        for limb in exponent.iter().rev() {
+            for bit in (0..64).rev() {
+                result = result.square();
+                if (limb >> bit) & 1 == 1 {
+                    result = result.mul(self);
+                }
+            }
+        }
+        result
+    }
+
+    // Multiplicative inverse; zero maps to zero
+    fn inv(self) -> Fe {
+        self.pow(&P_MINUS_2)
+    }
+
+    fn div(self, other: Fe) -> Fe {
+        self.mul(other.inv())
+    }
+
+    fn half(self) -> Fe {
+        self.div(Fe::from_u64(2))
+    }
+
+    // Square root when one exists (p = 3 mod 4)
+    fn sqrt(self) -> Option<Fe> {
+        let root = self.pow(&SQRT_EXPONENT);
+        (root.square() == self).then_some(root)
+    }
+
+    // Whether x is the X coordinate of a curve point, i.e. x^3 + 7 is a square
+    fn is_valid_x(self) -> bool {
+        curve_rhs(self).sqrt().is_some()
+    }
+}
+
+// x^3 + 7
+fn curve_rhs(x: Fe) -> Fe {
+    x.square().mul(x).add(Fe::from_u64(7))
+}
+
+// Map field elements (u, t) to an X coordinate on the curve
+fn xswiftec(mut u: Fe, mut t: Fe) -> Fe {
+    if u.is_zero() {
+        u = Fe::from_u64(1);
+    }
+    if t.is_zero() {
+        t = Fe::from_u64(1);
+    }
+    if curve_rhs(u).add(t.square()).is_zero() {
+        t = t.add(t);
+    }
+    let x = curve_rhs(u).sub(t.square()).div(t.add(t));
+    let y = x.add(t).div(MINUS_3_SQRT.mul(u));
+    let candidates = [
+        u.add(Fe::from_u64(4).mul(y.square())),
+        x.neg().div(y).sub(u).half(),
+        x.div(y).sub(u).half(),
+    ];
+    candidates.into_iter().find(|x| x.is_valid_x()).expect("one of the three candidates is always on the curve")
+}
+
+// Find t with xswiftec(u, t) = x using one of the eight inverse branches selected by case,
+// following the BIP324 reference: case & 2 picks how v is solved for, and case & 5 which of
+// four preimages is returned, so that every encoding of x is equally likely
+fn xswiftec_inv(x: Fe, u: Fe, case: u8) -> Option<Fe> {
+    let (v, s) = if case & 2 == 0 {
+        if x.neg().sub(u).is_valid_x() {
+            return None;
+        }
+        let s = curve_rhs(u).neg().div(u.square().add(u.mul(x)).add(x.square()));
+        (x, s)
+    } else {
+        let s = x.sub(u);
+        if s.is_zero() {
+            return None;
+        }
+        let r = s.neg().mul(Fe::from_u64(4).mul(curve_rhs(u)).add(Fe::from_u64(3).mul(s).mul(u.square()))).sqrt()?;
+        if case & 1 == 1 && r.is_zero() {
+            return None;
+        }
+        (u.neg().add(r.div(s)).half(), s)
+    };
+    let w = s.sqrt()?;
+    let one = Fe::from_u64(1);
+    let minus_half = one.sub(MINUS_3_SQRT).half(); // (1 - sqrt(-3)) / 2
+    let plus_half = one.add(MINUS_3_SQRT).half(); // (1 + sqrt(-3)) / 2
+    let t = match case & 5 {
+        0 => w.mul(u.mul(minus_half).add(v)).neg(),
+        1 => w.mul(u.mul(plus_half).add(v)),
+        4 => w.mul(u.mul(minus_half).add(v)),
+        _ => w.mul(u.mul(plus_half).add(v)).neg(),
+    };
+    Some(t)
+}
+
+// X coordinate encoded by a 64-byte ElligatorSwift encoding
+pub fn ellswift_decode(encoding: &[u8; 64]) -> [u8; 32] {
+    let u = Fe::from_bytes(encoding[..32].try_into().unwrap());
+    let t = Fe::from_bytes(encoding[32..].try_into().unwrap());
+    xswiftec(u, t).to_bytes()
+}
+
+// Encode the public key of secret as 64 random-looking bytes
+pub fn ellswift_create(secret: &SecretKey) -> [u8; 64] {
+    let secp = Secp256k1::signing_only();
+    let x = Fe::from_bytes(&secret.x_only_public_key(&secp).0.serialize());
+    loop {
+        let u = Fe::from_bytes(&rand::random::<[u8; 32]>());
+        if u.is_zero() {
+            continue;
+        }
+        let case = rand::random::<u8>() & 7;
+        // Each branch only applies to some (x, u); a wrong guess just means another try
+        if let Some(t) = xswiftec_inv(x, u, case) {
+            let mut encoding = [0u8; 64];
+            encoding[..32].copy_from_slice(&u.to_bytes());
+            encoding[32..].copy_from_slice(&t.to_bytes());
+            return encoding;
+        }
+    }
+}
+
+// X coordinate of secret times the point encoded by theirs
+pub fn ellswift_ecdh_xonly(theirs: &[u8; 64], secret: &SecretKey) -> [u8; 32] {
+    let x = XOnlyPublicKey::from_slice(&ellswift_decode(theirs)).expect("decoded X coordinates lie on the curve");
+    let point = ecdh::shared_secret_point(&PublicKey::from_x_only_public_key(x, Parity::Even), secret);
+    point[..32].try_into().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes<const N: usize>(hex: &str) -> [u8; N] {
+        let bytes: Vec<u8> = (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect();
+        bytes.try_into().unwrap()
+    }
+
+    fn fe(hex: &str) -> Fe {
+        Fe::from_bytes(&bytes(hex))
+    }
+
+    // Computed with an independent transcription of the BIP324 reference code; the first is
+    // also the first case of BIP324's ellswift_decode_test_vectors.csv
+    const DECODE_VECTORS: [(&str, &str); 6] = [
+        ("00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000", "edd1fd3e327ce90cc7a3542614289aee9682003e9cf7dcc9cf2ca9743be5aa0c"), // u = t = 0
+        ("fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2ffffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc34", "5e5936b181db0b658e33a8c61aa687dd31d11e1585e356646b4c2071cde7e942"), // u and t at or above p, reduced to 0 and 5
+        ("0000000000000000000000000000000000000000000000000000000000000005350ae3b48047adacdeea49fb8a0b289a94f726801078408aba79631fa7a1b6ba", "aaee74bef85ee588ed43aa2a45b30e457127b97a48a8cfdff4de85545d2f1ccf"), // u^3 + t^2 + 7 = 0
+        ("da291097dae2152b149166b7dfa6bfdb27c895f718ed29b708739378ca437717733cf4884b39cd6c9b4b4cd4c238f3575ead7a127134dc01dd2dbe43e1911981", "847257b123dbe28e2fdf90e95f3f72346beee0f91b8787ce6dec61e55fe17888"),
+        ("9196a72fe52eab34be5c4b5ae79371e026ffe15aee8a7424171dcea1a0072ab3baec8865c5bfd3c7927ac1db47133b4b24938b9240cd6c381bfdb8cb57daa332", "cdb1419668fcecc663df4e2fc7b263065b7aa3da1a773491bcaf8c9572069542"),
+        ("eb44a7d82915e51ff9c1be75ab29e6bcca285f215f7978772bea70332d8d890aaf9f83b76e666e41abdf12d9e7705d2919ea33a94aa6487d9d7d670c89423e8d", "94515f3488f2c0a287bf37614ba5a13db35d64d81bc7bf2413895199088b894f"),
+    ];
+
+    // (u, x, t for each of the eight cases), from the same transcription: only the first
+    // branch applies, both do, only the second does, and neither does
+    type InverseVector = (&'static str, &'static str, [Option<&'static str>; 8]);
+    const INVERSE_VECTORS: [InverseVector; 4] = [
+        (
+            "3ce16845077f9d97f484180e31e2df0b5e5a2d2490e592ce1d94b3101f2a98fa",
+            "2380ae7c6568e4823aa9fc376dcddd0fb2c0963b97c377dafb2b341ed8c58b9d",
+            [
+                Some("52a8ec9290315f1af17f54b5ae4b69876b91180376b92762954f875fac658314"),
+                Some("7fdcfaef4c9204d13e35bdff70e23ffdd0403c757110f643111fe535bc48f95a"),
+                None,
+                None,
+                Some("ad57136d6fcea0e50e80ab4a51b49678946ee7fc8946d89d6ab0789f539a791b"),
+                Some("80230510b36dfb2ec1ca42008f1dc0022fbfc38a8eef09bceee01ac943b702d5"),
+                None,
+                None,
+            ],
+        ),
+        (
+            "4458129e8118e42f8d47d8199ad6a314f07b8f81f2d8d291c0fecb2d2dc026c8",
+            "30d8113f4dc2ff285ec10094af1864c0ffe3d4a9b73081e159272a5e0a6b340c",
+            [
+                Some("ab4a257f54b5a18ae10638fe499cd559a6c3201ed3f7d07a84dd043355f30ea5"),
+                Some("5ae899aaf446f7b81c7c3b809a6a0c692eb240d2cb650b5999dc55e3b8af0277"),
+                Some("75d8929343fff07dcf2fe34a54a775cbf1b71cf563760a63630b707ab9ee6a12"),
+                Some("ee1bb99acf159d1942dacccd8bbbd9d8ac35b96f2676b48d8b22e18c67f7a273"),
+                Some("54b5da80ab4a5e751ef9c701b6632aa6593cdfe12c082f857b22fbcbaa0ced8a"),
+                Some("a51766550bb90847e383c47f6595f396d14dbf2d349af4a66623aa1b4750f9b8"),
+                Some("8a276d6cbc000f8230d01cb5ab588a340e48e30a9c89f59c9cf48f844611921d"),
+                Some("11e4466530ea62e6bd2533327444262753ca4690d9894b7274dd1e72980859bc"),
+            ],
+        ),
+        (
+            "ed9fd9d76ec2e087cbdfc90bc8a86a5920a02223ea4d29287ad57400f147c3b4",
+            "5f87d00e47d1ef254821d6f8927b0cfc8c5d003157946b4ac6c42bf12dcf3533",
+            [
+                None,
+                None,
+                Some("f8e7040e566010bfdf35d5bcda0b965f6ab290a68381f48d6f7e97fb6aa7bfbe"),
+                Some("1e4fef24e0ff324c57c478cdbf5ba47106364c816c3c96c52eab1405989afa20"),
+                None,
+                None,
+                Some("0718fbf1a99fef4020ca2a4325f469a0954d6f597c7e0b729081680395583c71"),
+                Some("e1b010db1f00cdb3a83b873240a45b8ef9c9b37e93c3693ad154ebf96765020f"),
+            ],
+        ),
+        ("56bf66f24d899b481d661470c52bd4853678949322a43812590e2f2ac401576e", "61794cccfc11009663b82ccb0b2ef1fd83419dd779c7fc632b5c410dbe747eba", [None; 8]),
+    ];
+
+    #[test]
+    fn decodes_vectors() {
+        for (encoding, x) in DECODE_VECTORS {
+            assert_eq!(ellswift_decode(&bytes(encoding)), bytes::<32>(x), "decoding {}", encoding);
+        }
+    }
+
+    #[test]
+    fn inverts_vectors_in_every_case() {
+        for (u, x, expected) in INVERSE_VECTORS {
+            let (u, x) = (fe(u), fe(x));
+            for (case, t) in expected.into_iter().enumerate() {
+                let found = xswiftec_inv(x, u, case as u8);
+                assert_eq!(found, t.map(fe), "case {}", case);
+                if let Some(t) = found {
+                    assert_eq!(xswiftec(u, t), x, "case {} decodes back", case);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn created_encodings_decode_to_the_public_key() {
+        let secp = Secp256k1::signing_only();
+        for _ in 0..8 {
+            let secret = SecretKey::from_slice(&rand::random::<[u8; 32]>()).unwrap();
+            let x = secret.x_only_public_key(&secp).0.serialize();
+            assert_eq!(ellswift_decode(&ellswift_create(&secret)), x);
+        }
+    }
+
+    #[test]
+    fn ecdh_agrees_on_both_sides() {
+        let ours = SecretKey::from_slice(&[0x11; 32]).unwrap();
+        let theirs = SecretKey::from_slice(&[0x22; 32]).unwrap();
+        let shared = ellswift_ecdh_xonly(&ellswift_create(&theirs), &ours);
+        assert_eq!(shared, ellswift_ecdh_xonly(&ellswift_create(&ours), &theirs));
+    }
+}
@@ -0,0 +1,94 @@
+use std::fmt::Write;
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::store::{PeerRecord, PeerStore};
+
+// Crawl results in the layout of sipa's bitcoin-seeder dnsseed.dump, so scripts written
+// against that seeder read ours, plus CSV and JSON with the same columns
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    Dump, // dnsseed.dump
+    Csv,
+    Json,
+}
+
+// One line of the dump
+#[derive(Debug, Clone, Serialize)]
+pub struct DumpEntry {
+    pub address: String,
+    pub good: bool,
+    pub last_success: u64,  // unix time, 0 if never
+    pub uptime: [f64; 5],   // reliability over 2 hours, 8 hours, a day, a week and 30 days
+    pub blocks: i32,        // start height from the latest handshake
+    pub services: u64,
+    pub version: i32,       // protocol version
+    pub user_agent: String,
+}
+
+impl DumpEntry {
+    fn from_record(record: &PeerRecord) -> Self {
+        DumpEntry {
+            address: record.addr.to_string(),
+            good: record.is_good(),
+            last_success: record.last_success.unwrap_or(0),
+            uptime: record.uptime.windows.map(|window| window.reliability),
+            blocks: record.start_height.unwrap_or(0),
+            services: record.services,
+            version: record.protocol_version.unwrap_or(0),
+            user_agent: record.user_agent.clone().unwrap_or_default(),
+        }
+    }
+}
+
+// Every peer visited at least once, ordered as bitcoin-seeder orders its dump: by 30-day
+// uptime, then 7-day uptime, then protocol version, highest first
+pub fn dump_entries(store: &PeerStore) -> Vec<DumpEntry> {
+    let mut entries: Vec<DumpEntry> = store.records().filter(|record| record.last_attempt.is_some()).map(DumpEntry::from_record).collect();
+    entries.sort_by(|a, b| b.uptime[4].total_cmp(&a.uptime[4]).then(b.uptime[3].total_cmp(&a.uptime[3])).then(b.version.cmp(&a.version)));
+    entries
+}
+
+pub fn export(entries: &[DumpEntry], format: ExportFormat) -> Result<String, serde_json::Error> {
+    match format {
+        ExportFormat::Dump => Ok(dump(entries)),
+        ExportFormat::Csv => Ok(csv(entries)),
+        ExportFormat::Json => serde_json::to_string_pretty(entries),
+    }
+}
+
+// Formatted field for field as bitcoin-seeder's dumper prints them
+fn dump(entries: &[DumpEntry]) -> String {
+    let mut out = String::from("# address                                        good  lastSuccess    %(2h)   %(8h)   %(1d)   %(7d)  %(30d)  blocks      svcs  version\n");
+    for entry in entries {
+        let [h2, h8, d1, d7, d30] = entry.uptime.map(|reliability| reliability * 100.0);
+        let _ = writeln!(
+            out,
+            "{:<47}  {:>4}  {:>11}  {:>6.2}% {:>6.2}% {:>6.2}% {:>6.2}% {:>6.2}%  {:>6}  {:08x}  {:>5} \"{}\"",
+            entry.address, entry.good as u8, entry.last_success, h2, h8, d1, d7, d30, entry.blocks, entry.services, entry.version, entry.user_agent
+        );
+    }
+    out
+}
+
+fn csv(entries: &[DumpEntry]) -> String {
+    let mut out = String::from("address,good,last_success,uptime_2h,uptime_8h,uptime_1d,uptime_7d,uptime_30d,blocks,services,version,user_agent\n");
+    for entry in entries {
+        let uptime: Vec<String> = entry.uptime.iter().map(|reliability| format!("{:.4}", reliability)).collect();
+        let _ = writeln!(
+            out,
+            "{},{},{},{},{},{:#x},{},\"{}\"",
+            entry.address,
+            entry.good as u8,
+            entry.last_success,
+            uptime.join(","),
+            entry.blocks,
+            entry.services,
+            entry.version,
+            entry.user_agent.replace('"', "\"\"")
+        );
+    }
+    out
+}
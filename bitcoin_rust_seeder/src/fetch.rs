@@ -0,0 +1,81 @@
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use bitcoin::consensus::deserialize;
+use bitcoin::{Block, Transaction};
+use block_breaker::stats::BlockStats;
+use block_breaker::summary::TransactionSummary;
+use block_breaker::BlockProcessor;
+
+use crate::message::{InvType, Inventory, Message, MessageError};
+use crate::peer::Peer;
+
+// Transaction or block downloaded after the peer announced it
+#[derive(Debug, Clone)]
+pub enum Fetched {
+    Transaction { raw: Vec<u8>, summary: TransactionSummary },
+    Block { raw: Vec<u8>, stats: BlockStats },
+    Undecodable { command: &'static str, raw: Vec<u8>, error: String },
+}
+
+// What a fetch session saw
+#[derive(Debug, Default)]
+pub struct FetchReport {
+    pub announced: usize, // inventory entries announced by the peer
+    pub requested: usize,
+    pub not_found: usize,
+    pub fetched: Vec<Fetched>,
+}
+
+// Listen for inv announcements for the given duration, request every announced transaction
+// and block (with witnesses) until max_items have been requested, and decode what arrives
+// with block_breaker's transaction and block tooling
+pub fn fetch_announced(peer: &mut Peer, duration: Duration, max_items: usize) -> Result<FetchReport, MessageError> {
+    let deadline = Instant::now() + duration;
+    let mut report = FetchReport::default();
+    let mut requested = HashSet::new();
+
+    while let Some(message) = peer.receive_before(deadline)? {
+        match message {
+            Message::Inv(items) => {
+                report.announced += items.len();
+                let wanted: Vec<Inventory> = items
+                    .into_iter()
+                    .filter_map(|item| match item.kind {
+                        InvType::Tx | InvType::WitnessTx => Some(Inventory { kind: InvType::WitnessTx, hash: item.hash }),
+                        InvType::Block | InvType::WitnessBlock => Some(Inventory { kind: InvType::WitnessBlock, hash: item.hash }),
+                        _ => None,
+                    })
+                    .filter(|item| requested.insert(item.hash))
+                    .take(max_items - report.requested)
+                    .collect();
+                if !wanted.is_empty() {
+                    report.requested += wanted.len();
+                    peer.send(&Message::GetData(wanted))?;
+                }
+            }
+            Message::NotFound(items) => report.not_found += items.len(),
+            Message::Tx(raw) => report.fetched.push(decode_transaction(raw)),
+            Message::Block(raw) => report.fetched.push(decode_block(raw)),
+            _ => {}
+        }
+        if report.requested >= max_items && report.fetched.len() + report.not_found >= report.requested {
+            break;
+        }
+    }
+    Ok(report)
+}
+
+fn decode_transaction(raw: Vec<u8>) -> Fetched {
+    match deserialize::<Transaction>(&raw) {
+        Ok(tx) => Fetched::Transaction { summary: BlockProcessor::summarize_transaction(0, &tx), raw },
+        Err(e) => Fetched::Undecodable { command: "tx", raw, error: e.to_string() },
+    }
+}
+
+fn decode_block(raw: Vec<u8>) -> Fetched {
+    match deserialize::<Block>(&raw) {
+        Ok(block) => Fetched::Block { stats: BlockProcessor::block_stats(&block, None), raw },
+        Err(e) => Fetched::Undecodable { command: "block", raw, error: e.to_string() },
+    }
+}
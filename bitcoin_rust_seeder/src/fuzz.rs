@@ -0,0 +1,263 @@
+use std::fmt;
+use std::io::ErrorKind;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::address::PeerAddr;
+use crate::message::{checksum, write_compact_size, Command, Message, MessageError, VersionMessage, HEADER_SIZE};
+use crate::peer::{ConnectConfig, Peer};
+
+// Handshake fuzzing: block_breaker's approach of taking something valid and breaking one
+// field at a time, applied to the version message we open a connection with. Each mutant
+// goes to the target on a fresh connection and we record what it does about it.
+
+// Offsets of the fixed-size fields at the start of a version payload
+const SERVICES_OFFSET: usize = 4;
+const TIMESTAMP_OFFSET: usize = 12;
+const RECEIVER_OFFSET: usize = 20;
+const SENDER_OFFSET: usize = 46;
+const NONCE_OFFSET: usize = 72;
+const USER_AGENT_OFFSET: usize = 80;
+
+// Longest user agent Bitcoin Core accepts
+const MAX_SUBVERSION_LENGTH: usize = 256;
+
+// Oldest protocol version Bitcoin Core still talks to
+const MIN_PEER_PROTO_VERSION: i32 = 31800;
+
+// One way of breaking the version message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionMutation {
+    Valid,                    // the untouched message, as a control
+    Empty,                    // no payload at all
+    TruncatedVersion,         // cut inside each field in turn
+    TruncatedServices,
+    TruncatedTimestamp,
+    TruncatedReceiver,
+    TruncatedSender,
+    TruncatedNonce,
+    TruncatedStartHeight,
+    NoRelay,                  // relay flag left off, as peers before BIP37 do; still valid
+    TrailingBytes,            // junk after the relay flag
+    UserAgentInvalidUtf8,
+    UserAgentLengthOverrun,   // length claims more bytes than the payload holds
+    UserAgentHugeLength,      // 64-bit compact size length
+    UserAgentNonCanonical,    // length in a wider compact size than it needs
+    UserAgentOversized,       // longer than MAX_SUBVERSION_LENGTH
+    ObsoleteVersion,          // protocol version below MIN_PEER_PROTO_VERSION
+    HeaderLengthShort,        // header length one byte less than the payload
+    HeaderLengthLong,         // header length beyond the bytes we send
+    BadChecksum,
+    Duplicate,                // two valid versions back to back
+}
+
+impl VersionMutation {
+    pub const ALL: [VersionMutation; 21] = [
+        VersionMutation::Valid,
+        VersionMutation::Empty,
+        VersionMutation::TruncatedVersion,
+        VersionMutation::TruncatedServices,
+        VersionMutation::TruncatedTimestamp,
+        VersionMutation::TruncatedReceiver,
+        VersionMutation::TruncatedSender,
+        VersionMutation::TruncatedNonce,
+        VersionMutation::TruncatedStartHeight,
+        VersionMutation::NoRelay,
+        VersionMutation::TrailingBytes,
+        VersionMutation::UserAgentInvalidUtf8,
+        VersionMutation::UserAgentLengthOverrun,
+        VersionMutation::UserAgentHugeLength,
+        VersionMutation::UserAgentNonCanonical,
+        VersionMutation::UserAgentOversized,
+        VersionMutation::ObsoleteVersion,
+        VersionMutation::HeaderLengthShort,
+        VersionMutation::HeaderLengthLong,
+        VersionMutation::BadChecksum,
+        VersionMutation::Duplicate,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            VersionMutation::Valid => "valid",
+            VersionMutation::Empty => "empty",
+            VersionMutation::TruncatedVersion => "truncated-version",
+            VersionMutation::TruncatedServices => "truncated-services",
+            VersionMutation::TruncatedTimestamp => "truncated-timestamp",
+            VersionMutation::TruncatedReceiver => "truncated-receiver",
+            VersionMutation::TruncatedSender => "truncated-sender",
+            VersionMutation::TruncatedNonce => "truncated-nonce",
+            VersionMutation::TruncatedStartHeight => "truncated-start-height",
+            VersionMutation::NoRelay => "no-relay",
+            VersionMutation::TrailingBytes => "trailing-bytes",
+            VersionMutation::UserAgentInvalidUtf8 => "user-agent-invalid-utf8",
+            VersionMutation::UserAgentLengthOverrun => "user-agent-length-overrun",
+            VersionMutation::UserAgentHugeLength => "user-agent-huge-length",
+            VersionMutation::UserAgentNonCanonical => "user-agent-non-canonical",
+            VersionMutation::UserAgentOversized => "user-agent-oversized",
+            VersionMutation::ObsoleteVersion => "obsolete-version",
+            VersionMutation::HeaderLengthShort => "header-length-short",
+            VersionMutation::HeaderLengthLong => "header-length-long",
+            VersionMutation::BadChecksum => "bad-checksum",
+            VersionMutation::Duplicate => "duplicate",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        VersionMutation::ALL.into_iter().find(|mutation| mutation.name() == name)
+    }
+
+    // The bytes to send in place of the version message, header included
+    pub fn wire(self, version: &VersionMessage, magic: [u8; 4]) -> Vec<u8> {
+        let mut version = version.clone();
+        if self == VersionMutation::ObsoleteVersion {
+            version.version = MIN_PEER_PROTO_VERSION - 1;
+        }
+        let valid = Message::Version(version.clone()).encode_payload();
+        // Where the user agent's bytes end and the start height begins
+        let start_height_offset = valid.len() - 5;
+
+        let payload = match self {
+            VersionMutation::Valid | VersionMutation::ObsoleteVersion | VersionMutation::HeaderLengthShort => valid.clone(),
+            VersionMutation::HeaderLengthLong | VersionMutation::BadChecksum | VersionMutation::Duplicate => valid.clone(),
+            VersionMutation::Empty => Vec::new(),
+            VersionMutation::TruncatedVersion => valid[..SERVICES_OFFSET / 2].to_vec(),
+            VersionMutation::TruncatedServices => valid[..SERVICES_OFFSET + 3].to_vec(),
+            VersionMutation::TruncatedTimestamp => valid[..TIMESTAMP_OFFSET + 5].to_vec(),
+            VersionMutation::TruncatedReceiver => valid[..RECEIVER_OFFSET + 13].to_vec(),
+            VersionMutation::TruncatedSender => valid[..SENDER_OFFSET + 13].to_vec(),
+            VersionMutation::TruncatedNonce => valid[..NONCE_OFFSET + 4].to_vec(),
+            VersionMutation::TruncatedStartHeight => valid[..start_height_offset + 2].to_vec(),
+            VersionMutation::NoRelay => valid[..valid.len() - 1].to_vec(),
+            VersionMutation::TrailingBytes => [valid.as_slice(), &[0xde, 0xad, 0xbe, 0xef]].concat(),
+            VersionMutation::UserAgentInvalidUtf8 => with_user_agent(&valid, start_height_offset, |out| {
+                write_compact_size(out, 6);
+                out.extend([0x2f, 0xc3, 0x28, 0xff, 0xfe, 0x2f]);
+            }),
+            VersionMutation::UserAgentLengthOverrun => with_user_agent(&valid, start_height_offset, |out| {
+                write_compact_size(out, 200);
+                out.extend(b"/overrun/");
+            }),
+            VersionMutation::UserAgentHugeLength => with_user_agent(&valid, start_height_offset, |out| {
+                write_compact_size(out, u64::MAX);
+                out.extend(b"/huge/");
+            }),
+            VersionMutation::UserAgentNonCanonical => with_user_agent(&valid, start_height_offset, |out| {
+                out.push(0xfd);
+                out.extend(15u16.to_le_bytes());
+                out.extend(b"/noncanonical1/");
+            }),
+            VersionMutation::UserAgentOversized => with_user_agent(&valid, start_height_offset, |out| {
+                write_compact_size(out, MAX_SUBVERSION_LENGTH as u64 + 1);
+                out.extend([b'a'; MAX_SUBVERSION_LENGTH + 1]);
+            }),
+        };
+
+        let mut wire = Vec::with_capacity(2 * (HEADER_SIZE + payload.len()));
+        wire.extend(magic);
+        wire.extend(Command::Version.to_bytes());
+        let length = match self {
+            VersionMutation::HeaderLengthShort => payload.len() - 1,
+            VersionMutation::HeaderLengthLong => payload.len() + 100,
+            _ => payload.len(),
+        };
+        wire.extend((length as u32).to_le_bytes());
+        let mut sum = checksum(&payload);
+        if self == VersionMutation::BadChecksum {
+            sum[0] ^= 0xff;
+        }
+        wire.extend(sum);
+        wire.extend(&payload);
+        if self == VersionMutation::Duplicate {
+            wire.extend_from_within(..);
+        }
+        wire
+    }
+}
+
+impl fmt::Display for VersionMutation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+// Replace the user agent, length and bytes, keeping everything around it
+fn with_user_agent(valid: &[u8], start_height_offset: usize, user_agent: impl FnOnce(&mut Vec<u8>)) -> Vec<u8> {
+    let mut payload = valid[..USER_AGENT_OFFSET].to_vec();
+    user_agent(&mut payload);
+    payload.extend(&valid[start_height_offset..]);
+    payload
+}
+
+// How the connection ended up after the target saw a mutant
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "verdict", rename_all = "snake_case")]
+pub enum Reaction {
+    Accepted,                     // acknowledged our version with a verack
+    Disconnected { after_ms: u64 },
+    Ignored,                      // kept the connection open without a verack
+    Unreadable { error: String }, // sent something we could not decode
+    Unreachable { error: String },
+}
+
+impl fmt::Display for Reaction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reaction::Accepted => write!(f, "accepted"),
+            Reaction::Disconnected { after_ms } => write!(f, "disconnected after {} ms", after_ms),
+            Reaction::Ignored => write!(f, "ignored"),
+            Reaction::Unreadable { error } => write!(f, "unreadable reply: {}", error),
+            Reaction::Unreachable { error } => write!(f, "unreachable: {}", error),
+        }
+    }
+}
+
+// What the target did with one mutant
+#[derive(Debug, Clone, Serialize)]
+pub struct FuzzOutcome {
+    pub case: &'static str,
+    pub sent_bytes: usize,
+    pub replies: Vec<String>, // commands the target sent, in order
+    pub reaction: Reaction,
+}
+
+// Send each mutant on its own connection and watch the target for up to `observe`, or until
+// it acknowledges or hangs up. Nothing is answered except pings, so a verack can only be the
+// target accepting the mutant.
+pub fn fuzz_handshake(addr: &PeerAddr, config: &ConnectConfig, mutations: &[VersionMutation], observe: Duration) -> Vec<FuzzOutcome> {
+    let version = config.version.build(addr);
+    mutations
+        .iter()
+        .map(|mutation| {
+            let wire = mutation.wire(&version, config.network.magic());
+            let mut replies = Vec::new();
+            let reaction = match Peer::connect(addr, config) {
+                Ok(mut peer) => observe_reaction(&mut peer, &wire, observe, &mut replies),
+                Err(e) => Reaction::Unreachable { error: e.to_string() },
+            };
+            FuzzOutcome { case: mutation.name(), sent_bytes: wire.len(), replies, reaction }
+        })
+        .collect()
+}
+
+fn observe_reaction(peer: &mut Peer, wire: &[u8], observe: Duration, replies: &mut Vec<String>) -> Reaction {
+    let sent = Instant::now();
+    if let Err(e) = peer.send_raw(wire) {
+        return Reaction::Unreachable { error: e.to_string() };
+    }
+    loop {
+        match peer.receive_before(sent + observe) {
+            Ok(Some(message)) => {
+                replies.push(message.command().name().to_string());
+                if message == Message::Verack {
+                    return Reaction::Accepted;
+                }
+            }
+            Ok(None) => return Reaction::Ignored,
+            Err(MessageError::Io(e)) if matches!(e.kind(), ErrorKind::UnexpectedEof | ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted) => {
+                return Reaction::Disconnected { after_ms: sent.elapsed().as_millis() as u64 };
+            }
+            Err(e) => return Reaction::Unreadable { error: e.to_string() },
+        }
+    }
+}
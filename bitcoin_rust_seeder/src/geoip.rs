@@ -0,0 +1,409 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::stats::by_count;
+use crate::store::PeerRecord;
+
+// Reader for MaxMind DB files, such as the GeoLite2 country and ASN databases: a binary
+// search tree over address bits whose leaves point into a section of typed data values
+
+// Start of the metadata map, near the end of the file
+const METADATA_MARKER: &[u8] = b"\xAB\xCD\xEFMaxMind.com";
+
+// Zero bytes between the search tree and the data section
+const DATA_SECTION_SEPARATOR: usize = 16;
+
+// Deepest nesting of maps and arrays decoded. GeoLite2 records go a few levels deep; the
+// cap makes a map that points back into itself fail instead of exhausting the stack.
+const MAX_DATA_DEPTH: usize = 32;
+
+// Countries and networks listed in the human summary; the JSON form has them all
+const TOP_ENTRIES: usize = 15;
+
+// Data section types
+const TYPE_EXTENDED: u8 = 0;
+const TYPE_POINTER: u8 = 1;
+const TYPE_STRING: u8 = 2;
+const TYPE_DOUBLE: u8 = 3;
+const TYPE_BYTES: u8 = 4;
+const TYPE_UINT16: u8 = 5;
+const TYPE_UINT32: u8 = 6;
+const TYPE_MAP: u8 = 7;
+const TYPE_INT32: u8 = 8;
+const TYPE_UINT64: u8 = 9;
+const TYPE_UINT128: u8 = 10;
+const TYPE_ARRAY: u8 = 11;
+const TYPE_BOOLEAN: u8 = 14;
+const TYPE_FLOAT: u8 = 15;
+
+// A decoded data value. Only strings, unsigned integers and maps are needed for lookups;
+// everything else is decoded to stay in step and then dropped.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    String(String),
+    Uint(u128),
+    Map(BTreeMap<String, Value>),
+    Other,
+}
+
+impl Value {
+    // Follow a path of map keys, e.g. ["country", "iso_code"]
+    fn path(&self, keys: &[&str]) -> Option<&Value> {
+        keys.iter().try_fold(self, |value, key| match value {
+            Value::Map(map) => map.get(*key),
+            _ => None,
+        })
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Uint(n) => u64::try_from(*n).ok(),
+            _ => None,
+        }
+    }
+}
+
+// Decoder over a data section; pointers are offsets from its start
+struct Decoder<'a> {
+    data: &'a [u8],
+}
+
+impl Decoder<'_> {
+    fn bytes(&self, offset: usize, n: usize) -> Result<&[u8], Box<dyn Error>> {
+        self.data.get(offset..offset + n).ok_or_else(|| "MaxMind DB data section is truncated".into())
+    }
+
+    fn uint(&self, offset: usize, n: usize) -> Result<u128, Box<dyn Error>> {
+        if n > 16 {
+            return Err(format!("{}-byte integer in MaxMind DB", n).into());
+        }
+        Ok(self.bytes(offset, n)?.iter().fold(0u128, |value, &byte| value << 8 | byte as u128))
+    }
+
+    // Decode the value at offset, returning it with the offset just past it
+    fn decode(&self, offset: usize) -> Result<(Value, usize), Box<dyn Error>> {
+        self.decode_nested(offset, 0)
+    }
+
+    // Decode a value found depth maps and arrays down. A pointer is followed in place, but
+    // the format forbids it to lead to another pointer, so chains of them are refused.
+    fn decode_nested(&self, offset: usize, depth: usize) -> Result<(Value, usize), Box<dyn Error>> {
+        if depth > MAX_DATA_DEPTH {
+            return Err(format!("MaxMind DB data nests deeper than {} levels", MAX_DATA_DEPTH).into());
+        }
+        let control = self.bytes(offset, 1)?[0];
+        let mut offset = offset + 1;
+        let mut kind = control >> 5;
+
+        if kind == TYPE_POINTER {
+            let size = ((control >> 3) & 0x3) as usize;
+            let high = (control & 0x7) as usize;
+            let target = match size {
+                0 => high << 8 | self.uint(offset, 1)? as usize,
+                1 => (high << 16 | self.uint(offset, 2)? as usize) + 2048,
+                2 => (high << 24 | self.uint(offset, 3)? as usize) + 526_336,
+                _ => self.uint(offset, 4)? as usize,
+            };
+            if self.bytes(target, 1)?[0] >> 5 == TYPE_POINTER {
+                return Err("MaxMind DB pointer points to another pointer".into());
+            }
+            let (value, _) = self.decode_nested(target, depth)?;
+            return Ok((value, offset + size + 1));
+        }
+        if kind == TYPE_EXTENDED {
+            kind = 7 + self.bytes(offset, 1)?[0];
+            offset += 1;
+        }
+        let size = match control & 0x1F {
+            29 => {
+                offset += 1;
+                29 + self.uint(offset - 1, 1)? as usize
+            }
+            30 => {
+                offset += 2;
+                285 + self.uint(offset - 2, 2)? as usize
+            }
+            31 => {
+                offset += 3;
+                65_821 + self.uint(offset - 3, 3)? as usize
+            }
+            size => size as usize,
+        };
+
+        match kind {
+            TYPE_STRING => Ok((Value::String(String::from_utf8_lossy(self.bytes(offset, size)?).into_owned()), offset + size)),
+            TYPE_UINT16 | TYPE_UINT32 | TYPE_UINT64 | TYPE_UINT128 => Ok((Value::Uint(self.uint(offset, size)?), offset + size)),
+            TYPE_MAP => {
+                let mut map = BTreeMap::new();
+                for _ in 0..size {
+                    let (key, next) = self.decode_nested(offset, depth + 1)?;
+                    let (value, next) = self.decode_nested(next, depth + 1)?;
+                    let Value::String(key) = key else {
+                        return Err("MaxMind DB map key is not a string".into());
+                    };
+                    map.insert(key, value);
+                    offset = next;
+                }
+                Ok((Value::Map(map), offset))
+            }
+            TYPE_ARRAY => {
+                for _ in 0..size {
+                    offset = self.decode_nested(offset, depth + 1)?.1;
+                }
+                Ok((Value::Other, offset))
+            }
+            TYPE_BOOLEAN => Ok((Value::Other, offset)), // the size is the value
+            TYPE_DOUBLE | TYPE_BYTES | TYPE_INT32 | TYPE_FLOAT => {
+                self.bytes(offset, size)?;
+                Ok((Value::Other, offset + size))
+            }
+            other => Err(format!("unsupported MaxMind DB data type {}", other).into()),
+        }
+    }
+}
+
+// An open MaxMind DB file
+pub struct MaxMindDb {
+    file: Vec<u8>,
+    node_count: usize,
+    record_size: usize, // bits per record, two records per node
+    ip_version: u64,
+    ipv4_start: usize,  // node reached after the 96 zero bits that prefix IPv4 in an IPv6 tree
+}
+
+impl MaxMindDb {
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let file = fs::read(path)?;
+        let marker = file
+            .windows(METADATA_MARKER.len())
+            .rposition(|window| window == METADATA_MARKER)
+            .ok_or_else(|| format!("{} is not a MaxMind DB file", path.display()))?;
+        let metadata_start = marker + METADATA_MARKER.len();
+        let (metadata, _) = Decoder { data: &file[metadata_start..] }.decode(0)?;
+        let field = |name: &str| metadata.path(&[name]).and_then(Value::as_u64).ok_or_else(|| format!("MaxMind DB metadata lacks {}", name));
+
+        let mut db = MaxMindDb {
+            node_count: field("node_count")? as usize,
+            record_size: field("record_size")? as usize,
+            ip_version: field("ip_version")?,
+            ipv4_start: 0,
+            file,
+        };
+        if ![24, 28, 32].contains(&db.record_size) {
+            return Err(format!("unsupported MaxMind DB record size {}", db.record_size).into());
+        }
+        if db.tree_size() + DATA_SECTION_SEPARATOR > marker {
+            return Err("MaxMind DB search tree overruns the file".into());
+        }
+        if db.ip_version == 6 {
+            for _ in 0..96 {
+                if db.ipv4_start >= db.node_count {
+                    break;
+                }
+                db.ipv4_start = db.record(db.ipv4_start, false);
+            }
+        }
+        Ok(db)
+    }
+
+    fn tree_size(&self) -> usize {
+        self.node_count * self.record_size / 4
+    }
+
+    // The left or right record of a node
+    fn record(&self, node: usize, right: bool) -> usize {
+        let bytes = &self.file[node * self.record_size / 4..][..self.record_size / 4];
+        let be = |b: &[u8]| b.iter().fold(0usize, |value, &byte| value << 8 | byte as usize);
+        match (self.record_size, right) {
+            (24, false) => be(&bytes[..3]),
+            (24, true) => be(&bytes[3..6]),
+            // The middle byte holds the high nibble of each record
+            (28, false) => (bytes[3] as usize >> 4) << 24 | be(&bytes[..3]),
+            (28, true) => (bytes[3] as usize & 0x0F) << 24 | be(&bytes[4..7]),
+            (_, false) => be(&bytes[..4]),
+            (_, true) => be(&bytes[4..8]),
+        }
+    }
+
+    // The data recorded for the network containing ip, if any
+    fn lookup(&self, ip: IpAddr) -> Result<Option<Value>, Box<dyn Error>> {
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+            v4 => v4,
+        };
+        let (bits, bit_count, mut node) = match ip {
+            IpAddr::V4(v4) => (u32::from(v4) as u128, 32, self.ipv4_start),
+            IpAddr::V6(_) if self.ip_version == 4 => return Ok(None),
+            IpAddr::V6(v6) => (u128::from(v6), 128, 0),
+        };
+        for i in (0..bit_count).rev() {
+            if node >= self.node_count {
+                break;
+            }
+            node = self.record(node, bits >> i & 1 == 1);
+        }
+        if node <= self.node_count {
+            return Ok(None); // node_count itself marks an empty network
+        }
+        let data = &self.file[self.tree_size() + DATA_SECTION_SEPARATOR..];
+        let offset = (node - self.node_count)
+            .checked_sub(DATA_SECTION_SEPARATOR)
+            .ok_or("MaxMind DB record points into the data section separator")?;
+        Ok(Some(Decoder { data }.decode(offset)?.0))
+    }
+}
+
+// Where a peer is, as far as the databases know
+#[derive(Debug, Clone, Default)]
+pub struct GeoInfo {
+    pub country: Option<String>, // ISO 3166 code
+    pub asn: Option<u32>,
+    pub as_org: Option<String>,
+}
+
+// A country database (GeoLite2-Country or -City) and an ASN database, either optional
+pub struct GeoIp {
+    country: Option<MaxMindDb>,
+    asn: Option<MaxMindDb>,
+}
+
+impl GeoIp {
+    pub fn open(country: Option<&Path>, asn: Option<&Path>) -> Result<Self, Box<dyn Error>> {
+        Ok(GeoIp { country: country.map(MaxMindDb::open).transpose()?, asn: asn.map(MaxMindDb::open).transpose()? })
+    }
+
+    pub fn locate(&self, ip: IpAddr) -> Result<GeoInfo, Box<dyn Error>> {
+        let mut info = GeoInfo::default();
+        if let Some(record) = self.country.as_ref().map(|db| db.lookup(ip)).transpose()?.flatten() {
+            // Anycast and satellite ranges may only have a registered country
+            info.country = [["country", "iso_code"], ["registered_country", "iso_code"]]
+                .iter()
+                .find_map(|path| record.path(path).and_then(Value::as_str))
+                .map(str::to_string);
+        }
+        if let Some(record) = self.asn.as_ref().map(|db| db.lookup(ip)).transpose()?.flatten() {
+            info.asn = record.path(&["autonomous_system_number"]).and_then(Value::as_u64).map(|asn| asn as u32);
+            info.as_org = record.path(&["autonomous_system_organization"]).and_then(Value::as_str).map(str::to_string);
+        }
+        Ok(info)
+    }
+}
+
+// How a set of stored peers is spread over countries and autonomous systems
+#[derive(Debug, Clone, Serialize)]
+pub struct GeoDistribution {
+    pub peers: usize,
+    pub unlocated: usize,                  // neither country nor ASN known, including non-IP peers
+    pub countries: BTreeMap<String, usize>,
+    pub asns: BTreeMap<String, usize>,     // keyed "AS<number> <organization>"
+}
+
+impl GeoDistribution {
+    pub fn from_records<'a>(records: impl IntoIterator<Item = &'a PeerRecord>) -> Self {
+        let mut distribution = GeoDistribution { peers: 0, unlocated: 0, countries: BTreeMap::new(), asns: BTreeMap::new() };
+        for record in records {
+            distribution.peers += 1;
+            if record.country.is_none() && record.asn.is_none() {
+                distribution.unlocated += 1;
+            }
+            if let Some(country) = &record.country {
+                *distribution.countries.entry(country.clone()).or_default() += 1;
+            }
+            if let Some(asn) = record.asn {
+                let name = format!("AS{} {}", asn, record.as_org.as_deref().unwrap_or("")).trim_end().to_string();
+                *distribution.asns.entry(name).or_default() += 1;
+            }
+        }
+        distribution
+    }
+
+    fn percent(&self, count: usize) -> f64 {
+        100.0 * count as f64 / self.peers.max(1) as f64
+    }
+
+    fn write_top(&self, f: &mut fmt::Formatter, counts: &BTreeMap<String, usize>) -> fmt::Result {
+        let sorted = by_count(counts);
+        for (name, count) in sorted.iter().take(TOP_ENTRIES) {
+            writeln!(f, "  {:<40} {:>5} ({:.1}%)", name, count, self.percent(*count))?;
+        }
+        if sorted.len() > TOP_ENTRIES {
+            writeln!(f, "  ... and {} more", sorted.len() - TOP_ENTRIES)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for GeoDistribution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Peers: {} ({} without a location)", self.peers, self.unlocated)?;
+        writeln!(f, "Countries ({}):", self.countries.len())?;
+        self.write_top(f, &self.countries)?;
+        writeln!(f, "Autonomous systems ({}):", self.asns.len())?;
+        self.write_top(f, &self.asns)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A combined country and ASN database with record size 28, covering 1.2.3.0/24 (DE, AS3320,
+    // its organization stored once and reached through a pointer), 8.8.8.0/24 (registered
+    // country only) and 2001:db8::/32 (JP)
+    const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/geoip-test.mmdb");
+
+    fn locate(ip: &str) -> GeoInfo {
+        let path = Path::new(FIXTURE);
+        GeoIp::open(Some(path), Some(path)).unwrap().locate(ip.parse().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn locates_fixture_networks() {
+        let info = locate("1.2.3.4");
+        assert_eq!((info.country.as_deref(), info.asn, info.as_org.as_deref()), (Some("DE"), Some(3320), Some("Deutsche Telekom AG")));
+        let info = locate("::ffff:1.2.3.200");
+        assert_eq!((info.country.as_deref(), info.asn), (Some("DE"), Some(3320)));
+        let info = locate("8.8.8.8");
+        assert_eq!((info.country.as_deref(), info.asn, info.as_org.as_deref()), (Some("US"), Some(15169), Some("Google LLC")));
+        let info = locate("2001:db8::1");
+        assert_eq!((info.country.as_deref(), info.asn, info.as_org.as_deref()), (Some("JP"), Some(2497), Some("IIJ")));
+        let info = locate("9.9.9.9");
+        assert_eq!((info.country, info.asn, info.as_org), (None, None, None));
+    }
+
+    #[test]
+    fn refuses_pointer_to_pointer() {
+        // A pointer at offset 0 to a pointer at offset 2, which points back to 0
+        let decoder = Decoder { data: &[0x20, 0x02, 0x20, 0x00] };
+        assert!(decoder.decode(0).is_err());
+    }
+
+    #[test]
+    fn limits_nesting() {
+        // A one-entry map whose value points back to the map itself
+        let decoder = Decoder { data: &[0xE1, 0x41, b'a', 0x20, 0x00] };
+        assert!(decoder.decode(0).is_err());
+    }
+
+    #[test]
+    fn refuses_records_into_separator() {
+        // One node, both of whose 24-bit records point 5 bytes into the separator
+        let mut file = [[0, 0, 6]; 2].concat();
+        file.extend([0; DATA_SECTION_SEPARATOR]);
+        let db = MaxMindDb { file, node_count: 1, record_size: 24, ip_version: 4, ipv4_start: 0 };
+        assert!(db.lookup("1.2.3.4".parse().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,92 @@
+use std::thread;
+use std::time::Duration;
+
+use bitcoin::BlockHash;
+use serde::Serialize;
+
+use crate::address::PeerAddr;
+use crate::message::MessageError;
+use crate::peer::{ConnectConfig, Peer};
+use crate::tips::{query_tip, BestChain, TipStatus};
+
+// Height comparison: ask a set of peers where their tips are at the same moment and single
+// out the ones that disagree with the rest, to spot forks and lagging nodes at a glance
+
+// One peer's answer
+#[derive(Debug, Clone, Serialize)]
+pub struct PeerHeight {
+    pub peer: PeerAddr,
+    pub user_agent: Option<String>,
+    pub advertised_height: Option<i32>, // start height from its version message
+    pub tip: Option<TipStatus>,         // placed against the reference chain
+    pub tip_hash: Option<BlockHash>,
+    pub error: Option<String>,
+}
+
+// Why a peer stands out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outlier {
+    Behind,
+    Ahead,
+    Forked,
+    OffMedian, // advertised height far from the others' when its tip could not be placed
+}
+
+impl Outlier {
+    pub fn name(self) -> &'static str {
+        match self {
+            Outlier::Behind => "behind",
+            Outlier::Ahead => "ahead",
+            Outlier::Forked => "forked",
+            Outlier::OffMedian => "off median",
+        }
+    }
+}
+
+// Query every peer at once, each on its own connection
+pub fn query_heights(peers: &[PeerAddr], config: &ConnectConfig, chain: &BestChain, stale_blocks: u32, timeout: Duration) -> Vec<PeerHeight> {
+    thread::scope(|scope| {
+        let handles: Vec<_> = peers.iter().map(|peer| scope.spawn(move || query_height(peer, config, chain, stale_blocks, timeout))).collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    })
+}
+
+fn query_height(addr: &PeerAddr, config: &ConnectConfig, chain: &BestChain, stale_blocks: u32, timeout: Duration) -> PeerHeight {
+    let mut result = PeerHeight { peer: addr.clone(), user_agent: None, advertised_height: None, tip: None, tip_hash: None, error: None };
+    let outcome = (|| -> Result<(), MessageError> {
+        let mut peer = Peer::open(addr, config)?;
+        let version = peer.version.clone().unwrap();
+        result.user_agent = Some(version.user_agent);
+        result.advertised_height = Some(version.start_height);
+        if let Some((status, hash)) = query_tip(&mut peer, chain, stale_blocks, timeout)? {
+            result.tip = Some(status);
+            result.tip_hash = Some(hash);
+        }
+        Ok(())
+    })();
+    result.error = outcome.err().map(|e| e.to_string());
+    result
+}
+
+// Median advertised height of the peers that answered
+pub fn median_height(results: &[PeerHeight]) -> Option<i32> {
+    let mut heights: Vec<i32> = results.iter().filter_map(|result| result.advertised_height).collect();
+    heights.sort_unstable();
+    heights.get(heights.len() / 2).copied()
+}
+
+// Whether a peer stands out: its tip placed off the reference chain's, or, when it could not
+// be placed, an advertised height more than stale_blocks from the median
+pub fn outlier(result: &PeerHeight, median: Option<i32>, stale_blocks: u32) -> Option<Outlier> {
+    match result.tip {
+        Some(TipStatus::Synced { .. }) => None,
+        Some(TipStatus::Behind { .. }) => Some(Outlier::Behind),
+        Some(TipStatus::Ahead { .. }) => Some(Outlier::Ahead),
+        Some(TipStatus::Forked { .. }) => Some(Outlier::Forked),
+        None => {
+            let (height, median) = (result.advertised_height?, median?);
+            (height.abs_diff(median) > stale_blocks).then_some(Outlier::OffMedian)
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+use std::net::TcpStream;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::addrman::AddrMan;
+use crate::message::{Message, MessageError, VersionMessage};
+use crate::peer::{ConnectConfig, FailureKind, Features, Peer};
+
+// Inbound side: answer peers that connect to us the way a node would, enough to test other
+// P2P implementations against this crate
+
+// Bitcoin Core's getaddr reply limits: at most 1000 addresses, and 23% of those known
+const MAX_GETADDR_COUNT: usize = 1000;
+const MAX_GETADDR_PCT: usize = 23;
+
+// Listener settings
+#[derive(Debug, Clone)]
+pub struct ListenConfig {
+    pub connection: ConnectConfig, // what we announce, and the I/O and handshake timeouts
+    pub max_connections: usize,    // inbound peers served at once; more are turned away
+    pub session_timeout: Duration, // how long one inbound peer is kept
+}
+
+impl Default for ListenConfig {
+    fn default() -> Self {
+        ListenConfig {
+            connection: ConnectConfig::default(),
+            max_connections: 125,
+            session_timeout: Duration::from_secs(600),
+        }
+    }
+}
+
+// What happened during one inbound connection
+#[derive(Debug, Clone)]
+pub struct InboundSession {
+    pub version: Option<VersionMessage>, // None when the handshake did not complete
+    pub features: Features,
+    pub messages: BTreeMap<String, usize>, // commands received after the handshake
+    pub addresses_sent: usize,
+    pub addresses_received: usize,
+    pub duration: Duration,
+    pub error: Option<String>, // why the session ended early, if it did
+}
+
+// Handshake as the responder, answer the first getaddr from the address manager and
+// learn the addresses the peer relays, until it leaves or the session times out
+pub fn serve_inbound(stream: TcpStream, config: &ListenConfig, addrman: &Mutex<AddrMan>) -> InboundSession {
+    let started = Instant::now();
+    let mut session = InboundSession {
+        version: None,
+        features: Features::default(),
+        messages: BTreeMap::new(),
+        addresses_sent: 0,
+        addresses_received: 0,
+        duration: Duration::ZERO,
+        error: None,
+    };
+    let outcome = (|| -> Result<(), MessageError> {
+        let mut peer = Peer::accept(stream, &config.connection)?;
+        session.version = Some(peer.respond_handshake(&config.connection.version, config.connection.handshake_timeout)?);
+        session.features = peer.features;
+
+        let deadline = started + config.session_timeout;
+        let mut answered_getaddr = false;
+        loop {
+            let message = match peer.receive_before(deadline) {
+                Ok(Some(message)) => message,
+                Ok(None) => break,
+                // Hanging up once the handshake is done is how a session normally ends
+                Err(e) if FailureKind::classify(&e) == FailureKind::Disconnected => break,
+                Err(e) => return Err(e),
+            };
+            session.features = peer.features;
+            *session.messages.entry(message.command().name().to_string()).or_default() += 1;
+            let now = unix_time();
+            match message {
+                // Only the first getaddr is answered, so repeated requests cannot scrape us
+                Message::GetAddr if !answered_getaddr => {
+                    answered_getaddr = true;
+                    let entries = addrman.lock().unwrap().get_addr(MAX_GETADDR_COUNT, MAX_GETADDR_PCT, now);
+                    session.addresses_sent = entries.len();
+                    if peer.features.addr_v2 {
+                        peer.send(&Message::AddrV2(entries))?;
+                    } else {
+                        peer.send(&Message::Addr(entries))?;
+                    }
+                }
+                Message::Addr(entries) | Message::AddrV2(entries) => {
+                    session.addresses_received += entries.len();
+                    addrman.lock().unwrap().add(entries, &peer.addr, now);
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    })();
+
+    if let Err(e) = outcome {
+        session.error = Some(e.to_string());
+    }
+    session.duration = started.elapsed();
+    session
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
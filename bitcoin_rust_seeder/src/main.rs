@@ -1,96 +1,1358 @@
-use std::net::TcpStream;
-use std::time::{SystemTime, UNIX_EPOCH};
-use std::io::{Write, Read};
-
-// Bitcoin network magic bytes (mainnet)
-const MAGIC: [u8; 4] = [0xF9, 0xBE, 0xB4, 0xD9];
-
-fn main() -> std::io::Result<()> {
-    // Connect to node
-    let mut stream = TcpStream::connect("34.90.43.75:8333")?;
-
-    // Construct version message
-    let version_payload = build_version_payload();
-    let checksum:[u8; 32]= sha256d(&version_payload)[0..4].try_into().unwrap();
-    
-    // Build full message
-    let mut message = Vec::new();
-    message.extend(MAGIC);                 // Magic bytes
-    message.extend(b"version\0\0\0\0\0");   // Command name (12 bytes)
-    message.extend((version_payload.len() as u32).to_le_bytes()); // Payload size
-    message.extend(checksum);               // Checksum
-    message.extend(version_payload);        // Actual payload
-
-    // Send version message
-    stream.write_all(&message)?;
-    println!("Sent version message");
-
-    // Read response
-    let mut buffer = [0u8; 1024];
-    loop {
-        let bytes_read = stream.read(&mut buffer)?;
-        if bytes_read == 0 { break; }
-        
-        // Simple message parsing (real implementation would need proper framing)
-        if let Some(verack_pos) = buffer[..bytes_read].windows(4).position(|w| w == MAGIC) {
-            let command = &buffer[verack_pos+4..verack_pos+16];
-            if command.starts_with(b"verack") {
-                println!("Received verack!");
-                break;
+mod address;
+mod addrman;
+mod announce;
+mod bip324;
+mod bloom;
+mod capture;
+mod chacha;
+mod config;
+mod crawler;
+mod discovery;
+mod dns;
+mod ellswift;
+mod export;
+mod fetch;
+mod fuzz;
+#[cfg(feature = "geoip")]
+mod geoip;
+mod heights;
+mod listen;
+mod mempool;
+mod message;
+mod misbehavior;
+mod network;
+mod peer;
+mod ratelimit;
+mod reliability;
+mod sha3;
+mod services;
+mod shutdown;
+mod socks;
+mod spv;
+mod stats;
+mod store;
+mod sync;
+mod tips;
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::net::{SocketAddr, TcpListener, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use bitcoin::address::NetworkUnchecked;
+use bitcoin::consensus::serialize;
+use bitcoin::hashes::hex::FromHex;
+use bitcoin::{Address, BlockHash};
+use block_breaker::BlockProcessor;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+
+use address::PeerAddr;
+use addrman::AddrMan;
+use announce::{watch_announcements, AnnouncedVia, WatchEvent};
+use bloom::{script_pushes, BloomFilter, BLOOM_UPDATE_ALL, BLOOM_UPDATE_NONE, BLOOM_UPDATE_P2PUBKEY_ONLY};
+use capture::{read_capture, Direction};
+use crawler::{crawl, CrawlConfig, CrawlReport, NetworkCrawl};
+use dns::{DnsConfig, DnsServer};
+use export::{dump_entries, export, ExportFormat};
+use fetch::{fetch_announced, Fetched};
+use fuzz::{fuzz_handshake, Reaction, VersionMutation};
+#[cfg(feature = "geoip")]
+use geoip::{GeoDistribution, GeoIp};
+use heights::{median_height, outlier, query_heights};
+use listen::{serve_inbound, ListenConfig};
+use mempool::mempool_snapshot;
+use message::{Command, Message};
+use misbehavior::BanList;
+use network::Network;
+use peer::{ConnectConfig, Peer, VersionConfig};
+use ratelimit::SubnetLimit;
+use services::NODE_BLOOM;
+use spv::fetch_filtered_blocks;
+use stats::CrawlStats;
+use store::PeerStore;
+use sync::sync_headers;
+use tips::{BestChain, TipStatus};
+
+#[derive(Parser, Debug)]
+#[command(name = "bitcoin_rust_seeder", about = "Crawl the Bitcoin P2P network for reachable peers")]
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    #[command(about = "Crawl the network from seed peers, following the addresses they hand out")]
+    Crawl(CrawlArgs),
+    #[command(about = "Crawl in rounds until stopped, retesting known peers when due and dropping dead ones")]
+    Daemon(DaemonArgs),
+    #[command(about = "Download the header chain from one peer and validate it")]
+    Headers(HeadersArgs),
+    #[command(about = "Compare the heights and tips of a list of peers, highlighting the ones that disagree")]
+    Heights(HeightsArgs),
+    #[command(about = "Download transactions and blocks a peer announces and decode them")]
+    Fetch(FetchArgs),
+    #[command(about = "Stay connected to peers and timestamp the blocks each announces, to measure propagation")]
+    Watch(WatchArgs),
+    #[command(about = "Ask a peer for its mempool and export the advertised txids as JSON")]
+    Mempool(MempoolArgs),
+    #[command(about = "Open connections with deliberately malformed version messages and record how a node reacts")]
+    Fuzz(FuzzArgs),
+    #[command(about = "Load a BIP37 bloom filter into a peer and verify the filtered blocks it returns")]
+    Spv(SpvArgs),
+    #[command(about = "Accept inbound connections, answer getaddr from a store's addresses and log who connects")]
+    Listen(ListenArgs),
+    #[command(about = "Decode a message capture offline, checking that every message survives the codec")]
+    Replay(ReplayArgs),
+    #[command(about = "List the most reliable peers in a store")]
+    Peers(PeersArgs),
+    #[command(about = "Write a store's peers in bitcoin-seeder's dnsseed.dump format, or as CSV or JSON")]
+    Export(ExportArgs),
+    #[cfg(feature = "geoip")]
+    #[command(about = "Tag a store's peers with country and ASN from MaxMind databases and report how they are spread")]
+    Geoip(GeoipArgs),
+    #[command(about = "Serve good peers from a store as DNS A/AAAA records for a seed domain")]
+    Dns(DnsArgs),
+}
+
+// Options shared by every command that connects to peers
+#[derive(Args, Debug)]
+struct ConnectionArgs {
+    #[arg(long, value_enum, default_value_t = Network::Mainnet, help = "Network to use, selecting its magic bytes, default port and DNS seeds")]
+    network: Network,
+
+    #[arg(long, default_value_t = ConnectConfig::default().connect_timeout.as_secs(), help = "Seconds to wait for a TCP connection")]
+    connect_timeout: u64,
+
+    #[arg(long, default_value_t = ConnectConfig::default().io_timeout.as_secs(), help = "Seconds to wait on any single read or write")]
+    io_timeout: u64,
+
+    #[arg(long, default_value_t = ConnectConfig::default().handshake_timeout.as_secs(), help = "Seconds allowed for the whole version handshake, and likewise for the v2 key exchange before it")]
+    handshake_timeout: u64,
+
+    #[arg(long, help = "Try the BIP324 encrypted transport first, falling back to v1")]
+    v2_transport: bool,
+
+    #[arg(long, help = "SOCKS5 proxy to connect through, e.g. 127.0.0.1:9050 for Tor; required for .onion peers")]
+    proxy: Option<SocketAddr>,
+
+    #[arg(long, default_value_t = VersionConfig::default().version, help = "Protocol version to announce")]
+    protocol_version: i32,
+
+    #[arg(long, value_parser = services::parse, default_value = "0x1", help = "Service bits to announce, as a number or names such as network,witness")]
+    services: u64,
+
+    #[arg(long, default_value = "", help = "User agent to announce, e.g. /bitcoin_rust_seeder:0.1.0/")]
+    user_agent: String,
+
+    #[arg(long, default_value_t = VersionConfig::default().start_height, help = "Block height to announce")]
+    start_height: i32,
+
+    #[arg(long, help = "Ask peers not to relay transactions to us")]
+    no_relay: bool,
+
+    #[arg(long, help = "Reachable address to announce as ours in version messages, e.g. where listen accepts connections [default: none]")]
+    advertise: Option<SocketAddr>,
+
+    #[arg(long, help = "Record every message sent and received to a file per connection in this directory, for replay")]
+    capture_dir: Option<PathBuf>,
+}
+
+impl ConnectionArgs {
+    fn config(&self) -> ConnectConfig {
+        ConnectConfig {
+            network: self.network,
+            connect_timeout: Duration::from_secs(self.connect_timeout),
+            io_timeout: Duration::from_secs(self.io_timeout),
+            handshake_timeout: Duration::from_secs(self.handshake_timeout),
+            v2_transport: self.v2_transport,
+            proxy: self.proxy,
+            capture_dir: self.capture_dir.clone(),
+            version: VersionConfig {
+                version: self.protocol_version,
+                services: self.services,
+                user_agent: self.user_agent.clone(),
+                start_height: self.start_height,
+                relay: !self.no_relay,
+                advertise: self.advertise,
+            },
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct CrawlArgs {
+    #[arg(help = "Peers to start crawling from, as host or ip with optional :port [default: the network's DNS seeds]")]
+    seeds: Vec<String>,
+
+    #[command(flatten)]
+    connection: ConnectionArgs,
+
+    #[arg(long, default_value_t = CrawlConfig::default().concurrency, help = "Number of peers visited in parallel")]
+    concurrency: usize,
+
+    #[arg(long, default_value_t = CrawlConfig::default().max_peers, help = "Stop after visiting this many peers")]
+    max_peers: usize,
+
+    #[arg(long, default_value_t = CrawlConfig::default().addr_timeout.as_secs(), help = "Seconds to wait for addr replies after getaddr")]
+    addr_timeout: u64,
+
+    #[arg(long, default_value_t = CrawlConfig::default().retries, help = "Times to retry a peer that timed out or disconnected during the handshake")]
+    retries: u32,
+
+    #[arg(long, default_value_t = CrawlConfig::default().retry_backoff.as_millis() as u64, help = "Milliseconds before the first retry, doubling for each further one")]
+    retry_backoff_ms: u64,
+
+    #[arg(long, default_value_t = CrawlConfig::default().pings, help = "Pings sent to each reachable peer to measure round-trip latency")]
+    pings: u32,
+
+    #[arg(long, default_value_t = CrawlConfig::default().ping_interval.as_millis() as u64, help = "Milliseconds between pings")]
+    ping_interval_ms: u64,
+
+    #[arg(long, default_value_t = SubnetLimit::default().interval.as_millis() as u64, help = "Milliseconds between connections to the same subnet, retries included; 0 for no limit")]
+    subnet_interval_ms: u64,
+
+    #[arg(long, default_value_t = SubnetLimit::default().ipv4_prefix, value_parser = clap::value_parser!(u8).range(0..=32), help = "Prefix length grouping IPv4 peers into subnets; 32 limits each host on its own")]
+    ipv4_prefix: u8,
+
+    #[arg(long, default_value_t = SubnetLimit::default().ipv6_prefix, value_parser = clap::value_parser!(u8).range(0..=128), help = "Prefix length grouping IPv6 and CJDNS peers into subnets")]
+    ipv6_prefix: u8,
+
+    #[arg(long, help = "Header file written by headers --out, taken as the best chain; peers' tips are checked against it with getheaders")]
+    best_chain: Option<PathBuf>,
+
+    #[arg(long, default_value_t = CrawlConfig::default().stale_blocks, help = "Blocks behind the best chain's tip a peer may be and still count as synced")]
+    stale_blocks: u32,
+
+    #[arg(long, help = "JSON file of known peers, loaded to seed the crawl and updated with its results")]
+    store: Option<PathBuf>,
+
+    #[arg(long, help = "Write aggregate crawl statistics as JSON to this file")]
+    stats: Option<PathBuf>,
+
+    #[arg(long, requires = "store", help = "Write the store's peers to this file in bitcoin-seeder's dnsseed.dump format once saved")]
+    dump: Option<PathBuf>,
+
+    #[arg(long, help = "JSON ban list of misbehaving peers, skipped by the crawl and updated with its results")]
+    ban_list: Option<PathBuf>,
+
+    #[arg(long, default_value_t = 24, help = "Hours a peer stays banned once its misbehavior score reaches 100")]
+    ban_hours: u64,
+
+    #[arg(long, help = "TOML file of settings keyed by option name, with [networks.<name>] tables; options given here take precedence")]
+    config: Option<PathBuf>,
+
+    #[arg(long, value_enum, value_delimiter = ',', help = "Crawl several networks at once over one pool of connections, e.g. mainnet,testnet4,signet; each takes its seeds and output files from its [networks.<name>] table in --config")]
+    networks: Vec<Network>,
+}
+
+#[derive(Args, Debug)]
+struct DaemonArgs {
+    #[command(flatten)]
+    crawl: CrawlArgs,
+
+    #[arg(long, default_value_t = 600, help = "Seconds from the start of one crawl round to the start of the next")]
+    interval: u64,
+
+    #[arg(long, default_value_t = 30, help = "Days a peer may go without a successful visit before it is dropped from the store")]
+    max_age_days: u64,
+}
+
+#[derive(Args, Debug)]
+struct HeadersArgs {
+    #[arg(help = "Peer to sync from, as host or ip with optional :port")]
+    peer: String,
+
+    #[command(flatten)]
+    connection: ConnectionArgs,
+
+    #[arg(long, default_value_t = 20_000, help = "Stop after this many headers past genesis")]
+    max_headers: usize,
+
+    #[arg(long, help = "Write the headers as concatenated 80-byte records, as block_breaker's headers command reads them")]
+    out: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct HeightsArgs {
+    #[arg(required = true, help = "Peers to compare, as host or ip with optional :port")]
+    peers: Vec<String>,
+
+    #[command(flatten)]
+    connection: ConnectionArgs,
+
+    #[arg(long, help = "Header file written by headers --out to place tips against [default: download headers from the first peer]")]
+    best_chain: Option<PathBuf>,
+
+    #[arg(long, default_value_t = 2_000_000, help = "Stop downloading the reference chain after this many headers past genesis")]
+    max_headers: usize,
+
+    #[arg(long, default_value_t = CrawlConfig::default().stale_blocks, help = "Blocks a peer may lag the best chain or the median height before it is flagged")]
+    stale_blocks: u32,
+
+    #[arg(long, help = "Write each peer's answer as JSON to this file")]
+    json: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct FetchArgs {
+    #[arg(help = "Peer to listen to, as host or ip with optional :port")]
+    peer: String,
+
+    #[command(flatten)]
+    connection: ConnectionArgs,
+
+    #[arg(long, default_value_t = 60, help = "Seconds to listen for announcements")]
+    duration: u64,
+
+    #[arg(long, default_value_t = 100, help = "Stop after requesting this many transactions and blocks")]
+    max_items: usize,
+
+    #[arg(long, help = "Directory to save each raw transaction (<txid>.tx) and block (<hash>.block) in")]
+    out_dir: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct WatchArgs {
+    #[arg(required = true, help = "Peers to watch, as host or ip with optional :port")]
+    peers: Vec<String>,
+
+    #[command(flatten)]
+    connection: ConnectionArgs,
+
+    #[arg(long, default_value_t = 3600, help = "Seconds to watch for; Ctrl-C ends the watch early")]
+    duration: u64,
+
+    #[arg(long, help = "Write every announcement and the per-peer summary as JSON to this file")]
+    json: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct MempoolArgs {
+    #[arg(help = "Peer to query, as host or ip with optional :port")]
+    peer: String,
+
+    #[command(flatten)]
+    connection: ConnectionArgs,
+
+    #[arg(long, default_value_t = 60, help = "Seconds to wait for the whole reply")]
+    timeout: u64,
+
+    #[arg(long, default_value_t = 10, help = "Stop once no inv has arrived for this many seconds")]
+    idle_timeout: u64,
+
+    #[arg(long, help = "Write the snapshot as JSON to this file instead of stdout")]
+    out: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct SpvArgs {
+    #[arg(help = "Peer to query, which must serve bloom filters (NODE_BLOOM), as host or ip with optional :port")]
+    peer: String,
+
+    #[command(flatten)]
+    connection: ConnectionArgs,
+
+    #[arg(long = "block", required = true, help = "Hash of a block to request filtered; repeat for more, requested in order")]
+    blocks: Vec<BlockHash>,
+
+    #[arg(long = "address", help = "Address whose transactions to match; repeat for more")]
+    addresses: Vec<String>,
+
+    #[arg(long = "element", help = "Hex data element to put in the filter, such as a txid or serialized outpoint; repeat for more")]
+    elements: Vec<String>,
+
+    #[arg(long, default_value_t = 0.0001, help = "False positive rate the filter is sized for")]
+    fp_rate: f64,
+
+    #[arg(long, value_parser = parse_bloom_update, default_value = "none", help = "How the peer updates the filter on a match: none, all or p2pubkey-only")]
+    bloom_update: u8,
+
+    #[arg(long, default_value_t = 30, help = "Seconds to wait for each block's reply")]
+    timeout: u64,
+}
+
+#[derive(Args, Debug)]
+struct FuzzArgs {
+    #[arg(help = "Node to fuzz, as host or ip with optional :port")]
+    peer: String,
+
+    #[command(flatten)]
+    connection: ConnectionArgs,
+
+    #[arg(long = "case", value_delimiter = ',', help = "Mutations to send, e.g. empty,bad-checksum [default: all of them]")]
+    cases: Vec<String>,
+
+    #[arg(long, default_value_t = 5, help = "Seconds to watch the node's reaction to each mutation")]
+    observe: u64,
+
+    #[arg(long, help = "Write the outcomes as JSON to this file")]
+    json: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct ListenArgs {
+    #[command(flatten)]
+    connection: ConnectionArgs,
+
+    #[arg(long, help = "TCP address to listen on [default: 0.0.0.0 on the network's port]")]
+    bind: Option<SocketAddr>,
+
+    #[arg(long, help = "JSON peer store written by crawl --store, whose addresses answer getaddr")]
+    store: Option<PathBuf>,
+
+    #[arg(long, default_value_t = ListenConfig::default().max_connections, help = "Inbound peers served at once")]
+    max_connections: usize,
+
+    #[arg(long, default_value_t = ListenConfig::default().session_timeout.as_secs(), help = "Seconds to keep each inbound peer connected")]
+    session_timeout: u64,
+}
+
+#[derive(Args, Debug)]
+struct ReplayArgs {
+    #[arg(help = "Capture file written with --capture-dir")]
+    capture: PathBuf,
+
+    #[arg(long, help = "Print each decoded message in full")]
+    verbose: bool,
+}
+
+#[derive(Args, Debug)]
+struct PeersArgs {
+    #[arg(help = "JSON peer store written by crawl --store")]
+    store: PathBuf,
+
+    #[arg(long, default_value_t = 25, help = "Number of peers to list")]
+    limit: usize,
+
+    #[arg(long, value_parser = services::parse, default_value = "0", help = "Only list peers offering all these service bits, as a number or names such as network,witness")]
+    require_services: u64,
+}
+
+#[derive(Args, Debug)]
+struct ExportArgs {
+    #[arg(help = "JSON peer store written by crawl --store")]
+    store: PathBuf,
+
+    #[arg(long, value_enum, default_value_t = ExportFormat::Dump, help = "Output format: bitcoin-seeder's dump layout, CSV or JSON")]
+    format: ExportFormat,
+
+    #[arg(long, help = "File to write [default: standard output]")]
+    out: Option<PathBuf>,
+}
+
+#[cfg(feature = "geoip")]
+#[derive(Args, Debug)]
+struct GeoipArgs {
+    #[arg(help = "JSON peer store written by crawl --store; peers are tagged in place")]
+    store: PathBuf,
+
+    #[arg(long, help = "MaxMind country database, e.g. GeoLite2-Country.mmdb or GeoLite2-City.mmdb")]
+    country_db: Option<PathBuf>,
+
+    #[arg(long, help = "MaxMind ASN database, e.g. GeoLite2-ASN.mmdb")]
+    asn_db: Option<PathBuf>,
+
+    #[arg(long, help = "Report on every known peer rather than only the good ones")]
+    all: bool,
+
+    #[arg(long, help = "Write the distribution as JSON to this file")]
+    json: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct DnsArgs {
+    #[arg(help = "JSON peer store written by crawl --store, reloaded periodically")]
+    store: PathBuf,
+
+    #[arg(long, help = "Seed domain to answer for, e.g. seed.example.com")]
+    domain: String,
+
+    #[arg(long, default_value = "0.0.0.0:53", help = "UDP address to listen on")]
+    bind: String,
+
+    #[arg(long, value_enum, default_value_t = Network::Mainnet, help = "Network whose default port served peers must listen on")]
+    network: Network,
+
+    #[arg(long, default_value_t = DnsConfig::default().ttl, help = "TTL of the records served, in seconds")]
+    ttl: u32,
+
+    #[arg(long, default_value_t = DnsConfig::default().max_records, help = "Addresses per reply")]
+    max_records: usize,
+
+    #[arg(long, value_parser = services::parse, default_value = "0", help = "Service bits peers served for the bare domain must offer; x<hex>.<domain> queries choose their own")]
+    require_services: u64,
+
+    #[arg(long, default_value_t = 60, help = "Seconds between store reloads")]
+    reload_interval: u64,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let (cli, command_line) = parse_cli()?;
+    match cli.command {
+        Commands::Crawl(args) => run_crawl(args, &command_line),
+        Commands::Daemon(args) => run_daemon(args, &command_line),
+        Commands::Headers(args) => run_headers(args),
+        Commands::Heights(args) => run_heights(args),
+        Commands::Fetch(args) => run_fetch(args),
+        Commands::Watch(args) => run_watch(args),
+        Commands::Mempool(args) => run_mempool(args),
+        Commands::Spv(args) => run_spv(args),
+        Commands::Listen(args) => run_listen(args),
+        Commands::Replay(args) => run_replay(args),
+        Commands::Fuzz(args) => run_fuzz(args),
+        Commands::Peers(args) => run_peers(args),
+        Commands::Export(args) => run_export(args),
+        #[cfg(feature = "geoip")]
+        Commands::Geoip(args) => run_geoip(args),
+        Commands::Dns(args) => run_dns(args),
+    }
+}
+
+// The arguments a command was started with, and what clap made of them before any config
+// file was folded in, for commands that parse them again with changes
+struct CommandLine {
+    args: Vec<OsString>,
+    matches: ArgMatches,
+}
+
+// Parse the command line, folding in the settings of the file given with --config to any
+// command that takes one
+fn parse_cli() -> Result<(Cli, CommandLine), Box<dyn std::error::Error>> {
+    parse_args(env::args_os().collect())
+}
+
+fn parse_args(args: Vec<OsString>) -> Result<(Cli, CommandLine), Box<dyn std::error::Error>> {
+    let mut command = Cli::command();
+    let matches = command.try_get_matches_from_mut(args.clone()).unwrap_or_else(|e| e.exit());
+    let (name, sub_matches) = matches.subcommand().expect("a subcommand is required");
+    let Some(path) = sub_matches.try_get_one::<PathBuf>("config").ok().flatten() else {
+        let cli = Cli::from_arg_matches(&matches)?;
+        return Ok((cli, CommandLine { args, matches }));
+    };
+    let document = config::load(path)?;
+    let network = match sub_matches.value_source("network") {
+        Some(ValueSource::CommandLine) => sub_matches.get_raw("network").and_then(|mut values| values.next()).and_then(|value| value.to_str()),
+        _ => config::network(&document),
+    };
+    let file_args = config::file_args(&document, network.unwrap_or("mainnet"), command.find_subcommand(name).unwrap(), sub_matches)?;
+    let cli = Cli::parse_from(args.iter().cloned().chain(file_args));
+    Ok((cli, CommandLine { args, matches }))
+}
+
+// Settings for each network a crawl covers: its own network, or each given with --networks,
+// parsed from the command line as though that network had been chosen with --network, so
+// it picks up its [networks.<name>] table from the config file
+fn network_crawl_args(args: CrawlArgs, command_line: &CommandLine) -> Result<Vec<CrawlArgs>, Box<dyn std::error::Error>> {
+    if args.networks.is_empty() {
+        return Ok(vec![args]);
+    }
+    if command_line.matches.subcommand().is_some_and(|(_, sub_matches)| sub_matches.value_source("network") == Some(ValueSource::CommandLine)) {
+        return Err("--network and --networks cannot be used together".into());
+    }
+    let mut all: Vec<CrawlArgs> = Vec::new();
+    for network in &args.networks {
+        if all.iter().any(|other| other.connection.network == *network) {
+            return Err(format!("{:?} is given twice in --networks", network).into());
+        }
+        let name = network.to_possible_value().unwrap().get_name().to_string();
+        let network_line = command_line.args.iter().cloned().chain([OsString::from("--network"), OsString::from(name)]).collect();
+        all.push(match parse_args(network_line)?.0.command {
+            Commands::Crawl(args) => args,
+            Commands::Daemon(args) => args.crawl,
+            _ => unreachable!("only crawl and daemon take --networks"),
+        });
+    }
+
+    // Networks keep separate stores, statistics, dumps and ban lists
+    let mut outputs: Vec<(&Path, Network)> = Vec::new();
+    for args in &all {
+        for path in [&args.store, &args.stats, &args.dump, &args.ban_list].into_iter().flatten() {
+            if let Some((_, other)) = outputs.iter().find(|(used, _)| *used == path.as_path()) {
+                return Err(format!("{:?} and {:?} would both write {}; set it for each in its [networks.<name>] table", other, args.connection.network, path.display()).into());
+            }
+            outputs.push((path, args.connection.network));
+        }
+    }
+    Ok(all)
+}
+
+// One network's part of a crawl or daemon run, with the store and ban list it updates
+struct NetworkRun {
+    args: CrawlArgs,
+    config: CrawlConfig,
+    store: PeerStore,
+    bans: BanList,
+}
+
+impl NetworkRun {
+    fn new(args: CrawlArgs) -> Result<Self, Box<dyn std::error::Error>> {
+        let config = crawl_config(&args)?;
+        let store = match &args.store {
+            Some(path) => PeerStore::load(path)?,
+            None => PeerStore::default(),
+        };
+        let bans = match &args.ban_list {
+            Some(path) => BanList::load(path)?,
+            None => BanList::default(),
+        };
+        Ok(NetworkRun { args, config, store, bans })
+    }
+
+    fn network(&self) -> Network {
+        self.args.connection.network
+    }
+
+    // Record what a crawl learned and write every output, dropping peers not seen working
+    // for max_age seconds first when given. Returns how many were dropped.
+    fn save(&mut self, report: &CrawlReport, max_age: Option<u64>) -> Result<usize, Box<dyn std::error::Error>> {
+        if let Some(path) = &self.args.ban_list {
+            record_misbehavior(report, &mut self.bans, self.args.ban_hours);
+            self.bans.save(path)?;
+        }
+        let Some(path) = &self.args.store else {
+            return Ok(0);
+        };
+        record_results(report, &mut self.store);
+        let dropped = max_age.map_or(0, |max_age| self.store.age_out(unix_time(), max_age));
+        self.store.save(path)?;
+        if let Some(path) = &self.args.dump {
+            fs::write(path, export(&dump_entries(&self.store), ExportFormat::Dump)?)?;
+        }
+        Ok(dropped)
+    }
+}
+
+// Crawl every network at once over one pool of workers
+fn crawl_runs(runs: &[NetworkRun]) -> Vec<CrawlReport> {
+    // Resolved for every crawl, since DNS seeds hand out different peers over time.
+    // Addresses learned in earlier crawls come back through the store once they are due.
+    let seeds: Vec<Vec<PeerAddr>> = runs
+        .iter()
+        .map(|run| {
+            let mut seeds = resolve_seeds(run.network(), &run.args.seeds);
+            seeds.extend(run.store.due_for_retest(unix_time()));
+            seeds
+        })
+        .collect();
+    let networks: Vec<NetworkCrawl> = runs.iter().zip(&seeds).map(|(run, seeds)| NetworkCrawl { seeds, config: &run.config, bans: &run.bans }).collect();
+    crawl(&networks)
+}
+
+fn run_crawl(args: CrawlArgs, command_line: &CommandLine) -> Result<(), Box<dyn std::error::Error>> {
+    let mut runs = network_crawl_args(args, command_line)?.into_iter().map(NetworkRun::new).collect::<Result<Vec<_>, _>>()?;
+
+    // Interrupting stops new visits; peers already connected finish and everything learned
+    // is still reported and saved
+    shutdown::install();
+    let reports = crawl_runs(&runs);
+    if shutdown::requested() {
+        eprintln!("Crawl interrupted, keeping the {} peers visited so far", reports.iter().map(|report| report.results.len()).sum::<usize>());
+    }
+
+    let several = runs.len() > 1;
+    for (run, report) in runs.iter_mut().zip(&reports) {
+        if several {
+            println!("{:?}:", run.network());
+        }
+        print_results(report);
+        println!("Visited {} peers, {} reachable, {} addresses discovered", report.results.len(), report.reachable(), report.discovered.len());
+        let stats = CrawlStats::from_report(report);
+        print!("{}", stats);
+        if let Some(path) = &run.args.stats {
+            fs::write(path, serde_json::to_string_pretty(&stats)?)?;
+            println!("Wrote crawl statistics to {}", path.display());
+        }
+
+        run.save(report, None)?;
+        if let Some(path) = &run.args.ban_list {
+            println!("Saved ban list with {} banned peers to {}", run.bans.banned(unix_time()), path.display());
+        }
+        if let Some(path) = &run.args.store {
+            println!("Saved {} peers to {}", run.store.len(), path.display());
+        }
+        if let Some(path) = &run.args.dump {
+            println!("Wrote dump to {}", path.display());
+        }
+    }
+
+    Ok(())
+}
+
+fn run_daemon(args: DaemonArgs, command_line: &CommandLine) -> Result<(), Box<dyn std::error::Error>> {
+    let mut runs = Vec::new();
+    for crawl_args in network_crawl_args(args.crawl, command_line)? {
+        if crawl_args.store.is_none() {
+            return Err(format!("daemon needs --store for {:?} to keep what it learns between rounds", crawl_args.connection.network).into());
+        }
+        runs.push(NetworkRun::new(crawl_args)?);
+    }
+    let interval = Duration::from_secs(args.interval);
+    let max_age = args.max_age_days * 86400;
+
+    // SIGTERM or SIGINT ends the round in progress early and the daemon once it is saved
+    shutdown::install();
+    let several = runs.len() > 1;
+    let mut rounds = 0;
+    while !shutdown::requested() {
+        rounds += 1;
+        let started = Instant::now();
+        let reports = crawl_runs(&runs);
+
+        for (run, report) in runs.iter_mut().zip(&reports) {
+            let dropped = run.save(report, Some(max_age))?;
+            if let Some(path) = &run.args.stats {
+                fs::write(path, serde_json::to_string_pretty(&CrawlStats::from_report(report))?)?;
+            }
+            let network = if several { format!(" on {:?}", run.network()) } else { String::new() };
+            println!(
+                "Round {}{} took {:.0}s: visited {} peers, {} reachable, {} addresses discovered, {} dead peers dropped, {} peers stored",
+                rounds,
+                network,
+                started.elapsed().as_secs_f64(),
+                report.results.len(),
+                report.reachable(),
+                report.discovered.len(),
+                dropped,
+                run.store.len()
+            );
+        }
+
+        while !shutdown::requested() && started.elapsed() < interval {
+            thread::sleep(interval.saturating_sub(started.elapsed()).min(Duration::from_millis(200)));
+        }
+    }
+    for run in &runs {
+        println!("Stopped after {} rounds with {} peers saved to {}", rounds, run.store.len(), run.args.store.as_ref().unwrap().display());
+    }
+    Ok(())
+}
+
+fn crawl_config(args: &CrawlArgs) -> Result<CrawlConfig, Box<dyn std::error::Error>> {
+    let mut config = CrawlConfig {
+        connection: args.connection.config(),
+        concurrency: args.concurrency,
+        max_peers: args.max_peers,
+        addr_timeout: Duration::from_secs(args.addr_timeout),
+        retries: args.retries,
+        retry_backoff: Duration::from_millis(args.retry_backoff_ms),
+        pings: args.pings,
+        ping_interval: Duration::from_millis(args.ping_interval_ms),
+        best_chain: None,
+        stale_blocks: args.stale_blocks,
+        subnet_limit: SubnetLimit {
+            ipv4_prefix: args.ipv4_prefix,
+            ipv6_prefix: args.ipv6_prefix,
+            interval: Duration::from_millis(args.subnet_interval_ms),
+        },
+    };
+    if let Some(path) = &args.best_chain {
+        let chain = load_best_chain(path, args.connection.network)?;
+        println!("Checking peers' tips against {} headers up to height {}", path.display(), chain.tip_height());
+        config.best_chain = Some(Arc::new(chain));
+    }
+    Ok(config)
+}
+
+// Resolve seed names, the network's DNS seeds when none are given, skipping any that fail
+fn resolve_seeds(network: Network, names: &[String]) -> Vec<PeerAddr> {
+    let names: Vec<&str> = if names.is_empty() { network.dns_seeds().to_vec() } else { names.iter().map(String::as_str).collect() };
+    let mut seeds = Vec::new();
+    for name in names {
+        match network.resolve(name) {
+            Ok(addrs) => seeds.extend(addrs),
+            Err(e) => eprintln!("Could not resolve seed {}: {}", name, e),
+        }
+    }
+    seeds
+}
+
+fn print_results(report: &CrawlReport) {
+    for result in &report.results {
+        let mut details = if result.v2 { "v2".to_string() } else { "v1".to_string() };
+        if let Some(feerate) = result.fee_filter {
+            details.push_str(&format!(", feefilter {} sat/kvB", feerate));
+        }
+        if let Some(sendcmpct) = result.compact_blocks {
+            let mode = if sendcmpct.announce { "high" } else { "low" };
+            details.push_str(&format!(", cmpct v{} {}-bandwidth", sendcmpct.version, mode));
+        }
+        if let Some(error) = &result.addr_error {
+            details.push_str(&format!(", getaddr failed ({})", error));
+        }
+        if let Some(ping) = result.ping {
+            let ms = |rtt: Duration| rtt.as_secs_f64() * 1000.0;
+            details.push_str(&format!(", ping {:.1}/{:.1}/{:.1} ms over {}", ms(ping.min), ms(ping.avg), ms(ping.max), ping.samples));
+        }
+        if let Some(error) = &result.ping_error {
+            details.push_str(&format!(", ping failed ({})", error));
+        }
+        for (name, supported) in [("wtxidrelay", result.features.wtxid_relay), ("sendaddrv2", result.features.addr_v2), ("sendheaders", result.features.send_headers)] {
+            if supported {
+                details.push_str(&format!(", {}", name));
+            }
+        }
+        if let Some(tip) = result.tip {
+            details.push_str(&format!(", chain {}", tip));
+        }
+        if let Some(error) = &result.tip_error {
+            details.push_str(&format!(", tip check failed ({})", error));
+        }
+        if !result.misbehavior.is_empty() {
+            let violations: Vec<String> = result.misbehavior.iter().map(ToString::to_string).collect();
+            details.push_str(&format!(", misbehaved ({})", violations.join(", ")));
+        }
+        match (&result.version, &result.error) {
+            (Some(version), None) => println!("{} {} (protocol {}, height {}, {}): {} addresses", result.addr, version.user_agent, version.version, version.start_height, details, result.addresses_received),
+            (Some(version), Some(error)) => println!("{} {} (protocol {}, height {}, {}): {}", result.addr, version.user_agent, version.version, version.start_height, details, error),
+            (None, error) => println!(
+                "{} unreachable ({}, {} attempts): {}",
+                result.addr,
+                result.failure.map_or("unknown".to_string(), |kind| kind.to_string()),
+                result.attempts,
+                error.as_deref().unwrap_or("unknown error")
+            ),
+        }
+    }
+}
+
+fn record_misbehavior(report: &CrawlReport, bans: &mut BanList, ban_hours: u64) {
+    let now = unix_time();
+    for result in &report.results {
+        if bans.misbehaving(&result.addr, &result.misbehavior, now, Duration::from_secs(ban_hours * 3600)) {
+            println!("Banned {} for {} hours", result.addr, ban_hours);
+        }
+    }
+}
+
+fn record_results(report: &CrawlReport, store: &mut PeerStore) {
+    let now = unix_time();
+    store.merge_discovered(&report.discovered, now);
+    for result in &report.results {
+        store.record_result(result, now);
+    }
+}
+
+fn run_headers(args: HeadersArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let network = args.connection.network;
+    let genesis = network.genesis_header().ok_or_else(|| format!("no genesis header known for {:?}", network))?;
+    let addr = network.resolve(&args.peer)?.into_iter().next().ok_or_else(|| format!("{} did not resolve", args.peer))?;
+
+    let mut peer = Peer::open(&addr, &args.connection.config())?;
+    let report = sync_headers(&mut peer, genesis, &network.consensus_params(), args.max_headers)?;
+
+    let (tip_height, tip_hash) = report.tip();
+    println!("Peer {} advertised height {}", report.peer, report.advertised_height);
+    println!("Received headers up to height {}: {}", tip_height, tip_hash);
+    println!("Valid headers: {} (chainwork {:x})", report.validation.headers_checked, report.validation.chainwork);
+    if let Some(invalid) = &report.validation.first_invalid {
+        println!("First invalid header at height {}: {} ({:?})", invalid.height, invalid.block_hash, invalid.error);
+    }
+
+    if let Some(path) = &args.out {
+        let bytes: Vec<u8> = report.headers.iter().flat_map(serialize).collect();
+        fs::write(path, bytes)?;
+        println!("Wrote {} headers to {}", report.headers.len(), path.display());
+    }
+    Ok(())
+}
+
+fn run_heights(args: HeightsArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let network = args.connection.network;
+    let mut peers = Vec::new();
+    for peer in &args.peers {
+        peers.push(network.resolve(peer)?.into_iter().next().ok_or_else(|| format!("{} did not resolve", peer))?);
+    }
+    let config = args.connection.config();
+
+    let chain = match &args.best_chain {
+        Some(path) => load_best_chain(path, network)?,
+        None => {
+            let genesis = network.genesis_header().ok_or_else(|| format!("no genesis header known for {:?}; pass --best-chain", network))?;
+            println!("Downloading headers from {} as the reference chain", peers[0]);
+            let report = sync_headers(&mut Peer::open(&peers[0], &config)?, genesis, &network.consensus_params(), args.max_headers)?;
+            BestChain::from_headers(&report.headers)?
+        }
+    };
+    let results = query_heights(&peers, &config, &chain, args.stale_blocks, config.io_timeout);
+    let median = median_height(&results);
+
+    println!("Reference tip at height {}, median advertised height {}", chain.tip_height(), median.map_or("-".to_string(), |height| height.to_string()));
+    println!("{:<40} {:<24} {:>10} {:>10}  {:<64}  status", "peer", "user agent", "advertised", "tip", "tip hash");
+    for result in &results {
+        if let Some(error) = &result.error {
+            if result.advertised_height.is_none() {
+                println!("{:<40} {}", result.peer.to_string(), error);
+                continue;
+            }
+        }
+        let status = result.tip.map_or("unplaced".to_string(), |tip| tip.to_string());
+        let flag = outlier(result, median, args.stale_blocks).map_or(String::new(), |outlier| format!("  <- {}", outlier.name()));
+        println!(
+            "{:<40} {:<24} {:>10} {:>10}  {:<64}  {}{}",
+            result.peer.to_string(),
+            result.user_agent.as_deref().unwrap_or(""),
+            result.advertised_height.map_or("-".to_string(), |height| height.to_string()),
+            result.tip.map_or("-".to_string(), |tip| tip.height().to_string()),
+            result.tip_hash.map_or("-".to_string(), |hash| hash.to_string()),
+            status,
+            flag
+        );
+    }
+    let outliers = results.iter().filter(|result| outlier(result, median, args.stale_blocks).is_some()).count();
+    let unreachable = results.iter().filter(|result| result.advertised_height.is_none()).count();
+    println!("{} peers, {} unreachable, {} outliers", results.len(), unreachable, outliers);
+
+    if let Some(path) = &args.json {
+        fs::write(path, serde_json::to_string_pretty(&results)?)?;
+    }
+    Ok(())
+}
+
+fn run_fetch(args: FetchArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let network = args.connection.network;
+    let addr = network.resolve(&args.peer)?.into_iter().next().ok_or_else(|| format!("{} did not resolve", args.peer))?;
+    if let Some(dir) = &args.out_dir {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut peer = Peer::open(&addr, &args.connection.config())?;
+    println!("Listening to {} for {} seconds", addr, args.duration);
+    let report = fetch_announced(&mut peer, Duration::from_secs(args.duration), args.max_items)?;
+
+    for item in &report.fetched {
+        let (name, raw) = match item {
+            Fetched::Transaction { raw, summary } => {
+                println!(
+                    "tx {} {} bytes, {} vB, {} inputs, {} outputs, {} sat out",
+                    summary.txid, summary.size, summary.vsize, summary.inputs.len(), summary.outputs.len(), summary.total_output_value
+                );
+                (format!("{}.tx", summary.txid), raw)
+            }
+            Fetched::Block { raw, stats } => {
+                println!("block {} {} bytes, {} WU, {} transactions", stats.block_hash, stats.size, stats.weight, stats.tx_count);
+                (format!("{}.block", stats.block_hash), raw)
+            }
+            Fetched::Undecodable { command, raw, error } => {
+                println!("{} of {} bytes failed to decode: {}", command, raw.len(), error);
+                continue;
+            }
+        };
+        if let Some(dir) = &args.out_dir {
+            fs::write(dir.join(name), raw)?;
+        }
+    }
+    println!(
+        "{} announced, {} requested, {} received, {} not found",
+        report.announced, report.requested, report.fetched.len(), report.not_found
+    );
+    Ok(())
+}
+
+fn run_watch(args: WatchArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let network = args.connection.network;
+    let mut peers = Vec::new();
+    for peer in &args.peers {
+        peers.push(network.resolve(peer)?.into_iter().next().ok_or_else(|| format!("{} did not resolve", peer))?);
+    }
+    shutdown::install();
+
+    println!("Watching {} peers for block announcements", peers.len());
+    let report = watch_announcements(&peers, &args.connection.config(), Duration::from_secs(args.duration), |event, delay_ms| match event {
+        WatchEvent::Connected { peer, user_agent, height } => println!("{} connected: {} at height {}", peer, user_agent, height),
+        WatchEvent::Announced { peer, hash, via, block_time, received_ms } => {
+            let via = match via {
+                AnnouncedVia::Headers => "headers",
+                AnnouncedVia::Inv => "inv",
+            };
+            match (delay_ms, block_time) {
+                (Some(0), Some(time)) => {
+                    let age = (*received_ms / 1000) as i64 - *time as i64;
+                    println!("{} from {} via {}, first, {}s after its timestamp", hash, peer, via, age)
+                }
+                (Some(0), None) => println!("{} from {} via {}, first", hash, peer, via),
+                _ => println!("{} from {} via {}, +{} ms", hash, peer, via, delay_ms.unwrap_or(0)),
+            }
+        }
+        WatchEvent::Closed { peer, error: Some(error) } => println!("{} disconnected: {}", peer, error),
+        WatchEvent::Closed { .. } => {}
+    });
+
+    println!("\nSaw {} blocks", report.blocks.len());
+    for peer in &report.peers {
+        match (&peer.error, peer.connected) {
+            (Some(error), false) => println!("{:<40} not connected: {}", peer.peer.to_string(), error),
+            _ => println!(
+                "{:<40} {} blocks, first for {}, {} via headers, mean delay {}",
+                peer.peer.to_string(),
+                peer.blocks,
+                peer.first,
+                peer.via_headers,
+                peer.mean_delay_ms.map_or("-".to_string(), |ms| format!("{} ms", ms))
+            ),
+        }
+    }
+    if let Some(path) = &args.json {
+        fs::write(path, serde_json::to_string_pretty(&report)?)?;
+    }
+    Ok(())
+}
+
+fn run_mempool(args: MempoolArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let network = args.connection.network;
+    let addr = network.resolve(&args.peer)?.into_iter().next().ok_or_else(|| format!("{} did not resolve", args.peer))?;
+
+    // Peers do not announce transactions to clients that turned relay off
+    let mut connection = args.connection.config();
+    connection.version.relay = true;
+    let mut peer = Peer::open(&addr, &connection)?;
+    let snapshot = mempool_snapshot(&mut peer, Duration::from_secs(args.timeout), Duration::from_secs(args.idle_timeout))?;
+
+    let json = serde_json::to_string_pretty(&snapshot)?;
+    match &args.out {
+        Some(path) => {
+            fs::write(path, json)?;
+            eprintln!("Wrote {} txids to {}", snapshot.txids.len(), path.display());
+        }
+        None => println!("{}", json),
+    }
+    Ok(())
+}
+
+fn run_fuzz(args: FuzzArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let network = args.connection.network;
+    let addr = network.resolve(&args.peer)?.into_iter().next().ok_or_else(|| format!("{} did not resolve", args.peer))?;
+    let mut mutations = if args.cases.is_empty() {
+        VersionMutation::ALL.to_vec()
+    } else {
+        let names = VersionMutation::ALL.map(VersionMutation::name).join(", ");
+        args.cases
+            .iter()
+            .map(|case| VersionMutation::from_name(case).ok_or_else(|| format!("unknown case {}, expected one of {}", case, names)))
+            .collect::<Result<Vec<_>, _>>()?
+    };
+    // A closing control tells whether the node turned against us partway, which would make
+    // every reaction after that point say more about us than about the mutation
+    mutations.push(VersionMutation::Valid);
+
+    let mut connection = args.connection.config();
+    connection.v2_transport = false;
+    println!("Fuzzing the handshake of {} with {} mutations", addr, mutations.len() - 1);
+    let outcomes = fuzz_handshake(&addr, &connection, &mutations, Duration::from_secs(args.observe));
+
+    let (control, outcomes) = outcomes.split_last().unwrap();
+    for outcome in outcomes {
+        let replies = if outcome.replies.is_empty() { String::new() } else { format!(" (replied {})", outcome.replies.join(", ")) };
+        println!("{:<26} {:>5} bytes  {}{}", outcome.case, outcome.sent_bytes, outcome.reaction, replies);
+    }
+    if control.reaction != Reaction::Accepted {
+        println!("Closing control was not accepted ({}); the node may have banned us during the run", control.reaction);
+    }
+    if let Some(path) = &args.json {
+        fs::write(path, serde_json::to_string_pretty(outcomes)?)?;
+    }
+    Ok(())
+}
+
+fn run_spv(args: SpvArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let network = args.connection.network;
+    let addr = network.resolve(&args.peer)?.into_iter().next().ok_or_else(|| format!("{} did not resolve", args.peer))?;
+
+    let mut elements = Vec::new();
+    for address in &args.addresses {
+        let address = Address::<NetworkUnchecked>::from_str(address)?.require_network(network.address_network())?;
+        elements.extend(script_pushes(&address.script_pubkey()));
+    }
+    for element in &args.elements {
+        elements.push(Vec::<u8>::from_hex(element)?);
+    }
+    if elements.is_empty() {
+        return Err("give at least one --address or --element to filter on".into());
+    }
+    let mut filter = BloomFilter::new(elements.len(), args.fp_rate, rand::random(), args.bloom_update);
+    elements.iter().for_each(|element| filter.insert(element));
+
+    let mut peer = Peer::open(&addr, &args.connection.config())?;
+    if peer.version.as_ref().is_some_and(|version| version.services & NODE_BLOOM == 0) {
+        eprintln!("Warning: {} does not advertise NODE_BLOOM and will likely disconnect", addr);
+    }
+    println!("Loaded a {}-byte filter with {} hash functions into {}", filter.data.len(), filter.hash_funcs, addr);
+    let report = fetch_filtered_blocks(&mut peer, filter, &args.blocks, Duration::from_secs(args.timeout))?;
+
+    for block in &report.blocks {
+        match &block.error {
+            Some(error) => println!("block {} rejected: {}", block.block_hash, error),
+            None => println!(
+                "block {} (time {}) verified: {} of {} transactions matched",
+                block.block_hash, block.header.time, block.proofs.len(), block.total_transactions
+            ),
+        }
+        for proof in &block.proofs {
+            match block.transactions.iter().find(|tx| tx.txid() == proof.txid) {
+                Some(tx) => {
+                    let summary = BlockProcessor::summarize_transaction(proof.position, tx);
+                    println!("  tx {} at {}: {} vB, {} inputs, {} outputs, {} sat out", proof.txid, proof.position, summary.vsize, summary.inputs.len(), summary.outputs.len(), summary.total_output_value);
+                }
+                None => println!("  tx {} at {}: proven but not sent", proof.txid, proof.position),
+            }
+        }
+    }
+    println!(
+        "{} of {} blocks received, {} not found, {} filter updates sent",
+        report.blocks.len(), args.blocks.len(), report.not_found, report.filter_updates
+    );
+    Ok(())
+}
+
+fn run_listen(args: ListenArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let network = args.connection.network;
+    let config = ListenConfig {
+        connection: args.connection.config(),
+        max_connections: args.max_connections,
+        session_timeout: Duration::from_secs(args.session_timeout),
+    };
+    let bind = args.bind.unwrap_or_else(|| SocketAddr::from(([0, 0, 0, 0], network.default_port())));
+
+    let mut addrman = AddrMan::default();
+    if let Some(path) = &args.store {
+        let source = PeerAddr::Ip(bind);
+        let added = addrman.add(PeerStore::load(path)?.addr_entries(), &source, unix_time());
+        println!("Loaded {} addresses from {}", added, path.display());
+    }
+    let addrman = Mutex::new(addrman);
+    let active = AtomicUsize::new(0);
+
+    let listener = TcpListener::bind(bind)?;
+    println!("Listening on {}", listener.local_addr()?);
+    thread::scope(|scope| {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("Could not accept a connection: {}", e);
+                    continue;
+                }
+            };
+            let from = stream.peer_addr().map_or("unknown".to_string(), |addr| addr.to_string());
+            if active.load(Ordering::SeqCst) >= config.max_connections {
+                println!("{} turned away: {} inbound peers already connected", from, config.max_connections);
+                continue;
+            }
+            println!("{} connected", from);
+            active.fetch_add(1, Ordering::SeqCst);
+            let (config, addrman, active) = (&config, &addrman, &active);
+            scope.spawn(move || {
+                let session = serve_inbound(stream, config, addrman);
+                active.fetch_sub(1, Ordering::SeqCst);
+                let mut details = match &session.version {
+                    Some(version) => format!(
+                        "{} (protocol {}, services {:#x}, height {})",
+                        version.user_agent, version.version, version.services, version.start_height
+                    ),
+                    None => "no handshake".to_string(),
+                };
+                for (name, supported) in [("wtxidrelay", session.features.wtxid_relay), ("sendaddrv2", session.features.addr_v2), ("sendheaders", session.features.send_headers)] {
+                    if supported {
+                        details.push_str(&format!(", {}", name));
+                    }
+                }
+                let messages: Vec<String> = session.messages.iter().map(|(command, count)| format!("{} {}", command, count)).collect();
+                println!(
+                    "{} left after {:.1}s: {}; received [{}]; sent {} addresses, learned {}{}",
+                    from,
+                    session.duration.as_secs_f64(),
+                    details,
+                    messages.join(", "),
+                    session.addresses_sent,
+                    session.addresses_received,
+                    session.error.map_or(String::new(), |error| format!("; {}", error))
+                );
+            });
+        }
+    });
+    Ok(())
+}
+
+fn run_replay(args: ReplayArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let records = read_capture(&args.capture)?;
+    let start = records.first().map_or(0, |record| record.time_us);
+    let (mut sent, mut failed) = (0, 0);
+    for record in &records {
+        let direction = match record.direction {
+            Direction::Received => "recv",
+            Direction::Sent => {
+                sent += 1;
+                "sent"
+            }
+        };
+        let elapsed = (record.time_us - start) as f64 / 1e6;
+        let name = String::from_utf8_lossy(&record.command).trim_end_matches('\0').to_string();
+        let decoded = Command::from_bytes(record.command).and_then(|command| Message::decode(&command, &record.payload));
+        match decoded {
+            Ok(message) => {
+                // Decoding drops nothing we keep, so a payload that re-encodes differently
+                // points at a codec bug or a peer padding its messages
+                let roundtrip = if message.encode_payload() == record.payload { "" } else { " (re-encodes differently)" };
+                println!("{:>11.6} {} {:<12} {:>8} bytes{}", elapsed, direction, name, record.payload.len(), roundtrip);
+                if args.verbose {
+                    println!("    {:?}", message);
+                }
+            }
+            Err(e) => {
+                failed += 1;
+                println!("{:>11.6} {} {:<12} {:>8} bytes: {}", elapsed, direction, name, record.payload.len(), e);
             }
         }
     }
+    println!("Replayed {} messages ({} sent, {} received), {} failed to decode", records.len(), sent, records.len() - sent, failed);
+    Ok(())
+}
+
+fn run_peers(args: PeersArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let store = PeerStore::load(&args.store)?;
+    let best = store.best(args.limit, args.require_services);
+    for record in &best {
+        let latency = record.handshake_ms.map_or("-".to_string(), |ms| format!("{} ms", ms));
+        let ping = record.ping_avg_ms.map_or("-".to_string(), |ms| format!("{:.1} ms", ms));
+        // Peers on our chain near its tip need no mention
+        let tip = match record.tip {
+            Some(tip @ (TipStatus::Behind { .. } | TipStatus::Ahead { .. } | TipStatus::Forked { .. })) => format!(" [{}]", tip),
+            _ => String::new(),
+        };
+        let location = match (&record.country, record.asn) {
+            (Some(country), Some(asn)) => format!(" [{} AS{}]", country, asn),
+            (Some(country), None) => format!(" [{}]", country),
+            (None, Some(asn)) => format!(" [AS{}]", asn),
+            (None, None) => String::new(),
+        };
+        println!(
+            "{}{}{} score {:.3} ({} ok, {} failed, handshake {}, ping {}) {}",
+            record.addr,
+            location,
+            tip,
+            record.score(),
+            record.successes,
+            record.failures,
+            latency,
+            ping,
+            record.user_agent.as_deref().unwrap_or("")
+        );
+    }
+    println!("Listed {} good peers of {} known", best.len(), store.len());
+    Ok(())
+}
 
+fn run_export(args: ExportArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let store = PeerStore::load(&args.store)?;
+    let entries = dump_entries(&store);
+    let text = export(&entries, args.format)?;
+    match &args.out {
+        Some(path) => {
+            fs::write(path, text)?;
+            println!("Wrote {} peers to {}", entries.len(), path.display());
+        }
+        None => print!("{}", text),
+    }
     Ok(())
 }
 
-fn build_version_payload() -> Vec<u8> {
-    let mut payload = Vec::new();
-    
-    // Protocol version (70015 = latest before BIP324)
-    payload.extend(70015u32.to_le_bytes());
-    
-    // Services (NODE_NETWORK)
-    payload.extend(1u64.to_le_bytes());
-    
-    // Timestamp
-    let timestamp = SystemTime::now()
+#[cfg(feature = "geoip")]
+fn run_geoip(args: GeoipArgs) -> Result<(), Box<dyn std::error::Error>> {
+    if args.country_db.is_none() && args.asn_db.is_none() {
+        return Err("give --country-db, --asn-db or both".into());
+    }
+    let geoip = GeoIp::open(args.country_db.as_deref(), args.asn_db.as_deref())?;
+    let mut store = PeerStore::load(&args.store)?;
+    let located = store.tag_locations(&geoip)?;
+    store.save(&args.store)?;
+    println!("Located {} of {} known peers", located, store.len());
+
+    let distribution = if args.all {
+        GeoDistribution::from_records(store.records())
+    } else {
+        GeoDistribution::from_records(store.records().filter(|record| record.is_good()))
+    };
+    print!("{}", distribution);
+    if let Some(path) = &args.json {
+        fs::write(path, serde_json::to_string_pretty(&distribution)?)?;
+    }
+    Ok(())
+}
+
+fn run_dns(args: DnsArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut server = DnsServer::new(DnsConfig {
+        domain: args.domain.clone(),
+        ttl: args.ttl,
+        max_records: args.max_records,
+        required_services: args.require_services,
+    });
+    let socket = UdpSocket::bind(&args.bind)?;
+    let reload_interval = Duration::from_secs(args.reload_interval);
+    // Wake up at least once per interval so the store is reloaded even when no queries come
+    socket.set_read_timeout(Some(reload_interval))?;
+    println!("Serving {} on {}", args.domain, socket.local_addr()?);
+
+    let mut last_reload: Option<Instant> = None;
+    let mut buf = [0u8; 512];
+    loop {
+        if last_reload.is_none_or(|at| at.elapsed() >= reload_interval) {
+            // A crawl may be rewriting the store; keep serving the old set if it fails to load
+            match PeerStore::load(&args.store) {
+                Ok(store) => {
+                    // Records carry an IP but no port, so only IP peers on the default port qualify
+                    let peers = store.best(usize::MAX, 0).into_iter().filter_map(|record| match record.addr {
+                        PeerAddr::Ip(addr) if addr.port() == args.network.default_port() => Some((addr.ip(), record.services)),
+                        _ => None,
+                    });
+                    server.set_peers(peers);
+                    println!("Loaded {} good peers", server.peer_count());
+                }
+                Err(e) => eprintln!("Could not reload {}: {}", args.store.display(), e),
+            }
+            last_reload = Some(Instant::now());
+        }
+
+        let (length, from) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if let Some(reply) = server.respond(&buf[..length]) {
+            if let Err(e) = socket.send_to(&reply, from) {
+                eprintln!("Could not reply to {}: {}", from, e);
+            }
+        }
+    }
+}
+
+fn parse_bloom_update(value: &str) -> Result<u8, String> {
+    match value {
+        "none" => Ok(BLOOM_UPDATE_NONE),
+        "all" => Ok(BLOOM_UPDATE_ALL),
+        "p2pubkey-only" => Ok(BLOOM_UPDATE_P2PUBKEY_ONLY),
+        _ => Err(format!("unknown bloom update mode {}", value)),
+    }
+}
+
+// Load a headers file to check peers' tips against, making sure it belongs to the network
+fn load_best_chain(path: &Path, network: Network) -> Result<BestChain, Box<dyn std::error::Error>> {
+    let chain = BestChain::load(path)?;
+    if network.genesis_header().is_some_and(|genesis| genesis.block_hash() != chain.genesis()) {
+        return Err(format!("{} does not start at the {:?} genesis block", path.display(), network).into());
+    }
+    Ok(chain)
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap()
-        .as_secs() as i64;
-    payload.extend(timestamp.to_le_bytes());
-    
-    // Receiver address (IPv4 mapped to IPv6)
-    payload.extend(1u64.to_le_bytes()); // Services
-    payload.extend([0x00; 12]);          // IPv6 prefix
-    payload.extend([0xFF, 0xFF]);        // IPv4 marker
-    payload.extend([34, 90, 43, 75]);    // IP address
-    payload.extend(8333u16.to_be_bytes()); // Port
-    
-    // Sender address (empty)
-    payload.extend(0u64.to_le_bytes());  // Services
-    payload.extend([0x00; 16]);          // IPv6
-    payload.extend(0u16.to_be_bytes());  // Port
-    
-    // Nonce
-    payload.extend(123456789u64.to_le_bytes());
-    
-    // User agent
-    payload.push(0x00); // Compact size (length 0)
-    
-    // Start height
-    payload.extend(0i32.to_le_bytes());
-    
-    // Relay flag
-    payload.push(0x01); // True
-    
-    payload
-}
-
-// Double SHA-256 implementation for checksum
-fn sha256d(data: &[u8]) -> [u8; 32] {
-    use sha2::{Digest, Sha256};
-    let first = Sha256::digest(data);
-    let second = Sha256::digest(&first);
-    second.into()
-}
\ No newline at end of file
+        .as_secs()
+}
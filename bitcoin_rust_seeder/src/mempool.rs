@@ -0,0 +1,45 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use bitcoin::hashes::Hash;
+use bitcoin::Txid;
+use serde::Serialize;
+
+use crate::address::PeerAddr;
+use crate::message::{InvType, Message, MessageError};
+use crate::peer::Peer;
+
+// Transactions a peer advertised in reply to a mempool request
+#[derive(Debug, Clone, Serialize)]
+pub struct MempoolSnapshot {
+    pub peer: PeerAddr,
+    pub taken_at: u64, // unix seconds
+    pub txids: Vec<String>,
+}
+
+// Send mempool and gather the txids of the inv flood that answers it. The reply spans many
+// inv messages, so collection ends once no inv has arrived for idle_timeout, or at timeout.
+// Peers only answer when they serve bloom filters (NODE_BLOOM) or whitelist us.
+pub fn mempool_snapshot(peer: &mut Peer, timeout: Duration, idle_timeout: Duration) -> Result<MempoolSnapshot, MessageError> {
+    peer.send(&Message::MemPool)?;
+    let deadline = Instant::now() + timeout;
+    let mut last_inv = Instant::now();
+    let mut txids = Vec::new();
+
+    while let Some(message) = peer.receive_before(deadline.min(last_inv + idle_timeout))? {
+        if let Message::Inv(items) = message {
+            last_inv = Instant::now();
+            txids.extend(
+                items
+                    .iter()
+                    .filter(|item| matches!(item.kind, InvType::Tx | InvType::WitnessTx))
+                    .map(|item| Txid::from_byte_array(item.hash).to_string()),
+            );
+        }
+    }
+
+    Ok(MempoolSnapshot {
+        peer: peer.addr.clone(),
+        taken_at: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+        txids,
+    })
+}
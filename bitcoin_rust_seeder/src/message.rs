@@ -0,0 +1,1110 @@
+use std::fmt;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+
+use bitcoin::block::Header;
+use bitcoin::consensus::{deserialize, serialize};
+use bitcoin::hashes::Hash;
+use bitcoin::hash_types::TxMerkleNode;
+use bitcoin::BlockHash;
+
+use crate::address::PeerAddr;
+use crate::bloom::{BloomFilter, MAX_BLOOM_FILTER_SIZE, MAX_FILTER_ADD_SIZE};
+
+// magic (4) + command (12) + payload length (4) + checksum (4)
+pub const HEADER_SIZE: usize = 24;
+
+// Largest payload accepted from a peer, matching Bitcoin Core's MAX_PROTOCOL_MESSAGE_LENGTH
+pub const MAX_PAYLOAD_SIZE: usize = 4_000_000;
+
+// Most entries an addr message may carry
+pub const MAX_ADDR_ENTRIES: usize = 1000;
+
+// Most headers a headers message may carry, and the count that signals more are available
+pub const MAX_HEADERS_RESULTS: usize = 2000;
+
+// Longest address an addrv2 entry may carry, for any network (BIP155)
+pub const MAX_ADDRV2_SIZE: usize = 512;
+
+// Most entries an inv, getdata or notfound message may carry
+pub const MAX_INV_SIZE: usize = 50_000;
+
+// Most hashes accepted in a block locator
+pub const MAX_LOCATOR_SIZE: usize = 101;
+
+// Bytes asked of the socket per read while filling the receive buffer
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+// Errors raised while encoding, decoding or framing messages
+#[derive(Debug)]
+pub enum MessageError {
+    Io(std::io::Error),
+    BadMagic([u8; 4]),
+    BadChecksum { expected: [u8; 4], found: [u8; 4] },
+    PayloadTooLarge(usize),
+    InvalidCommand([u8; 12]),
+    Truncated(&'static str),
+    TooManyEntries { count: usize, limit: usize },
+    MissingGarbageTerminator,
+    V2Rejected, // the peer hung up before sending its v2 key
+    Decryption,
+    UnknownShortId(u8),
+    BadAddressLength { network: u8, length: usize },
+    SelfConnection, // the peer's version carried our own nonce
+}
+
+impl fmt::Display for MessageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MessageError::Io(e) => write!(f, "I/O error: {}", e),
+            MessageError::BadMagic(magic) => write!(f, "unexpected network magic {:02x?}", magic),
+            MessageError::BadChecksum { expected, found } => write!(f, "checksum mismatch: expected {:02x?}, found {:02x?}", expected, found),
+            MessageError::PayloadTooLarge(size) => write!(f, "payload of {} bytes exceeds {}", size, MAX_PAYLOAD_SIZE),
+            MessageError::InvalidCommand(command) => write!(f, "invalid command bytes {:02x?}", command),
+            MessageError::Truncated(field) => write!(f, "payload truncated while reading {}", field),
+            MessageError::TooManyEntries { count, limit } => write!(f, "{} entries exceed the limit of {}", count, limit),
+            MessageError::MissingGarbageTerminator => write!(f, "no v2 garbage terminator within {} bytes", crate::bip324::MAX_GARBAGE_SIZE),
+            MessageError::V2Rejected => write!(f, "peer closed the connection before sending its v2 key"),
+            MessageError::Decryption => write!(f, "v2 packet failed authentication"),
+            MessageError::UnknownShortId(id) => write!(f, "unknown v2 short message id {}", id),
+            MessageError::BadAddressLength { network, length } => write!(f, "{}-byte address is invalid for BIP155 network {}", length, network),
+            MessageError::SelfConnection => write!(f, "connected to ourselves: the version nonce is our own"),
+        }
+    }
+}
+
+impl std::error::Error for MessageError {}
+
+impl From<std::io::Error> for MessageError {
+    fn from(e: std::io::Error) -> Self {
+        MessageError::Io(e)
+    }
+}
+
+// Message types the seeder understands; anything else is carried as Unknown
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Version,
+    Verack,
+    Ping,
+    Pong,
+    GetAddr,
+    Addr,
+    GetHeaders,
+    Headers,
+    Inv,
+    GetData,
+    NotFound,
+    Tx,
+    Block,
+    MemPool,
+    FeeFilter,
+    SendCmpct,
+    SendAddrV2,
+    AddrV2,
+    WtxidRelay,
+    SendHeaders,
+    FilterLoad,
+    FilterAdd,
+    FilterClear,
+    MerkleBlock,
+    Unknown(String),
+}
+
+impl Command {
+    pub fn name(&self) -> &str {
+        match self {
+            Command::Version => "version",
+            Command::Verack => "verack",
+            Command::Ping => "ping",
+            Command::Pong => "pong",
+            Command::GetAddr => "getaddr",
+            Command::Addr => "addr",
+            Command::GetHeaders => "getheaders",
+            Command::Headers => "headers",
+            Command::Inv => "inv",
+            Command::GetData => "getdata",
+            Command::NotFound => "notfound",
+            Command::Tx => "tx",
+            Command::Block => "block",
+            Command::MemPool => "mempool",
+            Command::FeeFilter => "feefilter",
+            Command::SendCmpct => "sendcmpct",
+            Command::SendAddrV2 => "sendaddrv2",
+            Command::AddrV2 => "addrv2",
+            Command::WtxidRelay => "wtxidrelay",
+            Command::SendHeaders => "sendheaders",
+            Command::FilterLoad => "filterload",
+            Command::FilterAdd => "filteradd",
+            Command::FilterClear => "filterclear",
+            Command::MerkleBlock => "merkleblock",
+            Command::Unknown(name) => name,
+        }
+    }
+
+    // NUL-padded 12-byte wire form
+    pub fn to_bytes(&self) -> [u8; 12] {
+        let mut bytes = [0u8; 12];
+        let name = self.name().as_bytes();
+        bytes[..name.len().min(12)].copy_from_slice(&name[..name.len().min(12)]);
+        bytes
+    }
+
+    // Parse the wire form: printable ASCII followed only by NUL padding
+    pub fn from_bytes(bytes: [u8; 12]) -> Result<Self, MessageError> {
+        let end = bytes.iter().position(|b| *b == 0).unwrap_or(12);
+        if bytes[end..].iter().any(|b| *b != 0) || !bytes[..end].iter().all(|b| b.is_ascii_graphic()) {
+            return Err(MessageError::InvalidCommand(bytes));
+        }
+        let name = std::str::from_utf8(&bytes[..end]).expect("ASCII checked above");
+        Ok(match name {
+            "version" => Command::Version,
+            "verack" => Command::Verack,
+            "ping" => Command::Ping,
+            "pong" => Command::Pong,
+            "getaddr" => Command::GetAddr,
+            "addr" => Command::Addr,
+            "getheaders" => Command::GetHeaders,
+            "headers" => Command::Headers,
+            "inv" => Command::Inv,
+            "getdata" => Command::GetData,
+            "notfound" => Command::NotFound,
+            "tx" => Command::Tx,
+            "block" => Command::Block,
+            "mempool" => Command::MemPool,
+            "feefilter" => Command::FeeFilter,
+            "sendcmpct" => Command::SendCmpct,
+            "sendaddrv2" => Command::SendAddrV2,
+            "addrv2" => Command::AddrV2,
+            "wtxidrelay" => Command::WtxidRelay,
+            "sendheaders" => Command::SendHeaders,
+            "filterload" => Command::FilterLoad,
+            "filteradd" => Command::FilterAdd,
+            "filterclear" => Command::FilterClear,
+            "merkleblock" => Command::MerkleBlock,
+            other => Command::Unknown(other.to_string()),
+        })
+    }
+}
+
+// Network address as carried in version messages (no timestamp)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetAddr {
+    pub services: u64,
+    pub ip: Ipv6Addr, // IPv4 addresses are IPv4-mapped
+    pub port: u16,
+}
+
+impl NetAddr {
+    pub fn new(addr: SocketAddr, services: u64) -> Self {
+        let ip = match addr.ip() {
+            IpAddr::V4(v4) => v4.to_ipv6_mapped(),
+            IpAddr::V6(v6) => v6,
+        };
+        NetAddr { services, ip, port: addr.port() }
+    }
+
+    // All-zero address, as sent for our own (unknown) address
+    pub fn unspecified() -> Self {
+        NetAddr { services: 0, ip: Ipv6Addr::UNSPECIFIED, port: 0 }
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend(self.services.to_le_bytes());
+        out.extend(self.ip.octets());
+        out.extend(self.port.to_be_bytes()); // port is big-endian on the wire
+    }
+
+    fn decode(reader: &mut PayloadReader) -> Result<Self, MessageError> {
+        Ok(NetAddr {
+            services: reader.u64("address services")?,
+            ip: Ipv6Addr::from(reader.array::<16>("address ip")?),
+            port: u16::from_be_bytes(reader.array::<2>("address port")?),
+        })
+    }
+}
+
+// Entry of an addr or addrv2 message: a network address with the time it was last seen
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddrEntry {
+    pub timestamp: u32,
+    pub services: u64,
+    pub addr: PeerAddr,
+}
+
+impl AddrEntry {
+    // The endpoint as an addr message carries it; CJDNS travels there as plain IPv6, and
+    // overlay networks not at all
+    fn v1_socket_addr(&self) -> Option<SocketAddr> {
+        match &self.addr {
+            PeerAddr::Ip(addr) => Some(*addr),
+            PeerAddr::Cjdns { ip, port } => Some(SocketAddr::new(IpAddr::V6(*ip), *port)),
+            PeerAddr::Onion { .. } | PeerAddr::I2p { .. } => None,
+        }
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend(self.timestamp.to_le_bytes());
+        NetAddr::new(self.v1_socket_addr().expect("only v1 addresses are encoded"), self.services).encode(out);
+    }
+
+    fn decode(reader: &mut PayloadReader) -> Result<Self, MessageError> {
+        let timestamp = reader.u32("address timestamp")?;
+        let NetAddr { services, ip, port } = NetAddr::decode(reader)?;
+        let ip = match ip.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => IpAddr::V6(ip),
+        };
+        Ok(AddrEntry { timestamp, services, addr: PeerAddr::Ip(SocketAddr::new(ip, port)) })
+    }
+
+    fn encode_v2(&self, out: &mut Vec<u8>) {
+        let (network, bytes) = self.addr.to_bip155().expect("only encodable addresses are sent");
+        out.extend(self.timestamp.to_le_bytes());
+        write_compact_size(out, self.services);
+        out.push(network);
+        write_compact_size(out, bytes.len() as u64);
+        out.extend(bytes);
+        out.extend(self.addr.port().to_be_bytes());
+    }
+
+    // Decode a BIP155 entry; None for networks we cannot use, which are skipped
+    fn decode_v2(reader: &mut PayloadReader) -> Result<Option<Self>, MessageError> {
+        let timestamp = reader.u32("address timestamp")?;
+        let services = reader.compact_size("address services")?;
+        let network = reader.u8("address network")?;
+        let length = reader.compact_size("address length")? as usize;
+        if length > MAX_ADDRV2_SIZE || PeerAddr::bip155_length(network).is_some_and(|expected| expected != length) {
+            return Err(MessageError::BadAddressLength { network, length });
+        }
+        let bytes = reader.bytes(length, "address")?;
+        let port = u16::from_be_bytes(reader.array::<2>("address port")?);
+        Ok(PeerAddr::from_bip155(network, bytes, port).map(|addr| AddrEntry { timestamp, services, addr }))
+    }
+}
+
+// Payload of a version message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionMessage {
+    pub version: i32,
+    pub services: u64,
+    pub timestamp: i64,
+    pub receiver: NetAddr,
+    pub sender: NetAddr,
+    pub nonce: u64,
+    pub user_agent: String,
+    pub start_height: i32,
+    pub relay: bool,
+}
+
+impl VersionMessage {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend(self.version.to_le_bytes());
+        out.extend(self.services.to_le_bytes());
+        out.extend(self.timestamp.to_le_bytes());
+        self.receiver.encode(out);
+        self.sender.encode(out);
+        out.extend(self.nonce.to_le_bytes());
+        write_compact_size(out, self.user_agent.len() as u64);
+        out.extend(self.user_agent.as_bytes());
+        out.extend(self.start_height.to_le_bytes());
+        out.push(self.relay as u8);
+    }
+
+    fn decode(reader: &mut PayloadReader) -> Result<Self, MessageError> {
+        let version = reader.i32("version")?;
+        let services = reader.u64("services")?;
+        let timestamp = reader.i64("timestamp")?;
+        let receiver = NetAddr::decode(reader)?;
+        let sender = NetAddr::decode(reader)?;
+        let nonce = reader.u64("nonce")?;
+        let user_agent_len = reader.compact_size("user agent length")? as usize;
+        let user_agent = String::from_utf8_lossy(reader.bytes(user_agent_len, "user agent")?).into_owned();
+        let start_height = reader.i32("start height")?;
+        // Peers older than BIP37 omit the relay flag, which then defaults to true
+        let relay = if reader.is_empty() { true } else { reader.u8("relay")? != 0 };
+        Ok(VersionMessage { version, services, timestamp, receiver, sender, nonce, user_agent, start_height, relay })
+    }
+}
+
+// Kind of object an inventory entry refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvType {
+    Error,
+    Tx,
+    Block,
+    FilteredBlock,
+    CompactBlock,
+    WitnessTx,
+    WitnessBlock,
+    Unknown(u32),
+}
+
+// Flag requesting the witness serialization in getdata (BIP144)
+const MSG_WITNESS_FLAG: u32 = 1 << 30;
+
+impl InvType {
+    pub fn from_u32(value: u32) -> Self {
+        match value {
+            0 => InvType::Error,
+            1 => InvType::Tx,
+            2 => InvType::Block,
+            3 => InvType::FilteredBlock,
+            4 => InvType::CompactBlock,
+            v if v == 1 | MSG_WITNESS_FLAG => InvType::WitnessTx,
+            v if v == 2 | MSG_WITNESS_FLAG => InvType::WitnessBlock,
+            other => InvType::Unknown(other),
+        }
+    }
+
+    pub fn to_u32(self) -> u32 {
+        match self {
+            InvType::Error => 0,
+            InvType::Tx => 1,
+            InvType::Block => 2,
+            InvType::FilteredBlock => 3,
+            InvType::CompactBlock => 4,
+            InvType::WitnessTx => 1 | MSG_WITNESS_FLAG,
+            InvType::WitnessBlock => 2 | MSG_WITNESS_FLAG,
+            InvType::Unknown(value) => value,
+        }
+    }
+}
+
+// Entry of an inv, getdata or notfound message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Inventory {
+    pub kind: InvType,
+    pub hash: [u8; 32], // internal byte order
+}
+
+impl Inventory {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend(self.kind.to_u32().to_le_bytes());
+        out.extend(self.hash);
+    }
+
+    fn decode_list(reader: &mut PayloadReader) -> Result<Vec<Self>, MessageError> {
+        let count = reader.compact_size("inventory count")? as usize;
+        if count > MAX_INV_SIZE {
+            return Err(MessageError::TooManyEntries { count, limit: MAX_INV_SIZE });
+        }
+        (0..count)
+            .map(|_| {
+                Ok(Inventory {
+                    kind: InvType::from_u32(reader.u32("inventory type")?),
+                    hash: reader.array::<32>("inventory hash")?,
+                })
+            })
+            .collect()
+    }
+}
+
+// Request for headers following the first locator hash we share with the peer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GetHeadersMessage {
+    pub version: u32,
+    pub locator: Vec<BlockHash>, // newest first
+    pub stop_hash: BlockHash,    // all zeros for as many as the peer will send
+}
+
+impl GetHeadersMessage {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend(self.version.to_le_bytes());
+        write_compact_size(out, self.locator.len() as u64);
+        self.locator.iter().for_each(|hash| out.extend(hash.to_byte_array()));
+        out.extend(self.stop_hash.to_byte_array());
+    }
+
+    fn decode(reader: &mut PayloadReader) -> Result<Self, MessageError> {
+        let version = reader.u32("getheaders version")?;
+        let count = reader.compact_size("locator count")? as usize;
+        if count > MAX_LOCATOR_SIZE {
+            return Err(MessageError::TooManyEntries { count, limit: MAX_LOCATOR_SIZE });
+        }
+        let locator = (0..count)
+            .map(|_| reader.array::<32>("locator hash").map(BlockHash::from_byte_array))
+            .collect::<Result<_, _>>()?;
+        let stop_hash = BlockHash::from_byte_array(reader.array::<32>("stop hash")?);
+        Ok(GetHeadersMessage { version, locator, stop_hash })
+    }
+}
+
+// Block header with a partial merkle tree proving which transactions matched the loaded
+// filter (BIP37)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleBlockMessage {
+    pub header: Header,
+    pub total_transactions: u32,
+    pub hashes: Vec<TxMerkleNode>, // depth-first, internal byte order
+    pub flags: Vec<u8>,            // traversal bits, least significant bit first
+}
+
+impl MerkleBlockMessage {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend(serialize(&self.header));
+        out.extend(self.total_transactions.to_le_bytes());
+        write_compact_size(out, self.hashes.len() as u64);
+        self.hashes.iter().for_each(|hash| out.extend(hash.to_byte_array()));
+        write_compact_size(out, self.flags.len() as u64);
+        out.extend(&self.flags);
+    }
+
+    fn decode(reader: &mut PayloadReader) -> Result<Self, MessageError> {
+        let bytes = reader.array::<80>("merkleblock header")?;
+        let header = deserialize(&bytes).expect("80 bytes always decode as a header");
+        let total_transactions = reader.u32("merkleblock transaction count")?;
+        // Bounded by the payload size, as each hash takes 32 bytes
+        let count = reader.compact_size("merkleblock hash count")? as usize;
+        if count > MAX_PAYLOAD_SIZE / 32 {
+            return Err(MessageError::TooManyEntries { count, limit: MAX_PAYLOAD_SIZE / 32 });
+        }
+        let hashes = (0..count)
+            .map(|_| reader.array::<32>("merkleblock hash").map(TxMerkleNode::from_byte_array))
+            .collect::<Result<_, _>>()?;
+        let size = reader.compact_size("merkleblock flag bytes")? as usize;
+        let flags = reader.bytes(size, "merkleblock flags")?.to_vec();
+        Ok(MerkleBlockMessage { header, total_transactions, hashes, flags })
+    }
+}
+
+// Compact block relay preference (BIP152)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendCmpctMessage {
+    pub announce: bool, // high-bandwidth mode: announce new blocks with cmpctblock directly
+    pub version: u64,
+}
+
+// A decoded P2P message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Version(VersionMessage),
+    Verack,
+    Ping(u64),
+    Pong(u64),
+    GetAddr,
+    Addr(Vec<AddrEntry>),
+    AddrV2(Vec<AddrEntry>),
+    SendAddrV2,
+    GetHeaders(GetHeadersMessage),
+    Headers(Vec<Header>),
+    Inv(Vec<Inventory>),
+    GetData(Vec<Inventory>),
+    NotFound(Vec<Inventory>),
+    Tx(Vec<u8>),    // serialized transaction, left for callers to decode
+    Block(Vec<u8>), // serialized block
+    MemPool,
+    FeeFilter(u64), // minimum feerate for relayed transactions, in sat/kvB
+    SendCmpct(SendCmpctMessage),
+    WtxidRelay,  // BIP339: announce transactions by wtxid, sent before verack
+    SendHeaders, // BIP130: announce new blocks with headers instead of inv
+    FilterLoad(BloomFilter),
+    FilterAdd(Vec<u8>), // element added to the loaded filter
+    FilterClear,
+    MerkleBlock(MerkleBlockMessage),
+    Unknown { command: String, payload: Vec<u8> },
+}
+
+impl Message {
+    pub fn command(&self) -> Command {
+        match self {
+            Message::Version(_) => Command::Version,
+            Message::Verack => Command::Verack,
+            Message::Ping(_) => Command::Ping,
+            Message::Pong(_) => Command::Pong,
+            Message::GetAddr => Command::GetAddr,
+            Message::Addr(_) => Command::Addr,
+            Message::AddrV2(_) => Command::AddrV2,
+            Message::SendAddrV2 => Command::SendAddrV2,
+            Message::GetHeaders(_) => Command::GetHeaders,
+            Message::Headers(_) => Command::Headers,
+            Message::Inv(_) => Command::Inv,
+            Message::GetData(_) => Command::GetData,
+            Message::NotFound(_) => Command::NotFound,
+            Message::Tx(_) => Command::Tx,
+            Message::Block(_) => Command::Block,
+            Message::MemPool => Command::MemPool,
+            Message::FeeFilter(_) => Command::FeeFilter,
+            Message::SendCmpct(_) => Command::SendCmpct,
+            Message::WtxidRelay => Command::WtxidRelay,
+            Message::SendHeaders => Command::SendHeaders,
+            Message::FilterLoad(_) => Command::FilterLoad,
+            Message::FilterAdd(_) => Command::FilterAdd,
+            Message::FilterClear => Command::FilterClear,
+            Message::MerkleBlock(_) => Command::MerkleBlock,
+            Message::Unknown { command, .. } => Command::Unknown(command.clone()),
+        }
+    }
+
+    pub fn encode_payload(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        match self {
+            Message::Version(version) => version.encode(&mut out),
+            Message::Verack | Message::GetAddr | Message::MemPool | Message::SendAddrV2 | Message::WtxidRelay | Message::SendHeaders | Message::FilterClear => {}
+            Message::Ping(nonce) | Message::Pong(nonce) => out.extend(nonce.to_le_bytes()),
+            Message::Addr(entries) => {
+                let entries: Vec<&AddrEntry> = entries.iter().filter(|entry| entry.v1_socket_addr().is_some()).collect();
+                write_compact_size(&mut out, entries.len() as u64);
+                entries.iter().for_each(|entry| entry.encode(&mut out));
+            }
+            Message::AddrV2(entries) => {
+                let entries: Vec<&AddrEntry> = entries.iter().filter(|entry| entry.addr.to_bip155().is_some()).collect();
+                write_compact_size(&mut out, entries.len() as u64);
+                entries.iter().for_each(|entry| entry.encode_v2(&mut out));
+            }
+            Message::GetHeaders(getheaders) => getheaders.encode(&mut out),
+            Message::Headers(headers) => {
+                write_compact_size(&mut out, headers.len() as u64);
+                for header in headers {
+                    out.extend(serialize(header));
+                    out.push(0); // transaction count, always zero
+                }
+            }
+            Message::Inv(items) | Message::GetData(items) | Message::NotFound(items) => {
+                write_compact_size(&mut out, items.len() as u64);
+                items.iter().for_each(|item| item.encode(&mut out));
+            }
+            Message::Tx(bytes) | Message::Block(bytes) => out.extend(bytes),
+            Message::FeeFilter(feerate) => out.extend(feerate.to_le_bytes()),
+            Message::SendCmpct(sendcmpct) => {
+                out.push(sendcmpct.announce as u8);
+                out.extend(sendcmpct.version.to_le_bytes());
+            }
+            Message::FilterLoad(filter) => {
+                write_compact_size(&mut out, filter.data.len() as u64);
+                out.extend(&filter.data);
+                out.extend(filter.hash_funcs.to_le_bytes());
+                out.extend(filter.tweak.to_le_bytes());
+                out.push(filter.flags);
+            }
+            Message::FilterAdd(element) => {
+                write_compact_size(&mut out, element.len() as u64);
+                out.extend(element);
+            }
+            Message::MerkleBlock(merkleblock) => merkleblock.encode(&mut out),
+            Message::Unknown { payload, .. } => out.extend(payload),
+        }
+        out
+    }
+
+    // Decode a payload for a command; trailing bytes are tolerated, as Bitcoin Core does
+    pub fn decode(command: &Command, payload: &[u8]) -> Result<Self, MessageError> {
+        let mut reader = PayloadReader::new(payload);
+        Ok(match command {
+            Command::Version => Message::Version(VersionMessage::decode(&mut reader)?),
+            Command::Verack => Message::Verack,
+            Command::Ping => Message::Ping(reader.u64("ping nonce")?),
+            Command::Pong => Message::Pong(reader.u64("pong nonce")?),
+            Command::GetAddr => Message::GetAddr,
+            Command::Addr => {
+                let count = reader.compact_size("addr count")? as usize;
+                if count > MAX_ADDR_ENTRIES {
+                    return Err(MessageError::TooManyEntries { count, limit: MAX_ADDR_ENTRIES });
+                }
+                Message::Addr((0..count).map(|_| AddrEntry::decode(&mut reader)).collect::<Result<_, _>>()?)
+            }
+            Command::AddrV2 => {
+                let count = reader.compact_size("addrv2 count")? as usize;
+                if count > MAX_ADDR_ENTRIES {
+                    return Err(MessageError::TooManyEntries { count, limit: MAX_ADDR_ENTRIES });
+                }
+                let entries: Vec<Option<AddrEntry>> = (0..count).map(|_| AddrEntry::decode_v2(&mut reader)).collect::<Result<_, _>>()?;
+                Message::AddrV2(entries.into_iter().flatten().collect())
+            }
+            Command::SendAddrV2 => Message::SendAddrV2,
+            Command::GetHeaders => Message::GetHeaders(GetHeadersMessage::decode(&mut reader)?),
+            Command::Headers => {
+                let count = reader.compact_size("headers count")? as usize;
+                if count > MAX_HEADERS_RESULTS {
+                    return Err(MessageError::TooManyEntries { count, limit: MAX_HEADERS_RESULTS });
+                }
+                let mut headers = Vec::with_capacity(count);
+                for _ in 0..count {
+                    let bytes = reader.array::<80>("header")?;
+                    headers.push(deserialize(&bytes).expect("80 bytes always decode as a header"));
+                    reader.compact_size("header transaction count")?;
+                }
+                Message::Headers(headers)
+            }
+            Command::Inv => Message::Inv(Inventory::decode_list(&mut reader)?),
+            Command::GetData => Message::GetData(Inventory::decode_list(&mut reader)?),
+            Command::NotFound => Message::NotFound(Inventory::decode_list(&mut reader)?),
+            Command::Tx => Message::Tx(payload.to_vec()),
+            Command::Block => Message::Block(payload.to_vec()),
+            Command::MemPool => Message::MemPool,
+            Command::FeeFilter => Message::FeeFilter(reader.u64("feefilter feerate")?),
+            Command::SendCmpct => Message::SendCmpct(SendCmpctMessage {
+                announce: reader.u8("sendcmpct announce")? != 0,
+                version: reader.u64("sendcmpct version")?,
+            }),
+            Command::WtxidRelay => Message::WtxidRelay,
+            Command::SendHeaders => Message::SendHeaders,
+            Command::FilterLoad => {
+                let size = reader.compact_size("filter size")? as usize;
+                if size > MAX_BLOOM_FILTER_SIZE {
+                    return Err(MessageError::TooManyEntries { count: size, limit: MAX_BLOOM_FILTER_SIZE });
+                }
+                Message::FilterLoad(BloomFilter {
+                    data: reader.bytes(size, "filter data")?.to_vec(),
+                    hash_funcs: reader.u32("filter hash functions")?,
+                    tweak: reader.u32("filter tweak")?,
+                    flags: reader.u8("filter flags")?,
+                })
+            }
+            Command::FilterAdd => {
+                let size = reader.compact_size("filteradd size")? as usize;
+                if size > MAX_FILTER_ADD_SIZE {
+                    return Err(MessageError::TooManyEntries { count: size, limit: MAX_FILTER_ADD_SIZE });
+                }
+                Message::FilterAdd(reader.bytes(size, "filteradd element")?.to_vec())
+            }
+            Command::FilterClear => Message::FilterClear,
+            Command::MerkleBlock => Message::MerkleBlock(MerkleBlockMessage::decode(&mut reader)?),
+            Command::Unknown(name) => Message::Unknown { command: name.clone(), payload: payload.to_vec() },
+        })
+    }
+
+    pub fn to_envelope(&self, magic: [u8; 4]) -> NetworkEnvelope {
+        NetworkEnvelope { magic, command: self.command(), payload: self.encode_payload() }
+    }
+}
+
+// A framed message: header fields plus the raw payload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkEnvelope {
+    pub magic: [u8; 4],
+    pub command: Command,
+    pub payload: Vec<u8>,
+}
+
+impl NetworkEnvelope {
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_SIZE + self.payload.len());
+        out.extend(self.magic);
+        out.extend(self.command.to_bytes());
+        out.extend((self.payload.len() as u32).to_le_bytes());
+        out.extend(checksum(&self.payload));
+        out.extend(&self.payload);
+        out
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), MessageError> {
+        writer.write_all(&self.serialize())?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    // Read exactly one message. Magic and length are checked as soon as the header is in,
+    // so garbage or an oversized length is rejected before any payload is waited for, and
+    // nothing is consumed until the whole message has arrived. A message failing its
+    // checksum is consumed all the same, leaving the stream aligned on the next header.
+    pub fn read_from<R: Read>(buffer: &mut RecvBuffer, reader: &mut R, magic: [u8; 4]) -> Result<Self, MessageError> {
+        let header: [u8; HEADER_SIZE] = buffer.fill(reader, HEADER_SIZE)?.try_into().unwrap();
+        let (command, length) = match Self::parse_header(&header, magic) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                // Drop the bad header up to the next magic, or the next read fails on it again
+                buffer.skip_to(magic);
+                return Err(e);
+            }
+        };
+        let expected: [u8; 4] = header[20..24].try_into().unwrap();
+
+        buffer.fill(reader, HEADER_SIZE + length)?;
+        let payload = buffer.consume(HEADER_SIZE + length)[HEADER_SIZE..].to_vec();
+        let found = checksum(&payload);
+        if found != expected {
+            return Err(MessageError::BadChecksum { expected, found });
+        }
+        Ok(NetworkEnvelope { magic, command, payload })
+    }
+
+    // Command and payload length from a message header
+    fn parse_header(header: &[u8; HEADER_SIZE], magic: [u8; 4]) -> Result<(Command, usize), MessageError> {
+        let found_magic: [u8; 4] = header[..4].try_into().unwrap();
+        if found_magic != magic {
+            return Err(MessageError::BadMagic(found_magic));
+        }
+        let command = Command::from_bytes(header[4..16].try_into().unwrap())?;
+        let length = u32::from_le_bytes(header[16..20].try_into().unwrap()) as usize;
+        if length > MAX_PAYLOAD_SIZE {
+            return Err(MessageError::PayloadTooLarge(length));
+        }
+        Ok((command, length))
+    }
+}
+
+// Bytes read from a peer that do not yet make up a whole message. A read may stop partway
+// through one, when receive_before's deadline passes or the peer sends it in pieces; what did
+// arrive waits here, so the next receive carries on from it instead of reading the rest of
+// the message as a new one.
+#[derive(Debug, Default)]
+pub struct RecvBuffer {
+    data: Vec<u8>, // data[start..end] is buffered, data[end..] is room to read into
+    start: usize,
+    end: usize,
+}
+
+impl RecvBuffer {
+    // The first n buffered bytes, reading until that many have arrived. Reads go straight
+    // into the room after the buffered bytes, so the buffer is only zeroed when it grows.
+    pub fn fill<R: Read>(&mut self, reader: &mut R, n: usize) -> Result<&[u8], MessageError> {
+        while self.end - self.start < n {
+            self.make_room(n);
+            match reader.read(&mut self.data[self.end..]) {
+                Ok(0) => return Err(io::Error::from(ErrorKind::UnexpectedEof).into()),
+                Ok(read) => self.end += read,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(&self.data[self.start..self.start + n])
+    }
+
+    // Once less than a chunk of room is left, move the buffered bytes to the front and grow
+    // the buffer if n bytes and a chunk still do not fit
+    fn make_room(&mut self, n: usize) {
+        if self.data.len() - self.end >= READ_CHUNK_SIZE {
+            return;
+        }
+        self.data.copy_within(self.start..self.end, 0);
+        self.end -= self.start;
+        self.start = 0;
+        if self.data.len() < n + READ_CHUNK_SIZE {
+            self.data.resize(n + READ_CHUNK_SIZE, 0);
+        }
+    }
+
+    // Take the first n bytes, once a whole message is in. They stay valid until the next fill.
+    pub fn consume(&mut self, n: usize) -> &[u8] {
+        let start = self.start;
+        self.start += n;
+        if self.start == self.end {
+            (self.start, self.end) = (0, 0);
+        }
+        &self.data[start..start + n]
+    }
+
+    // Drop buffered bytes up to the next occurrence of magic after the first byte. Up to three
+    // trailing bytes are kept when it is not found, since they may begin it.
+    pub fn skip_to(&mut self, magic: [u8; 4]) {
+        let rest = &self.data[self.start + 1..self.end];
+        let skip = rest.windows(4).position(|window| window == magic).unwrap_or(rest.len().saturating_sub(3));
+        self.consume(skip + 1);
+    }
+}
+
+// First four bytes of the double SHA-256 of the payload
+pub fn checksum(payload: &[u8]) -> [u8; 4] {
+    sha256d(payload)[..4].try_into().unwrap()
+}
+
+// Double SHA-256 implementation for checksum
+pub fn sha256d(data: &[u8]) -> [u8; 32] {
+    use sha2::{Digest, Sha256};
+    let first = Sha256::digest(data);
+    let second = Sha256::digest(first);
+    second.into()
+}
+
+pub fn write_compact_size(out: &mut Vec<u8>, n: u64) {
+    match n {
+        0..=0xfc => out.push(n as u8),
+        0xfd..=0xffff => {
+            out.push(0xfd);
+            out.extend((n as u16).to_le_bytes());
+        }
+        0x10000..=0xffff_ffff => {
+            out.push(0xfe);
+            out.extend((n as u32).to_le_bytes());
+        }
+        _ => {
+            out.push(0xff);
+            out.extend(n.to_le_bytes());
+        }
+    }
+}
+
+// Cursor over a payload that reports which field ran out of bytes
+pub struct PayloadReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> PayloadReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        PayloadReader { data }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn bytes(&mut self, n: usize, field: &'static str) -> Result<&'a [u8], MessageError> {
+        if self.data.len() < n {
+            return Err(MessageError::Truncated(field));
+        }
+        let (head, tail) = self.data.split_at(n);
+        self.data = tail;
+        Ok(head)
+    }
+
+    pub fn array<const N: usize>(&mut self, field: &'static str) -> Result<[u8; N], MessageError> {
+        Ok(self.bytes(N, field)?.try_into().unwrap())
+    }
+
+    pub fn u8(&mut self, field: &'static str) -> Result<u8, MessageError> {
+        Ok(self.array::<1>(field)?[0])
+    }
+
+    pub fn u16(&mut self, field: &'static str) -> Result<u16, MessageError> {
+        Ok(u16::from_le_bytes(self.array(field)?))
+    }
+
+    pub fn u32(&mut self, field: &'static str) -> Result<u32, MessageError> {
+        Ok(u32::from_le_bytes(self.array(field)?))
+    }
+
+    pub fn i32(&mut self, field: &'static str) -> Result<i32, MessageError> {
+        Ok(i32::from_le_bytes(self.array(field)?))
+    }
+
+    pub fn u64(&mut self, field: &'static str) -> Result<u64, MessageError> {
+        Ok(u64::from_le_bytes(self.array(field)?))
+    }
+
+    pub fn i64(&mut self, field: &'static str) -> Result<i64, MessageError> {
+        Ok(i64::from_le_bytes(self.array(field)?))
+    }
+
+    pub fn compact_size(&mut self, field: &'static str) -> Result<u64, MessageError> {
+        Ok(match self.u8(field)? {
+            0xfd => self.u16(field)? as u64,
+            0xfe => self.u32(field)? as u64,
+            0xff => self.u64(field)?,
+            n => n as u64,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::Network;
+
+    const MAGIC: [u8; 4] = [0xF9, 0xBE, 0xB4, 0xD9];
+
+    fn read(bytes: &[u8]) -> Result<NetworkEnvelope, MessageError> {
+        NetworkEnvelope::read_from(&mut RecvBuffer::default(), &mut &bytes[..], MAGIC)
+    }
+
+    fn entry(addr: PeerAddr) -> AddrEntry {
+        AddrEntry { timestamp: 1_700_000_000, services: 0x409, addr }
+    }
+
+    // One of every message, with fields that differ from their defaults
+    fn messages() -> Vec<Message> {
+        let genesis = Network::Mainnet.genesis_header().unwrap();
+        let inventory = vec![
+            Inventory { kind: InvType::WitnessTx, hash: [1; 32] },
+            Inventory { kind: InvType::Block, hash: [2; 32] },
+            Inventory { kind: InvType::Unknown(7), hash: [3; 32] },
+        ];
+        vec![
+            Message::Version(VersionMessage {
+                version: 70016,
+                services: 0x409,
+                timestamp: 1_700_000_000,
+                receiver: NetAddr::new("192.0.2.1:8333".parse().unwrap(), 1),
+                sender: NetAddr::new("[2001:db8::1]:18333".parse().unwrap(), 0x409),
+                nonce: 0x0123_4567_89AB_CDEF,
+                user_agent: "/Satoshi:27.0.0/".to_string(),
+                start_height: 850_000,
+                relay: false,
+            }),
+            Message::Verack,
+            Message::Ping(1),
+            Message::Pong(u64::MAX),
+            Message::GetAddr,
+            Message::Addr(vec![entry("192.0.2.1:8333".parse::<SocketAddr>().unwrap().into()), entry("[2001:db8::2]:8333".parse::<SocketAddr>().unwrap().into())]),
+            Message::AddrV2(vec![
+                entry("192.0.2.1:8333".parse::<SocketAddr>().unwrap().into()),
+                entry(PeerAddr::from_bip155(4, &[4; 32], 8333).unwrap()),
+                entry(PeerAddr::from_bip155(5, &[5; 32], 0).unwrap()),
+                entry(PeerAddr::Cjdns { ip: "fc00::1".parse().unwrap(), port: 8333 }),
+            ]),
+            Message::SendAddrV2,
+            Message::GetHeaders(GetHeadersMessage {
+                version: 70016,
+                locator: vec![genesis.block_hash(), BlockHash::from_byte_array([9; 32])],
+                stop_hash: BlockHash::all_zeros(),
+            }),
+            Message::Headers(vec![genesis, genesis]),
+            Message::Inv(inventory.clone()),
+            Message::GetData(inventory.clone()),
+            Message::NotFound(inventory),
+            Message::Tx(vec![1, 0, 0, 0, 0]),
+            Message::Block(serialize(&genesis)),
+            Message::MemPool,
+            Message::FeeFilter(1000),
+            Message::SendCmpct(SendCmpctMessage { announce: true, version: 2 }),
+            Message::WtxidRelay,
+            Message::SendHeaders,
+            Message::FilterLoad(BloomFilter { data: vec![0xAA; 37], hash_funcs: 11, tweak: 0xDEAD_BEEF, flags: 1 }),
+            Message::FilterAdd(vec![0x55; 20]),
+            Message::FilterClear,
+            Message::MerkleBlock(MerkleBlockMessage {
+                header: genesis,
+                total_transactions: 3,
+                hashes: vec![TxMerkleNode::from_byte_array([6; 32]), TxMerkleNode::from_byte_array([7; 32])],
+                flags: vec![0x1D],
+            }),
+            Message::Unknown { command: "sendtxrcncl".to_string(), payload: vec![1, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0] },
+        ]
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        for message in messages() {
+            let bytes = message.to_envelope(MAGIC).serialize();
+            let envelope = read(&bytes).unwrap();
+            assert_eq!(envelope.command, message.command());
+            assert_eq!(Message::decode(&envelope.command, &envelope.payload).unwrap(), message, "{}", message.command().name());
+        }
+    }
+
+    #[test]
+    fn version_without_relay_flag_relays() {
+        let Message::Version(mut version) = messages().remove(0) else { unreachable!() };
+        let mut payload = Message::Version(version.clone()).encode_payload();
+        payload.pop();
+        version.relay = true;
+        assert_eq!(Message::decode(&Command::Version, &payload).unwrap(), Message::Version(version));
+    }
+
+    // Hands out one byte per read, timing out before each as a socket with a short read
+    // timeout does when the peer sends slowly
+    struct Trickle<'a> {
+        bytes: &'a [u8],
+        timed_out: bool,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.timed_out = !self.timed_out;
+            if self.timed_out {
+                return Err(ErrorKind::TimedOut.into());
+            }
+            let Some((&byte, rest)) = self.bytes.split_first() else { return Ok(0) };
+            buf[0] = byte;
+            self.bytes = rest;
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn reads_messages_arriving_a_byte_at_a_time() {
+        let sent = [messages().remove(0), Message::Ping(7)];
+        let bytes: Vec<u8> = sent.iter().flat_map(|message| message.to_envelope(MAGIC).serialize()).collect();
+        let (mut buffer, mut reader) = (RecvBuffer::default(), Trickle { bytes: &bytes, timed_out: false });
+        let mut timeouts = 0;
+        for message in sent {
+            let envelope = loop {
+                match NetworkEnvelope::read_from(&mut buffer, &mut reader, MAGIC) {
+                    Ok(envelope) => break envelope,
+                    Err(MessageError::Io(e)) if e.kind() == ErrorKind::TimedOut => timeouts += 1,
+                    Err(e) => panic!("{}", e),
+                }
+            };
+            assert_eq!(Message::decode(&envelope.command, &envelope.payload).unwrap(), message);
+        }
+        // Every byte took a timeout, and none was lost to one
+        assert_eq!(timeouts, bytes.len());
+        assert_eq!(buffer.start, buffer.end);
+    }
+
+    #[test]
+    fn rejects_bad_checksum() {
+        let mut bytes = Message::Ping(42).to_envelope(MAGIC).serialize();
+        bytes[HEADER_SIZE] ^= 1;
+        bytes.extend(Message::Verack.to_envelope(MAGIC).serialize());
+        let (mut buffer, mut reader) = (RecvBuffer::default(), &bytes[..]);
+        assert!(matches!(NetworkEnvelope::read_from(&mut buffer, &mut reader, MAGIC), Err(MessageError::BadChecksum { .. })));
+        // The bad message is consumed, so the next one still reads
+        assert_eq!(NetworkEnvelope::read_from(&mut buffer, &mut reader, MAGIC).unwrap().command, Command::Verack);
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let bytes = Message::Verack.to_envelope([0x0A, 0x03, 0xCF, 0x40]).serialize();
+        assert!(matches!(read(&bytes), Err(MessageError::BadMagic([0x0A, 0x03, 0xCF, 0x40]))));
+    }
+
+    // Reads a message after one that fails, which must have been skipped
+    fn read_after_error(mut bytes: Vec<u8>, error: impl Fn(&MessageError) -> bool) -> Command {
+        bytes.extend(Message::Ping(7).to_envelope(MAGIC).serialize());
+        let (mut buffer, mut reader) = (RecvBuffer::default(), &bytes[..]);
+        let first = NetworkEnvelope::read_from(&mut buffer, &mut reader, MAGIC).unwrap_err();
+        assert!(error(&first), "{}", first);
+        NetworkEnvelope::read_from(&mut buffer, &mut reader, MAGIC).unwrap().command
+    }
+
+    #[test]
+    fn skips_to_the_next_magic_after_a_bad_header() {
+        let garbage = vec![0xF9; 30];
+        assert_eq!(read_after_error(garbage, |e| matches!(e, MessageError::BadMagic(_))), Command::Ping);
+
+        let mut bad_command = Message::Verack.to_envelope(MAGIC).serialize();
+        bad_command[4] = 0xFF;
+        assert_eq!(read_after_error(bad_command, |e| matches!(e, MessageError::InvalidCommand(_))), Command::Ping);
+
+        let mut oversize = Message::Tx(vec![0; 40]).to_envelope(MAGIC).serialize();
+        oversize[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(read_after_error(oversize, |e| matches!(e, MessageError::PayloadTooLarge(_))), Command::Ping);
+    }
+
+    // Hands out at most `chunk` bytes per read
+    struct Chunked<'a> {
+        bytes: &'a [u8],
+        chunk: usize,
+    }
+
+    impl Read for Chunked<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.bytes.len().min(self.chunk).min(buf.len());
+            buf[..n].copy_from_slice(&self.bytes[..n]);
+            self.bytes = &self.bytes[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn reads_messages_larger_than_a_chunk() {
+        let sent = [Message::Ping(1), Message::Tx(vec![0x5A; 3 * READ_CHUNK_SIZE + 5]), Message::Block(vec![0xA5; READ_CHUNK_SIZE / 2]), Message::Pong(2)];
+        let bytes: Vec<u8> = sent.iter().flat_map(|message| message.to_envelope(MAGIC).serialize()).collect();
+        let (mut buffer, mut reader) = (RecvBuffer::default(), Chunked { bytes: &bytes, chunk: 10_000 });
+        for message in sent {
+            let envelope = NetworkEnvelope::read_from(&mut buffer, &mut reader, MAGIC).unwrap();
+            assert_eq!(Message::decode(&envelope.command, &envelope.payload).unwrap(), message);
+        }
+        assert_eq!(buffer.start, buffer.end);
+        assert!(buffer.data.len() <= 4 * READ_CHUNK_SIZE + 5 + HEADER_SIZE);
+    }
+
+    #[test]
+    fn rejects_oversize_payload_before_reading_it() {
+        let mut bytes = Message::Block(Vec::new()).to_envelope(MAGIC).serialize();
+        bytes[16..20].copy_from_slice(&(MAX_PAYLOAD_SIZE as u32 + 1).to_le_bytes());
+        // Only the header is there: the length alone must reject it
+        assert!(matches!(read(&bytes[..HEADER_SIZE]), Err(MessageError::PayloadTooLarge(size)) if size == MAX_PAYLOAD_SIZE + 1));
+    }
+
+    #[test]
+    fn rejects_truncated_var_ints() {
+        for (command, payload) in [(Command::Inv, &[0xFD, 0x01][..]), (Command::Addr, &[0xFE, 0, 0][..]), (Command::FilterAdd, &[0xFF][..])] {
+            assert!(matches!(Message::decode(&command, payload), Err(MessageError::Truncated(_))), "{}", command.name());
+        }
+        // A count that decodes but promises more entries than follow
+        assert!(matches!(Message::decode(&Command::Headers, &[0x02]), Err(MessageError::Truncated("header"))));
+        assert!(matches!(Message::decode(&Command::Inv, &[0xFE, 0x51, 0xC3, 0, 0]), Err(MessageError::TooManyEntries { count: 50_001, .. })));
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::address::PeerAddr;
+use crate::message::MessageError;
+
+// Protocol violations, scored in the spirit of Bitcoin Core's discouragement: a peer whose
+// score reaches the threshold is banned for a while and skipped by the crawler
+
+// Score at which a peer is banned
+pub const DISCOURAGEMENT_THRESHOLD: u32 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Misbehavior {
+    BadChecksum,
+    OversizedMessage, // payload beyond the protocol's size limit
+    OversizedList,    // more addr, inv, headers or locator entries than allowed
+    Malformed,        // truncated payload, invalid command bytes or address encoding
+    UnsolicitedData,  // tx, block, merkleblock or headers we never asked for
+}
+
+impl Misbehavior {
+    // The violation behind a receive error, if it was the peer's fault. Timeouts,
+    // disconnects and a foreign network magic are not misbehavior.
+    pub fn from_error(error: &MessageError) -> Option<Self> {
+        match error {
+            MessageError::BadChecksum { .. } => Some(Misbehavior::BadChecksum),
+            MessageError::PayloadTooLarge(_) => Some(Misbehavior::OversizedMessage),
+            MessageError::TooManyEntries { .. } => Some(Misbehavior::OversizedList),
+            MessageError::Truncated(_) | MessageError::InvalidCommand(_) | MessageError::BadAddressLength { .. } | MessageError::UnknownShortId(_) => {
+                Some(Misbehavior::Malformed)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn score(self) -> u32 {
+        match self {
+            Misbehavior::BadChecksum => 50,
+            Misbehavior::OversizedMessage => 100,
+            Misbehavior::OversizedList => 20, // what Core gives an oversized addr
+            Misbehavior::Malformed => 50,
+            Misbehavior::UnsolicitedData => 10,
+        }
+    }
+}
+
+impl fmt::Display for Misbehavior {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Misbehavior::BadChecksum => "bad checksum",
+            Misbehavior::OversizedMessage => "oversized message",
+            Misbehavior::OversizedList => "oversized list",
+            Misbehavior::Malformed => "malformed message",
+            Misbehavior::UnsolicitedData => "unsolicited data",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Discouragement state of one address
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BanEntry {
+    pub addr: PeerAddr,
+    pub score: u32,                             // accumulated since the last ban
+    pub banned_until: Option<u64>,              // unix time the current or last ban ends
+    pub violations: BTreeMap<Misbehavior, u32>, // every violation seen, by kind
+    pub last_violation: u64,
+}
+
+// Ban list persisted as a JSON array
+#[derive(Debug, Default)]
+pub struct BanList {
+    entries: BTreeMap<PeerAddr, BanEntry>,
+}
+
+impl BanList {
+    // Load a ban list, starting empty when the file does not exist yet
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let json = match fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(BanList::default()),
+            Err(e) => return Err(e.into()),
+        };
+        let entries: Vec<BanEntry> = serde_json::from_str(&json)?;
+        Ok(BanList { entries: entries.into_iter().map(|entry| (entry.addr.clone(), entry)).collect() })
+    }
+
+    // Write through a temporary file so an interrupted save leaves the old list intact
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let entries: Vec<&BanEntry> = self.entries.values().collect();
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(&entries)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn is_banned(&self, addr: &PeerAddr, now: u64) -> bool {
+        self.entries.get(addr).and_then(|entry| entry.banned_until).is_some_and(|until| until > now)
+    }
+
+    pub fn banned(&self, now: u64) -> usize {
+        self.entries.keys().filter(|addr| self.is_banned(addr, now)).count()
+    }
+
+    // Add violations to a peer's score, banning it for ban_time once the score reaches the
+    // threshold; returns whether this started a ban
+    pub fn misbehaving(&mut self, addr: &PeerAddr, violations: &[Misbehavior], now: u64, ban_time: Duration) -> bool {
+        if violations.is_empty() {
+            return false;
+        }
+        let entry = self.entries.entry(addr.clone()).or_insert_with(|| BanEntry {
+            addr: addr.clone(),
+            score: 0,
+            banned_until: None,
+            violations: BTreeMap::new(),
+            last_violation: now,
+        });
+        for violation in violations {
+            entry.score += violation.score();
+            *entry.violations.entry(*violation).or_default() += 1;
+        }
+        entry.last_violation = now;
+        if entry.score < DISCOURAGEMENT_THRESHOLD {
+            return false;
+        }
+        entry.score = 0;
+        entry.banned_until = Some(now + ban_time.as_secs());
+        true
+    }
+}
@@ -0,0 +1,119 @@
+use std::io;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+
+use crate::address::PeerAddr;
+
+use bitcoin::block::Header;
+use bitcoin::blockdata::constants::genesis_block;
+use block_breaker::pow::ConsensusParams;
+use clap::ValueEnum;
+
+// Bitcoin networks the seeder can crawl
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Network {
+    Mainnet,
+    Testnet3,
+    Testnet4,
+    Signet,
+    Regtest,
+}
+
+impl Network {
+    // Message start bytes, as in Bitcoin Core's chainparams
+    pub fn magic(self) -> [u8; 4] {
+        match self {
+            Network::Mainnet => [0xF9, 0xBE, 0xB4, 0xD9],
+            Network::Testnet3 => [0x0B, 0x11, 0x09, 0x07],
+            Network::Testnet4 => [0x1C, 0x16, 0x3F, 0x28],
+            Network::Signet => [0x0A, 0x03, 0xCF, 0x40], // default signet challenge
+            Network::Regtest => [0xFA, 0xBF, 0xB5, 0xDA],
+        }
+    }
+
+    pub fn default_port(self) -> u16 {
+        match self {
+            Network::Mainnet => 8333,
+            Network::Testnet3 => 18333,
+            Network::Testnet4 => 48333,
+            Network::Signet => 38333,
+            Network::Regtest => 18444,
+        }
+    }
+
+    // Genesis header, where this network's header chain starts. The bitcoin crate predates
+    // testnet4, so it has none.
+    pub fn genesis_header(self) -> Option<Header> {
+        let network = match self {
+            Network::Mainnet => bitcoin::Network::Bitcoin,
+            Network::Testnet3 => bitcoin::Network::Testnet,
+            Network::Testnet4 => return None,
+            Network::Signet => bitcoin::Network::Signet,
+            Network::Regtest => bitcoin::Network::Regtest,
+        };
+        Some(genesis_block(network).header)
+    }
+
+    // Difficulty rules the network's headers are validated with
+    pub fn consensus_params(self) -> ConsensusParams {
+        match self {
+            Network::Mainnet => ConsensusParams::MAINNET,
+            Network::Testnet3 => ConsensusParams::TESTNET3,
+            Network::Testnet4 => ConsensusParams::TESTNET4,
+            Network::Signet => ConsensusParams::SIGNET,
+            Network::Regtest => ConsensusParams::REGTEST,
+        }
+    }
+
+    // Network whose address format this one uses; testnet4 shares testnet3's
+    pub fn address_network(self) -> bitcoin::Network {
+        match self {
+            Network::Mainnet => bitcoin::Network::Bitcoin,
+            Network::Testnet3 | Network::Testnet4 => bitcoin::Network::Testnet,
+            Network::Signet => bitcoin::Network::Signet,
+            Network::Regtest => bitcoin::Network::Regtest,
+        }
+    }
+
+    // DNS seeds used when no seed peers are given
+    pub fn dns_seeds(self) -> &'static [&'static str] {
+        match self {
+            Network::Mainnet => &[
+                "seed.bitcoin.sipa.be",
+                "dnsseed.bluematt.me",
+                "seed.bitcoin.jonasschnelli.ch",
+                "seed.btc.petertodd.net",
+                "seed.bitcoin.sprovoost.nl",
+                "dnsseed.emzy.de",
+                "seed.bitcoin.wiz.biz",
+            ],
+            Network::Testnet3 => &[
+                "testnet-seed.bitcoin.jonasschnelli.ch",
+                "seed.tbtc.petertodd.net",
+                "seed.testnet.bitcoin.sprovoost.nl",
+                "testnet-seed.bluematt.me",
+            ],
+            Network::Testnet4 => &["seed.testnet4.bitcoin.sprovoost.nl", "seed.testnet4.wiz.biz"],
+            Network::Signet => &["seed.signet.bitcoin.sprovoost.nl"],
+            Network::Regtest => &[],
+        }
+    }
+
+    // Resolve "host", "ip" or either with an explicit ":port", filling in the default port.
+    // Onion and I2P names are not resolved here; they are what the peer address holds.
+    pub fn resolve(self, seed: &str) -> io::Result<Vec<PeerAddr>> {
+        if let Some(name) = PeerAddr::parse_name(seed, self.default_port()) {
+            return Ok(vec![name]);
+        }
+        if let Ok(addr) = seed.parse::<SocketAddr>() {
+            return Ok(vec![addr.into()]);
+        }
+        if let Ok(ip) = seed.parse::<IpAddr>() {
+            return Ok(vec![SocketAddr::new(ip, self.default_port()).into()]);
+        }
+        let addrs: Vec<SocketAddr> = match seed.rsplit_once(':') {
+            Some((host, port)) if port.parse::<u16>().is_ok() => (host, port.parse::<u16>().unwrap()).to_socket_addrs()?.collect(),
+            _ => (seed, self.default_port()).to_socket_addrs()?.collect(),
+        };
+        Ok(addrs.into_iter().map(PeerAddr::from).collect())
+    }
+}
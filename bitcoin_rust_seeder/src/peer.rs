@@ -0,0 +1,546 @@
+use std::fmt;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::address::PeerAddr;
+use crate::bip324::V2Transport;
+use crate::capture::{Capture, Direction};
+use crate::message::{AddrEntry, Message, MessageError, NetAddr, NetworkEnvelope, RecvBuffer, SendCmpctMessage, VersionMessage};
+use crate::misbehavior::Misbehavior;
+use crate::network::Network;
+use crate::shutdown;
+use crate::socks;
+
+// Protocol version we speak: the latest, which adds wtxidrelay (BIP339)
+pub const PROTOCOL_VERSION: i32 = 70016;
+
+// First protocol version that may negotiate wtxidrelay
+const WTXID_RELAY_VERSION: i32 = 70016;
+
+// Nonce sent in every version message of this process, inbound and outbound alike, so a
+// version carrying it back means we reached ourselves
+pub fn session_nonce() -> u64 {
+    static NONCE: OnceLock<u64> = OnceLock::new();
+    *NONCE.get_or_init(rand::random)
+}
+
+// What we announce in our version message; timestamp and addresses are filled in per peer
+#[derive(Debug, Clone)]
+pub struct VersionConfig {
+    pub version: i32,
+    pub services: u64,
+    pub user_agent: String,
+    pub start_height: i32,
+    pub relay: bool,
+    pub advertise: Option<SocketAddr>, // reachable address given as ours; unspecified when None
+}
+
+impl Default for VersionConfig {
+    fn default() -> Self {
+        VersionConfig {
+            version: PROTOCOL_VERSION,
+            services: 1,            // NODE_NETWORK
+            user_agent: String::new(),
+            start_height: 0,
+            relay: true,
+            advertise: None,
+        }
+    }
+}
+
+impl VersionConfig {
+    pub fn build(&self, peer: &PeerAddr) -> VersionMessage {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        VersionMessage {
+            version: self.version,
+            services: self.services,
+            timestamp,
+            receiver: match peer {
+                PeerAddr::Ip(addr) => NetAddr::new(*addr, 1),
+                PeerAddr::Cjdns { ip, port } => NetAddr::new(SocketAddr::new((*ip).into(), *port), 1),
+                PeerAddr::Onion { .. } | PeerAddr::I2p { .. } => NetAddr::unspecified(),
+            },
+            sender: self.advertise.map_or_else(NetAddr::unspecified, |addr| NetAddr::new(addr, self.services)),
+            nonce: session_nonce(),
+            user_agent: self.user_agent.clone(),
+            start_height: self.start_height,
+            relay: self.relay,
+        }
+    }
+}
+
+// How to reach and greet a peer
+#[derive(Debug, Clone)]
+pub struct ConnectConfig {
+    pub network: Network,
+    pub connect_timeout: Duration,
+    pub io_timeout: Duration,         // limit on any single read or write
+    pub handshake_timeout: Duration,  // limit on the whole version exchange, and on the v2 one
+    pub v2_transport: bool,           // try BIP324 first, falling back to v1
+    pub proxy: Option<SocketAddr>,    // SOCKS5 proxy for every connection, required for onion peers
+    pub capture_dir: Option<PathBuf>, // record every connection's messages to a file here
+    pub version: VersionConfig,
+}
+
+impl ConnectConfig {
+    // Whether connect can reach the address at all: onion services need the proxy, CJDNS is
+    // dialled directly over its tunnel, and I2P would need a SAM router, which we lack
+    pub fn reaches(&self, addr: &PeerAddr) -> bool {
+        match addr {
+            PeerAddr::Ip(_) | PeerAddr::Cjdns { .. } => true,
+            PeerAddr::Onion { .. } => self.proxy.is_some(),
+            PeerAddr::I2p { .. } => false,
+        }
+    }
+}
+
+impl Default for ConnectConfig {
+    fn default() -> Self {
+        ConnectConfig {
+            network: Network::Mainnet,
+            connect_timeout: Duration::from_secs(5),
+            io_timeout: Duration::from_secs(10),
+            handshake_timeout: Duration::from_secs(20),
+            v2_transport: false,
+            proxy: None,
+            capture_dir: None,
+            version: VersionConfig::default(),
+        }
+    }
+}
+
+// A connection to a single peer, over the v1 protocol or the BIP324 encrypted transport
+pub struct Peer {
+    pub addr: PeerAddr,
+    pub magic: [u8; 4],
+    stream: TcpStream,
+    transport: Option<V2Transport>,               // None for plaintext v1
+    buffer: RecvBuffer,                           // v1 bytes received ahead of a whole message
+    pub version: Option<VersionMessage>,          // the peer's version, once received
+    pub fee_filter: Option<u64>,                  // latest feefilter, in sat/kvB
+    pub compact_blocks: Option<SendCmpctMessage>, // latest sendcmpct
+    pub features: Features,
+    pub misbehavior: Vec<Misbehavior>,            // violations seen in messages that still decoded
+    pub capture: Option<Capture>,                 // where messages are recorded, if anywhere
+    data_requested: bool,                         // whether we sent getdata
+    headers_requested: bool,                      // whether we sent getheaders
+    headers_announced: bool,                      // whether we sent sendheaders
+    _shutdown: Option<shutdown::Registration>,    // closes the stream on shutdown
+}
+
+// Optional protocol features the peer signalled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Features {
+    pub wtxid_relay: bool,  // BIP339 wtxidrelay, before verack
+    pub addr_v2: bool,      // BIP155 sendaddrv2, before verack
+    pub send_headers: bool, // BIP130 sendheaders
+}
+
+// Round-trip times over a series of pings that were answered
+#[derive(Debug, Clone, Copy)]
+pub struct PingStats {
+    pub samples: u32,
+    pub min: Duration,
+    pub avg: Duration,
+    pub max: Duration,
+}
+
+impl Peer {
+    // Connect, directly or through the configured proxy, with a bounded connect time; reads
+    // and writes then fail after io_timeout so an unresponsive peer cannot stall the caller
+    pub fn connect(addr: &PeerAddr, config: &ConnectConfig) -> Result<Self, MessageError> {
+        let stream = match (addr, config.proxy) {
+            (PeerAddr::Cjdns { ip, port }, _) => TcpStream::connect_timeout(&SocketAddr::new((*ip).into(), *port), config.connect_timeout)?,
+            (PeerAddr::I2p { .. }, _) => return Err(io::Error::new(ErrorKind::Unsupported, "I2P peers need an I2P router").into()),
+            (_, Some(proxy)) => socks::connect(proxy, addr, config.connect_timeout)?,
+            (PeerAddr::Ip(ip), None) => TcpStream::connect_timeout(ip, config.connect_timeout)?,
+            (PeerAddr::Onion { .. }, None) => return Err(io::Error::new(ErrorKind::Unsupported, "onion peers need a proxy").into()),
+        };
+        stream.set_read_timeout(Some(config.io_timeout))?;
+        stream.set_write_timeout(Some(config.io_timeout))?;
+        let registration = shutdown::register(&stream);
+        Ok(Peer {
+            addr: addr.clone(),
+            magic: config.network.magic(),
+            stream,
+            transport: None,
+            buffer: RecvBuffer::default(),
+            version: None,
+            fee_filter: None,
+            compact_blocks: None,
+            features: Features::default(),
+            misbehavior: Vec::new(),
+            capture: config.capture_dir.as_deref().map(|dir| Capture::create(dir, addr)).transpose()?,
+            data_requested: false,
+            headers_requested: false,
+            headers_announced: false,
+            _shutdown: registration,
+        })
+    }
+
+    // Wrap a connection a peer opened to us. Inbound peers speak v1; we do not answer the
+    // v2 handshake.
+    pub fn accept(stream: TcpStream, config: &ConnectConfig) -> Result<Self, MessageError> {
+        stream.set_read_timeout(Some(config.io_timeout))?;
+        stream.set_write_timeout(Some(config.io_timeout))?;
+        let addr = PeerAddr::Ip(stream.peer_addr()?);
+        let capture = config.capture_dir.as_deref().map(|dir| Capture::create(dir, &addr)).transpose()?;
+        let registration = shutdown::register(&stream);
+        Ok(Peer {
+            addr,
+            magic: config.network.magic(),
+            stream,
+            transport: None,
+            buffer: RecvBuffer::default(),
+            version: None,
+            fee_filter: None,
+            compact_blocks: None,
+            features: Features::default(),
+            misbehavior: Vec::new(),
+            capture,
+            data_requested: false,
+            headers_requested: false,
+            headers_announced: false,
+            _shutdown: registration,
+        })
+    }
+
+    // Connect using the v2 encrypted transport, reconnecting over v1 only if the peer hung up
+    // before sending its key, as a v1-only peer does. The whole v2 handshake must finish
+    // within handshake_timeout; any other failure is returned.
+    pub fn connect_v2(addr: &PeerAddr, config: &ConnectConfig) -> Result<Self, MessageError> {
+        let mut peer = Peer::connect(addr, config)?;
+        let deadline = Instant::now() + config.handshake_timeout;
+        let result = V2Transport::initiate(&mut DeadlineStream { stream: &peer.stream, deadline, io_timeout: config.io_timeout }, peer.magic);
+        match result {
+            Ok(transport) => {
+                peer.stream.set_read_timeout(Some(config.io_timeout))?;
+                peer.stream.set_write_timeout(Some(config.io_timeout))?;
+                peer.transport = Some(transport);
+                Ok(peer)
+            }
+            Err(MessageError::V2Rejected) => Peer::connect(addr, config),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Connect as configured and complete the version handshake
+    pub fn open(addr: &PeerAddr, config: &ConnectConfig) -> Result<Self, MessageError> {
+        let mut peer = if config.v2_transport { Peer::connect_v2(addr, config)? } else { Peer::connect(addr, config)? };
+        peer.handshake(config.version.build(addr), config.handshake_timeout)?;
+        Ok(peer)
+    }
+
+    pub fn is_v2(&self) -> bool {
+        self.transport.is_some()
+    }
+
+    pub fn send(&mut self, message: &Message) -> Result<(), MessageError> {
+        match message {
+            Message::GetData(_) => self.data_requested = true,
+            Message::GetHeaders(_) => self.headers_requested = true,
+            Message::SendHeaders => self.headers_announced = true,
+            _ => {}
+        }
+        match &mut self.transport {
+            Some(transport) => transport.send(&mut self.stream, message)?,
+            None => message.to_envelope(self.magic).write_to(&mut self.stream)?,
+        }
+        if let Some(capture) = &mut self.capture {
+            capture.record(Direction::Sent, &message.command(), &message.encode_payload())?;
+        }
+        Ok(())
+    }
+
+    // Write bytes to a v1 connection as they are, bypassing the codec and any capture, to
+    // see how the peer copes with messages we could not otherwise encode
+    pub fn send_raw(&mut self, bytes: &[u8]) -> Result<(), MessageError> {
+        if self.transport.is_some() {
+            return Err(io::Error::new(ErrorKind::Unsupported, "raw messages need a v1 connection").into());
+        }
+        self.stream.write_all(bytes)?;
+        self.stream.flush()?;
+        Ok(())
+    }
+
+    // Read the next message, answering pings on the way so the connection stays alive and
+    // recording the peer's relay preferences and any data it pushes unasked. Every message is
+    // still returned to the caller.
+    pub fn receive(&mut self) -> Result<Message, MessageError> {
+        let (command, payload) = match &mut self.transport {
+            Some(transport) => transport.receive(&mut self.stream)?,
+            None => {
+                let envelope = NetworkEnvelope::read_from(&mut self.buffer, &mut self.stream, self.magic)?;
+                (envelope.command, envelope.payload)
+            }
+        };
+        if let Some(capture) = &mut self.capture {
+            capture.record(Direction::Received, &command, &payload)?;
+        }
+        let message = Message::decode(&command, &payload)?;
+        match &message {
+            Message::Ping(nonce) => self.send(&Message::Pong(*nonce))?,
+            Message::FeeFilter(feerate) => self.fee_filter = Some(*feerate),
+            Message::SendCmpct(sendcmpct) => {
+                // Answer the first offer in low-bandwidth mode: we never want blocks pushed
+                if self.compact_blocks.is_none() {
+                    self.send(&Message::SendCmpct(SendCmpctMessage { announce: false, version: sendcmpct.version }))?;
+                }
+                self.compact_blocks = Some(*sendcmpct);
+            }
+            Message::WtxidRelay => self.features.wtxid_relay = true,
+            Message::SendAddrV2 => self.features.addr_v2 = true,
+            Message::SendHeaders => self.features.send_headers = true,
+            Message::Tx(_) | Message::Block(_) | Message::MerkleBlock(_) if !self.data_requested => {
+                self.misbehavior.push(Misbehavior::UnsolicitedData)
+            }
+            Message::Headers(_) if !self.headers_requested && !self.headers_announced => self.misbehavior.push(Misbehavior::UnsolicitedData),
+            _ => {}
+        }
+        Ok(message)
+    }
+
+    // Exchange version and verack in either order: send our version, acknowledge the peer's
+    // version with a verack, and finish once the peer has acknowledged ours too, all within
+    // the timeout. Features are offered between the versions and our verack; sendheaders is
+    // only recorded, since asking for header announcements would hide the block invs that
+    // fetch waits for.
+    pub fn handshake(&mut self, version: VersionMessage, timeout: Duration) -> Result<VersionMessage, MessageError> {
+        let deadline = Instant::now() + timeout;
+        let our_version = version.version;
+        self.send(&Message::Version(version))?;
+        self.finish_handshake(our_version, deadline)
+    }
+
+    // The responder's side of the handshake: wait for the peer's version, ignoring anything
+    // sent before it as Bitcoin Core does, then answer with ours and our verack
+    pub fn respond_handshake(&mut self, config: &VersionConfig, timeout: Duration) -> Result<VersionMessage, MessageError> {
+        let deadline = Instant::now() + timeout;
+        let version = loop {
+            let message = self.receive_before(deadline)?;
+            if let Message::Version(version) = message.ok_or_else(|| io::Error::new(ErrorKind::TimedOut, "handshake timed out"))? {
+                break version;
+            }
+        };
+        // As Bitcoin Core does, drop a connection from ourselves before answering it
+        if version.nonce == session_nonce() {
+            return Err(MessageError::SelfConnection);
+        }
+        let ours = config.build(&self.addr);
+        let our_version = ours.version;
+        self.send(&Message::Version(ours))?;
+        self.acknowledge_version(our_version, version)?;
+        self.finish_handshake(our_version, deadline)
+    }
+
+    // Receive until both versions are exchanged and the peer's verack has arrived
+    fn finish_handshake(&mut self, our_version: i32, deadline: Instant) -> Result<VersionMessage, MessageError> {
+        let mut verack_received = false;
+        while self.version.is_none() || !verack_received {
+            let message = self.receive_before(deadline)?;
+            match message.ok_or_else(|| io::Error::new(ErrorKind::TimedOut, "handshake timed out"))? {
+                Message::Version(version) if version.nonce == session_nonce() => return Err(MessageError::SelfConnection),
+                Message::Version(version) if self.version.is_none() => self.acknowledge_version(our_version, version)?,
+                Message::Verack => verack_received = true,
+                _ => {}
+            }
+        }
+        Ok(self.version.clone().unwrap())
+    }
+
+    // Offer our features and send the verack that accepts the peer's version
+    fn acknowledge_version(&mut self, our_version: i32, version: VersionMessage) -> Result<(), MessageError> {
+        if our_version.min(version.version) >= WTXID_RELAY_VERSION {
+            self.send(&Message::WtxidRelay)?;
+        }
+        // BIP155: ask for addrv2 before our verack completes the handshake
+        self.send(&Message::SendAddrV2)?;
+        self.send(&Message::Verack)?;
+        self.version = Some(version);
+        Ok(())
+    }
+
+    // Like receive, but wait until the deadline instead of the I/O timeout, returning None
+    // once it passes. A timeout that strikes mid-message keeps what arrived for the next
+    // receive, so short deadlines are safe to poll with.
+    pub fn receive_before(&mut self, deadline: Instant) -> Result<Option<Message>, MessageError> {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Ok(None);
+        }
+        let io_timeout = self.stream.read_timeout()?;
+        self.stream.set_read_timeout(Some(remaining))?;
+        let result = self.receive();
+        self.stream.set_read_timeout(io_timeout)?;
+        match result {
+            Ok(message) => Ok(Some(message)),
+            Err(MessageError::Io(e)) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Send a ping with a random nonce and wait for the matching pong, returning the round
+    // trip; None if it does not come back before the timeout
+    pub fn ping(&mut self, timeout: Duration) -> Result<Option<Duration>, MessageError> {
+        let nonce = rand::random();
+        let sent = Instant::now();
+        self.send(&Message::Ping(nonce))?;
+        while let Some(message) = self.receive_before(sent + timeout)? {
+            if matches!(message, Message::Pong(pong) if pong == nonce) {
+                return Ok(Some(sent.elapsed()));
+            }
+        }
+        Ok(None)
+    }
+
+    // Ping count times, one ping every interval, and summarize the round trips; None when
+    // no pong came back. An unanswered ping ends the series, so a stalled peer costs one
+    // timeout, and so does a shutdown request. Messages arriving between pings are read and
+    // dropped. An error also ends the series and is returned beside the round trips measured
+    // before it, so they are not lost.
+    pub fn measure_latency(&mut self, count: u32, interval: Duration, timeout: Duration) -> (Option<PingStats>, Option<MessageError>) {
+        let mut rtts = Vec::new();
+        let error = (|| -> Result<(), MessageError> {
+            for i in 0..count {
+                let started = Instant::now();
+                match self.ping(timeout)? {
+                    Some(rtt) => rtts.push(rtt),
+                    None => break,
+                }
+                if i + 1 < count {
+                    while self.receive_before(started + interval)?.is_some() {}
+                }
+                if shutdown::requested() {
+                    break;
+                }
+            }
+            Ok(())
+        })()
+        .err();
+        let (Some(&min), Some(&max)) = (rtts.iter().min(), rtts.iter().max()) else {
+            return (None, error);
+        };
+        let samples = rtts.len() as u32;
+        (Some(PingStats { samples, min, avg: rtts.iter().sum::<Duration>() / samples, max }), error)
+    }
+
+    // Send getaddr and collect addr messages until one carries more than a single entry
+    // (peers also announce just themselves) or the timeout passes. An error ends the harvest
+    // but keeps the addresses that came before it.
+    pub fn harvest_addresses(&mut self, timeout: Duration) -> (Vec<AddrEntry>, Option<MessageError>) {
+        let mut entries = Vec::new();
+        if let Err(e) = self.send(&Message::GetAddr) {
+            return (entries, Some(e));
+        }
+        let deadline = Instant::now() + timeout;
+        loop {
+            match self.receive_before(deadline) {
+                Ok(Some(Message::Addr(batch) | Message::AddrV2(batch))) => {
+                    let complete = batch.len() > 1;
+                    entries.extend(batch);
+                    if complete {
+                        return (entries, None);
+                    }
+                }
+                Ok(Some(_)) => {}
+                Ok(None) => return (entries, None),
+                Err(e) => return (entries, Some(e)),
+            }
+        }
+    }
+}
+
+// A connection whose reads and writes all fail once the deadline passes, so a peer trickling
+// bytes cannot stretch a handshake past it; each is still limited by the I/O timeout
+struct DeadlineStream<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+    io_timeout: Duration,
+}
+
+impl DeadlineStream<'_> {
+    fn remaining(&self) -> io::Result<Duration> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::Error::new(ErrorKind::TimedOut, "handshake timed out"));
+        }
+        Ok(remaining.min(self.io_timeout))
+    }
+}
+
+impl Read for DeadlineStream<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.set_read_timeout(Some(self.remaining()?))?;
+        self.stream.read(buf)
+    }
+}
+
+impl Write for DeadlineStream<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.set_write_timeout(Some(self.remaining()?))?;
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+// Why a connection failed, coarse enough to decide whether trying again could help
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FailureKind {
+    Refused,        // nothing listening, or the proxy was turned away
+    Timeout,        // connect, read or handshake took too long
+    Disconnected,   // the peer closed or reset the connection
+    Unreachable,    // no route, or a network we cannot connect to
+    Protocol,       // the peer sent something we could not accept
+    SelfConnection, // the address leads back to us
+    Other,
+}
+
+impl FailureKind {
+    pub fn classify(error: &MessageError) -> Self {
+        let e = match error {
+            MessageError::Io(e) => e,
+            MessageError::SelfConnection => return FailureKind::SelfConnection,
+            _ => return FailureKind::Protocol,
+        };
+        match e.kind() {
+            ErrorKind::ConnectionRefused => FailureKind::Refused,
+            ErrorKind::TimedOut | ErrorKind::WouldBlock => FailureKind::Timeout,
+            ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted | ErrorKind::UnexpectedEof | ErrorKind::BrokenPipe => FailureKind::Disconnected,
+            ErrorKind::NetworkUnreachable | ErrorKind::HostUnreachable | ErrorKind::Unsupported => FailureKind::Unreachable,
+            _ => FailureKind::Other,
+        }
+    }
+
+    // Failures that may well not happen on the next attempt
+    pub fn is_transient(self) -> bool {
+        matches!(self, FailureKind::Timeout | FailureKind::Disconnected)
+    }
+}
+
+impl fmt::Display for FailureKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            FailureKind::Refused => "refused",
+            FailureKind::Timeout => "timeout",
+            FailureKind::Disconnected => "disconnected",
+            FailureKind::Unreachable => "unreachable",
+            FailureKind::Protocol => "protocol error",
+            FailureKind::SelfConnection => "self-connection",
+            FailureKind::Other => "other",
+        };
+        write!(f, "{}", name)
+    }
+}
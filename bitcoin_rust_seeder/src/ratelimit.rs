@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use crate::address::PeerAddr;
+
+// Per-subnet connection spacing, so a crawl never opens connections to one subnet back to
+// back: however many of its addresses are known, attempts on a subnet start at least an
+// interval apart, retries included
+
+// How addresses are grouped and how far apart their connections start
+#[derive(Debug, Clone, Copy)]
+pub struct SubnetLimit {
+    pub ipv4_prefix: u8,    // 24 groups by /24; 32 spaces out each host on its own
+    pub ipv6_prefix: u8,
+    pub interval: Duration, // zero turns limiting off
+}
+
+impl Default for SubnetLimit {
+    fn default() -> Self {
+        SubnetLimit { ipv4_prefix: 24, ipv6_prefix: 48, interval: Duration::from_secs(2) }
+    }
+}
+
+// A group of addresses connections are spaced across. Onion and I2P names say nothing about
+// where the service runs, so each is its own group.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Subnet {
+    V4(u32),
+    V6(u128),
+    Name(String),
+}
+
+// When each subnet may next be connected to
+#[derive(Debug)]
+pub struct SubnetLimiter {
+    limit: SubnetLimit,
+    next_free: HashMap<Subnet, Instant>,
+}
+
+impl SubnetLimiter {
+    pub fn new(limit: SubnetLimit) -> Self {
+        SubnetLimiter { limit, next_free: HashMap::new() }
+    }
+
+    // Whether a connection to addr may start now
+    pub fn is_ready(&self, addr: &PeerAddr, now: Instant) -> bool {
+        self.limit.interval.is_zero() || self.next_free.get(&self.subnet(addr)).is_none_or(|free| *free <= now)
+    }
+
+    // Book the first slot for addr's subnet at or after earliest, returning when it starts
+    pub fn reserve(&mut self, addr: &PeerAddr, earliest: Instant) -> Instant {
+        if self.limit.interval.is_zero() {
+            return earliest;
+        }
+        let subnet = self.subnet(addr);
+        let start = self.next_free.get(&subnet).map_or(earliest, |free| earliest.max(*free));
+        self.next_free.insert(subnet, start + self.limit.interval);
+        start
+    }
+
+    fn subnet(&self, addr: &PeerAddr) -> Subnet {
+        let ip = match addr {
+            PeerAddr::Ip(addr) => addr.ip(),
+            PeerAddr::Cjdns { ip, .. } => IpAddr::V6(*ip),
+            PeerAddr::Onion { host, .. } | PeerAddr::I2p { host, .. } => return Subnet::Name(host.clone()),
+        };
+        match ip.to_canonical() {
+            IpAddr::V4(ip) => Subnet::V4(u32::from(ip) & mask(self.limit.ipv4_prefix, 32) as u32),
+            IpAddr::V6(ip) => Subnet::V6(u128::from(ip) & mask(self.limit.ipv6_prefix, 128)),
+        }
+    }
+}
+
+// The top prefix bits of a width-bit address
+fn mask(prefix: u8, width: u32) -> u128 {
+    let prefix = (prefix as u32).min(width);
+    if prefix == 0 {
+        return 0;
+    }
+    (u128::MAX << (128 - prefix)) >> (128 - width)
+}
@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+
+// Uptime statistics in the style of sipa's bitcoin-seeder: each window keeps exponentially
+// decaying averages, so old outcomes fade with the window's time constant
+
+// Window lengths in seconds: 2 hours, 8 hours, a day, a week and a month
+const WINDOWS: [f64; 5] = [2.0 * 3600.0, 8.0 * 3600.0, 86400.0, 7.0 * 86400.0, 30.0 * 86400.0];
+
+// A window counts as good when its reliability and sample count both clear these
+const GOOD_RELIABILITY: [f64; 5] = [0.85, 0.70, 0.55, 0.45, 0.35];
+const GOOD_COUNT: [f64; 5] = [2.0, 4.0, 8.0, 16.0, 32.0];
+
+// Decaying average over one window
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct UptimeStat {
+    pub weight: f64,      // total weight of past samples, approaching 1 with history
+    pub count: f64,       // decayed number of samples
+    pub reliability: f64, // decayed fraction of samples that succeeded
+}
+
+impl UptimeStat {
+    fn update(&mut self, success: bool, age: f64, window: f64) {
+        let f = (-age / window).exp();
+        self.reliability = self.reliability * f + if success { 1.0 - f } else { 0.0 };
+        self.count = self.count * f + 1.0;
+        self.weight = self.weight * f + (1.0 - f);
+    }
+}
+
+// Uptime over every window, updated after each visit
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Uptime {
+    pub windows: [UptimeStat; 5],
+}
+
+impl Uptime {
+    // Fold in a visit made age seconds after the previous one
+    pub fn update(&mut self, success: bool, age: u64) {
+        for (stat, window) in self.windows.iter_mut().zip(WINDOWS) {
+            stat.update(success, age as f64, window);
+        }
+    }
+
+    // Whether any window shows enough successful samples to hand the peer out
+    pub fn is_good(&self) -> bool {
+        self.windows
+            .iter()
+            .zip(GOOD_RELIABILITY.iter().zip(GOOD_COUNT))
+            .any(|(stat, (&reliability, count))| stat.reliability > reliability && stat.count > count)
+    }
+
+    // Score between 0 and 1: the reliability of each window, discounted while it has few
+    // samples, averaged over the windows
+    pub fn score(&self) -> f64 {
+        let total: f64 = self
+            .windows
+            .iter()
+            .zip(GOOD_COUNT)
+            .map(|(stat, count)| stat.reliability * (stat.count / count).min(1.0))
+            .sum();
+        total / WINDOWS.len() as f64
+    }
+}
@@ -0,0 +1,50 @@
+// Service bits peers announce in version and addr messages
+
+pub const NODE_NETWORK: u64 = 1;
+pub const NODE_BLOOM: u64 = 1 << 2;
+pub const NODE_WITNESS: u64 = 1 << 3;
+pub const NODE_COMPACT_FILTERS: u64 = 1 << 6;
+pub const NODE_NETWORK_LIMITED: u64 = 1 << 10;
+pub const NODE_P2P_V2: u64 = 1 << 11;
+
+const NAMES: [(u64, &str); 6] = [
+    (NODE_NETWORK, "NODE_NETWORK"),
+    (NODE_BLOOM, "NODE_BLOOM"),
+    (NODE_WITNESS, "NODE_WITNESS"),
+    (NODE_COMPACT_FILTERS, "NODE_COMPACT_FILTERS"),
+    (NODE_NETWORK_LIMITED, "NODE_NETWORK_LIMITED"),
+    (NODE_P2P_V2, "NODE_P2P_V2"),
+];
+
+// Name of a single service bit, "bit N" for those without one
+pub fn name(bit: u32) -> String {
+    match NAMES.iter().find(|(flag, _)| *flag == 1 << bit) {
+        Some((_, name)) => name.to_string(),
+        None => format!("bit {}", bit),
+    }
+}
+
+// Parse service bits given as a number, decimal or 0x-prefixed hex, or as names joined by
+// commas, e.g. NODE_NETWORK,NODE_WITNESS or network,witness
+pub fn parse(value: &str) -> Result<u64, String> {
+    if let Some(hex) = value.strip_prefix("0x") {
+        return u64::from_str_radix(hex, 16).map_err(|e| format!("invalid service bits {}: {}", value, e));
+    }
+    if let Ok(bits) = value.parse() {
+        return Ok(bits);
+    }
+    value.split(',').try_fold(0, |bits, part| {
+        let wanted = part.trim().to_ascii_uppercase();
+        let wanted = wanted.strip_prefix("NODE_").unwrap_or(&wanted);
+        NAMES
+            .iter()
+            .find(|(_, name)| name.strip_prefix("NODE_") == Some(wanted))
+            .map(|(flag, _)| bits | flag)
+            .ok_or_else(|| format!("unknown service {}", part))
+    })
+}
+
+// Whether services include every bit of required
+pub fn has(services: u64, required: u64) -> bool {
+    services & required == required
+}
@@ -0,0 +1,73 @@
+// SHA3-256 (FIPS 202), needed for the checksum inside Tor v3 onion names
+
+const ROUND_CONSTANTS: [u64; 24] = [
+    0x0000000000000001, 0x0000000000008082, 0x800000000000808A, 0x8000000080008000,
+    0x000000000000808B, 0x0000000080000001, 0x8000000080008081, 0x8000000000008009,
+    0x000000000000008A, 0x0000000000000088, 0x0000000080008009, 0x000000008000000A,
+    0x000000008000808B, 0x800000000000008B, 0x8000000000008089, 0x8000000000008003,
+    0x8000000000008002, 0x8000000000000080, 0x000000000000800A, 0x800000008000000A,
+    0x8000000080008081, 0x8000000000008080, 0x0000000080000001, 0x8000000080008008,
+];
+
+// Rotation offsets and lane order of the combined rho and pi steps
+const ROTATIONS: [u32; 24] = [1, 3, 6, 10, 15, 21, 28, 36, 45, 55, 2, 14, 27, 41, 56, 8, 25, 43, 62, 18, 39, 61, 20, 44];
+const PI_LANES: [usize; 24] = [10, 7, 11, 17, 18, 3, 5, 16, 8, 21, 24, 4, 15, 23, 19, 13, 12, 2, 20, 14, 22, 9, 6, 1];
+
+// Bytes absorbed per permutation for a 256-bit output
+const RATE: usize = 136;
+
+fn keccak_f(state: &mut [u64; 25]) {
+    for round_constant in ROUND_CONSTANTS {
+        // Theta
+        let mut columns = [0u64; 5];
+        for (x, column) in columns.iter_mut().enumerate() {
+            *column = state[x] ^ state[x + 5] ^ state[x + 10] ^ state[x + 15] ^ state[x + 20];
+        }
+        for x in 0..5 {
+            let d = columns[(x + 4) % 5] ^ columns[(x + 1) % 5].rotate_left(1);
+            for y in 0..5 {
+                state[x + 5 * y] ^= d;
+            }
+        }
+
+        // Rho and pi
+        let mut carried = state[1];
+        for (&lane, &rotation) in PI_LANES.iter().zip(&ROTATIONS) {
+            let next = state[lane];
+            state[lane] = carried.rotate_left(rotation);
+            carried = next;
+        }
+
+        // Chi
+        for y in 0..5 {
+            let row: [u64; 5] = state[5 * y..5 * y + 5].try_into().unwrap();
+            for x in 0..5 {
+                state[5 * y + x] = row[x] ^ (!row[(x + 1) % 5] & row[(x + 2) % 5]);
+            }
+        }
+
+        // Iota
+        state[0] ^= round_constant;
+    }
+}
+
+pub fn sha3_256(data: &[u8]) -> [u8; 32] {
+    let mut state = [0u64; 25];
+    let mut padded = data.to_vec();
+    padded.push(0x06); // SHA3 domain separation plus the first padding bit
+    padded.resize(padded.len().next_multiple_of(RATE), 0);
+    *padded.last_mut().unwrap() |= 0x80;
+
+    for block in padded.chunks(RATE) {
+        for (lane, bytes) in state.iter_mut().zip(block.chunks(8)) {
+            *lane ^= u64::from_le_bytes(bytes.try_into().unwrap());
+        }
+        keccak_f(&mut state);
+    }
+
+    let mut digest = [0u8; 32];
+    for (bytes, lane) in digest.chunks_mut(8).zip(state) {
+        bytes.copy_from_slice(&lane.to_le_bytes());
+    }
+    digest
+}
@@ -0,0 +1,73 @@
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, Once};
+use std::thread;
+use std::time::Duration;
+
+// Shutdown on SIGINT or SIGTERM. Long-running commands poll requested() and wind down at the
+// next safe point, so what they gathered so far can still be written out. Peer connections
+// are registered here so that a thread blocked reading one is woken by closing the socket
+// instead of waiting out its I/O timeout.
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+static WATCHER: Once = Once::new();
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+static OPEN: Mutex<Vec<(u64, TcpStream)>> = Mutex::new(Vec::new());
+
+// How often the watcher thread looks for a shutdown request
+const WATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+extern "C" fn on_signal(_: libc::c_int) {
+    REQUESTED.store(true, Ordering::SeqCst);
+    // A second signal terminates at once, for when winding down takes too long
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_DFL);
+        libc::signal(libc::SIGTERM, libc::SIG_DFL);
+    }
+}
+
+pub fn install() {
+    let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+    // The handler may only touch the flag, so closing the sockets is left to a thread
+    WATCHER.call_once(|| {
+        thread::spawn(|| {
+            while !requested() {
+                thread::sleep(WATCH_INTERVAL);
+            }
+            close_open();
+        });
+    });
+}
+
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+// A connection that is closed when shutdown is requested, until this is dropped
+pub struct Registration(u64);
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        OPEN.lock().unwrap().retain(|(id, _)| *id != self.0);
+    }
+}
+
+pub fn register(stream: &TcpStream) -> Option<Registration> {
+    let clone = stream.try_clone().ok()?;
+    if requested() {
+        let _ = clone.shutdown(Shutdown::Both);
+    }
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    OPEN.lock().unwrap().push((id, clone));
+    Some(Registration(id))
+}
+
+fn close_open() {
+    for (_, stream) in OPEN.lock().unwrap().iter() {
+        let _ = stream.shutdown(Shutdown::Both);
+    }
+}
@@ -0,0 +1,103 @@
+use std::io::{self, Error, ErrorKind, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::time::Duration;
+
+use crate::address::PeerAddr;
+
+// SOCKS5 client (RFC 1928), enough to open TCP connections through Tor or another proxy.
+// Onion names are handed to the proxy as domain names for it to resolve.
+
+const VERSION: u8 = 5;
+const METHOD_NO_AUTH: u8 = 0;
+const METHOD_NONE_ACCEPTABLE: u8 = 0xFF;
+const CMD_CONNECT: u8 = 1;
+const ATYP_IPV4: u8 = 1;
+const ATYP_DOMAIN: u8 = 3;
+const ATYP_IPV6: u8 = 4;
+
+// Connect to target through the proxy. The connect timeout also bounds the negotiation, as
+// the proxy only replies once it has reached the target.
+pub fn connect(proxy: SocketAddr, target: &PeerAddr, connect_timeout: Duration) -> io::Result<TcpStream> {
+    let mut stream = TcpStream::connect_timeout(&proxy, connect_timeout)?;
+    stream.set_read_timeout(Some(connect_timeout))?;
+    stream.set_write_timeout(Some(connect_timeout))?;
+
+    stream.write_all(&[VERSION, 1, METHOD_NO_AUTH])?;
+    let mut choice = [0u8; 2];
+    stream.read_exact(&mut choice)?;
+    if choice[0] != VERSION {
+        return Err(Error::new(ErrorKind::InvalidData, "proxy does not speak SOCKS5"));
+    }
+    if choice[1] != METHOD_NO_AUTH {
+        let reason = if choice[1] == METHOD_NONE_ACCEPTABLE { "proxy requires authentication" } else { "proxy chose an unknown method" };
+        return Err(Error::new(ErrorKind::PermissionDenied, reason));
+    }
+
+    let mut request = vec![VERSION, CMD_CONNECT, 0];
+    match target {
+        PeerAddr::Ip(addr) => match addr.ip() {
+            IpAddr::V4(v4) => {
+                request.push(ATYP_IPV4);
+                request.extend(v4.octets());
+            }
+            IpAddr::V6(v6) => {
+                request.push(ATYP_IPV6);
+                request.extend(v6.octets());
+            }
+        },
+        PeerAddr::Cjdns { ip, .. } => {
+            request.push(ATYP_IPV6);
+            request.extend(ip.octets());
+        }
+        PeerAddr::Onion { host, .. } | PeerAddr::I2p { host, .. } => {
+            request.push(ATYP_DOMAIN);
+            request.push(host.len() as u8);
+            request.extend(host.as_bytes());
+        }
+    }
+    request.extend(target.port().to_be_bytes());
+    stream.write_all(&request)?;
+
+    // Version, reply code, reserved, then the bound address, which we skip
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply)?;
+    if reply[1] != 0 {
+        return Err(Error::new(ErrorKind::ConnectionRefused, format!("proxy could not connect: {}", reply_message(reply[1]))));
+    }
+    let bound_len = match reply[3] {
+        ATYP_IPV4 => 4,
+        ATYP_IPV6 => 16,
+        ATYP_DOMAIN => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len)?;
+            len[0] as usize
+        }
+        other => return Err(Error::new(ErrorKind::InvalidData, format!("proxy replied with address type {}", other))),
+    };
+    let mut bound = vec![0u8; bound_len + 2];
+    stream.read_exact(&mut bound)?;
+    Ok(stream)
+}
+
+// Reply codes from RFC 1928, plus the extended codes Tor uses for onion services
+fn reply_message(code: u8) -> &'static str {
+    match code {
+        0x01 => "general failure",
+        0x02 => "connection not allowed by ruleset",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        0xF0 => "onion service descriptor not found",
+        0xF1 => "onion service descriptor is invalid",
+        0xF2 => "onion service introduction failed",
+        0xF3 => "onion service rendezvous failed",
+        0xF4 => "onion service missing client authorization",
+        0xF5 => "onion service wrong client authorization",
+        0xF6 => "onion address is invalid",
+        0xF7 => "onion service introduction timed out",
+        _ => "unknown error",
+    }
+}
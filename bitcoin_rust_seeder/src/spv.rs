@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::time::{Duration, Instant};
+
+use bitcoin::block::Header;
+use bitcoin::consensus::{deserialize, serialize};
+use bitcoin::hash_types::{TxMerkleNode, Txid};
+use bitcoin::hashes::{sha256d, Hash};
+use bitcoin::{BlockHash, OutPoint, Transaction};
+use block_breaker::merkle::MerkleProof;
+use block_breaker::BlockProcessor;
+
+use crate::bloom::{BloomFilter, BLOOM_UPDATE_NONE};
+use crate::message::{InvType, Inventory, MerkleBlockMessage, Message, MessageError};
+use crate::peer::Peer;
+
+// Legacy SPV client: load a BIP37 filter into a peer, ask for filtered blocks and check the
+// partial merkle trees that come back against the block headers
+
+// Most transactions a block can hold: the weight limit over the smallest transaction weight
+const MAX_BLOCK_TRANSACTIONS: u32 = 4_000_000 / 240;
+
+// Why a merkleblock was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MerkleBlockError {
+    NoTransactions,
+    TooManyTransactions(u32),
+    TooManyHashes,
+    MissingBits,
+    MissingHashes,
+    UnusedData,      // bits or hashes left over after the traversal
+    DuplicateBranch, // identical sibling subtrees, the CVE-2012-2459 malleation
+    BadProofOfWork,
+    RootMismatch,
+}
+
+impl fmt::Display for MerkleBlockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MerkleBlockError::NoTransactions => write!(f, "merkleblock claims no transactions"),
+            MerkleBlockError::TooManyTransactions(count) => write!(f, "{} transactions exceed the {} a block can hold", count, MAX_BLOCK_TRANSACTIONS),
+            MerkleBlockError::TooManyHashes => write!(f, "more hashes than transactions"),
+            MerkleBlockError::MissingBits => write!(f, "flag bits ran out during the traversal"),
+            MerkleBlockError::MissingHashes => write!(f, "hashes ran out during the traversal"),
+            MerkleBlockError::UnusedData => write!(f, "hashes or flag bytes left unused"),
+            MerkleBlockError::DuplicateBranch => write!(f, "identical sibling subtrees"),
+            MerkleBlockError::BadProofOfWork => write!(f, "header does not meet its own target"),
+            MerkleBlockError::RootMismatch => write!(f, "merkle proof does not lead to the header's merkle root"),
+        }
+    }
+}
+
+impl std::error::Error for MerkleBlockError {}
+
+// A filtered block as received, with an inclusion proof for each matched transaction and
+// the matched transactions the peer sent along
+#[derive(Debug, Clone)]
+pub struct FilteredBlock {
+    pub block_hash: BlockHash,
+    pub header: Header,
+    pub total_transactions: u32,
+    pub proofs: Vec<MerkleProof>, // empty when the block failed verification
+    pub transactions: Vec<Transaction>,
+    pub error: Option<MerkleBlockError>,
+}
+
+// What an SPV session fetched
+#[derive(Debug, Default)]
+pub struct SpvReport {
+    pub blocks: Vec<FilteredBlock>,
+    pub not_found: usize,
+    pub filter_updates: usize, // outpoints added with filteradd
+}
+
+// Load the filter and request the blocks filtered, one at a time. A ping after each request
+// marks the end of its reply, as peers answer getdata in full before reading further
+// messages. With BLOOM_UPDATE_NONE the peer never grows the filter, so the outpoints of
+// matched outputs are added with filteradd, letting later blocks show their spends.
+pub fn fetch_filtered_blocks(peer: &mut Peer, mut filter: BloomFilter, blocks: &[BlockHash], timeout: Duration) -> Result<SpvReport, MessageError> {
+    peer.send(&Message::FilterLoad(filter.clone()))?;
+    let mut report = SpvReport::default();
+
+    for hash in blocks {
+        peer.send(&Message::GetData(vec![Inventory { kind: InvType::FilteredBlock, hash: hash.to_byte_array() }]))?;
+        let nonce = rand::random();
+        peer.send(&Message::Ping(nonce))?;
+
+        let deadline = Instant::now() + timeout;
+        let mut current: Option<FilteredBlock> = None;
+        loop {
+            let message = peer
+                .receive_before(deadline)?
+                .ok_or_else(|| io::Error::new(ErrorKind::TimedOut, format!("no complete reply for block {}", hash)))?;
+            match message {
+                Message::MerkleBlock(merkleblock) if merkleblock.header.block_hash() == *hash => current = Some(verify_merkle_block(merkleblock)),
+                Message::Tx(raw) => {
+                    let (Some(block), Ok(tx)) = (&mut current, deserialize::<Transaction>(&raw)) else {
+                        continue;
+                    };
+                    let txid = tx.txid();
+                    if !block.proofs.iter().any(|proof| proof.txid == txid) {
+                        continue;
+                    }
+                    if filter.flags == BLOOM_UPDATE_NONE {
+                        for (vout, output) in tx.output.iter().enumerate() {
+                            if filter.matches_script(&output.script_pubkey) {
+                                let outpoint = serialize(&OutPoint { txid, vout: vout as u32 });
+                                filter.insert(&outpoint);
+                                peer.send(&Message::FilterAdd(outpoint))?;
+                                report.filter_updates += 1;
+                            }
+                        }
+                    }
+                    block.transactions.push(tx);
+                }
+                Message::NotFound(items) => report.not_found += items.len(),
+                Message::Pong(pong) if pong == nonce => break,
+                _ => {}
+            }
+        }
+        report.blocks.extend(current);
+    }
+    Ok(report)
+}
+
+// Check the header's proof of work, rebuild the partial merkle tree against the header's
+// root and turn each matched leaf into a block_breaker proof verified against the header
+fn verify_merkle_block(merkleblock: MerkleBlockMessage) -> FilteredBlock {
+    let mut block = FilteredBlock {
+        block_hash: merkleblock.header.block_hash(),
+        header: merkleblock.header,
+        total_transactions: merkleblock.total_transactions,
+        proofs: Vec::new(),
+        transactions: Vec::new(),
+        error: None,
+    };
+    let proofs = merkleblock
+        .header
+        .validate_pow(merkleblock.header.target())
+        .map_err(|_| MerkleBlockError::BadProofOfWork)
+        .and_then(|_| PartialMerkleTree::new(&merkleblock))
+        .and_then(|tree| if tree.root == merkleblock.header.merkle_root { tree.proofs() } else { Err(MerkleBlockError::RootMismatch) });
+    match proofs {
+        Ok(proofs) if proofs.iter().all(|proof| BlockProcessor::verify_merkle_proof(proof, &merkleblock.header)) => block.proofs = proofs,
+        Ok(_) => block.error = Some(MerkleBlockError::RootMismatch),
+        Err(e) => block.error = Some(e),
+    }
+    block
+}
+
+// Partial merkle tree traversal as in Bitcoin Core's CPartialMerkleTree, keeping every
+// node hash it computes so branches can be read back for the matched leaves
+struct PartialMerkleTree<'a> {
+    message: &'a MerkleBlockMessage,
+    bits_used: usize,
+    hashes_used: usize,
+    nodes: HashMap<(u32, u32), TxMerkleNode>, // (height, position) to hash
+    matches: Vec<u32>,                        // positions of matched leaves
+    root: TxMerkleNode,
+    height: u32,
+}
+
+impl<'a> PartialMerkleTree<'a> {
+    fn new(message: &'a MerkleBlockMessage) -> Result<Self, MerkleBlockError> {
+        let total = message.total_transactions;
+        if total == 0 {
+            return Err(MerkleBlockError::NoTransactions);
+        }
+        if total > MAX_BLOCK_TRANSACTIONS {
+            return Err(MerkleBlockError::TooManyTransactions(total));
+        }
+        if message.hashes.len() > total as usize {
+            return Err(MerkleBlockError::TooManyHashes);
+        }
+        if message.flags.len() * 8 < message.hashes.len() {
+            return Err(MerkleBlockError::MissingBits);
+        }
+
+        let mut height = 0;
+        while width(total, height) > 1 {
+            height += 1;
+        }
+        let mut tree = PartialMerkleTree {
+            message,
+            bits_used: 0,
+            hashes_used: 0,
+            nodes: HashMap::new(),
+            matches: Vec::new(),
+            root: TxMerkleNode::all_zeros(),
+            height,
+        };
+        tree.root = tree.traverse(height, 0)?;
+        if tree.bits_used.div_ceil(8) != message.flags.len() || tree.hashes_used != message.hashes.len() {
+            return Err(MerkleBlockError::UnusedData);
+        }
+        Ok(tree)
+    }
+
+    fn traverse(&mut self, height: u32, position: u32) -> Result<TxMerkleNode, MerkleBlockError> {
+        let flags = &self.message.flags;
+        let bit = flags.get(self.bits_used / 8).ok_or(MerkleBlockError::MissingBits)? >> (self.bits_used % 8) & 1 == 1;
+        self.bits_used += 1;
+
+        let hash = if height == 0 || !bit {
+            // A hash given outright: a pruned subtree, or a leaf that matched when the bit is set
+            let hash = *self.message.hashes.get(self.hashes_used).ok_or(MerkleBlockError::MissingHashes)?;
+            self.hashes_used += 1;
+            if height == 0 && bit {
+                self.matches.push(position);
+            }
+            hash
+        } else {
+            let left = self.traverse(height - 1, position * 2)?;
+            let right = if position * 2 + 1 < width(self.message.total_transactions, height - 1) {
+                let right = self.traverse(height - 1, position * 2 + 1)?;
+                if right == left {
+                    return Err(MerkleBlockError::DuplicateBranch);
+                }
+                right
+            } else {
+                left
+            };
+            merkle_parent(&left, &right)
+        };
+        self.nodes.insert((height, position), hash);
+        Ok(hash)
+    }
+
+    // A proof for each matched leaf. Every node on a matched leaf's path was expanded, so
+    // both children of each are known; a missing right sibling means the node was paired
+    // with itself.
+    fn proofs(&self) -> Result<Vec<MerkleProof>, MerkleBlockError> {
+        self.matches
+            .iter()
+            .map(|&position| {
+                let branch = (0..self.height)
+                    .map(|height| {
+                        let node = position >> height;
+                        self.nodes.get(&(height, node ^ 1)).or_else(|| self.nodes.get(&(height, node))).copied()
+                    })
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or(MerkleBlockError::MissingHashes)?;
+                Ok(MerkleProof {
+                    txid: Txid::from_raw_hash(self.nodes[&(0, position)].to_raw_hash()),
+                    position: position as usize,
+                    branch,
+                    merkle_root: self.root,
+                })
+            })
+            .collect()
+    }
+}
+
+// Number of nodes at a height of the tree over total leaves
+fn width(total: u32, height: u32) -> u32 {
+    (total + (1 << height) - 1) >> height
+}
+
+fn merkle_parent(left: &TxMerkleNode, right: &TxMerkleNode) -> TxMerkleNode {
+    let mut data = [0u8; 64];
+    data[..32].copy_from_slice(left.as_byte_array());
+    data[32..].copy_from_slice(right.as_byte_array());
+    TxMerkleNode::from_raw_hash(sha256d::Hash::hash(&data))
+}
@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::crawler::CrawlReport;
+use crate::services;
+
+// A reachable peer counts as lagging this many blocks below the median height
+const LAGGING_BLOCKS: i32 = 6;
+
+// User agents listed in the human summary; the JSON form has them all
+const TOP_USER_AGENTS: usize = 10;
+
+// Aggregate view of one crawl, printed for people and serialized for tools
+#[derive(Debug, Clone, Serialize)]
+pub struct CrawlStats {
+    pub visited: usize,
+    pub reachable: usize,
+    pub unreachable: usize,
+    pub reachable_ratio: f64,
+    pub failures: BTreeMap<String, usize>, // unreachable peers by failure kind
+    pub misbehavior: BTreeMap<String, usize>, // peers committing each violation
+    pub user_agents: BTreeMap<String, usize>,
+    pub protocol_versions: BTreeMap<i32, usize>,
+    pub services: BTreeMap<String, usize>, // reachable peers announcing each bit
+    pub capabilities: BTreeMap<&'static str, usize>,
+    pub tips: BTreeMap<&'static str, usize>, // reachable peers by tip status, when checked
+    pub heights: Option<HeightSpread>, // None when no peer was reachable
+}
+
+// Start heights announced by reachable peers
+#[derive(Debug, Clone, Serialize)]
+pub struct HeightSpread {
+    pub min: i32,
+    pub median: i32,
+    pub max: i32,
+    pub lagging: usize, // peers more than LAGGING_BLOCKS below the median
+}
+
+impl CrawlStats {
+    pub fn from_report(report: &CrawlReport) -> Self {
+        let mut stats = CrawlStats {
+            visited: report.results.len(),
+            reachable: report.reachable(),
+            unreachable: report.results.len() - report.reachable(),
+            reachable_ratio: 0.0,
+            failures: BTreeMap::new(),
+            misbehavior: BTreeMap::new(),
+            user_agents: BTreeMap::new(),
+            protocol_versions: BTreeMap::new(),
+            services: BTreeMap::new(),
+            capabilities: report.capabilities().into_iter().collect(),
+            tips: BTreeMap::new(),
+            heights: None,
+        };
+        if stats.visited > 0 {
+            stats.reachable_ratio = stats.reachable as f64 / stats.visited as f64;
+        }
+
+        let mut heights = Vec::new();
+        for result in &report.results {
+            let mut kinds = result.misbehavior.clone();
+            kinds.sort();
+            kinds.dedup();
+            for kind in kinds {
+                *stats.misbehavior.entry(kind.to_string()).or_default() += 1;
+            }
+            let Some(version) = &result.version else {
+                let kind = result.failure.map_or("unknown".to_string(), |kind| kind.to_string());
+                *stats.failures.entry(kind).or_default() += 1;
+                continue;
+            };
+            *stats.user_agents.entry(version.user_agent.clone()).or_default() += 1;
+            *stats.protocol_versions.entry(version.version).or_default() += 1;
+            for bit in (0..64).filter(|bit| version.services & (1 << bit) != 0) {
+                *stats.services.entry(services::name(bit)).or_default() += 1;
+            }
+            heights.push(version.start_height);
+            if let Some(tip) = result.tip {
+                *stats.tips.entry(tip.name()).or_default() += 1;
+            }
+        }
+
+        if !heights.is_empty() {
+            heights.sort_unstable();
+            let median = heights[heights.len() / 2];
+            stats.heights = Some(HeightSpread {
+                min: heights[0],
+                median,
+                max: heights[heights.len() - 1],
+                lagging: heights.iter().filter(|&&height| height < median - LAGGING_BLOCKS).count(),
+            });
+        }
+        stats
+    }
+
+    fn percent(&self, count: usize) -> f64 {
+        100.0 * count as f64 / self.reachable.max(1) as f64
+    }
+}
+
+// Counts sorted from most to least common, ties by name
+pub fn by_count<K: Clone + Ord>(counts: &BTreeMap<K, usize>) -> Vec<(K, usize)> {
+    let mut sorted: Vec<(K, usize)> = counts.iter().map(|(key, count)| (key.clone(), *count)).collect();
+    sorted.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    sorted
+}
+
+impl fmt::Display for CrawlStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Reachable: {} of {} ({:.1}%)", self.reachable, self.visited, 100.0 * self.reachable_ratio)?;
+        for (kind, count) in by_count(&self.failures) {
+            writeln!(f, "  {:<24} {:>5}", kind, count)?;
+        }
+        if !self.misbehavior.is_empty() {
+            writeln!(f, "Misbehaving peers:")?;
+            for (kind, count) in by_count(&self.misbehavior) {
+                writeln!(f, "  {:<24} {:>5}", kind, count)?;
+            }
+        }
+        if self.reachable == 0 {
+            return Ok(());
+        }
+
+        if let Some(heights) = &self.heights {
+            writeln!(
+                f,
+                "Heights: min {}, median {}, max {}; {} peers more than {} blocks behind the median",
+                heights.min, heights.median, heights.max, heights.lagging, LAGGING_BLOCKS
+            )?;
+        }
+        if !self.tips.is_empty() {
+            writeln!(f, "Chain tips:")?;
+            for (status, count) in by_count(&self.tips) {
+                writeln!(f, "  {:<24} {:>5} ({:.1}%)", status, count, self.percent(count))?;
+            }
+        }
+        writeln!(f, "User agents:")?;
+        let user_agents = by_count(&self.user_agents);
+        for (user_agent, count) in user_agents.iter().take(TOP_USER_AGENTS) {
+            writeln!(f, "  {:<24} {:>5} ({:.1}%)", user_agent, count, self.percent(*count))?;
+        }
+        if user_agents.len() > TOP_USER_AGENTS {
+            writeln!(f, "  ... and {} more", user_agents.len() - TOP_USER_AGENTS)?;
+        }
+        writeln!(f, "Protocol versions:")?;
+        for (version, count) in by_count(&self.protocol_versions) {
+            writeln!(f, "  {:<24} {:>5} ({:.1}%)", version, count, self.percent(count))?;
+        }
+        writeln!(f, "Services:")?;
+        for (service, count) in by_count(&self.services) {
+            writeln!(f, "  {:<24} {:>5} ({:.1}%)", service, count, self.percent(count))?;
+        }
+        writeln!(f, "Capabilities:")?;
+        for (name, count) in by_count(&self.capabilities) {
+            writeln!(f, "  {:<24} {:>5} ({:.1}%)", name, count, self.percent(count))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,343 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::address::PeerAddr;
+use crate::crawler::CrawlResult;
+use crate::discovery::DiscoveredPeers;
+#[cfg(feature = "geoip")]
+use crate::geoip::GeoIp;
+use crate::message::AddrEntry;
+use crate::peer::{FailureKind, Features};
+use crate::reliability::Uptime;
+use crate::services;
+use crate::tips::TipStatus;
+
+// How long to wait before visiting a peer again, by how it has behaved so far
+const RETEST_GOOD: u64 = 60 * 60;
+const RETEST_SEEN: u64 = 4 * 60 * 60; // answered at some point but not reliably
+const RETEST_UNREACHABLE: u64 = 24 * 60 * 60;
+
+// Share of the score taken by the lifetime success ratio rather than the uptime windows
+const SUCCESS_RATIO_WEIGHT: f64 = 0.25;
+// Handshake time that halves a peer's score
+const LATENCY_HALF_SCORE_MS: f64 = 1000.0;
+
+// What we know about one peer across crawls. Times are unix seconds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerRecord {
+    pub addr: PeerAddr,
+    pub services: u64,
+    pub first_seen: u64,              // when the address was first learned
+    pub last_seen: u64,               // newest addr timestamp or successful visit
+    pub last_attempt: Option<u64>,
+    pub last_success: Option<u64>,    // last completed handshake
+    pub user_agent: Option<String>,
+    pub protocol_version: Option<i32>,
+    pub start_height: Option<i32>,
+    pub fee_filter: Option<u64>,          // sat/kvB
+    pub compact_blocks_version: Option<u64>,
+    pub compact_blocks_announce: Option<bool>,
+    #[serde(default)]
+    pub features: Features,
+    pub last_error: Option<String>,
+    pub last_failure: Option<FailureKind>,
+    #[serde(default)]
+    pub successes: u32,
+    #[serde(default)]
+    pub failures: u32,
+    pub handshake_ms: Option<u64>, // moving average of connect plus handshake time
+    #[serde(default)]
+    pub ping_min_ms: Option<f64>, // ping round trips from the latest visit that measured any
+    #[serde(default)]
+    pub ping_avg_ms: Option<f64>,
+    #[serde(default)]
+    pub ping_max_ms: Option<f64>,
+    #[serde(default)]
+    pub tip: Option<TipStatus>, // from the latest visit that checked it against a reference chain
+    #[serde(default)]
+    pub uptime: Uptime,
+    #[serde(default)]
+    pub country: Option<String>, // ISO 3166 code, from a GeoIP database
+    #[serde(default)]
+    pub asn: Option<u32>,
+    #[serde(default)]
+    pub as_org: Option<String>,
+}
+
+impl PeerRecord {
+    fn new(addr: PeerAddr, services: u64, now: u64) -> Self {
+        PeerRecord {
+            addr,
+            services,
+            first_seen: now,
+            last_seen: now,
+            last_attempt: None,
+            last_success: None,
+            user_agent: None,
+            protocol_version: None,
+            start_height: None,
+            fee_filter: None,
+            compact_blocks_version: None,
+            compact_blocks_announce: None,
+            features: Features::default(),
+            last_error: None,
+            last_failure: None,
+            successes: 0,
+            failures: 0,
+            handshake_ms: None,
+            ping_min_ms: None,
+            ping_avg_ms: None,
+            ping_max_ms: None,
+            tip: None,
+            uptime: Uptime::default(),
+            country: None,
+            asn: None,
+            as_org: None,
+        }
+    }
+
+    // Score between 0 and 1 for ranking peers: uptime over the windows blended with the
+    // share of all visits that succeeded, scaled down as the handshake gets slower. Peers
+    // without a measured handshake score 0, below every peer that completed one.
+    pub fn score(&self) -> f64 {
+        let Some(handshake_ms) = self.handshake_ms else { return 0.0 };
+        let attempts = self.successes + self.failures;
+        let success_ratio = if attempts == 0 { 0.0 } else { self.successes as f64 / attempts as f64 };
+        let reliability = (1.0 - SUCCESS_RATIO_WEIGHT) * self.uptime.score() + SUCCESS_RATIO_WEIGHT * success_ratio;
+        reliability * LATENCY_HALF_SCORE_MS / (LATENCY_HALF_SCORE_MS + handshake_ms as f64)
+    }
+
+    // Reliable enough to hand out to clients
+    pub fn is_good(&self) -> bool {
+        self.uptime.is_good()
+    }
+
+    fn retest_due(&self, now: u64) -> bool {
+        let Some(last_attempt) = self.last_attempt else { return true };
+        let interval = if self.is_good() {
+            RETEST_GOOD
+        } else if self.last_success.is_some() {
+            RETEST_SEEN
+        } else {
+            RETEST_UNREACHABLE
+        };
+        now.saturating_sub(last_attempt) >= interval
+    }
+
+    // Dead once its latest visit failed and it has gone max_age without a successful one,
+    // counted from when we learned of it if it never had any. Addresses not visited yet
+    // die when nobody has gossiped them for max_age.
+    fn is_dead(&self, now: u64, max_age: u64) -> bool {
+        match self.last_attempt {
+            Some(last_attempt) => {
+                self.last_success != Some(last_attempt) && now.saturating_sub(self.last_success.unwrap_or(self.first_seen)) > max_age
+            }
+            None => now.saturating_sub(self.last_seen) > max_age,
+        }
+    }
+}
+
+// Peer records persisted as a JSON array
+#[derive(Debug, Default)]
+pub struct PeerStore {
+    peers: BTreeMap<PeerAddr, PeerRecord>,
+}
+
+impl PeerStore {
+    // Load a store, starting empty when the file does not exist yet
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let json = match fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(PeerStore::default()),
+            Err(e) => return Err(e.into()),
+        };
+        let records: Vec<PeerRecord> = serde_json::from_str(&json)?;
+        Ok(PeerStore { peers: records.into_iter().map(|record| (record.addr.clone(), record)).collect() })
+    }
+
+    // Write through a temporary file so an interrupted save leaves the old store intact
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let records: Vec<&PeerRecord> = self.peers.values().collect();
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(&records)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+
+    // Addresses worth visiting again: never tried, or last tried longer ago than their
+    // behaviour warrants, highest score first and so never-reached addresses last
+    pub fn due_for_retest(&self, now: u64) -> Vec<PeerAddr> {
+        let mut records: Vec<&PeerRecord> = self.peers.values().filter(|record| record.retest_due(now)).collect();
+        records.sort_by(|a, b| b.score().total_cmp(&a.score()));
+        records.into_iter().map(|record| record.addr.clone()).collect()
+    }
+
+    // Drop dead peers, returning how many went
+    pub fn age_out(&mut self, now: u64, max_age: u64) -> usize {
+        let before = self.peers.len();
+        self.peers.retain(|_, record| !record.is_dead(now, max_age));
+        before - self.peers.len()
+    }
+
+    // Good peers offering every required service bit to serve to clients, best first
+    pub fn best(&self, limit: usize, required_services: u64) -> Vec<&PeerRecord> {
+        let mut records: Vec<&PeerRecord> = self
+            .peers
+            .values()
+            .filter(|record| record.is_good() && services::has(record.services, required_services))
+            .collect();
+        records.sort_by(|a, b| b.score().total_cmp(&a.score()));
+        records.truncate(limit);
+        records
+    }
+
+    // Every known address as an addr entry, stamped with when it was last seen
+    pub fn addr_entries(&self) -> Vec<AddrEntry> {
+        self.peers
+            .values()
+            .map(|record| AddrEntry { timestamp: record.last_seen as u32, services: record.services, addr: record.addr.clone() })
+            .collect()
+    }
+
+    pub fn records(&self) -> impl Iterator<Item = &PeerRecord> {
+        self.peers.values()
+    }
+
+    // Tag every IP peer with its country and autonomous system, returning how many the
+    // databases placed; Tor, I2P and CJDNS peers have no location to look up
+    #[cfg(feature = "geoip")]
+    pub fn tag_locations(&mut self, geoip: &GeoIp) -> Result<usize, Box<dyn Error>> {
+        let mut located = 0;
+        for record in self.peers.values_mut() {
+            let PeerAddr::Ip(addr) = &record.addr else { continue };
+            let info = geoip.locate(addr.ip())?;
+            if info.country.is_some() || info.asn.is_some() {
+                located += 1;
+            }
+            record.country = info.country;
+            record.asn = info.asn;
+            record.as_org = info.as_org;
+        }
+        Ok(located)
+    }
+
+    // Add addresses learned from addr messages
+    pub fn merge_discovered(&mut self, discovered: &DiscoveredPeers, now: u64) {
+        for entry in discovered.entries() {
+            let addr = entry.addr.clone();
+            let seen = (entry.timestamp as u64).min(now);
+            let record = self.peers.entry(addr.clone()).or_insert_with(|| PeerRecord::new(addr, entry.services, now));
+            if seen > record.last_seen {
+                record.last_seen = seen;
+                record.services = entry.services;
+            }
+        }
+    }
+
+    // Record the outcome of visiting a peer
+    pub fn record_result(&mut self, result: &CrawlResult, now: u64) {
+        let record = self.peers.entry(result.addr.clone()).or_insert_with(|| PeerRecord::new(result.addr.clone(), 0, now));
+        let success = result.version.is_some();
+        record.uptime.update(success, now.saturating_sub(record.last_attempt.unwrap_or(0)));
+        record.last_attempt = Some(now);
+        record.last_error = result.error.clone();
+        record.last_failure = result.failure;
+        if success {
+            record.successes += 1;
+        } else {
+            record.failures += 1;
+        }
+        if let Some(latency) = result.handshake_latency {
+            let sample = latency.as_millis() as u64;
+            record.handshake_ms = Some(record.handshake_ms.map_or(sample, |average| (average * 3 + sample) / 4));
+        }
+        if let Some(version) = &result.version {
+            record.last_success = Some(now);
+            record.last_seen = now;
+            record.services = version.services;
+            record.user_agent = Some(version.user_agent.clone());
+            record.protocol_version = Some(version.version);
+            record.start_height = Some(version.start_height);
+            record.fee_filter = result.fee_filter;
+            record.compact_blocks_version = result.compact_blocks.map(|sendcmpct| sendcmpct.version);
+            record.compact_blocks_announce = result.compact_blocks.map(|sendcmpct| sendcmpct.announce);
+            record.features = result.features;
+        }
+        if let Some(ping) = result.ping {
+            // Kept to the microsecond
+            let ms = |rtt: Duration| (rtt.as_secs_f64() * 1e6).round() / 1000.0;
+            record.ping_min_ms = Some(ms(ping.min));
+            record.ping_avg_ms = Some(ms(ping.avg));
+            record.ping_max_ms = Some(ms(ping.max));
+        }
+        if result.tip.is_some() {
+            record.tip = result.tip;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A peer visited hourly, succeeding at the given visits, its handshakes taking handshake_ms
+    fn record(port: u16, outcomes: &[bool], handshake_ms: Option<u64>) -> PeerRecord {
+        let mut record = PeerRecord::new(PeerAddr::Ip(([192, 0, 2, 1], port).into()), 1, 0);
+        for (i, &success) in outcomes.iter().enumerate() {
+            record.uptime.update(success, 3600);
+            record.last_attempt = Some(i as u64 * 3600);
+            if success {
+                record.successes += 1;
+            } else {
+                record.failures += 1;
+            }
+        }
+        record.handshake_ms = handshake_ms;
+        record
+    }
+
+    fn store(records: Vec<PeerRecord>) -> PeerStore {
+        PeerStore { peers: records.into_iter().map(|record| (record.addr.clone(), record)).collect() }
+    }
+
+    fn ports(addrs: impl IntoIterator<Item = PeerAddr>) -> Vec<u16> {
+        addrs
+            .into_iter()
+            .map(|addr| match addr {
+                PeerAddr::Ip(addr) => addr.port(),
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn score_weighs_latency_and_success_ratio() {
+        let always = [true; 40];
+        let mut flaky = [true; 40];
+        flaky[..10].fill(false);
+        let (fast, slow, unreliable) = (record(1, &always, Some(100)), record(2, &always, Some(2000)), record(3, &flaky, Some(100)));
+        assert!(fast.score() > slow.score());
+        assert!(fast.score() > unreliable.score());
+        assert!(fast.score() <= 1.0);
+        assert_eq!(record(4, &[], None).score(), 0.0);
+    }
+
+    #[test]
+    fn ranks_unmeasured_peers_last() {
+        let always = [true; 40];
+        let peers = store(vec![record(1, &[], None), record(2, &always, Some(2000)), record(3, &always, Some(100)), record(4, &[false], None)]);
+        assert_eq!(ports(peers.best(10, 0).into_iter().map(|record| record.addr.clone())), [3, 2]);
+        let due = ports(peers.due_for_retest(40 * 24 * 3600));
+        assert_eq!(due[..2], [3, 2]);
+    }
+}
@@ -0,0 +1,59 @@
+use bitcoin::block::Header;
+use bitcoin::hashes::Hash;
+use bitcoin::BlockHash;
+use block_breaker::chain::{HeaderChain, HeaderChainReport};
+use block_breaker::pow::ConsensusParams;
+
+use crate::address::PeerAddr;
+use crate::message::{GetHeadersMessage, Message, MessageError, MAX_HEADERS_RESULTS};
+use crate::peer::{Peer, PROTOCOL_VERSION};
+
+// Header chain downloaded from one peer, validated with block_breaker's chain rules
+#[derive(Debug, Clone)]
+pub struct HeaderSyncReport {
+    pub peer: PeerAddr,
+    pub advertised_height: i32, // start height from the peer's version message
+    pub headers: Vec<Header>,   // starting with genesis
+    pub validation: HeaderChainReport,
+}
+
+impl HeaderSyncReport {
+    // Height and hash of the last header the peer sent
+    pub fn tip(&self) -> (u32, BlockHash) {
+        let tip = self.headers.last().expect("headers start with genesis");
+        (self.headers.len() as u32 - 1, tip.block_hash())
+    }
+}
+
+// Request headers after our tip until the peer sends a short batch (it has no more) or
+// max_headers have arrived, then validate the chain from genesis under the network's rules
+pub fn sync_headers(peer: &mut Peer, genesis: Header, params: &ConsensusParams, max_headers: usize) -> Result<HeaderSyncReport, MessageError> {
+    let mut headers = vec![genesis];
+    while headers.len() <= max_headers {
+        let tip = headers.last().unwrap().block_hash();
+        peer.send(&Message::GetHeaders(GetHeadersMessage {
+            version: PROTOCOL_VERSION as u32,
+            locator: vec![tip],
+            stop_hash: BlockHash::all_zeros(),
+        }))?;
+
+        let batch = loop {
+            if let Message::Headers(batch) = peer.receive()? {
+                break batch;
+            }
+        };
+        let complete = batch.len() < MAX_HEADERS_RESULTS;
+        headers.extend(batch);
+        if complete {
+            break;
+        }
+    }
+    headers.truncate(max_headers + 1);
+
+    Ok(HeaderSyncReport {
+        peer: peer.addr.clone(),
+        advertised_height: peer.version.as_ref().map_or(0, |version| version.start_height),
+        validation: HeaderChain::validate(&headers, 0, params),
+        headers,
+    })
+}
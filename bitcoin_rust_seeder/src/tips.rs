@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use bitcoin::block::Header;
+use bitcoin::consensus::deserialize;
+use bitcoin::hashes::Hash;
+use bitcoin::BlockHash;
+use serde::{Deserialize, Serialize};
+
+use crate::message::{GetHeadersMessage, Message, MessageError};
+use crate::peer::{Peer, PROTOCOL_VERSION};
+
+// Stale and forked peer detection: ask peers for the headers after our locator and see
+// where their tips fall against the best chain we know of
+
+// Where a peer's tip is relative to the best known chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum TipStatus {
+    Synced { height: u32 },                   // on our chain, at most stale_blocks behind its tip
+    Behind { height: u32, blocks: u32 },      // on our chain, further behind
+    Ahead { height: u32, blocks: u32 },       // extends past our tip; the reference chain is stale
+    Forked { fork_height: u32, height: u32 }, // on a branch off our chain after fork_height
+}
+
+impl TipStatus {
+    // Height of the peer's tip
+    pub fn height(self) -> u32 {
+        match self {
+            TipStatus::Synced { height } | TipStatus::Behind { height, .. } | TipStatus::Ahead { height, .. } | TipStatus::Forked { height, .. } => height,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TipStatus::Synced { .. } => "synced",
+            TipStatus::Behind { .. } => "behind",
+            TipStatus::Ahead { .. } => "ahead",
+            TipStatus::Forked { .. } => "forked",
+        }
+    }
+}
+
+impl fmt::Display for TipStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TipStatus::Synced { height } => write!(f, "synced at {}", height),
+            TipStatus::Behind { height, blocks } => write!(f, "{} blocks behind at {}", blocks, height),
+            TipStatus::Ahead { height, blocks } => write!(f, "{} blocks ahead at {}", blocks, height),
+            TipStatus::Forked { fork_height, height } => write!(f, "forked after {}, tip at {}", fork_height, height),
+        }
+    }
+}
+
+// A header chain from genesis, as written by headers --out, taken as the best chain
+#[derive(Debug)]
+pub struct BestChain {
+    hashes: Vec<BlockHash>, // by height
+    heights: HashMap<BlockHash, u32>,
+}
+
+impl BestChain {
+    // Load concatenated 80-byte headers, checking that each builds on the one before
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let bytes = fs::read(path)?;
+        if bytes.is_empty() || bytes.len() % 80 != 0 {
+            return Err(format!("{} is not a file of 80-byte headers", path.display()).into());
+        }
+        let headers = bytes.chunks_exact(80).map(deserialize).collect::<Result<Vec<Header>, _>>()?;
+        BestChain::from_headers(&headers).map_err(|e| format!("{}: {}", path.display(), e).into())
+    }
+
+    // Take headers from genesis on as the best chain, checking that each builds on the one
+    // before
+    pub fn from_headers(headers: &[Header]) -> Result<Self, String> {
+        if headers.is_empty() {
+            return Err("no headers".to_string());
+        }
+        let mut hashes: Vec<BlockHash> = Vec::with_capacity(headers.len());
+        for (height, header) in headers.iter().enumerate() {
+            if height > 0 && header.prev_blockhash != hashes[height - 1] {
+                return Err(format!("header {} does not build on the one before", height));
+            }
+            hashes.push(header.block_hash());
+        }
+        let heights = hashes.iter().enumerate().map(|(height, hash)| (*hash, height as u32)).collect();
+        Ok(BestChain { hashes, heights })
+    }
+
+    pub fn genesis(&self) -> BlockHash {
+        self.hashes[0]
+    }
+
+    pub fn tip_height(&self) -> u32 {
+        self.hashes.len() as u32 - 1
+    }
+
+    // Block locator as Bitcoin Core builds it: the last ten blocks, then doubling steps back
+    // to genesis
+    pub fn locator(&self) -> Vec<BlockHash> {
+        let mut locator = Vec::new();
+        let mut height = self.tip_height() as i64;
+        let mut step = 1;
+        while height > 0 {
+            locator.push(self.hashes[height as usize]);
+            if locator.len() >= 10 {
+                step *= 2;
+            }
+            height -= step;
+        }
+        locator.push(self.genesis());
+        locator
+    }
+
+    // Place a peer from its answer to our locator, which holds the headers after the last
+    // block we share. An empty answer means its tip is one of the locator blocks; which one
+    // only its advertised height can tell.
+    pub fn classify(&self, headers: &[Header], advertised_height: i32, stale_blocks: u32) -> Option<TipStatus> {
+        let tip = self.tip_height();
+        let on_chain = |height: u32| {
+            let blocks = tip.saturating_sub(height);
+            if blocks > stale_blocks {
+                TipStatus::Behind { height, blocks }
+            } else {
+                TipStatus::Synced { height }
+            }
+        };
+        let Some(first) = headers.first() else {
+            return Some(on_chain((advertised_height.max(0) as u32).min(tip)));
+        };
+        // Headers that do not connect to our chain say nothing about where the peer is
+        let fork_height = *self.heights.get(&first.prev_blockhash)?;
+        let peer_height = fork_height + headers.len() as u32;
+        let shared = headers
+            .iter()
+            .zip(fork_height + 1..)
+            .take_while(|(header, height)| self.hashes.get(*height as usize) == Some(&header.block_hash()))
+            .count() as u32;
+        let last_shared = fork_height + shared;
+        Some(if shared == headers.len() as u32 {
+            on_chain(peer_height)
+        } else if last_shared == tip {
+            TipStatus::Ahead { height: peer_height, blocks: peer_height - tip }
+        } else {
+            TipStatus::Forked { fork_height: last_shared, height: peer_height }
+        })
+    }
+}
+
+// Send our locator and place the peer from the headers it answers with; None when it does
+// not answer within the timeout or its headers do not connect. A full batch means the peer
+// has more, so its height is then a lower bound.
+pub fn check_tip(peer: &mut Peer, chain: &BestChain, stale_blocks: u32, timeout: Duration) -> Result<Option<TipStatus>, MessageError> {
+    Ok(query_tip(peer, chain, stale_blocks, timeout)?.map(|(status, _)| status))
+}
+
+// check_tip, also naming the peer's tip: the last header it sent, or the block of ours it
+// stopped at when it sent none
+pub fn query_tip(peer: &mut Peer, chain: &BestChain, stale_blocks: u32, timeout: Duration) -> Result<Option<(TipStatus, BlockHash)>, MessageError> {
+    peer.send(&Message::GetHeaders(GetHeadersMessage {
+        version: PROTOCOL_VERSION as u32,
+        locator: chain.locator(),
+        stop_hash: BlockHash::all_zeros(),
+    }))?;
+    let deadline = Instant::now() + timeout;
+    while let Some(message) = peer.receive_before(deadline)? {
+        if let Message::Headers(headers) = message {
+            let advertised_height = peer.version.as_ref().map_or(0, |version| version.start_height);
+            return Ok(chain.classify(&headers, advertised_height, stale_blocks).map(|status| {
+                let hash = headers.last().map_or_else(|| chain.hashes[status.height() as usize], Header::block_hash);
+                (status, hash)
+            }));
+        }
+    }
+    Ok(None)
+}